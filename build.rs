@@ -0,0 +1,29 @@
+//! Selects the depth of the merkle tree of note commitments at build time.
+//!
+//! The depth must equal the 'tree_depth' of the ZEOS token contract of the deployment (see: Global) since it determines
+//! the circuit and thus the proving and verifying keys. Set it with the environment variable ZEOS_MERKLE_DEPTH, e.g.:
+//!
+//!     ZEOS_MERKLE_DEPTH=32 wasm-pack build --target web
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The default depth of the merkle tree (16 leaves per tree)
+const DEFAULT_MERKLE_DEPTH: usize = 4;
+
+fn main()
+{
+    println!("cargo:rerun-if-env-changed=ZEOS_MERKLE_DEPTH");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let depth = match env::var("ZEOS_MERKLE_DEPTH") {
+        Ok(v) => v.trim().parse::<usize>().unwrap_or_else(|_| panic!("ZEOS_MERKLE_DEPTH must be a number: '{}'", v)),
+        Err(_) => DEFAULT_MERKLE_DEPTH
+    };
+    // only merkle trees with depth up to 32 are supported by the circuit design
+    assert!((1..=32).contains(&depth), "ZEOS_MERKLE_DEPTH must be between 1 and 32: {}", depth);
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("merkle_depth.rs");
+    fs::write(out, format!("/// $\\mathsf{{MerkleDepth^{{Orchard}}}}$ (selected at build time, see: build.rs)\npub const MERKLE_DEPTH_ORCHARD: usize = {};\n", depth)).unwrap();
+}
@@ -6,7 +6,7 @@ use crate::{
     keys::Scope::External,
     note_encryption::OrchardDomain,
 };
-use pasta_curves::pallas;
+use pasta_curves::{pallas, Fp};
 use rand::RngCore;
 use ff::Field;
 use group::GroupEncoding;
@@ -26,8 +26,22 @@ pub const ZA_BURNFT2: u64       = 0x7;
 pub const ZA_BURNNFT: u64       = 0x8;
 pub const ZA_BURNAUTH: u64      = 0x9;
 
+/// The maximum size of a zaction memo in bytes (its length is serialized as a single byte)
+pub const MAX_ZACTION_MEMO_SIZE: usize = 255;
+/// The size of a serialized zaction (without memo): type, public inputs and memo length
+pub const ZACTION_SIZE: usize = 8 + 32 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 32 + 32 + 8 + 8 + 1;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ZActionError
+{
+    #[error("zaction memo too long: {0} bytes (max. 255)")]
+    MemoTooLong(usize),
+    #[error("invalid zaction encoding: {0}")]
+    InvalidEncoding(String)
+}
+
 // ZEOS ZAction (See equivalent struct 'zaction' in zeosio.hpp)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ZAction
 {
     za_type: u64,
@@ -59,9 +73,20 @@ impl ZAction
         self.za_type
     }
 
-    /// serialize EOS
-    pub fn serialize_eos(&self) -> String
+    /// Returns the public memo of this zaction (e.g. the memo of the transfer to the receiving EOS account of a burn)
+    pub fn memo(&self) -> &String
     {
+        &self.memo
+    }
+
+    /// serialize EOS. Fails if the memo exceeds MAX_ZACTION_MEMO_SIZE.
+    pub fn serialize_eos(&self) -> Result<String, ZActionError>
+    {
+        if self.memo.len() > MAX_ZACTION_MEMO_SIZE
+        {
+            return Err(ZActionError::MemoTooLong(self.memo.len()));
+        }
+
         let mut res = String::from(hex::encode(self.za_type.to_le_bytes()));
         res.push_str(&hex::encode(self.ins.anchor.inner().0[0].to_le_bytes()));
         res.push_str(&hex::encode(self.ins.anchor.inner().0[1].to_le_bytes()));
@@ -101,12 +126,51 @@ impl ZAction
         res.push_str(&hex::encode(self.ins.cmc.inner().0[3].to_le_bytes()));
         res.push_str(&hex::encode(self.ins.accb.inner().to_le_bytes()));
         res.push_str(&hex::encode(self.ins.accc.inner().to_le_bytes()));
-        let memo = if self.memo.len() > 255 {&self.memo.as_bytes()[0..255]} else {self.memo.as_bytes()};
-        let len = format!("{:02X?}", memo.len());
+        let len = format!("{:02X?}", self.memo.len());
         res.push_str(&len);
-        res.push_str(&hex::encode(memo));
+        res.push_str(&hex::encode(self.memo.as_bytes()));
+
+        Ok(res)
+    }
+
+    /// deserialize EOS: parses the zaction at the beginning of the hex string 'str' (see: serialize_eos). Returns the
+    /// zaction and the number of hex characters it occupies in 'str'.
+    pub fn deserialize_eos(str: &str) -> Result<(Self, usize), ZActionError>
+    {
+        let mut bytes = [0; ZACTION_SIZE];
+        let header = str.get(0..ZACTION_SIZE*2).ok_or(ZActionError::InvalidEncoding(String::from("too short")))?;
+        hex::decode_to_slice(header, &mut bytes).map_err(|e| ZActionError::InvalidEncoding(e.to_string()))?;
+
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i+8].try_into().unwrap());
+        let fp_at = |i: usize| Fp([u64_at(i), u64_at(i+8), u64_at(i+16), u64_at(i+24)]);
+
+        let za_type = u64_at(0);
+        let anchor = Anchor::from(fp_at(8));
+        let nf = Nullifier::from(fp_at(40));
+        let rk: Option<pallas::Affine> = pallas::Affine::from_xy(fp_at(72), fp_at(104)).into();
+        let rk = rk.ok_or(ZActionError::InvalidEncoding(String::from("rk")))?;
+        let rk = VerificationKey::try_from(rk.to_bytes()).map_err(|_| ZActionError::InvalidEncoding(String::from("rk")))?;
+        let nft = match bytes[136] {
+            0 => false,
+            1 => true,
+            _ => return Err(ZActionError::InvalidEncoding(String::from("nft")))
+        };
+        let b_d1 = NoteValue::from_raw(u64_at(137));
+        let b_d2 = NoteValue::from_raw(u64_at(145));
+        let b_sc = NoteValue::from_raw(u64_at(153));
+        let c_d1 = NoteValue::from_raw(u64_at(161));
+        let cmb = ExtractedNoteCommitment::from(fp_at(169));
+        let cmc = ExtractedNoteCommitment::from(fp_at(201));
+        let accb = NoteValue::from_raw(u64_at(233));
+        let accc = NoteValue::from_raw(u64_at(241));
+
+        let memo_len = bytes[249] as usize;
+        let memo = str.get(ZACTION_SIZE*2..(ZACTION_SIZE + memo_len)*2).ok_or(ZActionError::InvalidEncoding(String::from("memo too short")))?;
+        let memo = hex::decode(memo).map_err(|e| ZActionError::InvalidEncoding(e.to_string()))?;
+        let memo = String::from_utf8(memo).map_err(|e| ZActionError::InvalidEncoding(e.to_string()))?;
 
-        res
+        let ins = Instance::from_parts(anchor, nf, rk, nft, b_d1, b_d2, b_sc, c_d1, cmb, cmc, accb, accc);
+        Ok((ZAction::from_parts(za_type, ins, memo), (ZACTION_SIZE + memo_len) * 2))
     }
 }
 
@@ -139,6 +203,21 @@ impl RawZAction
         memo: String,
         mut rng: R
     ) -> Self {
+        Self::from_parts_with_alpha(za_type, fvk, auth_path_a, pallas::Scalar::random(&mut rng), note_a, note_b, note_c, memo)
+    }
+
+    /// Constructs a `RawZAction` from its constituent parts using a given randomizer 'alpha_a' (e.g. when restoring
+    /// an unproven transaction).
+    pub fn from_parts_with_alpha(
+        za_type: u64,
+        fvk: &FullViewingKey,
+        auth_path_a: Option<MerklePath>,
+        alpha_a: pallas::Scalar,
+        note_a: Option<Note>,
+        note_b: Option<Note>,
+        note_c: Option<Note>,
+        memo: String
+    ) -> Self {
 
         // TODO checks
 
@@ -146,7 +225,7 @@ impl RawZAction
             za_type,
             fvk: fvk.clone(),
             auth_path_a,
-            alpha_a: pallas::Scalar::random(&mut rng),
+            alpha_a,
             note_a,
             note_b,
             note_c,
@@ -196,6 +275,12 @@ impl RawZAction
         self.note_c
     }
 
+    /// Returns the memo of this action
+    pub fn memo(&self) -> &String
+    {
+        &self.memo
+    }
+
     /// returns the corresponding ZAction
     pub fn zaction(&self) -> ZAction
     {
@@ -274,9 +359,10 @@ impl RawZAction
         // note_c is transmitted only if part C of the circuit is involved. This includes BURNFT2 (Burn flag set)
         if self.za_type == ZA_TRANSFERFT || self.za_type == ZA_BURNFT || self.za_type == ZA_BURNFT2
         {
-            // encrypt note_c
-            // TODO: if note_c.d1 == 0 add dummy note
-            let ne = NoteEncryption::new(Some(self.fvk.to_ovk(External)), self.note_c.unwrap());
+            // encrypt note_c: a note_c without value (e.g. the second output of a TRANSFERFT whose note is paid out
+            // entirely) is not recoverable by the sender either
+            let ovk = if self.note_c.unwrap().d1().inner() == 0 { None } else { Some(self.fvk.to_ovk(External)) };
+            let ne = NoteEncryption::new(ovk, self.note_c.unwrap());
             let esk = OrchardDomain::derive_esk(&self.note_c.unwrap()).unwrap();
             let epk = OrchardDomain::ka_derive_public(&self.note_c.unwrap(), &esk);
             let encrypted_note = TransmittedNoteCiphertext {
@@ -293,7 +379,7 @@ impl RawZAction
 #[cfg(test)]
 mod tests
 {
-    use super::{RawZAction, Note};
+    use super::{RawZAction, ZAction, Note, ZActionError};
     use rand_core::OsRng;
 
     #[test]
@@ -302,6 +388,18 @@ mod tests
         let mut rng = OsRng.clone();
         let (_sk, fvk, note) = Note::dummy(&mut rng, None, None);
         let rza = RawZAction::from_parts(0xDEADBEEFDEADBEEF, &fvk, None, None, Some(note), None, String::from("mschoenebeck"), rng);
-        println!("{}", rza.zaction().serialize_eos());
+        let str = rza.zaction().serialize_eos().unwrap();
+
+        // round trip
+        let (za, len) = ZAction::deserialize_eos(&str).unwrap();
+        assert_eq!(len, str.len());
+        assert_eq!(za.memo(), "mschoenebeck");
+        assert_eq!(za.instance().to_halo2_instance(), rza.zaction().instance().to_halo2_instance());
+        assert_eq!(za.serialize_eos().unwrap(), str);
+        assert!(ZAction::deserialize_eos(&str[0..str.len()-2]).is_err());
+
+        // memos are never truncated silently
+        let rza = RawZAction::from_parts(0xDEADBEEFDEADBEEF, &fvk, None, None, Some(note), None, "x".repeat(256), rng);
+        assert_eq!(rza.zaction().serialize_eos(), Err(ZActionError::MemoTooLong(256)));
     }
 }
\ No newline at end of file
@@ -8,8 +8,31 @@ use rand_core::RngCore;
 use crate::FullViewingKey;
 use crate::SpendingKey;
 use crate::keys::Scope;
+use crate::network::Network;
 use bech32::{FromBase32, ToBase32, Variant};
 
+/// The length of the raw encoding of an address
+pub const RAW_ADDRESS_SIZE: usize = 43;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum AddressError
+{
+    #[error("invalid bech32m encoding: {0}")]
+    InvalidEncoding(String),
+    #[error("invalid checksum")]
+    BadChecksum,
+    #[error("expected bech32m but found bech32 encoding")]
+    WrongVariant,
+    #[error("not a shielded address: unknown prefix {0}")]
+    UnknownPrefix(String),
+    #[error("expected an address of network {expected} but found an address of network {found}")]
+    WrongNetwork{ expected: Network, found: Network },
+    #[error("expected {expected} bytes but found {found}")]
+    InvalidLength{ expected: usize, found: usize },
+    #[error("invalid diversifier or diversified transmission key (pk_d)")]
+    InvalidKey
+}
+
 /// A shielded payment address.
 ///
 /// # Examples
@@ -78,20 +101,32 @@ impl Address {
         })
     }
 
-    /// Encodes this address as Bech32m
-    pub fn to_bech32m(&self) -> String
+    /// Encodes this address as Bech32m using the human readable part of 'network'
+    pub fn to_bech32m(&self, network: Network) -> String
     {
-        bech32::encode("za", self.to_raw_address_bytes().to_base32(), Variant::Bech32m).unwrap()
+        bech32::encode(network.address_hrp(), self.to_raw_address_bytes().to_base32(), Variant::Bech32m).unwrap()
     }
 
-    /// Parse a Bech32m encoded address
-    pub fn from_bech32m(str: &String) -> CtOption<Self>
+    /// Parse a Bech32m encoded address of 'network'. Fails if 'str' is not a valid Bech32m encoded address or if it
+    /// belongs to another network.
+    pub fn from_bech32m(str: &str, network: Network) -> Result<Self, AddressError>
     {
-        let (hrp, data, variant) = bech32::decode(&str).unwrap();
-        let bytes: [u8; 43] = Vec::<u8>::from_base32(&data).unwrap()[0..43].try_into().expect("from_bech32m: incorrect length");
-        assert_eq!(hrp, "za");
-        assert_eq!(variant, Variant::Bech32m);
-        Address::from_raw_address_bytes(&bytes)
+        let (hrp, data, variant) = bech32::decode(str).map_err(|e| match e {
+            bech32::Error::InvalidChecksum => AddressError::BadChecksum,
+            e => AddressError::InvalidEncoding(e.to_string())
+        })?;
+        if variant != Variant::Bech32m
+        {
+            return Err(AddressError::WrongVariant);
+        }
+        let found = Network::from_address_hrp(&hrp).ok_or(AddressError::UnknownPrefix(hrp))?;
+        if found != network
+        {
+            return Err(AddressError::WrongNetwork{ expected: network, found });
+        }
+        let bytes = Vec::<u8>::from_base32(&data).map_err(|e| AddressError::InvalidEncoding(e.to_string()))?;
+        let bytes: [u8; RAW_ADDRESS_SIZE] = bytes.try_into().map_err(|b: Vec<u8>| AddressError::InvalidLength{ expected: RAW_ADDRESS_SIZE, found: b.len() })?;
+        Option::from(Address::from_raw_address_bytes(&bytes)).ok_or(AddressError::InvalidKey)
     }
 }
 
@@ -117,15 +152,60 @@ pub mod testing {
     }
 
     use rand::rngs::OsRng;
+    use crate::network::Network;
+    use super::AddressError;
+    use bech32::{ToBase32, Variant};
 
     #[test]
     fn test_bech32m_encode_decode()
     {
         let mut rng = OsRng.clone();
         let a = Address::dummy(&mut rng);
-        let encoded = a.to_bech32m();
+        let encoded = a.to_bech32m(Network::Kylin);
         println!("{}", encoded);
-        let decoded = Address::from_bech32m(&encoded).unwrap();
+        assert!(encoded.starts_with("za1"));
+        let decoded = Address::from_bech32m(&encoded, Network::Kylin).unwrap();
         assert_eq!(a.to_raw_address_bytes(), decoded.to_raw_address_bytes());
+
+        // an address of another network is rejected
+        assert_eq!(Address::from_bech32m(&encoded, Network::Mainnet), Err(AddressError::WrongNetwork{ expected: Network::Mainnet, found: Network::Kylin }));
+        assert_eq!(Address::from_bech32m(&a.to_bech32m(Network::Mainnet), Network::Jungle), Err(AddressError::WrongNetwork{ expected: Network::Jungle, found: Network::Mainnet }));
+    }
+
+    #[test]
+    fn test_bech32m_decode_errors()
+    {
+        let mut rng = OsRng.clone();
+        let encoded = Address::dummy(&mut rng).to_bech32m(Network::Mainnet);
+
+        // a single mistyped character
+        let mut chars: Vec<char> = encoded.chars().collect();
+        chars[10] = if chars[10] == 'q' { 'p' } else { 'q' };
+        let mistyped: String = chars.into_iter().collect();
+        assert_eq!(Address::from_bech32m(&mistyped, Network::Mainnet), Err(AddressError::BadChecksum));
+
+        assert!(matches!(Address::from_bech32m("", Network::Mainnet), Err(AddressError::InvalidEncoding(_))));
+        assert!(matches!(Address::from_bech32m("mschoenebeck", Network::Mainnet), Err(AddressError::InvalidEncoding(_))));
+
+        let raw = Address::dummy(&mut rng).to_raw_address_bytes();
+        let bech32 = bech32::encode(Network::Mainnet.address_hrp(), raw.to_base32(), Variant::Bech32).unwrap();
+        assert_eq!(Address::from_bech32m(&bech32, Network::Mainnet), Err(AddressError::WrongVariant));
+        let unknown = bech32::encode("zs", raw.to_base32(), Variant::Bech32m).unwrap();
+        assert_eq!(Address::from_bech32m(&unknown, Network::Mainnet), Err(AddressError::UnknownPrefix("zs".to_string())));
+        let short = bech32::encode(Network::Mainnet.address_hrp(), raw[0..42].to_base32(), Variant::Bech32m).unwrap();
+        assert_eq!(Address::from_bech32m(&short, Network::Mainnet), Err(AddressError::InvalidLength{ expected: 43, found: 42 }));
+        let invalid = bech32::encode(Network::Mainnet.address_hrp(), [0xff; 43].to_base32(), Variant::Bech32m).unwrap();
+        assert_eq!(Address::from_bech32m(&invalid, Network::Mainnet), Err(AddressError::InvalidKey));
+    }
+
+    #[test]
+    fn test_bech32m_legacy_addresses()
+    {
+        // addresses encoded before network profiles existed ('za' for every address) are Kylin addresses
+        let mut rng = OsRng.clone();
+        let a = Address::dummy(&mut rng);
+        let legacy = bech32::encode("za", a.to_raw_address_bytes().to_base32(), Variant::Bech32m).unwrap();
+        assert_eq!(Address::from_bech32m(&legacy, Network::default()).unwrap().to_raw_address_bytes(), a.to_raw_address_bytes());
+        assert_eq!(Address::from_bech32m(&legacy, Network::Mainnet), Err(AddressError::WrongNetwork{ expected: Network::Mainnet, found: Network::Kylin }));
     }
 }
@@ -0,0 +1,75 @@
+//! Base85 (Z85) encoding as used for private deposits into third party smart contracts.
+//!
+//! See also: https://rfc.zeromq.org/spec/32/
+
+const ALPHABET: &[u8; 85] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+/// Encodes 'data' as base85 string. The length of 'data' must be a multiple of 4. Every 4 bytes of
+/// input result in 5 characters of output, i.e. a 32 bytes note commitment is encoded as 40 characters.
+pub fn encode(data: &[u8]) -> String
+{
+    assert!(data.len() % 4 == 0, "base85 encode: input length must be a multiple of 4");
+    let mut res = String::with_capacity(data.len() / 4 * 5);
+    for chunk in data.chunks(4)
+    {
+        let mut value = u32::from_be_bytes(chunk.try_into().unwrap());
+        let mut block = [0; 5];
+        for i in (0..5).rev()
+        {
+            block[i] = ALPHABET[(value % 85) as usize];
+            value /= 85;
+        }
+        res.push_str(std::str::from_utf8(&block).unwrap());
+    }
+    res
+}
+
+/// Decodes a base85 string. Returns 'None' if the length of 'str' is not a multiple of 5 or if it
+/// contains characters which are not part of the alphabet.
+pub fn decode(str: &str) -> Option<Vec<u8>>
+{
+    if str.len() % 5 != 0
+    {
+        return None;
+    }
+    let mut res = Vec::with_capacity(str.len() / 5 * 4);
+    for chunk in str.as_bytes().chunks(5)
+    {
+        let mut value: u64 = 0;
+        for c in chunk
+        {
+            let digit = ALPHABET.iter().position(|a| a == c)?;
+            value = value * 85 + digit as u64;
+        }
+        if value > u32::MAX as u64
+        {
+            return None;
+        }
+        res.extend_from_slice(&(value as u32).to_be_bytes());
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{encode, decode};
+
+    #[test]
+    fn test_z85()
+    {
+        // test vector from the Z85 specification
+        let data = [0x86, 0x4F, 0xD2, 0x6F, 0xB5, 0x59, 0xF7, 0x5B];
+        assert_eq!(encode(&data), "HelloWorld");
+        assert_eq!(decode("HelloWorld").unwrap(), data.to_vec());
+
+        let cm = [0xAB; 32];
+        let str = encode(&cm);
+        assert_eq!(str.len(), 40);
+        assert_eq!(decode(&str).unwrap(), cm.to_vec());
+
+        assert!(decode("Hello").is_some());
+        assert!(decode("Hell").is_none());
+        assert!(decode("Hell\"").is_none());
+    }
+}
@@ -0,0 +1,149 @@
+//! Chain indexer for ZEOS.
+//!
+//! Mirrors the global data sets of the ZEOS token contract into a local SQLite database (see: zeos_orchard::indexer)
+//! and serves the sync API for wallets. The indexer either follows the contract tables of an EOS API endpoint (polled
+//! every --poll-interval seconds) or indexes the action traces of a recording (see: zeos_orchard::validator::Fixture).
+//!
+//! Usage: zeos-indexer [OPTIONS]
+
+use std::process::exit;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use nonempty::NonEmpty;
+use zeos_orchard::contract::TokenContract;
+use zeos_orchard::indexer::{self, Batch, Index};
+use zeos_orchard::validator::Fixture;
+use zeos_orchard::Network;
+
+const USAGE: &str = "Usage: zeos-indexer [OPTIONS]
+
+Options:
+  --network <NAME>          mainnet, kylin, jungle or local (default: $ZEOS_NETWORK or kylin)
+  --db <PATH>               the database of the index (default: ~/.zeos/<network>.index)
+  --listen <ADDR>           address of the sync API (default: 127.0.0.1:8790)
+  --endpoint <URL>          the EOS API endpoint whose contract tables are followed (default: the network's endpoints)
+  --poll-interval <SECS>    seconds between polls of the contract tables (default: 5)
+  --fixture <PATH>          index the action traces of a recording instead of following the contract tables";
+
+struct Args
+{
+    network: Network,
+    db: String,
+    listen: String,
+    endpoint: Option<String>,
+    poll_interval: Duration,
+    fixture: Option<String>
+}
+
+impl Args
+{
+    fn parse(args: Vec<String>) -> Result<Self, String>
+    {
+        let mut flags = Vec::new();
+        let mut it = args.into_iter();
+        while let Some(arg) = it.next()
+        {
+            let (name, value) = match arg.split_once('=') {
+                Some((n, v)) if n.starts_with("--") => (n.to_string(), v.to_string()),
+                _ if arg.starts_with("--") => (arg.clone(), it.next().ok_or_else(|| format!("missing value of {}", arg))?),
+                _ => return Err(format!("unknown argument: {}", arg))
+            };
+            flags.push((name, value));
+        }
+        let flag = |name: &str| flags.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+
+        let network: Network = match flag("--network").or_else(|| std::env::var("ZEOS_NETWORK").ok()) {
+            Some(n) => n.parse().map_err(|e| format!("{}", e))?,
+            None => Network::default()
+        };
+        let db = match flag("--db") {
+            Some(path) => path,
+            None => {
+                let home = std::env::var("HOME").map_err(|_| String::from("HOME not set: use --db"))?;
+                let dir = std::path::PathBuf::from(home).join(".zeos");
+                std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+                dir.join(format!("{}.index", network)).display().to_string()
+            }
+        };
+        let poll_interval = match flag("--poll-interval") {
+            Some(v) => Duration::from_secs(v.parse().map_err(|_| format!("invalid poll interval: {}", v))?),
+            None => Duration::from_secs(5)
+        };
+        Ok(Args {
+            network,
+            db,
+            listen: flag("--listen").unwrap_or(String::from("127.0.0.1:8790")),
+            endpoint: flag("--endpoint"),
+            poll_interval,
+            fixture: flag("--fixture")
+        })
+    }
+}
+
+/// Polls the contract tables and adds new notes and leaves to 'index' (the index stays available to the sync API while
+/// the contract is polled)
+fn follow(index: &Mutex<Index>, contract: &TokenContract, interval: Duration)
+{
+    loop
+    {
+        let (note_count, leaf_count) = {
+            let index = index.lock().unwrap();
+            (index.note_count(), index.tree().leaf_count())
+        };
+        let res = pollster::block_on(Batch::poll(contract, note_count, leaf_count)).and_then(|batch| index.lock().unwrap().apply(batch));
+        match res {
+            Ok(0) => (),
+            Ok(n) => println!("indexed {} notes (total: {})", n, note_count + n as u64),
+            Err(e) => eprintln!("poll failed: {}", e)
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn run(args: Args) -> Result<(), String>
+{
+    let mut index = Index::open(&args.db, args.network).map_err(|e| format!("{}: {}", args.db, e))?;
+    println!("index of {} at {} ({} notes)", args.network, args.db, index.note_count());
+
+    if let Some(path) = &args.fixture
+    {
+        let fixture: Fixture = std::fs::read_to_string(path).map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let n = index.index_fixture(&fixture).map_err(|e| format!("{}: {}", path, e))?;
+        println!("indexed {} transactions (total: {} notes)", n, index.note_count());
+    }
+
+    let server = tiny_http::Server::http(&args.listen).map_err(|e| format!("failed to listen on {}: {}", args.listen, e))?;
+    let index = Arc::new(Mutex::new(index));
+    if args.fixture.is_none()
+    {
+        let contract = match &args.endpoint {
+            Some(url) => TokenContract::new(args.network, NonEmpty::new(url.clone())),
+            None => TokenContract::from_network(args.network)
+        };
+        let index = index.clone();
+        let interval = args.poll_interval;
+        std::thread::spawn(move || follow(&index, &contract, interval));
+    }
+    println!("listening on http://{}", args.listen);
+    indexer::serve(&index, &server);
+    Ok(())
+}
+
+fn main()
+{
+    let args = match Args::parse(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+    if let Err(e) = run(args)
+    {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
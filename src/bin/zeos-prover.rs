@@ -0,0 +1,29 @@
+//! Standalone prover service for ZEOS transactions.
+//!
+//! Accepts the serialized circuit witnesses and instances of a bundle via `POST /prove`, calculates the proof on this
+//! (multi-core) machine and returns the proof bytes (see: zeos_orchard::prover).
+//!
+//! Usage: zeos-prover [ADDRESS]    (default: 127.0.0.1:8787)
+
+use zeos_orchard::circuit::{Circuit, K};
+use zeos_orchard::prover::serve;
+use zeos_orchard::MERKLE_DEPTH_ORCHARD;
+use rustzeos::halo2::ProvingKey;
+
+fn main()
+{
+    let addr = std::env::args().nth(1).unwrap_or(String::from("127.0.0.1:8787"));
+
+    println!("building proving key (merkle tree depth: {})...", MERKLE_DEPTH_ORCHARD);
+    let pk = ProvingKey::build(Circuit::default(), K);
+
+    let server = match tiny_http::Server::http(&addr) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("failed to listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+    println!("listening on http://{}", addr);
+    serve(&server, &pk);
+}
@@ -0,0 +1,111 @@
+//! Relayer service for ZEOS transactions.
+//!
+//! Authorizes and pushes the transactions of users who hold shielded notes only (and no EOS account) in exchange for
+//! a fee paid to the relayer's shielded address (see: zeos_orchard::relayer). Each transaction is checked (actions,
+//! signatures, proof and fee output) before it is signed and pushed by the push command.
+//!
+//! Usage: zeos-relayer [OPTIONS] --viewing-key <ZFVK> --auth <ACTOR@PERMISSION> --fee <QUANTITY>
+
+use std::process::exit;
+
+use rustzeos::halo2::VerifyingKey;
+use zeos_orchard::builder::EOSAuthorization;
+use zeos_orchard::circuit::{Circuit, K};
+use zeos_orchard::keys::FullViewingKey;
+use zeos_orchard::relayer::Relayer;
+use zeos_orchard::{Asset, Network, MERKLE_DEPTH_ORCHARD};
+
+const USAGE: &str = "Usage: zeos-relayer [OPTIONS] --viewing-key <ZFVK> --auth <ACTOR@PERMISSION> --fee <QUANTITY>
+
+Options:
+  --network <NAME>          mainnet, kylin, jungle or local (default: $ZEOS_NETWORK or kylin)
+  --listen <ADDR>           address of the relayer endpoints (default: 127.0.0.1:8789)
+  --viewing-key <ZFVK>      full viewing key of the wallet which receives the fees (e.g. from 'zeos export-viewing-key')
+  --auth <ACTOR@PERMISSION> the EOS authorization the relayer signs with
+  --fee <QUANTITY>          the fee per transaction (e.g. \"0.1000 EOS\")
+  --fee-contract <ACCOUNT>  the token contract of the fee (default: eosio.token)
+  --push-cmd <COMMAND>      shell command which reads the JSON list of actions from stdin, signs and pushes them
+                            (e.g. using cleos). Without it approved transactions are returned but not pushed.";
+
+struct Args
+{
+    network: Network,
+    listen: String,
+    fvk: FullViewingKey,
+    auth: EOSAuthorization,
+    fee: Asset,
+    push_cmd: Option<String>
+}
+
+impl Args
+{
+    fn parse(args: Vec<String>) -> Result<Self, String>
+    {
+        let mut flags = Vec::new();
+        let mut it = args.into_iter();
+        while let Some(arg) = it.next()
+        {
+            let (name, value) = match arg.split_once('=') {
+                Some((n, v)) if n.starts_with("--") => (n.to_string(), v.to_string()),
+                _ if arg.starts_with("--") => (arg.clone(), it.next().ok_or_else(|| format!("missing value of {}", arg))?),
+                _ => return Err(format!("unknown argument: {}", arg))
+            };
+            flags.push((name, value));
+        }
+        let flag = |name: &str| flags.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+        let required = |name: &str| flag(name).ok_or_else(|| format!("missing {}", name));
+
+        let network: Network = match flag("--network").or_else(|| std::env::var("ZEOS_NETWORK").ok()) {
+            Some(n) => n.parse().map_err(|e| format!("{}", e))?,
+            None => Network::default()
+        };
+        let fvk = FullViewingKey::from_bech32m(&required("--viewing-key")?, network).map_err(|e| format!("--viewing-key: {}", e))?;
+        let auth = required("--auth")?;
+        let (actor, permission) = auth.split_once('@').unwrap_or((&auth, "active"));
+        let fee_contract = flag("--fee-contract").unwrap_or(String::from("eosio.token"));
+        let fee = required("--fee")?;
+        let fee = Asset::from_quantity_string(&fee, &fee_contract).ok_or_else(|| format!("invalid fee: {}", fee))?;
+        if fee.is_nft()
+        {
+            return Err(String::from("the fee must be a fungible token"));
+        }
+        Ok(Args {
+            network,
+            listen: flag("--listen").unwrap_or(String::from("127.0.0.1:8789")),
+            fvk,
+            auth: EOSAuthorization::new(actor, permission),
+            fee,
+            push_cmd: flag("--push-cmd")
+        })
+    }
+}
+
+fn main()
+{
+    let args = match Args::parse(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+
+    println!("building verifying key (merkle tree depth: {})...", MERKLE_DEPTH_ORCHARD);
+    let relayer = Relayer::new(args.network, args.fvk, args.auth, args.fee, VerifyingKey::build(Circuit::default(), K));
+    let info = relayer.info();
+
+    let server = match tiny_http::Server::http(&args.listen) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("failed to listen on {}: {}", args.listen, e);
+            exit(1);
+        }
+    };
+    println!("relaying on {} for {} to {}", info.network, info.fee.to_quantity_string(), info.address);
+    if args.push_cmd.is_none()
+    {
+        println!("no --push-cmd: approved transactions are returned but not pushed");
+    }
+    println!("listening on http://{}", args.listen);
+    relayer.serve(&server, args.push_cmd.as_deref());
+}
@@ -0,0 +1,131 @@
+//! Off-chain validator for ZEOS transactions.
+//!
+//! Validates a recording of executed transactions (see: zeos_orchard::validator::Fixture) or the transactions fetched
+//! from a Hyperion history API in order: verifies the proofs and replays the zactions against a local copy of the
+//! commitment tree, root set and nullifier set. The local copy is empty or a snapshot of the contract tables of an EOS
+//! API endpoint. Proofs which are not part of the recording are fetched from an IPFS gateway if one is given. Exits
+//! with status 1 if any transaction is invalid.
+//!
+//! Usage: zeos-validator [OPTIONS] <FIXTURE>
+//!        zeos-validator [OPTIONS] --history <URL>
+
+use std::process::exit;
+
+use nonempty::NonEmpty;
+use rustzeos::halo2::VerifyingKey;
+use zeos_orchard::circuit::{Circuit, K};
+use zeos_orchard::contract::TokenContract;
+use zeos_orchard::validator::{Fixture, HistoryApi, IpfsGateway, Ledger, ProofSource, Validator};
+use zeos_orchard::{Network, MERKLE_DEPTH_ORCHARD};
+
+const USAGE: &str = "Usage: zeos-validator [OPTIONS] <FIXTURE>
+       zeos-validator [OPTIONS] --history <URL>
+
+Options:
+  --gateway <URL>    fetch proofs which are not part of the fixture from this IPFS gateway (e.g. https://ipfs.io)
+  --history <URL>    validate the transactions fetched from this Hyperion history API instead of a fixture
+  --network <NAME>   the network of the history API: mainnet, kylin, jungle or local (default: $ZEOS_NETWORK or kylin)
+  --after <BLOCK>    validate the transactions of the history API executed after this block (default: 0)
+  --limit <N>        the maximum number of transactions fetched from the history API (default: 100)
+  --endpoint <URL>   start with a snapshot of the contract tables of this EOS API endpoint instead of an empty ledger
+                     (the transactions executed after the current head block are validated)
+  --json             machine readable output";
+
+fn fail(msg: String) -> !
+{
+    eprintln!("{}\n\n{}", msg, USAGE);
+    exit(2);
+}
+
+fn main()
+{
+    let mut gateway = None;
+    let mut history = None;
+    let mut network = None;
+    let mut after = None;
+    let mut limit = 100;
+    let mut endpoint = None;
+    let mut json = false;
+    let mut fixture = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next()
+    {
+        match arg.as_str()
+        {
+            "--gateway" => gateway = args.next().map(|url| IpfsGateway{ url }),
+            "--history" => history = args.next().map(|url| HistoryApi{ url }),
+            "--network" => network = Some(args.next().unwrap_or_default().parse::<Network>().unwrap_or_else(|e| fail(e.to_string()))),
+            "--after" => after = Some(args.next().and_then(|v| v.parse::<u64>().ok()).unwrap_or_else(|| fail(String::from("invalid block number")))),
+            "--limit" => limit = args.next().and_then(|v| v.parse::<u64>().ok()).unwrap_or_else(|| fail(String::from("invalid limit"))),
+            "--endpoint" => endpoint = args.next(),
+            "--json" => json = true,
+            _ if fixture.is_none() && !arg.starts_with("--") => fixture = Some(arg),
+            _ => fail(format!("unknown argument: {}", arg))
+        }
+    }
+    let network = network.or_else(|| std::env::var("ZEOS_NETWORK").ok().and_then(|n| n.parse().ok())).unwrap_or_default();
+
+    // the snapshot of the contract tables is taken at (about) the current head block
+    let mut ledger = Ledger::default();
+    if let Some(url) = endpoint
+    {
+        let contract = TokenContract::new(network, NonEmpty::new(url.clone()));
+        let snapshot = pollster::block_on(async {
+            let head = contract.get_info().await?.head_block_num;
+            Ledger::from_contract(&contract).await.map(|ledger| (head, ledger))
+        });
+        match snapshot {
+            Ok((head, snapshot)) => {
+                after = after.or(Some(head));
+                ledger = snapshot;
+            }
+            Err(e) => {
+                eprintln!("{}: {}", url, e);
+                exit(1);
+            }
+        }
+    }
+
+    let fixture: Fixture = match (fixture, history) {
+        (Some(path), None) => match std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string())) {
+            Ok(fixture) => fixture,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                exit(1);
+            }
+        },
+        (None, Some(history)) => match pollster::block_on(history.fetch(network, after.unwrap_or(0), limit)) {
+            Ok(fixture) => fixture,
+            Err(e) => {
+                eprintln!("{}: {}", history.url, e);
+                exit(1);
+            }
+        },
+        (Some(_), Some(_)) => fail(String::from("either a fixture or --history")),
+        (None, None) => fail(String::from("missing fixture"))
+    };
+
+    eprintln!("building verifying key (merkle tree depth: {})...", MERKLE_DEPTH_ORCHARD);
+    let mut validator = Validator::with_ledger(fixture.network, VerifyingKey::build(Circuit::default(), K), ledger);
+    let reports = validator.run(&fixture, gateway.as_ref().map(|g| g as &dyn ProofSource));
+
+    if json
+    {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    }
+    else
+    {
+        for r in reports.iter()
+        {
+            match &r.error {
+                None => println!("{} (block {}): valid ({} zactions)", r.id, r.block_number, r.zactions),
+                Some(e) => println!("{} (block {}): INVALID: {}", r.id, r.block_number, e)
+            }
+        }
+        println!("{} of {} transactions valid, {} notes, {} nullifiers", reports.iter().filter(|r| r.valid).count(), reports.len(), validator.ledger().tree().leaf_count(), validator.ledger().nullifier_count());
+    }
+    if reports.iter().any(|r| !r.valid)
+    {
+        exit(1);
+    }
+}
@@ -0,0 +1,444 @@
+//! Command line wallet for ZEOS.
+//!
+//! Uses the same 'Wallet', 'TransactionBuilder' and 'TokenContract' as the browser wallet. The wallet is stored in a
+//! password encrypted file (see: zeos_orchard::wallet_file). Transactions are printed as JSON list of EOS actions
+//! ready to be signed and pushed (e.g. with cleos).
+//!
+//! Usage: zeos [OPTIONS] <COMMAND> [ARGS]    (see: zeos help)
+
+use std::path::PathBuf;
+use std::process::exit;
+
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde_json::{json, Value};
+use zeos_orchard::builder::{BatchLimits, EOSAuthorization};
+use zeos_orchard::relayer;
+use zeos_orchard::wallet::Wallet;
+use zeos_orchard::wallet_file;
+use zeos_orchard::{Asset, Network};
+
+const USAGE: &str = "Usage: zeos [OPTIONS] <COMMAND> [ARGS]
+
+Options:
+  --network <NAME>      mainnet, kylin, jungle or local (default: $ZEOS_NETWORK or kylin)
+  --wallet <PATH>       wallet file (default: ~/.zeos/<network>.wallet)
+  --prover <URL>        use a remote prover service instead of proving locally
+  --indexer <URL>       synchronize with a ZEOS indexer instead of the contract tables
+  --json                machine readable output
+
+Commands:
+  init                                  create a new wallet and print its seed phrase
+  restore                               restore a wallet from a seed phrase, spending key, full or incoming viewing key
+  address [--new | --all]               show the current (a new or all) shielded address(es)
+  sync                                  synchronize the wallet with the ZEOS token contract
+  balance                               show the fungible token balances
+  nfts                                  show the non-fungible tokens
+  history                               show the received and sent notes
+  shield <ACCOUNT> <ASSET> [TO]         shield an asset of EOS account ACCOUNT (to the current address)
+  transfer <TO> <ASSET> --auth <ACTOR@PERMISSION> [--memo <MEMO>]
+                                        transfer an asset to a shielded address
+  relay <TO> <ASSET> --relayer <URL> [--memo <MEMO>]
+                                        transfer an asset via a relayer which authorizes and pushes the transaction
+                                        in exchange for its fee (no EOS account required)
+  unshield <ASSET> <ACCOUNT> --auth <ACTOR@PERMISSION> [--memo <MEMO>]
+                                        unshield an asset into EOS account ACCOUNT
+  request <ASSET> [--memo <MEMO>] [--label <LABEL>]
+                                        create a payment request URI (zeos:...) for an asset to the current address
+  pay <URI> --auth <ACTOR@PERMISSION>   pay all payments of a payment request URI
+  export-viewing-key [--incoming | --outgoing]
+                                        export the full (incoming or outgoing) viewing key
+
+Assets are fungible quantities of known tokens (e.g. \"1.0000 EOS\") or NFTs as <ID>@<CONTRACT>. The wallet password is
+read from $ZEOS_WALLET_PASSWORD or prompted for.";
+
+/// Parsed command line: options, command, positional arguments and command flags
+struct Args
+{
+    network: Network,
+    wallet: PathBuf,
+    prover: Option<String>,
+    indexer: Option<String>,
+    json: bool,
+    command: String,
+    positional: Vec<String>,
+    flags: Vec<(String, Option<String>)>
+}
+
+impl Args
+{
+    fn parse(args: Vec<String>) -> Result<Self, String>
+    {
+        // flags which take a value
+        const VALUE_FLAGS: [&str; 8] = ["--network", "--wallet", "--prover", "--indexer", "--auth", "--memo", "--relayer", "--label"];
+
+        let mut flags = Vec::new();
+        let mut positional = Vec::new();
+        let mut it = args.into_iter();
+        while let Some(arg) = it.next()
+        {
+            if arg.starts_with("--")
+            {
+                let (name, value) = match arg.split_once('=') {
+                    Some((n, v)) => (n.to_string(), Some(v.to_string())),
+                    None if VALUE_FLAGS.contains(&arg.as_str()) => (arg.clone(), Some(it.next().ok_or_else(|| format!("missing value of {}", arg))?)),
+                    None => (arg, None)
+                };
+                flags.push((name, value));
+            }
+            else
+            {
+                positional.push(arg);
+            }
+        }
+        if positional.is_empty()
+        {
+            return Err(String::from("missing command"));
+        }
+        let command = positional.remove(0);
+
+        let flag = |name: &str| flags.iter().find(|(n, _)| n == name).and_then(|(_, v)| v.clone());
+        let network: Network = match flag("--network").or_else(|| std::env::var("ZEOS_NETWORK").ok()) {
+            Some(n) => n.parse().map_err(|e| format!("{}", e))?,
+            None => Network::default()
+        };
+        let wallet = match flag("--wallet") {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let home = std::env::var("HOME").map_err(|_| String::from("HOME not set: use --wallet"))?;
+                PathBuf::from(home).join(".zeos").join(format!("{}.wallet", network))
+            }
+        };
+        Ok(Args {
+            network,
+            wallet,
+            prover: flag("--prover"),
+            indexer: flag("--indexer"),
+            json: flags.iter().any(|(n, _)| n == "--json"),
+            command,
+            positional,
+            flags
+        })
+    }
+
+    fn flag(&self, name: &str) -> Option<String>
+    {
+        self.flags.iter().find(|(n, _)| n == name).and_then(|(_, v)| v.clone())
+    }
+
+    fn has_flag(&self, name: &str) -> bool
+    {
+        self.flags.iter().any(|(n, _)| n == name)
+    }
+
+    fn arg(&self, i: usize, name: &str) -> Result<String, String>
+    {
+        self.positional.get(i).cloned().ok_or_else(|| format!("missing argument: {}", name))
+    }
+}
+
+/// The result of a command: JSON for machine readable output and text for humans
+struct Output
+{
+    json: Value,
+    text: String
+}
+
+fn password(confirm: bool) -> Result<String, String>
+{
+    if let Ok(pw) = std::env::var("ZEOS_WALLET_PASSWORD")
+    {
+        return Ok(pw);
+    }
+    let pw = rpassword::prompt_password("wallet password: ").map_err(|e| e.to_string())?;
+    if confirm && rpassword::prompt_password("repeat password: ").map_err(|e| e.to_string())? != pw
+    {
+        return Err(String::from("passwords do not match"));
+    }
+    Ok(pw)
+}
+
+fn load(args: &Args) -> Result<(Wallet, String), String>
+{
+    let pw = password(false)?;
+    let mut wallet = wallet_file::load(&args.wallet, &pw).map_err(|e| format!("{}: {}", args.wallet.display(), e))?;
+    if wallet.settings().network() != args.network
+    {
+        return Err(format!("{} is a {} wallet (use --network {})", args.wallet.display(), wallet.settings().network(), wallet.settings().network()));
+    }
+    if args.prover.is_some()
+    {
+        wallet.set_prover_url(args.prover.clone());
+    }
+    if let Some(url) = &args.indexer
+    {
+        wallet.set_zeos_endpoints(vec![url.clone()]);
+    }
+    Ok((wallet, pw))
+}
+
+fn save(args: &Args, wallet: &Wallet, pw: &str) -> Result<(), String>
+{
+    wallet_file::save(&args.wallet, wallet, pw).map_err(|e| format!("{}: {}", args.wallet.display(), e))
+}
+
+fn create(args: &Args, wallet: Wallet) -> Result<(), String>
+{
+    if args.wallet.exists()
+    {
+        return Err(format!("{} already exists", args.wallet.display()));
+    }
+    save(args, &wallet, &password(true)?)
+}
+
+fn quantity(wallet: &Wallet, symbol: &str, amount: u64) -> String
+{
+    match wallet.settings().ft_contract(symbol) {
+        Some((contract, sym)) => Asset::ft(amount, *sym, contract).to_quantity_string(),
+        None => format!("{} {}", amount, symbol)
+    }
+}
+
+fn transaction_output(actions: String) -> Result<Output, String>
+{
+    let json: Value = serde_json::from_str(&actions).map_err(|e| e.to_string())?;
+    let text = serde_json::to_string_pretty(&json).unwrap();
+    Ok(Output{ json: json!({ "actions": json }), text })
+}
+
+fn run(args: &Args) -> Result<Output, String>
+{
+    let err = |e: zeos_orchard::wallet::WalletError| e.to_string();
+    match args.command.as_str()
+    {
+        "init" => {
+            let mut bytes = [0; 32];
+            OsRng.fill_bytes(&mut bytes);
+            let seed = hex::encode(bytes);
+            let wallet = Wallet::new_for_network(seed.clone(), args.network.to_string()).map_err(err)?;
+            let address = wallet.address(0);
+            create(args, wallet)?;
+            Ok(Output{
+                json: json!({ "wallet": args.wallet, "network": args.network, "seed": seed, "address": address }),
+                text: format!("created {} wallet {}\n\nseed phrase (write it down, it is the only backup of this wallet):\n{}\n\naddress: {}", args.network, args.wallet.display(), seed, address)
+            })
+        }
+        "restore" => {
+            let secret = rpassword::prompt_password("seed phrase, spending key, full or incoming viewing key: ").map_err(|e| e.to_string())?;
+            let secret = secret.trim().to_string();
+            let wallet = if secret.starts_with("zsk") || secret.starts_with("zfvk") || secret.starts_with("zivk") {
+                Wallet::from_key(secret, args.network.to_string()).map_err(err)?
+            } else {
+                Wallet::new_for_network(secret, args.network.to_string()).map_err(err)?
+            };
+            let watch_only = wallet.is_watch_only();
+            create(args, wallet)?;
+            Ok(Output{
+                json: json!({ "wallet": args.wallet, "network": args.network, "watch_only": watch_only }),
+                text: format!("restored {}{} wallet {} (run 'zeos sync')", if watch_only { "watch-only " } else { "" }, args.network, args.wallet.display())
+            })
+        }
+        "address" => {
+            let (mut wallet, pw) = load(args)?;
+            if args.has_flag("--all")
+            {
+                let mut addresses: Vec<(u32, String)> = wallet.addresses().map_err(err)?.into_iter().collect();
+                addresses.push((wallet.diversifier_index(), wallet.address(wallet.diversifier_index())));
+                addresses.sort();
+                let text = addresses.iter().map(|(i, a)| format!("{:>4}  {}", i, a)).collect::<Vec<String>>().join("\n");
+                return Ok(Output{ json: json!(addresses.into_iter().map(|(i, a)| json!({ "index": i, "address": a })).collect::<Vec<Value>>()), text });
+            }
+            let address = if args.has_flag("--new") {
+                let address = wallet.derive_new_wallet_address();
+                save(args, &wallet, &pw)?;
+                address
+            } else {
+                wallet.address(wallet.diversifier_index())
+            };
+            Ok(Output{ json: json!({ "index": wallet.diversifier_index(), "address": address }), text: address })
+        }
+        "sync" => {
+            let (mut wallet, pw) = load(args)?;
+            pollster::block_on(wallet.sync()).map_err(err)?;
+            save(args, &wallet, &pw)?;
+            let state = wallet.state();
+            Ok(Output{ json: json!(state), text: format!("synced: {} notes, {} leaves", state.note_count, state.leaf_count) })
+        }
+        "balance" => {
+            let (wallet, _) = load(args)?;
+            let mut balances: Vec<(String, u64)> = wallet.balances().map_err(err)?.into_iter().collect();
+            balances.sort();
+            let text = balances.iter().map(|(s, a)| quantity(&wallet, s, *a)).collect::<Vec<String>>().join("\n");
+            let json = balances.iter().map(|(s, a)| (s.clone(), json!(quantity(&wallet, s, *a)))).collect::<serde_json::Map<String, Value>>();
+            Ok(Output{ json: Value::Object(json), text })
+        }
+        "nfts" => {
+            let (wallet, _) = load(args)?;
+            let nfts = wallet.nfts().map_err(err)?;
+            let mut text: Vec<String> = nfts.iter().flat_map(|(c, ids)| ids.iter().map(move |id| format!("{}@{}", id, c))).collect();
+            text.sort();
+            Ok(Output{ json: json!(nfts), text: text.join("\n") })
+        }
+        "history" => {
+            let (wallet, _) = load(args)?;
+            let history = serde_json::to_value(wallet.history().map_err(err)?).map_err(|e| e.to_string())?;
+            let mut text = Vec::new();
+            for e in history.as_array().unwrap()
+            {
+                let asset: Asset = serde_json::from_value(e["asset"].clone()).map_err(|e| e.to_string())?;
+                let asset = if asset.is_nft() { format!("{}@{}", asset.d1(), asset.contract()) } else { asset.to_quantity_string() };
+                // notes from reversible blocks are rolled back if the chain forks
+                let direction = if e["confirmed"].as_bool().unwrap_or(true) { e["direction"].as_str().unwrap().to_string() } else { format!("{}*", e["direction"].as_str().unwrap()) };
+                text.push(format!("{:>8}  {:>10}  {:<9}  {:>20}  {}  {}", e["id"], e["block_number"], direction, asset, e["address"].as_str().unwrap(), e["memo"].as_str().unwrap()));
+            }
+            Ok(Output{ json: history, text: text.join("\n") })
+        }
+        "shield" => {
+            let (wallet, _) = load(args)?;
+            let account = args.arg(0, "ACCOUNT")?;
+            let asset = wallet.parse_asset(&args.arg(1, "ASSET")?).map_err(err)?;
+            let to = args.positional.get(2).cloned().unwrap_or_else(|| wallet.address(wallet.diversifier_index()));
+            transaction_output(pollster::block_on(wallet.shield_asset(&account, &asset, &to)).map_err(err)?)
+        }
+        "transfer" => {
+            let (wallet, _) = load(args)?;
+            let to = args.arg(0, "TO")?;
+            let asset = wallet.parse_asset(&args.arg(1, "ASSET")?).map_err(err)?;
+            let auth = args.flag("--auth").ok_or(String::from("missing --auth <ACTOR@PERMISSION>"))?;
+            let (actor, permission) = auth.split_once('@').unwrap_or((&auth, "active"));
+            let eos_auth = vec![EOSAuthorization::new(actor, permission)];
+            let memo = args.flag("--memo").unwrap_or_default();
+            transaction_output(pollster::block_on(wallet.transfer_asset(&to, &asset, &memo, &eos_auth)).map_err(err)?)
+        }
+        "relay" => {
+            let (wallet, _) = load(args)?;
+            let to = args.arg(0, "TO")?;
+            let asset = wallet.parse_asset(&args.arg(1, "ASSET")?).map_err(err)?;
+            let url = args.flag("--relayer").ok_or(String::from("missing --relayer <URL>"))?;
+            let memo = args.flag("--memo").unwrap_or_default();
+            let info = pollster::block_on(relayer::get_relayer_info(&url))?;
+            let req = pollster::block_on(wallet.relay_transfer(&info, &to, &asset, &memo)).map_err(err)?;
+            let res = pollster::block_on(relayer::relay(&url, &req))?;
+            Ok(Output {
+                json: serde_json::to_value(&res).map_err(|e| e.to_string())?,
+                text: format!("relayed (fee: {}): {}", Asset::ft(res.fee, info.fee.d2(), info.fee.contract()).to_quantity_string(), res.result)
+            })
+        }
+        "unshield" => {
+            let (wallet, _) = load(args)?;
+            let asset = wallet.parse_asset(&args.arg(0, "ASSET")?).map_err(err)?;
+            let account = args.arg(1, "ACCOUNT")?;
+            let memo = args.flag("--memo").unwrap_or_default();
+            let auth = args.flag("--auth").ok_or(String::from("missing --auth <ACTOR@PERMISSION>"))?;
+            let (actor, permission) = auth.split_once('@').unwrap_or((&auth, "active"));
+            let eos_auth = vec![EOSAuthorization::new(actor, permission)];
+            transaction_output(pollster::block_on(wallet.unshield_asset(&asset, &account, &memo, &eos_auth)).map_err(err)?)
+        }
+        "request" => {
+            let (wallet, _) = load(args)?;
+            let asset = wallet.parse_asset(&args.arg(0, "ASSET")?).map_err(err)?;
+            let memo = args.flag("--memo").unwrap_or_default();
+            let uri = wallet.payment_request(&asset, &memo, args.flag("--label").as_deref()).map_err(err)?.to_uri();
+            Ok(Output{ json: json!({ "uri": uri }), text: uri })
+        }
+        "pay" => {
+            let (wallet, _) = load(args)?;
+            let request = wallet.parse_payment_request(&args.arg(0, "URI")?).map_err(err)?;
+            let auth = args.flag("--auth").ok_or(String::from("missing --auth <ACTOR@PERMISSION>"))?;
+            let (actor, permission) = auth.split_once('@').unwrap_or((&auth, "active"));
+            let eos_auth = vec![EOSAuthorization::new(actor, permission)];
+            let txs = pollster::block_on(wallet.send_payments(&request.to_payments(), &BatchLimits::default(), &eos_auth)).map_err(err)?;
+            transaction_output(format!("[{}]", txs.join(",")))
+        }
+        "export-viewing-key" => {
+            let (wallet, _) = load(args)?;
+            let key = if args.has_flag("--incoming") {
+                wallet.export_incoming_viewing_key()
+            } else if args.has_flag("--outgoing") {
+                wallet.export_outgoing_viewing_key()
+            } else {
+                wallet.export_full_viewing_key()
+            }.map_err(err)?;
+            Ok(Output{ json: json!({ "key": key }), text: key })
+        }
+        "help" => Ok(Output{ json: json!({ "usage": USAGE }), text: USAGE.to_string() }),
+        cmd => Err(format!("unknown command: {}", cmd))
+    }
+}
+
+fn main()
+{
+    let args = match Args::parse(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+    match run(&args) {
+        Ok(out) if args.json => println!("{}", serde_json::to_string_pretty(&out.json).unwrap()),
+        Ok(out) => if !out.text.is_empty() { println!("{}", out.text) },
+        Err(e) if args.json => {
+            println!("{}", json!({ "error": e }));
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::PathBuf;
+    use super::{run, Args};
+    use zeos_orchard::Network;
+
+    fn args(args: &[&str]) -> Result<Args, String>
+    {
+        Args::parse(args.iter().map(|a| a.to_string()).collect())
+    }
+
+    #[test]
+    fn parse()
+    {
+        let a = args(&["--network", "jungle", "--wallet=/tmp/w.wallet", "transfer", "za1...", "1.0000 EOS", "--auth", "alice@active", "--memo=a=b", "--json"]).unwrap();
+        assert_eq!(a.network, Network::Jungle);
+        assert_eq!(a.wallet, PathBuf::from("/tmp/w.wallet"));
+        assert_eq!(a.command, "transfer");
+        assert_eq!(a.positional, vec!["za1...".to_string(), "1.0000 EOS".to_string()]);
+        assert_eq!(a.flag("--auth").as_deref(), Some("alice@active"));
+        assert_eq!(a.flag("--memo").as_deref(), Some("a=b"));
+        assert!(a.json);
+        assert!(a.arg(2, "TO").unwrap_err().contains("TO"));
+
+        // flags without value and options after the command
+        let a = args(&["address", "--new", "--network", "mainnet", "--wallet", "/tmp/m.wallet"]).unwrap();
+        assert_eq!(a.network, Network::Mainnet);
+        assert_eq!(a.command, "address");
+        assert!(a.positional.is_empty());
+        assert!(a.has_flag("--new"));
+        assert!(!a.has_flag("--all"));
+        assert!(!a.json);
+
+        // the wallet file defaults to the network
+        if std::env::var("HOME").is_ok()
+        {
+            let a = args(&["--network", "kylin", "sync"]).unwrap();
+            assert!(a.wallet.ends_with(".zeos/kylin.wallet"));
+        }
+    }
+
+    #[test]
+    fn parse_errors()
+    {
+        assert_eq!(args(&[]).err().unwrap(), "missing command");
+        assert_eq!(args(&["--json"]).err().unwrap(), "missing command");
+        assert_eq!(args(&["transfer", "--auth"]).err().unwrap(), "missing value of --auth");
+        assert!(args(&["--network", "testnet", "--wallet", "/tmp/w.wallet", "sync"]).err().unwrap().contains("testnet"));
+        let a = args(&["--network", "kylin", "--wallet", "/tmp/w.wallet", "frobnicate"]).unwrap();
+        assert_eq!(run(&a).err().unwrap(), "unknown command: frobnicate");
+    }
+}
@@ -0,0 +1,142 @@
+//! Wallet daemon for ZEOS.
+//!
+//! Serves the JSON-RPC API of zeos_orchard::daemon for one or more wallet files created with the 'zeos' command line
+//! wallet and synchronizes them in the background.
+//!
+//! Usage: zeosd [OPTIONS] --wallet <NAME=PATH> [--wallet <NAME=PATH> ...]
+
+use std::path::PathBuf;
+use std::process::exit;
+use std::sync::Arc;
+use std::time::Duration;
+
+use zeos_orchard::daemon::Daemon;
+use zeos_orchard::simulator::{SimulatedChain, DEFAULT_SIMULATOR_ADDRESS};
+use zeos_orchard::wallet_file;
+use zeos_orchard::Network;
+
+const USAGE: &str = "Usage: zeosd [OPTIONS] --wallet <NAME=PATH> [--wallet <NAME=PATH> ...]
+
+Options:
+  --listen <ADDR>           address of the JSON-RPC API (default: 127.0.0.1:8788)
+  --wallet <NAME=PATH>      serve the wallet file PATH as wallet NAME (repeatable)
+  --sync-interval <SECS>    seconds between synchronizations (default: 10)
+  --webhook <URL>           post events (e.g. incoming notes) to URL (repeatable)
+  --prover <URL>            use a remote prover service instead of proving locally
+  --indexer <URL>           synchronize with a ZEOS indexer instead of the contract tables
+  --simulate                serve a simulated local chain at 127.0.0.1:13015 (for wallets of network 'local')
+
+The wallet password is read from $ZEOS_WALLET_PASSWORD or prompted for (the same password for all wallets).";
+
+struct Args
+{
+    listen: String,
+    wallets: Vec<(String, PathBuf)>,
+    sync_interval: Duration,
+    webhooks: Vec<String>,
+    prover: Option<String>,
+    indexer: Option<String>,
+    simulate: bool
+}
+
+impl Args
+{
+    fn parse(args: Vec<String>) -> Result<Self, String>
+    {
+        let mut res = Args {
+            listen: String::from("127.0.0.1:8788"),
+            wallets: Vec::new(),
+            sync_interval: Duration::from_secs(10),
+            webhooks: Vec::new(),
+            prover: None,
+            indexer: None,
+            simulate: false
+        };
+        let mut it = args.into_iter();
+        while let Some(arg) = it.next()
+        {
+            let (name, inline) = match arg.split_once('=') {
+                Some((n, v)) if n.starts_with("--") => (n.to_string(), Some(v.to_string())),
+                _ => (arg.clone(), None)
+            };
+            let mut value = || inline.clone().or_else(|| it.next()).ok_or_else(|| format!("missing value of {}", name));
+            match name.as_str()
+            {
+                "--listen" => res.listen = value()?,
+                "--wallet" => {
+                    let v = value()?;
+                    let (n, path) = v.split_once('=').ok_or_else(|| format!("invalid wallet (expected NAME=PATH): {}", v))?;
+                    res.wallets.push((n.to_string(), PathBuf::from(path)));
+                }
+                "--sync-interval" => {
+                    let v = value()?;
+                    res.sync_interval = Duration::from_secs(v.parse().map_err(|_| format!("invalid sync interval: {}", v))?);
+                }
+                "--webhook" => res.webhooks.push(value()?),
+                "--prover" => res.prover = Some(value()?),
+                "--indexer" => res.indexer = Some(value()?),
+                "--simulate" => res.simulate = true,
+                _ => return Err(format!("unknown argument: {}", arg))
+            }
+        }
+        if res.wallets.is_empty()
+        {
+            return Err(String::from("missing --wallet"));
+        }
+        Ok(res)
+    }
+}
+
+fn run(args: Args) -> Result<(), String>
+{
+    let password = match std::env::var("ZEOS_WALLET_PASSWORD") {
+        Ok(pw) => pw,
+        Err(_) => rpassword::prompt_password("wallet password: ").map_err(|e| e.to_string())?
+    };
+    let mut daemon = Daemon::new(args.webhooks.clone());
+    for (name, path) in args.wallets.iter()
+    {
+        let mut wallet = wallet_file::load(path, &password).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if args.prover.is_some()
+        {
+            wallet.set_prover_url(args.prover.clone());
+        }
+        if let Some(url) = &args.indexer
+        {
+            wallet.set_zeos_endpoints(vec![url.clone()]);
+        }
+        if args.simulate && wallet.settings().network() != Network::Local
+        {
+            return Err(format!("{} is a {} wallet (--simulate requires network local)", path.display(), wallet.settings().network()));
+        }
+        daemon.add_wallet(name, wallet, Some((path.clone(), password.clone())));
+    }
+
+    if args.simulate
+    {
+        let server = tiny_http::Server::http(DEFAULT_SIMULATOR_ADDRESS).map_err(|e| format!("{}: {}", DEFAULT_SIMULATOR_ADDRESS, e))?;
+        eprintln!("simulated local chain at http://{}", DEFAULT_SIMULATOR_ADDRESS);
+        std::thread::spawn(move || SimulatedChain::new(Network::Local).serve(&server));
+    }
+
+    let server = tiny_http::Server::http(&args.listen).map_err(|e| format!("{}: {}", args.listen, e))?;
+    eprintln!("JSON-RPC API at http://{}", args.listen);
+    Arc::new(daemon).run(&server, args.sync_interval);
+    Ok(())
+}
+
+fn main()
+{
+    let args = match Args::parse(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+    if let Err(e) = run(args)
+    {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+}
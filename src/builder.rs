@@ -1,22 +1,33 @@
 //! Logic for building entire EOSIO transactions depending on ZEOS privacy actions.
 
 use crate::action::{RawZAction, ZA_MINTFT, ZA_MINTNFT, ZA_MINTAUTH, ZA_TRANSFERFT, ZA_TRANSFERNFT, ZA_BURNFT, ZA_BURNNFT, ZA_BURNAUTH};
-use crate::address::Address;
+use crate::address::{Address, AddressError};
 use crate::tree::MerklePath;
 use crate::note::{Note, Nullifier, NT_FT, NT_NFT, NT_AT, NH_BURN_FLAG};
 use crate::keys::SpendingKey;
 use crate::value::NoteValue;
 use crate::note::ExtractedNoteCommitment;
 use crate::keys::FullViewingKey;
+use crate::keys::Scope::External;
 use crate::bundle::Bundle;
 use crate::contract::NoteEx;
-use crate::eosio::name_to_value;
+use crate::eosio::{name_to_value, Asset};
+use crate::base85;
+use crate::unproven::UnprovenTransaction;
+use crate::prover::{RemoteProver, ProverError};
+use crate::note_encryption::{ENC_CIPHERTEXT_SIZE, OUT_CIPHERTEXT_SIZE};
+use crate::note::TransmittedNoteCiphertext;
+use crate::action::{ZAction, ZActionError, MAX_ZACTION_MEMO_SIZE, ZACTION_SIZE};
+use crate::primitives::redpallas::{Signature, SpendAuth};
+use crate::sighash::{sighash, sign_spends, signature_to_hex};
+use crate::network::Network;
 
 extern crate serde_json;
 
 use rand::rngs::OsRng;
 use rustzeos::halo2::{Proof, ProvingKey};
 use sha256::digest;
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZActionDesc
@@ -30,6 +41,220 @@ pub struct ZActionDesc
     pub(crate) memo: String,
 }
 
+impl ZActionDesc
+{
+    /// Creates a zaction descriptor of type 'za_type' which moves 'asset' to 'to'
+    pub fn from_asset(za_type: u64, to: &String, asset: &Asset, memo: &String) -> Self
+    {
+        ZActionDesc {
+            za_type,
+            to: to.clone(),
+            d1: asset.d1().to_string(),
+            d2: asset.d2().to_string(),
+            sc: asset.contract().clone(),
+            memo: memo.clone()
+        }
+    }
+}
+
+/// A single entry of a batch payment: 'asset' is sent to the shielded address 'to' using 'memo'
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payment
+{
+    pub(crate) to: String,
+    pub(crate) asset: Asset,
+    pub(crate) memo: String,
+}
+
+impl Payment
+{
+    pub fn new(to: &String, asset: &Asset, memo: &String) -> Self
+    {
+        Payment { to: to.clone(), asset: asset.clone(), memo: memo.clone() }
+    }
+}
+
+/// Limits of a single EOS transaction of a batch payment. The CPU time required to verify the proof of a 'begin'
+/// action grows with the number of zactions and the size of the 'begin' action's data grows with the number of
+/// zactions and encrypted notes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BatchLimits
+{
+    /// The maximum number of zactions per transaction
+    pub max_zactions: usize,
+    /// The maximum (estimated) size of the zactions and encrypted notes per transaction in bytes
+    pub max_size: usize,
+}
+
+impl Default for BatchLimits
+{
+    fn default() -> Self
+    {
+        BatchLimits { max_zactions: 8, max_size: 32 * 1024 }
+    }
+}
+
+/// A zaction whose notes are already determined but whose merkle path (in case a note is spent) is not yet fetched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedZAction
+{
+    pub(crate) za_type: u64,
+    pub(crate) note_a: Option<NoteEx>,
+    pub(crate) note_b: Option<Note>,
+    pub(crate) note_c: Option<Note>,
+    pub(crate) memo: String,
+}
+
+impl PlannedZAction
+{
+    /// Returns the number of output notes of this zaction
+    pub fn outputs(&self) -> usize
+    {
+        self.note_b.iter().count() + self.note_c.iter().count()
+    }
+
+    /// Returns the length of the hex encoded serialized zaction
+    pub fn serialized_size(&self) -> usize
+    {
+        (ZACTION_SIZE + self.memo.len()) * 2
+    }
+
+    /// Estimates the number of bytes this zaction adds to the 'begin' action of a transaction: the hex encoded
+    /// serialized zaction plus the encrypted output notes and the spend authorization signature (if a note is spent).
+    pub fn estimated_size(&self) -> usize
+    {
+        self.serialized_size() + self.outputs() * ENCRYPTED_NOTE_SIZE + self.note_a.iter().count() * SPEND_AUTH_SIG_SIZE
+    }
+}
+
+/// The plan of a transaction: all notes are selected and all zactions are determined but neither merkle paths are
+/// fetched nor the proof is calculated. Contains an estimate of the resources required to execute the transaction
+/// which can be shown to the user before proving.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionPlan
+{
+    /// The EOS actions before the privacy sequence
+    pub(crate) pre: Vec<EOSAction>,
+    /// The 'step' actions of the privacy sequence each with the zactions it depends on
+    pub(crate) steps: Vec<(EOSAction, Vec<PlannedZAction>)>,
+    /// The EOS actions after the privacy sequence
+    pub(crate) post: Vec<EOSAction>,
+    /// The notes spent by this transaction
+    pub(crate) spent_notes: Vec<NoteEx>,
+    /// The notes created by this transaction (including change)
+    pub(crate) outputs: Vec<Note>,
+    /// The number of circuits of the proof (there is no circuit for ZA_MINTAUTH)
+    pub(crate) circuits: usize,
+    /// The estimated size of the proof in bytes
+    pub(crate) proof_size: usize,
+    /// The estimated size of the 'data' of each EOS action of the final transaction in bytes
+    pub(crate) action_sizes: Vec<usize>,
+    /// The estimated time required to calculate the proof in milliseconds
+    pub(crate) proving_time_ms: u64,
+}
+
+/// The size of a Halo2 proof without circuits (in bytes). The proof size grows linearly with the number of circuits: a
+/// proof of one ZEOS circuit (K = 11) takes 5424 bytes (see: 'circuit_proof_test_case.bin'). The 'proof_size' test
+/// checks the estimate against actual proofs.
+const PROOF_SIZE_BASE: usize = 3152;
+/// The size of a Halo2 proof per circuit (in bytes): the commitments and evaluations of the advice columns, lookups
+/// and the permutation of each circuit (see: 'PROOF_SIZE_BASE')
+const PROOF_SIZE_PER_CIRCUIT: usize = 2272;
+/// The estimated proving time per circuit (single threaded) in milliseconds. This is not derived from the circuit but
+/// a rough value for a current desktop CPU: the actual proving time depends on the hardware.
+const PROVING_TIME_PER_CIRCUIT_MS: u64 = 1500;
+
+impl TransactionPlan
+{
+    /// Creates a transaction plan from its parts and calculates the resource estimates
+    pub fn new(pre: Vec<EOSAction>, steps: Vec<(EOSAction, Vec<PlannedZAction>)>, post: Vec<EOSAction>) -> Self
+    {
+        let zactions: Vec<&PlannedZAction> = steps.iter().flat_map(|(_, pzas)| pzas.iter()).collect();
+        let spent_notes = zactions.iter().filter_map(|pza| pza.note_a.clone()).collect();
+        let outputs = zactions.iter().flat_map(|pza| pza.note_b.iter().chain(pza.note_c.iter()).cloned()).collect();
+        let circuits = zactions.iter().filter(|pza| pza.za_type != ZA_MINTAUTH).count();
+        let proof_size = if circuits > 0 { PROOF_SIZE_BASE + circuits * PROOF_SIZE_PER_CIRCUIT } else { 0 };
+
+        let mut action_sizes: Vec<usize> = pre.iter().map(|a| a.data.len()).collect();
+        if !zactions.is_empty()
+        {
+            // 'begin' contains the proof URI, the encrypted notes, the spend authorization signatures and all 'step'
+            // actions with their serialized zactions (including the dummy zaction) while each 'step' action itself is empty
+            let mut begin = 64;
+            for (action, pzas) in steps.iter()
+            {
+                begin += 128 + action.data.len();
+                if !pzas.is_empty()
+                {
+                    begin += 2 + ZACTION_SIZE * 2 + pzas.iter().map(|pza| pza.serialized_size()).sum::<usize>();
+                }
+            }
+            begin += zactions.iter().map(|pza| pza.outputs() * ENCRYPTED_NOTE_SIZE).sum::<usize>();
+            begin += zactions.iter().filter(|pza| pza.note_a.is_some()).count() * SPEND_AUTH_SIG_SIZE;
+            action_sizes.push(begin);
+            action_sizes.extend(vec![2; steps.len()]);
+        }
+        else
+        {
+            action_sizes.extend(steps.iter().map(|(a, _)| a.data.len()));
+        }
+        action_sizes.extend(post.iter().map(|a| a.data.len()));
+
+        TransactionPlan {
+            pre,
+            steps,
+            post,
+            spent_notes,
+            outputs,
+            circuits,
+            proof_size,
+            action_sizes,
+            proving_time_ms: circuits as u64 * PROVING_TIME_PER_CIRCUIT_MS,
+        }
+    }
+
+    /// Returns the notes spent by this transaction
+    pub fn spent_notes(&self) -> &Vec<NoteEx>
+    {
+        &self.spent_notes
+    }
+
+    /// Returns the notes created by this transaction
+    pub fn outputs(&self) -> &Vec<Note>
+    {
+        &self.outputs
+    }
+
+    /// Returns the number of circuits of the proof
+    pub fn circuits(&self) -> usize
+    {
+        self.circuits
+    }
+
+    /// Returns the estimated proof size in bytes (see: 'PROOF_SIZE_BASE')
+    pub fn proof_size(&self) -> usize
+    {
+        self.proof_size
+    }
+
+    /// Returns the estimated size of each EOS action's data of the final transaction in bytes
+    pub fn action_sizes(&self) -> &Vec<usize>
+    {
+        &self.action_sizes
+    }
+
+    /// Returns the estimated proving time in milliseconds (see: 'PROVING_TIME_PER_CIRCUIT_MS')
+    pub fn proving_time_ms(&self) -> u64
+    {
+        self.proving_time_ms
+    }
+}
+
+/// The size of a JSON encoded (hex) encrypted note: epk, encrypted note and encrypted outgoing plaintext
+const ENCRYPTED_NOTE_SIZE: usize = (32 + ENC_CIPHERTEXT_SIZE + OUT_CIPHERTEXT_SIZE) * 2 + 64;
+/// The size of a JSON encoded (hex) spend authorization signature
+const SPEND_AUTH_SIG_SIZE: usize = 64 * 2 + 3;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EOSAuthorization
 {
@@ -37,6 +262,15 @@ pub struct EOSAuthorization
     pub(crate) permission: String,
 }
 
+impl EOSAuthorization
+{
+    /// Creates the authorization of EOS account 'actor' using 'permission' (e.g. "active")
+    pub fn new(actor: &str, permission: &str) -> Self
+    {
+        EOSAuthorization{ actor: actor.to_string(), permission: permission.to_string() }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EOSAction
 {
@@ -67,20 +301,22 @@ pub trait HasMerkleTree
         &mut self,
         array_index: u64,
         leaf_count: u64,
-    ) -> MerklePath;
+    ) -> Result<MerklePath, String>;
 
-    /// fetches a merkle node index by value of the note commitment
+    /// fetches a merkle node index by value of the note commitment ('None' if the note commitment doesn't exist)
     async fn get_merkle_index(
         &self,
         hash: ExtractedNoteCommitment
-    ) -> Option<u64>;
+    ) -> Result<Option<u64>, String>;
 }
 
 #[derive(thiserror::Error, Debug, Clone)]
 pub enum TransactionBuilderError
 {
     #[error("Error: {0}")]
-    GeneralError(String)
+    GeneralError(String),
+    #[error("invalid address '{address}': {err}")]
+    InvalidAddress{ address: String, err: AddressError }
 }
 
 impl From<halo2_proofs::plonk::Error> for TransactionBuilderError
@@ -91,19 +327,44 @@ impl From<halo2_proofs::plonk::Error> for TransactionBuilderError
     }
 }
 
+impl From<ProverError> for TransactionBuilderError
+{
+    fn from(err: ProverError) -> Self
+    {
+        Self::GeneralError(err.to_string())
+    }
+}
+
+impl From<ZActionError> for TransactionBuilderError
+{
+    fn from(err: ZActionError) -> Self
+    {
+        Self::GeneralError(err.to_string())
+    }
+}
+
 /// ...
 #[derive(Debug)]
 pub struct TransactionBuilder
 {
-    leaf_count: u64
+    network: Network,
+    leaf_count: u64,
+    prover: Option<RemoteProver>
 }
 
 impl TransactionBuilder
 {
     /// ...
-    pub fn new(leaf_count: u64) -> Self
+    pub fn new(network: Network, leaf_count: u64) -> Self
     {
-        TransactionBuilder { leaf_count }
+        TransactionBuilder { network, leaf_count, prover: None }
+    }
+
+    /// Sends the witnesses of all circuits to the remote 'prover' instead of proving locally
+    pub fn with_remote_prover(mut self, prover: RemoteProver) -> Self
+    {
+        self.prover = Some(prover);
+        self
     }
 
     /// ...
@@ -117,275 +378,613 @@ impl TransactionBuilder
         eos_auth: &Vec<EOSAuthorization>
     ) -> Result<(Option<Proof>, Vec<EOSAction>), TransactionBuilderError>
     {
-        let mut rng = OsRng.clone();
+        let fvk = FullViewingKey::from(sk);
+        let plan = plan_transaction(self.network, &fvk, notes, action_descs)?;
+        self.build_planned_transaction(pk, sk, plan, contract, eos_auth).await
+    }
+
+    /// Builds the transaction of a 'plan' (see: plan_transaction) by fetching the merkle paths of all spent notes,
+    /// authorizing the spends and calculating the proof.
+    pub async fn build_planned_transaction<D: HasMerkleTree>(
+        &self,
+        pk: &ProvingKey,
+        sk: &SpendingKey,
+        plan: TransactionPlan,
+        contract: &mut D,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Result<(Option<Proof>, Vec<EOSAction>), TransactionBuilderError>
+    {
+        let fvk = FullViewingKey::from(sk);
+        let mut steps = Vec::new();
+        for (action, planned) in plan.steps
+        {
+            steps.push((action, self.create_planned_zactions(&fvk, planned, contract).await?));
+        }
+        self.finalize_transaction(pk, sk, plan.pre, steps, plan.post, eos_auth).await
+    }
 
-        // Walk through the whole list of action descriptors to detect the sequence of actions 
-        // with privacy dependencies (aka zactions) within this transaction.
-        let mut z_begin = -1;
-        let mut z_end = -1;
-        for i in 0..action_descs.len()
+    /// Creates the unproven transaction described by 'action_descs' (see: UnprovenTransaction). Only the full viewing
+    /// key is required which allows to create transactions on a watch-only device and to authorize and prove them
+    /// somewhere else.
+    pub async fn build_unproven_transaction<D: HasMerkleTree>(
+        &self,
+        fvk: &FullViewingKey,
+        notes: &mut Vec<NoteEx>,
+        action_descs: &Vec<EOSActionDesc>,
+        contract: &mut D,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Result<UnprovenTransaction, TransactionBuilderError>
+    {
+        let plan = plan_transaction(self.network, fvk, notes, action_descs)?;
+        let mut steps = Vec::new();
+        for (action, planned) in plan.steps
         {
-            if action_descs[i].zaction_descs.len() > 0
-            {
-                z_end = i as i32;
-                if z_begin == -1
-                {
-                    z_begin = i as i32;
-                }
-            }
+            steps.push((action, self.create_planned_zactions(fvk, planned, contract).await?));
         }
+        Ok(UnprovenTransaction::from_parts(self.network, fvk, plan.pre, steps, plan.post, eos_auth))
+    }
 
-        // no zactions in this transaction => just return all EOSActions
-        if z_begin == -1
+    /// Creates the final EOS transaction from the sequence of 'step' actions (each with the raw zactions it depends on)
+    /// and the EOS actions before ('pre') and after ('post') the privacy sequence. Signs the sighash of the transaction
+    /// for each spent note (see: sighash), calculates the proof of all raw zactions (locally or by the remote prover) and
+    /// adds the 'begin' and 'step' actions to the transaction.
+    pub async fn finalize_transaction(
+        &self,
+        pk: &ProvingKey,
+        sk: &SpendingKey,
+        pre: Vec<EOSAction>,
+        steps: Vec<(EOSAction, Vec<RawZAction>)>,
+        post: Vec<EOSAction>,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Result<(Option<Proof>, Vec<EOSAction>), TransactionBuilderError>
+    {
+        let mut rng = OsRng.clone();
+        let raw_zactions: Vec<RawZAction> = steps.iter().flat_map(|(_, rzactions_step)| rzactions_step.iter().cloned()).collect();
+        if raw_zactions.is_empty()
         {
-            let tx: Vec<EOSAction> = action_descs.iter().map(|ad| ad.action.clone()).collect();
-            return Ok((None, tx));
+            return Ok((None, assemble_transaction(self.network, None, &Vec::new(), &Vec::new(), pre, steps, post, eos_auth)?));
         }
+        let bundle = Bundle::from_parts(raw_zactions.clone());
+        let encrypted_notes = bundle.encrypted_notes(&mut rng);
+        let sighash = transaction_sighash(&pre, &steps, &post, &encrypted_notes)?;
+        let sigs = sign_spends(sk, &raw_zactions, &sighash, &mut rng);
+        let proof = match &self.prover {
+            Some(prover) => {
+                let (witnesses, instances) = bundle.witnesses(&mut rng);
+                prover.prove(witnesses, &instances).await?
+            },
+            None => bundle.proof(pk, &mut rng)?.0
+        };
+        let tx = assemble_transaction(self.network, Some(&proof), &encrypted_notes, &sigs, pre, steps, post, eos_auth)?;
+        Ok((Some(proof), tx))
+    }
 
-        // copy all EOS actions into the tx until the privacy sequence starts...
-        let z_begin = z_begin as usize;
-        let z_end = z_end as usize;
-        let mut tx: Vec<EOSAction> = action_descs[0..z_begin].iter().map(|ad| ad.action.clone()).collect();
-        
-        // process 'step' actions of privacy sequence
-        let mut list = Vec::new();
-        let mut raw_zactions = Vec::new();
-        for i in z_begin..=z_end
+    /// Builds a private deposit of 'asset' into the third party smart contract 'receiver' (see: Private Deposits and
+    /// Withdrawals). A new auth token is minted for 'receiver' and 'asset' is burned into 'receiver' with the base85
+    /// encoded commitment of the auth token (followed by 'memo') as memo of the resulting transfer. Returns the auth
+    /// note in addition to the transaction since it is required to privately withdraw the deposit later on.
+    pub async fn build_private_deposit<D: HasMerkleTree>(
+        &self,
+        pk: &ProvingKey,
+        sk: &SpendingKey,
+        notes: &mut Vec<NoteEx>,
+        asset: &Asset,
+        receiver: &String,
+        memo: &String,
+        contract: &mut D,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Result<(Option<Proof>, Vec<EOSAction>, Note), TransactionBuilderError>
+    {
+        let mut rng = OsRng.clone();
+        let fvk = FullViewingKey::from(sk);
+
+        // mint a new auth token for the third party contract
+        let auth_note = Note::new(
+            NT_AT,
+            fvk.address_at(0u32, External),
+            NoteValue::from_raw(0),
+            NoteValue::from_raw(0),
+            NoteValue::from_raw(name_to_value(receiver)),
+            NoteValue::from_raw(1),
+            Nullifier::dummy(&mut rng),
+            rng,
+            [0; 512]);
+        let mut rzactions = vec![RawZAction::from_parts(
+            ZA_MINTAUTH,
+            &fvk,
+            None,
+            None,
+            Some(auth_note),
+            None,
+            String::from(""),
+            rng
+        )];
+
+        // burn the asset into the third party contract using the encoded auth token commitment as memo
+        let cm_auth: ExtractedNoteCommitment = auth_note.commitment().into();
+        let mut deposit_memo = base85::encode(&cm_auth.to_bytes());
+        deposit_memo.push_str(memo);
+        if deposit_memo.len() > MAX_ZACTION_MEMO_SIZE
         {
-            let mut rzactions_step = Vec::new();
-            for zad in &action_descs[i].zaction_descs
-            {
-                // try to create vector of raw zactions from zaction descriptor
-                let rzas = self.create_raw_zactions(sk, notes, zad, contract).await;
-                if rzas.is_none()
-                {
-                    return Err(TransactionBuilderError::GeneralError(format!("ZActionDesc not executable: {}", serde_json::to_string(zad).unwrap())));
-                }
-                else
-                {
-                    rzactions_step.extend(rzas.unwrap());
-                }
-            }
-            // if there are zactions for this step encode the zactions of all raw zactions of this step (including the dummy zaction!) into the EOS actions 'data'
-            let mut a = action_descs[i].action.clone();
-            if rzactions_step.len() > 0
-            {
-                let mut ser_zactions = format!("{:02X?}", rzactions_step.len() + 1);
-                ser_zactions.push_str("efbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeadde000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000");
-                for rza in &rzactions_step
-                {
-                    ser_zactions.push_str(&rza.zaction().serialize_eos());
-                }
-                // append the already existing serialized data from before
-                ser_zactions.push_str(&action_descs[i].action.data);
-                a.data = ser_zactions;
-            }
-            list.push(a);
-            // add the raw zactions of this step to the list of all raw zactions
-            raw_zactions.extend(rzactions_step);
-        }
-
-        // process 'begin' action of privacy sequence
-        let ((proof, _, _), _, encrypted_notes) = Bundle::from_parts(raw_zactions).prepare(pk, &mut rng)?;
-        let data_str = format!("{{\"proof\":\"{}\",\"notes\":{},\"tx\":{}}}", 
-            get_liquidstorage_uri(hex::encode(proof.as_ref()), true),
-            serde_json::to_string(&encrypted_notes).unwrap(),
-            serde_json::to_string(&list).unwrap()
-        );
-
-        // add 'begin' and 'step' actions to transaction
-        tx.push(EOSAction{
-            account: String::from("thezeostoken"),
-            name: String::from("begin"),
-            authorization: eos_auth.clone(),
-            data: data_str
-        });
-        tx.extend(vec![EOSAction{
-            account: String::from("thezeostoken"),
-            name: String::from("step"),
-            authorization: eos_auth.clone(),
-            data: String::from("{}")
-        }; list.len()]);
-
-        // copy all EOS actions into the tx after the privacy sequence (if any)
-        if action_descs.len() >= z_end
+            return Err(ZActionError::MemoTooLong(deposit_memo.len()).into());
+        }
+        let desc = ZActionDesc::from_asset(if asset.is_nft() { ZA_BURNNFT } else { ZA_BURNFT }, receiver, asset, &deposit_memo);
+        rzactions.extend(self.create_raw_zactions(sk, notes, &desc, contract).await?);
+
+        let (proof, tx) = self.finalize_transaction(pk, sk, Vec::new(), vec![(exec_action(self.network), rzactions)], Vec::new(), eos_auth).await?;
+
+        Ok((proof, tx, auth_note))
+    }
+
+    /// Builds a private withdrawal of 'asset' from the third party smart contract 'receiver' (see: Private Deposits and
+    /// Withdrawals). The auth token with commitment 'cm_auth' is burned and 'asset' is minted back into the wallet address
+    /// 'to' using the hex encoded 'cm_auth' as memo which ties the minted note to the deposit. Both zactions are attached
+    /// to 'action' which is the private withdrawal action of the third party contract.
+    pub async fn build_private_withdrawal<D: HasMerkleTree>(
+        &self,
+        pk: &ProvingKey,
+        sk: &SpendingKey,
+        notes: &mut Vec<NoteEx>,
+        cm_auth: &ExtractedNoteCommitment,
+        asset: &Asset,
+        receiver: &String,
+        to: &Address,
+        action: &EOSAction,
+        contract: &mut D,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Result<(Option<Proof>, Vec<EOSAction>), TransactionBuilderError>
+    {
+        // in case of ZA_BURNAUTH the note commitment value of the auth note is stored in the 'to' field
+        let burn_auth = ZActionDesc {
+            za_type: ZA_BURNAUTH,
+            to: hex::encode(cm_auth.to_bytes()),
+            d1: String::from("0"),
+            d2: String::from("0"),
+            sc: receiver.clone(),
+            memo: String::from("")
+        };
+        let mint = ZActionDesc::from_asset(if asset.is_nft() { ZA_MINTNFT } else { ZA_MINTFT }, &to.to_bech32m(self.network), asset, &hex::encode(cm_auth.to_bytes()));
+        let action_descs = vec![EOSActionDesc {
+            action: action.clone(),
+            zaction_descs: vec![burn_auth, mint]
+        }];
+
+        self.build_transaction(pk, sk, notes, &action_descs, contract, eos_auth).await
+    }
+
+    /// Builds a batch payment of all 'payments' (see: plan_batch_payment). The zactions are split into as many EOS
+    /// transactions as required to stay within 'limits'. Returns the list of transactions (each with its proof) which
+    /// can be executed independently of each other.
+    pub async fn build_batch_payment<D: HasMerkleTree>(
+        &self,
+        pk: &ProvingKey,
+        sk: &SpendingKey,
+        notes: &mut Vec<NoteEx>,
+        payments: &Vec<Payment>,
+        limits: &BatchLimits,
+        contract: &mut D,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Result<Vec<(Option<Proof>, Vec<EOSAction>)>, TransactionBuilderError>
+    {
+        let fvk = FullViewingKey::from(sk);
+        let planned = plan_batch_payment(self.network, &fvk, notes, payments)?;
+        let mut res = Vec::new();
+        for chunk in split_planned_zactions(planned, limits)
         {
-            tx.extend(action_descs[z_end+1..].iter().map(|ad| ad.action.clone()).collect::<Vec<EOSAction>>());
+            let rzactions = self.create_planned_zactions(&fvk, chunk, contract).await?;
+            res.push(self.finalize_transaction(pk, sk, Vec::new(), vec![(exec_action(self.network), rzactions)], Vec::new(), eos_auth).await?);
         }
+        Ok(res)
+    }
 
-        Ok((Some(proof), tx))
+    /// Creates the raw zactions of 'planned' zactions by fetching the merkle paths of all spent notes.
+    pub async fn create_planned_zactions<D: HasMerkleTree>(
+        &self,
+        fvk: &FullViewingKey,
+        planned: Vec<PlannedZAction>,
+        contract: &mut D
+    ) -> Result<Vec<RawZAction>, TransactionBuilderError>
+    {
+        let rng = OsRng.clone();
+        let mut res = Vec::new();
+        for pza in planned
+        {
+            let auth_path = match &pza.note_a {
+                Some(n) => {
+                    let idx = contract.get_merkle_index(n.note.commitment().into()).await.map_err(TransactionBuilderError::GeneralError)?;
+                    if idx.is_none()
+                    {
+                        return Err(TransactionBuilderError::GeneralError(format!("note {} not found in merkle tree", n.id)));
+                    }
+                    Some(contract.get_sister_path(idx.unwrap(), self.leaf_count).await.map_err(TransactionBuilderError::GeneralError)?)
+                },
+                None => None
+            };
+            res.push(RawZAction::from_parts(
+                pza.za_type,
+                fvk,
+                auth_path,
+                pza.note_a.map(|n| n.note),
+                pza.note_b,
+                pza.note_c,
+                pza.memo,
+                rng
+            ));
+        }
+        Ok(res)
     }
 
     /// Create as many raw ZActions as needed in order to execute the action described by 'desc' using the pool of 'notes'.
-    /// Returns 'None' if the action described by 'desc' cannot be executed.
+    /// Returns an error if 'desc' is invalid or the action it describes cannot be executed (see: 'plan_zactions').
     pub async fn create_raw_zactions<D: HasMerkleTree>(
         &self,
         sk: &SpendingKey, 
         notes: &mut Vec<NoteEx>, 
         desc: &ZActionDesc, 
         contract: &mut D
-    ) -> Option<Vec<RawZAction>>
+    ) -> Result<Vec<RawZAction>, TransactionBuilderError>
     {
-        let mut rng = OsRng.clone();
-        let mut res = Vec::new();
         let fvk = FullViewingKey::from(sk);
+        let planned = plan_zactions(self.network, &fvk, notes, desc)?;
+        self.create_planned_zactions(&fvk, planned, contract).await
+    }
+}
 
-        match desc.za_type
+/// Plans the transaction described by 'action_descs' without fetching merkle paths or calculating the proof. Returns
+/// an error immediately if any zaction descriptor is not executable using the pool of 'notes' (e.g. insufficient funds).
+pub fn plan_transaction(network: Network, fvk: &FullViewingKey, notes: &mut Vec<NoteEx>, action_descs: &Vec<EOSActionDesc>) -> Result<TransactionPlan, TransactionBuilderError>
+{
+    // Walk through the whole list of action descriptors to detect the sequence of actions 
+    // with privacy dependencies (aka zactions) within this transaction.
+    let mut z_begin = -1;
+    let mut z_end = -1;
+    for i in 0..action_descs.len()
+    {
+        if action_descs[i].zaction_descs.len() > 0
         {
-            ZA_MINTFT | ZA_MINTNFT | ZA_MINTAUTH => {
-                let recipient = Address::from_bech32m(&desc.to).unwrap();
-                let mut memo_arr = [0; 512];
-                assert!(desc.memo.len() < 512);
-                memo_arr[0..desc.memo.len()].clone_from_slice(desc.memo.as_bytes());
-                let nft = if desc.za_type == ZA_MINTFT { 0 } else { 1 };
-                let note_b = Note::new(
-                    match desc.za_type { ZA_MINTFT => NT_FT, ZA_MINTNFT => NT_NFT, ZA_MINTAUTH => NT_AT, _ => 0 },
-                    recipient, 
-                    NoteValue::from_raw(desc.d1.parse().unwrap()),
-                    NoteValue::from_raw(desc.d2.parse().unwrap()), 
-                    NoteValue::from_raw(name_to_value(&desc.sc)), 
-                    NoteValue::from_raw(nft),
-                    Nullifier::dummy(&mut rng), 
-                    rng, 
-                    memo_arr);
-                let rza = RawZAction::from_parts(
-                    desc.za_type,
-                    &fvk,
-                    None,
-                    None,
-                    Some(note_b),
-                    None,
-                    String::from(""),
-                    rng
-                );
-                res.push(rza);
+            z_end = i as i32;
+            if z_begin == -1
+            {
+                z_begin = i as i32;
             }
-            ZA_BURNAUTH => {
-                // in this case the note commitment value of the auth note is stored in the 'to' field of 'desc'
-                let mut to_arr = [0; 32];
-                assert!(desc.to.len() == 2 * 32);
-                hex::decode_to_slice(desc.to.clone(), &mut to_arr).unwrap();
-                let nc = ExtractedNoteCommitment::from_bytes(&to_arr).unwrap();
-                match select_auth_note(notes, name_to_value(&desc.sc), nc) {
-                    Some(mut spent_note) => {
-                        spent_note.note.header |= NH_BURN_FLAG;
-                        let rza = RawZAction::from_parts(
-                            desc.za_type,
-                            &fvk,
-                            None,
-                            None,
-                            Some(spent_note.note),
-                            None,
-                            String::from(""),
-                            rng
-                        );
-                        res.push(rza);
-                    },
-                    None => return None
-                }
+        }
+    }
+
+    // no zactions in this transaction => just return all EOSActions
+    if z_begin == -1
+    {
+        let tx: Vec<EOSAction> = action_descs.iter().map(|ad| ad.action.clone()).collect();
+        return Ok(TransactionPlan::new(tx, Vec::new(), Vec::new()));
+    }
+
+    // copy all EOS actions into the tx until the privacy sequence starts...
+    let z_begin = z_begin as usize;
+    let z_end = z_end as usize;
+    let pre: Vec<EOSAction> = action_descs[0..z_begin].iter().map(|ad| ad.action.clone()).collect();
+
+    // process 'step' actions of privacy sequence: the notes are selected from a copy of the pool which replaces
+    // 'notes' only if all zactions can be planned
+    let mut pool = notes.clone();
+    let mut steps = Vec::new();
+    for i in z_begin..=z_end
+    {
+        let mut planned_step = Vec::new();
+        for zad in &action_descs[i].zaction_descs
+        {
+            // the memo of a burn is part of the zaction and must not be truncated
+            if (zad.za_type == ZA_BURNFT || zad.za_type == ZA_BURNNFT) && zad.memo.len() > MAX_ZACTION_MEMO_SIZE
+            {
+                return Err(ZActionError::MemoTooLong(zad.memo.len()).into());
             }
-            ZA_TRANSFERFT | ZA_BURNFT => {
-                match select_fungible_notes(notes, desc.d1.parse().unwrap(), desc.d2.parse().unwrap(), name_to_value(&desc.sc)) {
-                    Some((spent_notes, change)) => {
-                        let mut memo_arr = [0; 512];
-                        let mut recipient = Address::dummy(&mut rng); // dummy in case of burn
-                        if desc.za_type == ZA_TRANSFERFT
-                        {
-                            recipient = Address::from_bech32m(&desc.to).unwrap();
-                            assert!(desc.memo.len() < 512);
-                            memo_arr[0..desc.memo.len()].clone_from_slice(desc.memo.as_bytes());
-                        }
-                        else // desc.za_type == ZA_BURNFT
-                        {
-                            // in case of burn note_b's memo field contains the receiving EOS account name's value
-                            assert!(desc.to.len() <= 12);
-                            memo_arr[0..8].clone_from_slice(&name_to_value(&desc.to).to_be_bytes());
-                        }
-                        for i in 0..spent_notes.len()
-                        {
-                            let note_b = Note::new(
-                                NT_FT | if desc.za_type == ZA_BURNFT { NH_BURN_FLAG } else { 0 },
-                                recipient, 
-                                if i == spent_notes.len()-1 { NoteValue::from_raw(spent_notes[i].note.d1().inner() - change) } else { spent_notes[i].note.d1() },
-                                spent_notes[i].note.d2(),
-                                spent_notes[i].note.sc(),
-                                NoteValue::from_raw(0),
-                                spent_notes[i].note.nullifier(&fvk),
-                                rng, 
-                                memo_arr);
-                            let note_c = Note::new(
-                                NT_FT,
-                                spent_notes[i].note.recipient(), 
-                                if i == spent_notes.len()-1 { NoteValue::from_raw(change) } else { NoteValue::from_raw(0) },
-                                spent_notes[i].note.d2(),
-                                spent_notes[i].note.sc(),
-                                NoteValue::from_raw(0),
-                                spent_notes[i].note.nullifier(&fvk),
-                                rng,
-                                [0; 512]);
-                            let rza = RawZAction::from_parts(
-                                desc.za_type,
-                                &fvk,
-                                Some(contract.get_sister_path(contract.get_merkle_index(spent_notes[i].note.commitment().into()).await.unwrap(), self.leaf_count).await),
-                                Some(spent_notes[i].note),
-                                Some(note_b),
-                                Some(note_c),
-                                if desc.za_type == ZA_BURNFT { desc.memo.clone() } else { String::from("") },
-                                rng
-                            );
-                            res.push(rza);
-                        }
-                    },
-                    None => return None
-                }
+            // try to plan vector of zactions from zaction descriptor
+            planned_step.extend(plan_zactions(network, fvk, &mut pool, zad)?);
+        }
+        steps.push((action_descs[i].action.clone(), planned_step));
+    }
+
+    // copy all EOS actions into the tx after the privacy sequence (if any)
+    let post: Vec<EOSAction> = action_descs[z_end+1..].iter().map(|ad| ad.action.clone()).collect();
+
+    *notes = pool;
+    Ok(TransactionPlan::new(pre, steps, post))
+}
+
+/// Plans as many zactions as needed in order to execute the action described by 'desc' using the pool of 'notes'.
+/// Selects the notes to be spent and creates all output notes but does not fetch any merkle paths. Returns an error if
+/// 'desc' is invalid (e.g. an amount which is not a number or a memo which is too long) or if the action it describes
+/// cannot be executed (e.g. insufficient funds). A recipient which is not a shielded address of 'network' is reported as
+/// 'TransactionBuilderError::InvalidAddress'.
+pub fn plan_zactions(network: Network, fvk: &FullViewingKey, notes: &mut Vec<NoteEx>, desc: &ZActionDesc) -> Result<Vec<PlannedZAction>, TransactionBuilderError>
+{
+    let mut rng = OsRng.clone();
+    let mut res = Vec::new();
+    let not_executable = || TransactionBuilderError::GeneralError(format!("ZActionDesc not executable: {}", serde_json::to_string(desc).unwrap()));
+
+    match desc.za_type
+    {
+        ZA_MINTFT | ZA_MINTNFT | ZA_MINTAUTH => {
+            let recipient = parse_address(&desc.to, network)?;
+            let memo_arr = memo_to_array(&desc.memo)?;
+            let nft = if desc.za_type == ZA_MINTFT { 0 } else { 1 };
+            let note_b = Note::new(
+                match desc.za_type { ZA_MINTFT => NT_FT, ZA_MINTNFT => NT_NFT, ZA_MINTAUTH => NT_AT, _ => 0 },
+                recipient, 
+                NoteValue::from_raw(parse_value("d1", &desc.d1)?),
+                NoteValue::from_raw(parse_value("d2", &desc.d2)?), 
+                NoteValue::from_raw(name_to_value(&desc.sc)), 
+                NoteValue::from_raw(nft),
+                Nullifier::dummy(&mut rng), 
+                rng, 
+                memo_arr);
+            res.push(PlannedZAction{ za_type: desc.za_type, note_a: None, note_b: Some(note_b), note_c: None, memo: String::from("") });
+        }
+        ZA_BURNAUTH => {
+            // in this case the note commitment value of the auth note is stored in the 'to' field of 'desc'
+            let mut to_arr = [0; 32];
+            let nc = hex::decode_to_slice(&desc.to, &mut to_arr).ok()
+                .and_then(|_| Option::from(ExtractedNoteCommitment::from_bytes(&to_arr)))
+                .ok_or_else(|| TransactionBuilderError::GeneralError(format!("invalid note commitment: '{}'", desc.to)))?;
+            match select_auth_note(notes, name_to_value(&desc.sc), nc) {
+                Some(mut spent_note) => {
+                    spent_note.note.header |= NH_BURN_FLAG;
+                    res.push(PlannedZAction{ za_type: desc.za_type, note_a: None, note_b: Some(spent_note.note), note_c: None, memo: String::from("") });
+                },
+                None => return Err(not_executable())
             }
-            ZA_TRANSFERNFT | ZA_BURNNFT => {
-                match select_nonfungible_note(notes, desc.d1.parse().unwrap(), desc.d2.parse().unwrap(), name_to_value(&desc.sc)) {
-                    Some(spent_note) => {
-                        let mut memo_arr = [0; 512];
-                        let mut recipient = Address::dummy(&mut rng);
-                        if desc.za_type == ZA_TRANSFERNFT
-                        {
-                            recipient = Address::from_bech32m(&desc.to).unwrap();
-                            assert!(desc.memo.len() < 512);
-                            memo_arr[0..desc.memo.len()].clone_from_slice(desc.memo.as_bytes());
-                        }
-                        else // desc.za_type == ZA_BURNNFT
-                        {
-                            // in case of burn note_b's memo field contains the receiving EOS account name's value
-                            assert!(desc.to.len() <= 12);
-                            memo_arr[0..8].clone_from_slice(&name_to_value(&desc.to).to_be_bytes());
-                        }
+        }
+        ZA_TRANSFERFT | ZA_BURNFT => {
+            // reject invalid recipients (e.g. typos or addresses of another network) before any note is selected
+            let recipient = if desc.za_type == ZA_TRANSFERFT { parse_address(&desc.to, network)? } else { Address::dummy(&mut rng) }; // dummy in case of burn
+            match select_fungible_notes(notes, parse_value("d1", &desc.d1)?, parse_value("d2", &desc.d2)?, name_to_value(&desc.sc)) {
+                Some((spent_notes, change)) => {
+                    let mut memo_arr = [0; 512];
+                    if desc.za_type == ZA_TRANSFERFT
+                    {
+                        memo_arr = memo_to_array(&desc.memo)?;
+                    }
+                    else // desc.za_type == ZA_BURNFT
+                    {
+                        // in case of burn note_b's memo field contains the receiving EOS account name's value
+                        memo_arr[0..8].clone_from_slice(&eos_account_to_bytes(&desc.to)?);
+                    }
+                    for i in 0..spent_notes.len()
+                    {
                         let note_b = Note::new(
-                            NT_NFT | if desc.za_type == ZA_BURNNFT { NH_BURN_FLAG } else { 0 },
+                            NT_FT | if desc.za_type == ZA_BURNFT { NH_BURN_FLAG } else { 0 },
                             recipient, 
-                            spent_note.note.d1(),
-                            spent_note.note.d2(),
-                            spent_note.note.sc(),
-                            NoteValue::from_raw(1),
-                            spent_note.note.nullifier(&fvk),
+                            if i == spent_notes.len()-1 { NoteValue::from_raw(spent_notes[i].note.d1().inner() - change) } else { spent_notes[i].note.d1() },
+                            spent_notes[i].note.d2(),
+                            spent_notes[i].note.sc(),
+                            NoteValue::from_raw(0),
+                            spent_notes[i].note.nullifier(fvk),
                             rng, 
                             memo_arr);
-                        let rza = RawZAction::from_parts(
-                            desc.za_type,
-                            &fvk,
-                            Some(contract.get_sister_path(contract.get_merkle_index(spent_note.note.commitment().into()).await.unwrap(), self.leaf_count).await),
-                            Some(spent_note.note),
-                            Some(note_b),
-                            None,
-                            if desc.za_type == ZA_BURNNFT { desc.memo.clone() } else { String::from("") },
-                            rng
-                        );
-                        res.push(rza);
-                    },
-                    None => return None
-                }
+                        let note_c = Note::new(
+                            NT_FT,
+                            spent_notes[i].note.recipient(), 
+                            if i == spent_notes.len()-1 { NoteValue::from_raw(change) } else { NoteValue::from_raw(0) },
+                            spent_notes[i].note.d2(),
+                            spent_notes[i].note.sc(),
+                            NoteValue::from_raw(0),
+                            spent_notes[i].note.nullifier(fvk),
+                            rng,
+                            [0; 512]);
+                        res.push(PlannedZAction{
+                            za_type: desc.za_type,
+                            note_a: Some(spent_notes[i].clone()),
+                            note_b: Some(note_b),
+                            note_c: Some(note_c),
+                            memo: if desc.za_type == ZA_BURNFT { desc.memo.clone() } else { String::from("") }
+                        });
+                    }
+                },
+                None => return Err(not_executable())
+            }
+        }
+        ZA_TRANSFERNFT | ZA_BURNNFT => {
+            let recipient = if desc.za_type == ZA_TRANSFERNFT { parse_address(&desc.to, network)? } else { Address::dummy(&mut rng) };
+            match select_nonfungible_note(notes, parse_value("d1", &desc.d1)?, parse_value("d2", &desc.d2)?, name_to_value(&desc.sc)) {
+                Some(spent_note) => {
+                    let mut memo_arr = [0; 512];
+                    if desc.za_type == ZA_TRANSFERNFT
+                    {
+                        memo_arr = memo_to_array(&desc.memo)?;
+                    }
+                    else // desc.za_type == ZA_BURNNFT
+                    {
+                        // in case of burn note_b's memo field contains the receiving EOS account name's value
+                        memo_arr[0..8].clone_from_slice(&eos_account_to_bytes(&desc.to)?);
+                    }
+                    let note_b = Note::new(
+                        NT_NFT | if desc.za_type == ZA_BURNNFT { NH_BURN_FLAG } else { 0 },
+                        recipient, 
+                        spent_note.note.d1(),
+                        spent_note.note.d2(),
+                        spent_note.note.sc(),
+                        NoteValue::from_raw(1),
+                        spent_note.note.nullifier(fvk),
+                        rng, 
+                        memo_arr);
+                    res.push(PlannedZAction{
+                        za_type: desc.za_type,
+                        note_a: Some(spent_note),
+                        note_b: Some(note_b),
+                        note_c: None,
+                        memo: if desc.za_type == ZA_BURNNFT { desc.memo.clone() } else { String::from("") }
+                    });
+                },
+                None => return Err(not_executable())
             }
-            _ => return None,
         }
+        _ => return Err(not_executable()),
+    }
 
-        Some(res)
+    Ok(res)
+}
+
+/// Returns the 'step' actions of the privacy sequence. If there are raw zactions for a step the serialized zactions
+/// (including the dummy zaction!) are prepended to the 'data' of its EOS action. Fails if a zaction cannot be serialized.
+pub fn step_actions(steps: &Vec<(EOSAction, Vec<RawZAction>)>) -> Result<Vec<EOSAction>, ZActionError>
+{
+    let mut list = Vec::new();
+    for (action, rzactions_step) in steps
+    {
+        let mut a = action.clone();
+        if !rzactions_step.is_empty()
+        {
+            let mut ser_zactions = format!("{:02X?}", rzactions_step.len() + 1);
+            ser_zactions.push_str("efbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeaddeefbeadde000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000");
+            for rza in rzactions_step
+            {
+                ser_zactions.push_str(&rza.zaction().serialize_eos()?);
+            }
+            // append the already existing serialized data from before
+            ser_zactions.push_str(&action.data);
+            a.data = ser_zactions;
+        }
+        list.push(a);
     }
+    Ok(list)
+}
+
+/// Calculates the sighash of the transaction (see: sighash) which consists of the sequence of 'step' actions (each with
+/// the raw zactions it depends on), the EOS actions before ('pre') and after ('post') the privacy sequence and the
+/// 'encrypted_notes' of all raw zactions.
+pub fn transaction_sighash(
+    pre: &Vec<EOSAction>,
+    steps: &Vec<(EOSAction, Vec<RawZAction>)>,
+    post: &Vec<EOSAction>,
+    encrypted_notes: &Vec<TransmittedNoteCiphertext>
+) -> Result<[u8; 32], ZActionError>
+{
+    let zactions: Vec<ZAction> = steps.iter().flat_map(|(_, rzactions_step)| rzactions_step.iter().map(|rza| rza.zaction())).collect();
+    sighash(pre, &step_actions(steps)?, post, &zactions, encrypted_notes)
+}
+
+/// Assembles the final EOS transaction from the sequence of 'step' actions (each with the raw zactions it depends on)
+/// and the EOS actions before ('pre') and after ('post') the privacy sequence using the already calculated 'proof',
+/// 'encrypted_notes' and spend authorization signatures 'sigs' of all raw zactions. Adds the 'begin' and 'step' actions
+/// to the transaction. 'proof' must be 'Some' if there are any raw zactions. Fails if a zaction cannot be serialized.
+pub fn assemble_transaction(
+    network: Network,
+    proof: Option<&Proof>,
+    encrypted_notes: &Vec<TransmittedNoteCiphertext>,
+    sigs: &Vec<Signature<SpendAuth>>,
+    pre: Vec<EOSAction>,
+    steps: Vec<(EOSAction, Vec<RawZAction>)>,
+    post: Vec<EOSAction>,
+    eos_auth: &Vec<EOSAuthorization>
+) -> Result<Vec<EOSAction>, ZActionError>
+{
+    let mut tx = pre;
+    let list = step_actions(&steps)?;
+    if steps.iter().all(|(_, rzactions_step)| rzactions_step.is_empty())
+    {
+        tx.extend(list);
+        tx.extend(post);
+        return Ok(tx);
+    }
+
+    // process 'begin' action of privacy sequence
+    let proof = proof.expect("proof required for zactions");
+    let data_str = format!("{{\"proof\":\"{}\",\"notes\":{},\"sigs\":{},\"tx\":{}}}", 
+        get_liquidstorage_uri(hex::encode(proof.as_ref()), true),
+        serde_json::to_string(encrypted_notes).unwrap(),
+        serde_json::to_string(&sigs.iter().map(|sig| signature_to_hex(sig)).collect::<Vec<String>>()).unwrap(),
+        serde_json::to_string(&list).unwrap()
+    );
+
+    // add 'begin' and 'step' actions to transaction
+    tx.push(EOSAction{
+        account: network.token_contract().to_string(),
+        name: String::from("begin"),
+        authorization: eos_auth.clone(),
+        data: data_str
+    });
+    tx.extend(vec![EOSAction{
+        account: network.token_contract().to_string(),
+        name: String::from("step"),
+        authorization: eos_auth.clone(),
+        data: String::from("{}")
+    }; list.len()]);
+
+    // copy all EOS actions into the tx after the privacy sequence (if any)
+    tx.extend(post);
+
+    Ok(tx)
+}
+
+/// Returns the JSON string of 'actions' ready to be signed and pushed (e.g. with cleos). The 'data' of unpacked actions
+/// is embedded as JSON object instead of a JSON string.
+pub fn actions_json(actions: &Vec<EOSAction>) -> Result<String, serde_json::Error>
+{
+    // all non-serialized 'data' strings should be valid JSON => remove quotation marks and backslashes
+    Ok(serde_json::to_string(actions)?
+        .replace(r#""data":"{"#, r#""data":{"#)
+        .replace(r#"}"}"#, r#"}}"#)
+        .replace("\\", "")
+    )
+}
+
+/// Returns an empty 'exec' action of the ZEOS token contract of 'network' to which zactions can be attached
+pub fn exec_action(network: Network) -> EOSAction
+{
+    EOSAction{
+        account: network.token_contract().to_string(),
+        name: String::from("exec"),
+        authorization: vec![EOSAuthorization{ actor: network.token_contract().to_string(), permission: String::from("active") }],
+        data: String::from("")
+    }
+}
+
+/// Returns the action descriptors to shield 'asset' from EOS account 'account' into the wallet address 'to'. The
+/// asset is transferred to the ZEOS token contract (eosio.token compatible contracts for fungible tokens and
+/// atomicassets compatible contracts for NFTs) followed by the MINTFT (MINTNFT) zaction which mints the same asset.
+pub fn shield_action_descs(network: Network, account: &String, asset: &Asset, to: &String) -> Vec<EOSActionDesc>
+{
+    let transfer_data = if asset.is_nft() {
+        format!("{{\"from\":\"{}\", \"to\":\"{}\", \"asset_ids\":[\"{}\"], \"memo\":\"\"}}", account, network.token_contract(), asset.d1())
+    } else {
+        format!("{{\"from\":\"{}\", \"to\":\"{}\", \"quantity\":\"{}\", \"memo\":\"\"}}", account, network.token_contract(), asset.to_quantity_string())
+    };
+    vec![
+        EOSActionDesc{
+            action: EOSAction{
+                account: asset.contract().clone(),
+                name: String::from("transfer"),
+                authorization: vec![EOSAuthorization{ actor: account.clone(), permission: String::from("active") }],
+                data: transfer_data
+            },
+            zaction_descs: Vec::new()
+        },
+        EOSActionDesc{
+            action: exec_action(network),
+            zaction_descs: vec![ZActionDesc::from_asset(if asset.is_nft() { ZA_MINTNFT } else { ZA_MINTFT }, to, asset, &String::from(""))]
+        }
+    ]
+}
+
+/// Returns the action descriptors to transfer 'asset' from the wallet to the shielded address 'to' using 'memo'
+pub fn transfer_action_descs(network: Network, asset: &Asset, to: &String, memo: &String) -> Vec<EOSActionDesc>
+{
+    vec![
+        EOSActionDesc{
+            action: exec_action(network),
+            zaction_descs: vec![ZActionDesc::from_asset(if asset.is_nft() { ZA_TRANSFERNFT } else { ZA_TRANSFERFT }, to, asset, memo)]
+        }
+    ]
+}
+
+/// Returns the action descriptors to unshield 'asset' from the wallet into EOS account 'eos_account'. The BURNFT
+/// (BURNNFT) zaction transfers the asset from the ZEOS token contract to 'eos_account' using 'memo'.
+pub fn unshield_action_descs(network: Network, asset: &Asset, eos_account: &String, memo: &String) -> Vec<EOSActionDesc>
+{
+    vec![
+        EOSActionDesc{
+            action: exec_action(network),
+            zaction_descs: vec![ZActionDesc::from_asset(if asset.is_nft() { ZA_BURNNFT } else { ZA_BURNFT }, eos_account, asset, memo)]
+        }
+    ]
 }
 
 /// Very simple note selection algorithm: walk through all notes and pick notes of the demanded type until the sum
@@ -447,6 +1046,244 @@ pub fn select_auth_note(notes: &mut Vec<NoteEx>, sc: u64, nc: ExtractedNoteCommi
     None
 }
 
+/// Plans the zactions of a batch payment. All fungible payments of the same token are covered by a single note selection
+/// pass. Each TRANSFERFT spends one note and has two outputs (note_b and note_c) which is why each spent note is split
+/// across up to two recipients (see: 'assign_note'): preferably two recipients whose outstanding amounts add up to the
+/// note's value, otherwise one recipient is paid in full and the rest goes to a recipient who still needs at least that
+/// amount. Only if all outstanding amounts are too small to take the rest it goes back to the wallet as change and
+/// further notes are selected. Each NFT payment results in one TRANSFERNFT. Returns an error if 'notes' do not cover
+/// all 'payments' or if a recipient is not a shielded address of 'network' in which case 'notes' stays untouched.
+///
+/// The change is not consolidated into one output: a TRANSFERFT balances the spent note with its own two outputs, so
+/// each spent note whose rest goes back to the wallet creates a change note of its own. A note which is paid out
+/// entirely creates no change note: the second output (required by the circuit) carries no value and is sent to a
+/// dummy address.
+///
+/// Since notes created by a transaction cannot be spent by the same transaction, a single note can never pay more than
+/// two recipients: e.g. paying three recipients from a wallet with a single note requires to split the note first (by
+/// paying oneself) and fails with an error that says so.
+pub fn plan_batch_payment(network: Network, fvk: &FullViewingKey, notes: &mut Vec<NoteEx>, payments: &Vec<Payment>) -> Result<Vec<PlannedZAction>, TransactionBuilderError>
+{
+    let mut rng = OsRng.clone();
+    let mut pool = notes.clone();
+    let mut res = Vec::new();
+
+    // group fungible payments by token (symbol and contract) keeping the order of the payments
+    let mut groups: Vec<((u64, u64), Vec<(Address, u64, [u8; 512])>)> = Vec::new();
+    for p in payments
+    {
+        let recipient = parse_address(&p.to, network)?;
+        let memo_arr = memo_to_array(&p.memo)?;
+        if p.asset.is_nft()
+        {
+            let spent_note = select_nonfungible_note(&mut pool, p.asset.d1(), p.asset.d2(), name_to_value(p.asset.contract()));
+            if spent_note.is_none()
+            {
+                return Err(TransactionBuilderError::GeneralError(format!("NFT not available: {}", serde_json::to_string(&p.asset).unwrap())));
+            }
+            let spent_note = spent_note.unwrap();
+            let note_b = Note::new(
+                NT_NFT,
+                recipient,
+                spent_note.note.d1(),
+                spent_note.note.d2(),
+                spent_note.note.sc(),
+                NoteValue::from_raw(1),
+                spent_note.note.nullifier(fvk),
+                rng,
+                memo_arr);
+            res.push(PlannedZAction{ za_type: ZA_TRANSFERNFT, note_a: Some(spent_note), note_b: Some(note_b), note_c: None, memo: String::from("") });
+            continue;
+        }
+        let key = (p.asset.d2(), name_to_value(p.asset.contract()));
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, recipients)) => recipients.push((recipient, p.asset.d1(), memo_arr)),
+            None => groups.push((key, vec![(recipient, p.asset.d1(), memo_arr)]))
+        }
+    }
+
+    for ((symbol, sc), mut recipients) in groups
+    {
+        recipients.retain(|r| r.1 > 0);
+        let mut spent_notes: Vec<NoteEx> = Vec::new();
+        let mut change = 0u64;
+        while !recipients.is_empty()
+        {
+            // select notes for everything that is still missing (happens again only if a note's rest went back as change)
+            if spent_notes.is_empty()
+            {
+                let missing = recipients.iter().fold(0u64, |acc, r| acc.saturating_add(r.1));
+                match select_fungible_notes(&mut pool, missing, symbol, sc) {
+                    Some((selected, _)) => spent_notes = selected,
+                    None if change > 0 => return Err(TransactionBuilderError::GeneralError(format!(
+                        "not enough notes: {} of symbol {} (contract {}) missing for {} recipient(s) since {} went back as change (a spent note pays at most two recipients: split large notes by paying yourself first)",
+                        missing, symbol, sc, recipients.len(), change))),
+                    None => return Err(TransactionBuilderError::GeneralError(format!("not enough funds: {} of symbol {} (contract {}) missing", missing, symbol, sc)))
+                }
+            }
+            let spent_note = spent_notes.remove(0);
+            let value = spent_note.note.d1().inner();
+
+            let ((i, amount_b), c) = assign_note(value, &recipients);
+            let (recipient_b, memo_b) = (recipients[i].0, recipients[i].2);
+            recipients[i].1 -= amount_b;
+            let (recipient_c, memo_c, amount_c) = match c {
+                Some((j, amount_c)) => {
+                    recipients[j].1 -= amount_c;
+                    (recipients[j].0, recipients[j].2, amount_c)
+                }
+                // the rest goes back to the wallet
+                None if value > amount_b => {
+                    change += value - amount_b;
+                    (spent_note.note.recipient(), [0; 512], value - amount_b)
+                }
+                // there is no rest: the output without value is not received by any wallet
+                None => (Address::dummy(&mut rng), [0; 512], 0)
+            };
+            recipients.retain(|r| r.1 > 0);
+
+            let note_b = Note::new(
+                NT_FT,
+                recipient_b,
+                NoteValue::from_raw(amount_b),
+                spent_note.note.d2(),
+                spent_note.note.sc(),
+                NoteValue::from_raw(0),
+                spent_note.note.nullifier(fvk),
+                rng,
+                memo_b);
+            let note_c = Note::new(
+                NT_FT,
+                recipient_c,
+                NoteValue::from_raw(amount_c),
+                spent_note.note.d2(),
+                spent_note.note.sc(),
+                NoteValue::from_raw(0),
+                spent_note.note.nullifier(fvk),
+                rng,
+                memo_c);
+            res.push(PlannedZAction{ za_type: ZA_TRANSFERFT, note_a: Some(spent_note), note_b: Some(note_b), note_c: Some(note_c), memo: String::from("") });
+        }
+        // all recipients are paid: left over notes of the last selection (if any) stay in the wallet
+        pool.append(&mut spent_notes);
+    }
+
+    *notes = pool;
+    Ok(res)
+}
+
+/// Splits a note of 'value' across the outstanding amounts of 'recipients' (which must not be empty) using the two
+/// outputs of a TRANSFERFT. Returns the index of the recipient and the amount of note_b and optionally of note_c (if
+/// 'None' the rest of the note is change). In order of preference:
+///
+/// 1. two recipients are paid in full by the whole note
+/// 2. one recipient is paid in full by the whole note
+/// 3. one recipient is paid in full and the rest goes to a recipient who needs at least that amount
+/// 4. the whole note goes to a recipient who needs more than that amount
+/// 5. the recipient with the largest outstanding amount is paid in full and the rest is change
+fn assign_note(value: u64, recipients: &[(Address, u64, [u8; 512])]) -> ((usize, u64), Option<(usize, u64)>)
+{
+    let mut by_amount: HashMap<u64, usize> = HashMap::new();
+    for (i, r) in recipients.iter().enumerate()
+    {
+        if r.1 < value
+        {
+            if let Some(&j) = by_amount.get(&(value - r.1))
+            {
+                return ((j, value - r.1), Some((i, r.1)));
+            }
+            by_amount.entry(r.1).or_insert(i);
+        }
+    }
+    if let Some(i) = recipients.iter().position(|r| r.1 == value)
+    {
+        return ((i, value), None);
+    }
+    // the largest outstanding amount (first of equals) and the largest of the others
+    let largest = (0..recipients.len()).fold(0, |m, i| if recipients[i].1 > recipients[m].1 { i } else { m });
+    let second = (0..recipients.len()).filter(|i| *i != largest).fold(None, |m: Option<usize>, i| match m {
+        Some(m) if recipients[m].1 >= recipients[i].1 => Some(m),
+        _ => Some(i)
+    });
+    let below = (0..recipients.len()).filter(|i| recipients[*i].1 < value).fold(None, |m: Option<usize>, i| match m {
+        Some(m) if recipients[m].1 >= recipients[i].1 => Some(m),
+        _ => Some(i)
+    });
+    if let Some(i) = below
+    {
+        // the largest amount below the note's value: the rest goes to the largest of the other recipients if it fits
+        let other = if i == largest { second } else { Some(largest) };
+        if let Some(j) = other.filter(|j| recipients[*j].1 >= value - recipients[i].1)
+        {
+            return ((i, recipients[i].1), Some((j, value - recipients[i].1)));
+        }
+    }
+    if recipients[largest].1 > value
+    {
+        return ((largest, value), None);
+    }
+    ((largest, recipients[largest].1), None)
+}
+
+/// Splits 'planned' zactions into chunks each of which fits into a single EOS transaction according to 'limits'.
+pub fn split_planned_zactions(planned: Vec<PlannedZAction>, limits: &BatchLimits) -> Vec<Vec<PlannedZAction>>
+{
+    let mut res = Vec::new();
+    let mut chunk: Vec<PlannedZAction> = Vec::new();
+    let mut size = 0;
+    for pza in planned
+    {
+        let s = pza.estimated_size();
+        if !chunk.is_empty() && (chunk.len() >= limits.max_zactions || size + s > limits.max_size)
+        {
+            res.push(chunk);
+            chunk = Vec::new();
+            size = 0;
+        }
+        size += s;
+        chunk.push(pza);
+    }
+    if !chunk.is_empty()
+    {
+        res.push(chunk);
+    }
+    res
+}
+
+/// Parses the shielded 'address' of 'network' and reports the reason if it is not valid
+fn parse_address(address: &str, network: Network) -> Result<Address, TransactionBuilderError>
+{
+    Address::from_bech32m(address, network).map_err(|err| TransactionBuilderError::InvalidAddress{ address: address.to_string(), err })
+}
+
+/// Converts 'memo' into the fixed size memo field of a note
+fn memo_to_array(memo: &String) -> Result<[u8; 512], TransactionBuilderError>
+{
+    if memo.len() >= 512
+    {
+        return Err(TransactionBuilderError::GeneralError(format!("memo too long: {} bytes", memo.len())));
+    }
+    let mut memo_arr = [0; 512];
+    memo_arr[0..memo.len()].clone_from_slice(memo.as_bytes());
+    Ok(memo_arr)
+}
+
+/// Parses the amount or symbol 'value' of the field 'name' of a zaction descriptor
+fn parse_value(name: &str, value: &str) -> Result<u64, TransactionBuilderError>
+{
+    value.parse().map_err(|_| TransactionBuilderError::GeneralError(format!("invalid {}: '{}'", name, value)))
+}
+
+/// Encodes the EOS account name 'account' (the receiver of a burn) for the memo field of a note
+fn eos_account_to_bytes(account: &String) -> Result<[u8; 8], TransactionBuilderError>
+{
+    if account.is_empty() || account.len() > 12
+    {
+        return Err(TransactionBuilderError::GeneralError(format!("invalid EOS account name: '{}'", account)));
+    }
+    Ok(name_to_value(account).to_be_bytes())
+}
+
 /// calculate LiquidStorage URI used for the IPFS addressing of data
 pub fn get_liquidstorage_uri(input: String, short: bool) -> String
 {
@@ -458,11 +1295,15 @@ pub fn get_liquidstorage_uri(input: String, short: bool) -> String
 mod tests
 {
     use rand::{rngs::OsRng, seq::SliceRandom};
-    use crate::{note::NT_FT, note::NT_AT, tree::MerklePath, action::{ZA_TRANSFERFT, ZA_BURNFT, ZA_MINTFT, ZA_MINTNFT, ZA_MINTAUTH, ZA_TRANSFERNFT, ZA_BURNNFT, ZA_BURNAUTH}, keys::FullViewingKey, keys::Scope, note::ExtractedNoteCommitment, builder::get_liquidstorage_uri};
+    use crate::{note::NT_FT, note::NT_AT, note::NT_NFT, tree::MerklePath, action::{ZA_TRANSFERFT, ZA_BURNFT, ZA_MINTFT, ZA_MINTNFT, ZA_MINTAUTH, ZA_TRANSFERNFT, ZA_BURNNFT, ZA_BURNAUTH}, keys::FullViewingKey, keys::Scope, note::ExtractedNoteCommitment, builder::get_liquidstorage_uri};
     use super::{select_fungible_notes, select_auth_note, select_nonfungible_note, TransactionBuilder, Note, NoteValue, Address, Nullifier, NoteEx, SpendingKey, EOSAction, HasMerkleTree};
-    use super::name_to_value;
+    use super::{name_to_value, base85, Asset, shield_action_descs, unshield_action_descs};
     use super::{ZActionDesc, EOSActionDesc, EOSAuthorization};
+    use super::{Payment, PlannedZAction, BatchLimits, plan_batch_payment, split_planned_zactions, plan_transaction, plan_zactions, exec_action, TransactionBuilderError};
+    use super::{PROOF_SIZE_BASE, PROOF_SIZE_PER_CIRCUIT};
+    use crate::address::AddressError;
     use crate::circuit::{Circuit, K};
+    use crate::network::Network;
     use rustzeos::halo2::ProvingKey;
 
     #[test]
@@ -515,15 +1356,15 @@ mod tests
     pub struct DummyContract;
     impl HasMerkleTree for DummyContract
     {
-        async fn get_sister_path(&mut self, _array_index: u64, _leaf_count: u64) -> MerklePath
+        async fn get_sister_path(&mut self, _array_index: u64, _leaf_count: u64) -> Result<MerklePath, String>
         {
             let mut rng = OsRng.clone();
-            MerklePath::dummy(&mut rng)
+            Ok(MerklePath::dummy(&mut rng))
         }
 
-        async fn get_merkle_index(&self, _hash: ExtractedNoteCommitment) -> Option<u64>
+        async fn get_merkle_index(&self, _hash: ExtractedNoteCommitment) -> Result<Option<u64>, String>
         {
-            Some(0)
+            Ok(Some(0))
         }
     }
 
@@ -543,14 +1384,14 @@ mod tests
 
         let mut desc = ZActionDesc {
             za_type: ZA_MINTFT,
-            to: fvk.address_at(0u32, Scope::External).to_bech32m(),
+            to: fvk.address_at(0u32, Scope::External).to_bech32m(Network::Kylin),
             d1: "6".to_string(),
             d2: "1".to_string(),
             sc: "thezeostoken".to_string(),
             memo: String::from("")
         };
 
-        let tb = TransactionBuilder::new(0); // leaf_count not required for DummyContract's get_merkle_path()
+        let tb = TransactionBuilder::new(Network::Kylin, 0); // leaf_count not required for DummyContract's get_merkle_path()
         let mut dc = DummyContract;
         println!("{:?}", tb.create_raw_zactions(&sk, &mut notes.clone(), &desc, &mut dc).await.unwrap());
         desc.za_type = ZA_MINTNFT;
@@ -565,7 +1406,7 @@ mod tests
 
         let mut desc = ZActionDesc {
             za_type: ZA_TRANSFERNFT, 
-            to: fvk.address_at(0u32, Scope::External).to_bech32m(),
+            to: fvk.address_at(0u32, Scope::External).to_bech32m(Network::Kylin),
             d1: "1337".to_string(), 
             d2: "0".to_string(), 
             sc: "nftzeostoken".to_string(), 
@@ -581,6 +1422,37 @@ mod tests
 
     }
 
+    #[test]
+    fn invalid_zaction_descs()
+    {
+        let mut rng = OsRng.clone();
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        let mut notes = vec![NoteEx{id: 0, block_number: 0, note: Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(5), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&"thezeostoken".to_string())), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])}];
+        let to = fvk.address_at(0u32, Scope::External).to_bech32m(Network::Kylin);
+        let desc = |za_type: u64, to: &str, d1: &str, memo: usize| ZActionDesc {
+            za_type,
+            to: to.to_string(),
+            d1: d1.to_string(),
+            d2: "1".to_string(),
+            sc: "thezeostoken".to_string(),
+            memo: "m".repeat(memo)
+        };
+
+        // user supplied descriptors are reported as errors instead of panicking
+        assert!(plan_zactions(Network::Kylin, &fvk, &mut notes.clone(), &desc(ZA_MINTFT, &to, "6", 511)).is_ok());
+        let mut err = |d: ZActionDesc| plan_zactions(Network::Kylin, &fvk, &mut notes, &d).unwrap_err().to_string();
+        assert_eq!(err(desc(ZA_MINTFT, &to, "six", 0)), "Error: invalid d1: 'six'");
+        assert_eq!(err(desc(ZA_MINTFT, &to, "6", 512)), "Error: memo too long: 512 bytes");
+        assert_eq!(err(desc(ZA_TRANSFERFT, &to, "-1", 0)), "Error: invalid d1: '-1'");
+        assert_eq!(err(desc(ZA_BURNAUTH, "00", "0", 0)), "Error: invalid note commitment: '00'");
+        assert_eq!(err(desc(ZA_BURNFT, "thenameistoolong", "1", 0)), "Error: invalid EOS account name: 'thenameistoolong'");
+        assert!(err(desc(ZA_TRANSFERFT, &to, "6", 0)).starts_with("Error: ZActionDesc not executable"));
+        // the reason why a recipient is invalid is reported before any note is selected
+        let res = plan_zactions(Network::Mainnet, &fvk, &mut notes, &desc(ZA_TRANSFERFT, &to, "6", 0));
+        assert!(matches!(res, Err(TransactionBuilderError::InvalidAddress{ err: AddressError::WrongNetwork{ .. }, .. })));
+    }
+
     #[tokio::test]
     async fn transaction_building()
     {
@@ -633,7 +1505,7 @@ mod tests
             zaction_descs: [
                 ZActionDesc{
                     za_type: ZA_MINTFT,
-                    to: fvk.address_at(0u32, Scope::External).to_bech32m(),
+                    to: fvk.address_at(0u32, Scope::External).to_bech32m(Network::Kylin),
                     d1: "10000".to_string(),
                     d2: "1397703940".to_string(),
                     sc: "thezeostoken".to_string(),
@@ -707,7 +1579,7 @@ mod tests
         //let mut file = File::create("vk.txt").unwrap();
         //write!(file, "{}", hex::encode(arr).to_uppercase());
         
-        let tb = TransactionBuilder::new(0); // leaf_count not required for DummyContract's get_merkle_path()
+        let tb = TransactionBuilder::new(Network::Kylin, 0); // leaf_count not required for DummyContract's get_merkle_path()
         let mut dc = DummyContract;
         let pk = ProvingKey::build(Circuit::default(), K);
         let (proof, actions) = tb.build_transaction(
@@ -728,4 +1600,290 @@ mod tests
         //assert!(zeos_verifier::verify_zeos_proof(proof.unwrap().as_ref(), &inputs, &arr));
     }
 
+    #[test]
+    fn shield_unshield()
+    {
+        let mut rng = OsRng.clone();
+        let to = Address::dummy(&mut rng).to_bech32m(Network::Kylin);
+
+        let eos = Asset::ft(10000, 1397703940, &"eosio.token".to_string());
+        let descs = shield_action_descs(Network::Kylin, &"mschoenebeck".to_string(), &eos, &to);
+        assert_eq!(descs.len(), 2);
+        assert_eq!(descs[0].action.account, "eosio.token");
+        assert_eq!(descs[0].action.authorization[0].actor, "mschoenebeck");
+        assert!(descs[0].action.data.contains("\"quantity\":\"1.0000 EOS\""));
+        assert_eq!(descs[1].zaction_descs[0].za_type, ZA_MINTFT);
+        assert_eq!(descs[1].zaction_descs[0].d1, "10000");
+        assert_eq!(descs[1].zaction_descs[0].d2, "1397703940");
+        assert_eq!(descs[1].zaction_descs[0].sc, "eosio.token");
+
+        let nft = Asset::nft(1099511627776, &"atomicassets".to_string());
+        let descs = shield_action_descs(Network::Kylin, &"mschoenebeck".to_string(), &nft, &to);
+        assert_eq!(descs[0].action.account, "atomicassets");
+        assert!(descs[0].action.data.contains("\"asset_ids\":[\"1099511627776\"]"));
+        assert_eq!(descs[1].zaction_descs[0].za_type, ZA_MINTNFT);
+
+        let descs = unshield_action_descs(Network::Kylin, &eos, &"mschoenebeck".to_string(), &"withdrawal".to_string());
+        assert_eq!(descs.len(), 1);
+        assert_eq!(descs[0].zaction_descs[0].za_type, ZA_BURNFT);
+        assert_eq!(descs[0].zaction_descs[0].to, "mschoenebeck");
+        let descs = unshield_action_descs(Network::Kylin, &nft, &"mschoenebeck".to_string(), &"withdrawal".to_string());
+        assert_eq!(descs[0].zaction_descs[0].za_type, ZA_BURNNFT);
+    }
+
+    #[tokio::test]
+    async fn private_deposit()
+    {
+        let mut rng = OsRng.clone();
+
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+
+        let mut notes = Vec::new();
+        notes.push(NoteEx{id: 0, block_number: 0, note: Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(10000), NoteValue::from_raw(1397703940), NoteValue::from_raw(6138663591592764928), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])});
+
+        let auth = [EOSAuthorization{actor: "mschoenebeck".to_string(), permission: "active".to_string()}; 1];
+        let tb = TransactionBuilder::new(Network::Kylin, 0); // leaf_count not required for DummyContract's get_merkle_path()
+        let mut dc = DummyContract;
+        let pk = ProvingKey::build(Circuit::default(), K);
+        let (proof, actions, auth_note) = tb.build_private_deposit(
+            &pk,
+            &sk,
+            &mut notes,
+            &Asset::ft(5000, 1397703940, &"eosio.token".to_string()),
+            &"zeosexchange".to_string(),
+            &"deposit".to_string(),
+            &mut dc,
+            &auth.to_vec()
+        ).await.unwrap();
+
+        assert!(proof.is_some());
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].name, "begin");
+        assert_eq!(actions[1].name, "step");
+        assert_eq!(auth_note.header(), NT_AT);
+        assert_eq!(auth_note.sc().inner(), name_to_value(&"zeosexchange".to_string()));
+
+        // the burned asset's memo starts with the base85 encoded commitment of the auth token
+        let cm_auth: ExtractedNoteCommitment = auth_note.commitment().into();
+        let memo = format!("{}deposit", base85::encode(&cm_auth.to_bytes()));
+        assert!(actions[0].data.contains(&hex::encode(memo)));
+    }
+
+    #[tokio::test]
+    async fn batch_payment()
+    {
+        let mut rng = OsRng.clone();
+
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        let change_address = fvk.address_at(0u32, Scope::External);
+        let sc = "thezeostoken".to_string();
+
+        let mut notes = Vec::new();
+        notes.push(NoteEx{id: 0, block_number: 0, note: Note::new(NT_FT, change_address, NoteValue::from_raw(5), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&sc)), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])});
+        notes.push(NoteEx{id: 0, block_number: 0, note: Note::new(NT_FT, change_address, NoteValue::from_raw(3), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&sc)), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])});
+        notes.push(NoteEx{id: 0, block_number: 0, note: Note::new(NT_FT, change_address, NoteValue::from_raw(2), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&sc)), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])});
+        notes.push(NoteEx{id: 0, block_number: 0, note: Note::new(NT_NFT, change_address, NoteValue::from_raw(1337), NoteValue::from_raw(0), NoteValue::from_raw(name_to_value(&"atomicassets".to_string())), NoteValue::from_raw(1), Nullifier::dummy(&mut rng), rng, [0; 512])});
+
+        let recipients: Vec<Address> = (1..4u32).map(|i| fvk.address_at(i, Scope::External)).collect();
+        let payments = vec![
+            Payment::new(&recipients[0].to_bech32m(Network::Kylin), &Asset::ft(4, 1, &sc), &"payout 1".to_string()),
+            Payment::new(&recipients[1].to_bech32m(Network::Kylin), &Asset::ft(3, 1, &sc), &"payout 2".to_string()),
+            Payment::new(&recipients[2].to_bech32m(Network::Kylin), &Asset::nft(1337, &"atomicassets".to_string()), &"".to_string()),
+            Payment::new(&recipients[2].to_bech32m(Network::Kylin), &Asset::ft(2, 1, &sc), &"payout 3".to_string()),
+        ];
+
+        // not enough funds: notes stay untouched
+        let mut too_much = payments.clone();
+        too_much.push(Payment::new(&recipients[0].to_bech32m(Network::Kylin), &Asset::ft(2, 1, &sc), &"".to_string()));
+        assert!(plan_batch_payment(Network::Kylin, &fvk, &mut notes, &too_much).is_err());
+        assert_eq!(notes.len(), 4);
+
+        // one selection pass: all three fungible notes are spent, the NFT is transferred and there is exactly one change note
+        let planned = plan_batch_payment(Network::Kylin, &fvk, &mut notes.clone(), &payments).unwrap();
+        assert_eq!(planned.len(), 4);
+        assert_eq!(planned.iter().filter(|p| p.za_type == ZA_TRANSFERNFT).count(), 1);
+        let outputs: Vec<Note> = planned.iter().flat_map(|p| p.note_b.iter().chain(p.note_c.iter()).cloned()).collect();
+        let paid = |a: &Address| outputs.iter().filter(|n| n.recipient() == *a && n.nft().inner() == 0).map(|n| n.d1().inner()).sum::<u64>();
+        assert_eq!(paid(&recipients[0]), 4);
+        assert_eq!(paid(&recipients[1]), 3);
+        assert_eq!(paid(&recipients[2]), 2);
+        assert_eq!(paid(&change_address), 1);
+        assert_eq!(outputs.iter().filter(|n| n.recipient() == change_address && n.d1().inner() > 0).count(), 1);
+
+        // split into several transactions
+        let limits = BatchLimits{ max_zactions: 2, max_size: BatchLimits::default().max_size };
+        let chunks = split_planned_zactions(planned, &limits);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|c| c.len() <= 2));
+
+        let auth = [EOSAuthorization{actor: "mschoenebeck".to_string(), permission: "active".to_string()}; 1];
+        let tb = TransactionBuilder::new(Network::Kylin, 0); // leaf_count not required for DummyContract's get_merkle_path()
+        let mut dc = DummyContract;
+        let pk = ProvingKey::build(Circuit::default(), K);
+        let txs = tb.build_batch_payment(&pk, &sk, &mut notes, &payments, &limits, &mut dc, &auth.to_vec()).await.unwrap();
+        assert_eq!(txs.len(), 2);
+        for (proof, actions) in txs
+        {
+            assert!(proof.is_some());
+            assert_eq!(actions[0].name, "begin");
+        }
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn many_small_payments()
+    {
+        let mut rng = OsRng.clone();
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        let change_address = fvk.address_at(0u32, Scope::External);
+        let sc = "thezeostoken".to_string();
+        let mut note = |value: u64| NoteEx{id: 0, block_number: 0, note: Note::new(NT_FT, change_address, NoteValue::from_raw(value), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&sc)), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])};
+        let recipients: Vec<Address> = (1..7u32).map(|i| fvk.address_at(i, Scope::External)).collect();
+        let payments = |amounts: &[u64]| amounts.iter().enumerate().map(|(i, a)| Payment::new(&recipients[i].to_bech32m(Network::Kylin), &Asset::ft(*a, 1, &sc), &"".to_string())).collect::<Vec<Payment>>();
+        let paid = |planned: &Vec<PlannedZAction>, a: &Address| planned.iter().flat_map(|p| p.note_b.iter().chain(p.note_c.iter())).filter(|n| n.recipient() == *a).map(|n| n.d1().inner()).sum::<u64>();
+
+        // a single note pays two recipients without change
+        let mut notes = vec![note(10)];
+        let planned = plan_batch_payment(Network::Kylin, &fvk, &mut notes, &payments(&[3, 7])).unwrap();
+        assert_eq!(planned.len(), 1);
+        assert_eq!((paid(&planned, &recipients[0]), paid(&planned, &recipients[1]), paid(&planned, &change_address)), (3, 7, 0));
+        assert!(notes.is_empty());
+
+        // each note is split across the recipients it covers before anything goes back as change
+        let mut notes = vec![note(10), note(5)];
+        let planned = plan_batch_payment(Network::Kylin, &fvk, &mut notes, &payments(&[1, 4, 6])).unwrap();
+        assert_eq!(planned.len(), 2);
+        assert_eq!((paid(&planned, &recipients[0]), paid(&planned, &recipients[1]), paid(&planned, &recipients[2])), (1, 4, 6));
+        assert_eq!(paid(&planned, &change_address), 4);
+
+        // many small payments: each note pays two recipients whose amounts add up to its value
+        let amounts = [1, 2, 3, 9, 8, 7];
+        let mut notes = vec![note(10), note(10), note(10)];
+        let planned = plan_batch_payment(Network::Kylin, &fvk, &mut notes, &payments(&amounts)).unwrap();
+        assert_eq!(planned.len(), 3);
+        assert!((0..6).all(|i| paid(&planned, &recipients[i]) == amounts[i]));
+        assert_eq!(paid(&planned, &change_address), 0);
+
+        // notes which are paid out entirely create no change notes: their second outputs carry no value and go to
+        // dummy addresses
+        let mut notes = vec![note(5), note(3)];
+        let planned = plan_batch_payment(Network::Kylin, &fvk, &mut notes, &payments(&[8])).unwrap();
+        assert_eq!(planned.len(), 2);
+        assert_eq!(paid(&planned, &recipients[0]), 8);
+        let outputs: Vec<Note> = planned.iter().flat_map(|p| p.note_b.iter().chain(p.note_c.iter()).cloned()).collect();
+        assert!(outputs.iter().all(|n| n.recipient() != change_address));
+        assert_eq!(outputs.iter().filter(|n| n.d1().inner() == 0 && n.recipient() != recipients[0]).count(), 2);
+
+        // a single note cannot pay three recipients (two outputs per spent note): the error says so and the notes
+        // stay untouched
+        let mut notes = vec![note(10)];
+        match plan_batch_payment(Network::Kylin, &fvk, &mut notes, &payments(&[1, 1, 1])) {
+            Err(TransactionBuilderError::GeneralError(e)) => assert!(e.starts_with("not enough notes") && e.contains("split")),
+            _ => panic!("expected error")
+        }
+        assert_eq!(notes.len(), 1);
+        assert!(matches!(plan_batch_payment(Network::Kylin, &fvk, &mut notes, &payments(&[5, 6])), Err(TransactionBuilderError::GeneralError(e)) if e.starts_with("not enough funds")));
+    }
+
+    #[test]
+    fn transaction_planning()
+    {
+        let mut rng = OsRng.clone();
+
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        let to = fvk.address_at(1u32, Scope::External).to_bech32m(Network::Kylin);
+
+        let mut notes = Vec::new();
+        notes.push(NoteEx{id: 0, block_number: 0, note: Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(5), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&"thezeostoken".to_string())), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])});
+        notes.push(NoteEx{id: 1, block_number: 0, note: Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(3), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&"thezeostoken".to_string())), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])});
+        notes.push(NoteEx{id: 2, block_number: 0, note: Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(2), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&"thezeostoken".to_string())), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])});
+
+        let descs = vec![EOSActionDesc{
+            action: exec_action(Network::Kylin),
+            zaction_descs: vec![
+                ZActionDesc::from_asset(ZA_TRANSFERFT, &to, &Asset::ft(9, 1, &"thezeostoken".to_string()), &"".to_string()),
+                ZActionDesc::from_asset(ZA_MINTAUTH, &to, &Asset::nft(0, &"zeosexchange".to_string()), &"".to_string())
+            ]
+        }];
+        let plan = plan_transaction(Network::Kylin, &fvk, &mut notes.clone(), &descs).unwrap();
+        assert_eq!(plan.spent_notes().len(), 3);
+        assert_eq!(plan.outputs().len(), 7);
+        assert_eq!(plan.outputs().iter().map(|n| n.d1().inner()).sum::<u64>(), 10);
+        assert_eq!(plan.circuits(), 3);
+        assert_eq!(plan.proof_size(), PROOF_SIZE_BASE + 3 * PROOF_SIZE_PER_CIRCUIT);
+        assert_eq!(plan.action_sizes().len(), 2); // begin + step
+        assert!(plan.proving_time_ms() > 0);
+        assert!(serde_json::to_string(&plan).is_ok());
+
+        // insufficient funds are detected without fetching merkle paths or proving
+        let descs = vec![EOSActionDesc{
+            action: exec_action(Network::Kylin),
+            zaction_descs: vec![ZActionDesc::from_asset(ZA_TRANSFERFT, &to, &Asset::ft(11, 1, &"thezeostoken".to_string()), &"".to_string())]
+        }];
+        assert!(plan_transaction(Network::Kylin, &fvk, &mut notes, &descs).is_err());
+        assert_eq!(notes.len(), 3);
+
+        // the notes selected for the first descriptor remain in the pool if the second one is not executable
+        let descs = vec![
+            EOSActionDesc{
+                action: exec_action(Network::Kylin),
+                zaction_descs: vec![ZActionDesc::from_asset(ZA_TRANSFERFT, &to, &Asset::ft(6, 1, &"thezeostoken".to_string()), &"".to_string())]
+            },
+            EOSActionDesc{
+                action: exec_action(Network::Kylin),
+                zaction_descs: vec![ZActionDesc::from_asset(ZA_TRANSFERFT, &to, &Asset::ft(5, 1, &"thezeostoken".to_string()), &"".to_string())]
+            }
+        ];
+        assert!(plan_transaction(Network::Kylin, &fvk, &mut notes, &descs).is_err());
+        assert_eq!(notes.len(), 3);
+        assert_eq!(notes.iter().map(|n| n.note.d1().inner()).sum::<u64>(), 10);
+        let mut pool = notes.clone();
+        let plan = plan_transaction(Network::Kylin, &fvk, &mut pool, &descs[..1].to_vec()).unwrap();
+        assert_eq!(pool.len(), 3 - plan.spent_notes().len());
+
+        // the memo of a burn is never truncated
+        let descs = vec![EOSActionDesc{
+            action: exec_action(Network::Kylin),
+            zaction_descs: vec![ZActionDesc::from_asset(ZA_BURNFT, &"mschoenebeck".to_string(), &Asset::ft(1, 1, &"thezeostoken".to_string()), &"x".repeat(256))]
+        }];
+        assert!(plan_transaction(Network::Kylin, &fvk, &mut notes, &descs).is_err());
+        assert_eq!(notes.len(), 3);
+
+        // the address of another network is never paid
+        let descs = vec![EOSActionDesc{
+            action: exec_action(Network::Kylin),
+            zaction_descs: vec![ZActionDesc::from_asset(ZA_TRANSFERFT, &fvk.address_at(1u32, Scope::External).to_bech32m(Network::Mainnet), &Asset::ft(1, 1, &"thezeostoken".to_string()), &"".to_string())]
+        }];
+        assert!(matches!(
+            plan_transaction(Network::Kylin, &fvk, &mut notes, &descs),
+            Err(TransactionBuilderError::InvalidAddress{ err: AddressError::WrongNetwork{ expected: Network::Kylin, found: Network::Mainnet }, .. })
+        ));
+        assert_eq!(notes.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn proof_size()
+    {
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        let to = fvk.address_at(0u32, Scope::External).to_bech32m(Network::Kylin);
+        let mint = ZActionDesc::from_asset(ZA_MINTFT, &to, &Asset::ft(10000, 1397703940, &"eosio.token".to_string()), &"".to_string());
+        let pk = ProvingKey::build(Circuit::default(), K);
+        let tb = TransactionBuilder::new(Network::Kylin, 0);
+
+        // the estimate equals the size of actual proofs of one and two circuits
+        for circuits in [1, 2]
+        {
+            let descs = vec![EOSActionDesc{ action: exec_action(Network::Kylin), zaction_descs: vec![mint.clone(); circuits] }];
+            let plan = plan_transaction(Network::Kylin, &fvk, &mut Vec::new(), &descs).unwrap();
+            assert_eq!(plan.circuits(), circuits);
+            let estimate = plan.proof_size();
+            let (proof, _) = tb.build_planned_transaction(&pk, &sk, plan, &mut DummyContract, &Vec::new()).await.unwrap();
+            assert_eq!(proof.unwrap().as_ref().len(), estimate);
+        }
+    }
 }
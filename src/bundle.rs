@@ -1,17 +1,31 @@
 //! Structs related to bundles of Orchard actions.
 
 use rand::RngCore;
-use rustzeos::halo2::{Proof, ProvingKey};
+use rustzeos::halo2::{Proof, ProvingKey, VerifyingKey, Instance as ConcreteInstance};
+#[cfg(feature = "multicore")]
+use rayon::prelude::*;
 use crate::{
     action::{RawZAction, ZAction, ZA_MINTAUTH, ZA_MINTFT, ZA_MINTNFT, ZA_BURNAUTH},
     circuit::Instance,
-    keys::SpendValidatingKey,
     note::{Note, TransmittedNoteCiphertext},
     tree::MerklePath,
     value::NoteValue,
-    circuit::Circuit
+    circuit::Circuit,
+    prover::{CircuitWitness, RemoteProver, ProverError}
 };
-use halo2_proofs::circuit::Value;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum VerificationError
+{
+    #[error("no zactions to verify (there is no circuit for ZA_MINTAUTH)")]
+    NoCircuits,
+    #[error("the public inputs do not match the circuit")]
+    InvalidInstances,
+    #[error("invalid proof")]
+    InvalidProof,
+    #[error("transaction {index}: {error}")]
+    InvalidTransaction{ index: usize, error: Box<VerificationError> }
+}
 
 /// A bundle of actions to be applied to the ledger.
 #[derive(Debug, Clone)]
@@ -51,14 +65,14 @@ impl Bundle
         v
     }
 
-    /// Calculates and returns the proof for this bundle
-    pub fn proof<R: RngCore>(
+    /// Returns the witnesses and instances of all circuits of this bundle (there is no circuit for ZA_MINTAUTH
+    /// actions). Missing notes and merkle paths are filled with dummies.
+    pub fn witnesses<R: RngCore>(
         &self,
-        pk: &ProvingKey,
         mut rng: R
-    ) -> Result<(Proof, Vec<Circuit>, Vec<Instance>), halo2_proofs::plonk::Error>
+    ) -> (Vec<CircuitWitness>, Vec<Instance>)
     {
-        let mut circuits: Vec<Circuit> = Vec::new();
+        let mut witnesses: Vec<CircuitWitness> = Vec::new();
         let mut instances: Vec<Instance> = Vec::new();
 
         self.0.iter().for_each(|a| {
@@ -67,7 +81,6 @@ impl Bundle
             if a.za_type() != ZA_MINTAUTH
             {
                 let ins = a.zaction().instance();
-                instances.push(ins.clone());
 
                 let (_dummy_sk, dummy_fvk, dummy_note) = Note::dummy(&mut rng, None, Some(NoteValue::zero()));
                 let path = a.auth_path_a().get_or_insert(&MerklePath::dummy(&mut rng)).clone();
@@ -75,61 +88,108 @@ impl Bundle
                 let note_a = *a.note_a().get_or_insert(dummy_note);
                 let note_b = *a.note_b().get_or_insert(dummy_note);
                 let note_c = *a.note_c().get_or_insert(dummy_note);
-                
-                let ak: SpendValidatingKey = fvk.clone().into();
-                let nk = *fvk.nk();
-                // if this fails the spending authority (derived from fvk) for note a is wrong:
-                // note_a's address was not derived from this fvk
-                let rivk = fvk.rivk(fvk.scope_for_address(&note_a.recipient()).unwrap());
-                
-
-                circuits.push(Circuit {
-                    path: Value::known(path.auth_path()),
-                    pos: Value::known(path.position()),
-                    g_d_a: Value::known(note_a.recipient().g_d()),
-                    pk_d_a: Value::known(*note_a.recipient().pk_d()),
-                    d1_a: Value::known(note_a.d1()),
-                    d2_a: Value::known(note_a.d2()),
-                    rho_a: Value::known(note_a.rho()),
-                    psi_a: Value::known(note_a.rseed().psi(&note_a.rho())),
-                    rcm_a: Value::known(note_a.rseed().rcm(&note_a.rho())),
-                    cm_a: Value::known(note_a.commitment()),
-                    alpha: Value::known(a.alpha_a()),
-                    ak: Value::known(ak),
-                    nk: Value::known(nk),
-                    rivk: Value::known(rivk),
-                    g_d_b: Value::known(note_b.recipient().g_d()),
-                    pk_d_b: Value::known(*note_b.recipient().pk_d()),
-                    d1_b: Value::known(note_b.d1()),
-                    d2_b: Value::known(note_b.d2()),
-                    sc_b: Value::known(note_b.sc()),
-                    rho_b: Value::known(note_b.rho()),
-                    psi_b: Value::known(note_b.rseed().psi(&note_b.rho())),
-                    rcm_b: Value::known(note_b.rseed().rcm(&note_b.rho())),
-                    acc_b: Value::known(ins.accb),
-                    g_d_c: Value::known(note_c.recipient().g_d()),
-                    pk_d_c: Value::known(*note_c.recipient().pk_d()),
-                    d1_c: Value::known(note_c.d1()),
-                    psi_c: Value::known(note_c.rseed().psi(&note_c.rho())),
-                    rcm_c: Value::known(note_c.rseed().rcm(&note_c.rho())),
-                    acc_c: Value::known(ins.accc),
-                });
+
+                witnesses.push(CircuitWitness::from_parts(path, &fvk, a.alpha_a(), note_a, note_b, note_c, ins.accb, ins.accc));
+                instances.push(ins);
             }
         });
 
-        assert!(circuits.len() > 0, "bundle must contain more than just ZA_MINTAUTH");
+        assert!(witnesses.len() > 0, "bundle must contain more than just ZA_MINTAUTH");
+        (witnesses, instances)
+    }
+
+    /// Calculates and returns the proof for this bundle. Fails with 'ProverError::InvalidWitness' if the spending
+    /// authority (derived from fvk) for note a is wrong, i.e. note_a's address was not derived from this fvk.
+    pub fn proof<R: RngCore>(
+        &self,
+        pk: &ProvingKey,
+        mut rng: R
+    ) -> Result<(Proof, Vec<Circuit>, Vec<Instance>), ProverError>
+    {
+        let (witnesses, instances) = self.witnesses(&mut rng);
+        let circuits = witnesses.iter().map(|w| w.to_circuit()).collect::<Result<Vec<Circuit>, _>>()?;
         Ok((Proof::create(&pk, &circuits, &instances, rng)?, circuits, instances))
     }
 
+    /// Verifies 'proof' against the public inputs of all zactions of this bundle
+    pub fn verify(
+        &self,
+        proof: &Proof,
+        vk: &VerifyingKey
+    ) -> Result<(), VerificationError>
+    {
+        verify_zactions(proof.as_ref(), &self.zactions(), vk)
+    }
+
     /// Prepares a bundle for private transaction by calculating proof, the list of zactions and the encrypted note data
     pub fn prepare<R: RngCore>(
         &self,
         pk: &ProvingKey,
         mut rng: R
-    ) -> Result<((Proof, Vec<Circuit>, Vec<Instance>), Vec<ZAction>, Vec<TransmittedNoteCiphertext>), halo2_proofs::plonk::Error>
+    ) -> Result<((Proof, Vec<Circuit>, Vec<Instance>), Vec<ZAction>, Vec<TransmittedNoteCiphertext>), ProverError>
     {
         Ok((self.proof(pk, &mut rng)?, self.zactions(), self.encrypted_notes(&mut rng)))
     }
+
+    /// Prepares a bundle for private transaction like 'prepare' but sends the witnesses of all circuits to the
+    /// 'prover' service instead of proving locally
+    pub async fn prepare_remote<R: RngCore>(
+        &self,
+        prover: &RemoteProver,
+        mut rng: R
+    ) -> Result<(Proof, Vec<ZAction>, Vec<TransmittedNoteCiphertext>), ProverError>
+    {
+        let (witnesses, instances) = self.witnesses(&mut rng);
+        let proof = prover.prove(witnesses, &instances).await?;
+        Ok((proof, self.zactions(), self.encrypted_notes(&mut rng)))
+    }
+}
+
+/// Returns the instances of all 'zactions' in the order the circuits of the proof are expected (there is no circuit
+/// for ZA_MINTAUTH actions, see: Bundle::witnesses)
+pub fn instances(zactions: &[ZAction]) -> Vec<Instance>
+{
+    zactions.iter().filter(|za| za.za_type() != ZA_MINTAUTH).map(|za| za.instance()).collect()
+}
+
+/// Verifies the serialized 'proof' of a transaction against the public inputs of its 'zactions'. This only requires
+/// the public data of a transaction which allows backend services and validators to verify proofs without the full
+/// bundle.
+pub fn verify_zactions(
+    proof: &[u8],
+    zactions: &[ZAction],
+    vk: &VerifyingKey
+) -> Result<(), VerificationError>
+{
+    let instances: Vec<_> = instances(zactions).iter().map(|i| i.to_halo2_instance_vec()).collect();
+    if instances.is_empty()
+    {
+        return Err(VerificationError::NoCircuits);
+    }
+    Proof::new(proof.to_vec()).verify(vk, &instances).map_err(|e| match e {
+        halo2_proofs::plonk::Error::InvalidInstances => VerificationError::InvalidInstances,
+        _ => VerificationError::InvalidProof
+    })
+}
+
+/// Verifies the proofs of many transactions each given by its serialized proof and its zactions (see: verify_zactions).
+/// Each proof is verified on its own (this is not batch verification), in parallel if the 'multicore' feature is
+/// enabled. Returns the index of the first invalid transaction together with its error in case of an error.
+pub fn verify_transactions(
+    txs: &[(Vec<u8>, Vec<ZAction>)],
+    vk: &VerifyingKey
+) -> Result<(), VerificationError>
+{
+    #[cfg(feature = "multicore")]
+    let iter = txs.par_iter();
+    #[cfg(not(feature = "multicore"))]
+    let iter = txs.iter();
+
+    let results: Vec<Result<(), VerificationError>> = iter.map(|(proof, zactions)| verify_zactions(proof, zactions, vk)).collect();
+    match results.into_iter().enumerate().find_map(|(index, res)| res.err().map(|error| (index, error))) {
+        Some((index, error)) => Err(VerificationError::InvalidTransaction{ index, error: Box::new(error) }),
+        None => Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -137,7 +197,8 @@ mod tests
 {
     use rand::rngs::OsRng;
     use zeos_verifier::verify_zeos_proof;
-    use super::{Bundle, RawZAction};
+    use super::{Bundle, RawZAction, VerificationError, verify_zactions, verify_transactions};
+    use crate::prover::ProverError;
     use crate::{
         keys::{
             SpendingKey, FullViewingKey, Scope::External, PreparedIncomingViewingKey
@@ -377,6 +438,10 @@ mod tests
             Some(decrypted_note) => assert_eq!(decrypted_note, note8),
             None => panic!("Output8 recovery failed"),
         }
+
+        // a note spent by a foreign spending authority is reported instead of panicking
+        let action = RawZAction::from_parts(ZA_TRANSFERFT, &fvk_bob, Some(path), Some(note1), Some(note4), Some(note5), "".to_string(), rng);
+        assert!(matches!(Bundle::from_parts(vec![action]).proof(&pk, rng), Err(ProverError::InvalidWitness(_))));
     }
 
     #[test]
@@ -645,6 +710,18 @@ mod tests
         let vk = VerifyingKey::build(Circuit::default(), K);
         assert!(proof.verify(&vk, &instances).is_ok());
 
+        // verify proof using the public zactions only
+        assert!(bundle.verify(&proof, &vk).is_ok());
+        assert!(verify_zactions(proof.as_ref(), &zactions, &vk).is_ok());
+        let mut reordered = zactions.clone();
+        reordered.swap(0, 1);
+        assert_eq!(verify_zactions(proof.as_ref(), &reordered, &vk), Err(VerificationError::InvalidProof));
+        let txs = vec![(proof.as_ref().to_vec(), zactions.clone()), (proof.as_ref().to_vec(), reordered)];
+        assert_eq!(verify_transactions(&txs[0..1], &vk), Ok(()));
+        assert_eq!(verify_transactions(&txs, &vk), Err(VerificationError::InvalidTransaction{ index: 1, error: Box::new(VerificationError::InvalidProof) }));
+        let txs = vec![(proof.as_ref().to_vec(), zactions.clone()), (proof.as_ref().to_vec(), Vec::new())];
+        assert_eq!(verify_transactions(&txs, &vk), Err(VerificationError::InvalidTransaction{ index: 1, error: Box::new(VerificationError::NoCircuits) }));
+
         // verify proof using zeos verifier
         const ZI_SIZE: usize = 32 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 32 + 32 + 8 + 8;
         let mut inputs_str = "".to_string();
@@ -652,7 +729,7 @@ mod tests
         {
             if za.za_type() != ZA_MINTAUTH
             {
-                let za_str: String = za.serialize_eos().chars().skip(16).take(ZI_SIZE*2).collect();
+                let za_str: String = za.serialize_eos().unwrap().chars().skip(16).take(ZI_SIZE*2).collect();
                 inputs_str.push_str(&za_str);
             }
         }
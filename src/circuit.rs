@@ -916,9 +916,9 @@ impl Instance {
     ///
     /// This API can be used in combination with [`Proof::verify`] to build verification
     /// pipelines for many proofs, where you don't want to pass around the full bundle.
-    /// Use [`Bundle::verify_proof`] instead if you have the full bundle.
+    /// Use [`Bundle::verify`] instead if you have the full bundle.
     ///
-    /// [`Bundle::verify_proof`]: crate::Bundle::verify_proof
+    /// [`Bundle::verify`]: crate::Bundle::verify
     pub fn from_parts(
         anchor: Anchor,
         nf: Nullifier,
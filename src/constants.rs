@@ -6,8 +6,15 @@ pub mod util;
 pub use fixed_bases::{NullifierK, OrchardFixedBases, OrchardFixedBasesFull, ValueCommitV, H};
 pub use sinsemilla::{OrchardCommitDomains, OrchardHashDomains};
 
-/// $\mathsf{MerkleDepth^{Orchard}}$
-pub(crate) const MERKLE_DEPTH_ORCHARD: usize = 4;
+// $\mathsf{MerkleDepth^{Orchard}}$ is selected per deployment at build time (ZEOS_MERKLE_DEPTH, see: build.rs)
+include!(concat!(env!("OUT_DIR"), "/merkle_depth.rs"));
+
+// helper macros for the array layout of the merkle trees of the ZEOS token contract (see: 'tree::ContractTree'): the
+// nodes of a tree of depth 'd' are stored as array, a full tree is followed by the next one
+macro_rules! MT_ARR_LEAF_ROW_OFFSET     { ($d:expr) => { (1u64 << ($d)) - 1 }; }
+macro_rules! MT_ARR_FULL_TREE_OFFSET    { ($d:expr) => { (1u64 << (($d) + 1)) - 1 }; }
+macro_rules! MT_NUM_LEAVES              { ($d:expr) => { 1u64 << ($d) }; }
+pub(crate) use {MT_ARR_FULL_TREE_OFFSET, MT_ARR_LEAF_ROW_OFFSET, MT_NUM_LEAVES};
 
 /// The Pallas scalar field modulus is $q = 2^{254} + \mathsf{t_q}$.
 /// <https://github.com/zcash/pasta>
@@ -3,44 +3,53 @@
 use crate::tree::{MerkleHashOrchard, MerklePath};
 use nonempty::NonEmpty;
 use pasta_curves::Fp;
-use crate::note::{Note, TransmittedNoteCiphertext, Nullifier, RandomSeed, ExtractedNoteCommitment};
+use crate::note::{Note, CompactNoteCiphertext, TransmittedNoteCiphertext, Nullifier, RandomSeed, ExtractedNoteCommitment};
 use crate::note_encryption::{ENC_CIPHERTEXT_SIZE, try_note_decryption, try_output_recovery_with_ovk};
+use crate::note_encryption::{COMPACT_NOTE_SIZE, try_compact_note_decryption, try_compact_output_recovery_with_ovk};
 use crate::note_encryption::OUT_CIPHERTEXT_SIZE;
-use crate::tree::EMPTY_ROOTS;
+use crate::tree::{EMPTY_ROOTS, TreeDepthError, check_tree_depth};
 use crate::value::NoteValue;
 use crate::builder::HasMerkleTree;
 use crate::keys::PreparedIncomingViewingKey;
 use crate::keys::OutgoingViewingKey;
 use crate::address::Address;
-use crate::constants::MERKLE_DEPTH_ORCHARD;
+use crate::constants::{MERKLE_DEPTH_ORCHARD, MT_ARR_FULL_TREE_OFFSET, MT_ARR_LEAF_ROW_OFFSET, MT_NUM_LEAVES};
 use crate::eosio::value_to_name;
+use crate::network::Network;
 extern crate console_error_panic_hook;
 extern crate serde_json;
 use serde::ser::{Serialize, Serializer, SerializeStruct};
 use serde::de::{self, Deserialize, Deserializer, Visitor, SeqAccess, MapAccess};
 use std::fmt;
 use std::collections::HashMap;
+use crate::http;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use wasm_bindgen_futures::JsFuture;
-use web_sys::{Request, RequestInit, RequestMode, Response, FormData};
 
 
-// helper macros for merkle tree operations
-macro_rules! MT_ARR_LEAF_ROW_OFFSET     { ($d:expr) => { (1 << ($d)) - 1 }; }
-macro_rules! MT_ARR_FULL_TREE_OFFSET    { ($d:expr) => { (1 << (($d) + 1)) - 1 }; }
-macro_rules! MT_NUM_LEAVES              { ($d:expr) => { 1 << ($d) }; }
+/// The name of the (vRAM) table of the merkle tree of note commitments
+pub const MERKLE_TABLE: &str = "mteosram";
+/// The name of the (vRAM) table of encrypted notes
+pub const NOTES_TABLE: &str = "noteseosram";
+/// The name of the singleton table of the global state
+pub const GLOBAL_TABLE: &str = "global";
+/// The name of the table of nullifiers of spent notes
+pub const NULLIFIERS_TABLE: &str = "nullifiers";
+/// The name of the table of merkle roots
+pub const ROOTS_TABLE: &str = "roots";
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransmittedNoteCiphertextEx
 {
     /// This notes global ID.
-    id: u64,
+    pub(crate) id: u64,
     /// The current EOS block number when this note was added to the 
     /// global list of encrypted notes
-    block_number: u64,
+    pub(crate) block_number: u64,
+    /// The number of leaves of the merkle tree when this note was added
+    #[serde(default)]
+    pub(crate) leaf_index: u64,
     /// The actual encrypted note
-    encrypted_note: TransmittedNoteCiphertext
+    pub(crate) encrypted_note: TransmittedNoteCiphertext
 }
 
 impl Serialize for TransmittedNoteCiphertext
@@ -58,6 +67,70 @@ impl Serialize for TransmittedNoteCiphertext
     }
 }
 
+impl Serialize for CompactNoteCiphertext
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("CompactNoteCiphertext", 3)?;
+        state.serialize_field("epk_bytes", &hex::encode(self.epk_bytes))?;
+        state.serialize_field("enc_ciphertext", &hex::encode(self.enc_ciphertext))?;
+        state.serialize_field("out_ciphertext", &hex::encode(self.out_ciphertext))?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for CompactNoteCiphertext
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct HexFields
+        {
+            epk_bytes: String,
+            enc_ciphertext: String,
+            out_ciphertext: String
+        }
+
+        let h = HexFields::deserialize(deserializer)?;
+        let mut epk_bytes = [0; 32];
+        let mut enc_ciphertext = [0; COMPACT_NOTE_SIZE];
+        let mut out_ciphertext = [0; OUT_CIPHERTEXT_SIZE];
+        hex::decode_to_slice(h.epk_bytes, &mut epk_bytes).map_err(de::Error::custom)?;
+        hex::decode_to_slice(h.enc_ciphertext, &mut enc_ciphertext).map_err(de::Error::custom)?;
+        hex::decode_to_slice(h.out_ciphertext, &mut out_ciphertext).map_err(de::Error::custom)?;
+        Ok(CompactNoteCiphertext{ epk_bytes, enc_ciphertext, out_ciphertext })
+    }
+}
+
+impl<'de> Deserialize<'de> for TransmittedNoteCiphertext
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct HexFields
+        {
+            epk_bytes: String,
+            enc_ciphertext: String,
+            out_ciphertext: String
+        }
+
+        let h = HexFields::deserialize(deserializer)?;
+        let mut epk_bytes = [0; 32];
+        let mut enc_ciphertext = [0; ENC_CIPHERTEXT_SIZE];
+        let mut out_ciphertext = [0; OUT_CIPHERTEXT_SIZE];
+        hex::decode_to_slice(h.epk_bytes, &mut epk_bytes).map_err(de::Error::custom)?;
+        hex::decode_to_slice(h.enc_ciphertext, &mut enc_ciphertext).map_err(de::Error::custom)?;
+        hex::decode_to_slice(h.out_ciphertext, &mut out_ciphertext).map_err(de::Error::custom)?;
+        Ok(TransmittedNoteCiphertext{ epk_bytes, enc_ciphertext, out_ciphertext })
+    }
+}
+
 impl TransmittedNoteCiphertextEx
 {
     /// Try to decrypt note as receiver
@@ -100,6 +173,70 @@ impl TransmittedNoteCiphertextEx
 
 }
 
+/// The compact representation of a 'TransmittedNoteCiphertextEx' for light wallets (see: 'CompactNoteCiphertext')
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactNoteCiphertextEx
+{
+    /// This notes global ID.
+    pub(crate) id: u64,
+    /// The current EOS block number when this note was added to the 
+    /// global list of encrypted notes
+    pub(crate) block_number: u64,
+    /// The number of leaves of the merkle tree when this note was added
+    #[serde(default)]
+    pub(crate) leaf_index: u64,
+    /// The compact encrypted note
+    pub(crate) encrypted_note: CompactNoteCiphertext
+}
+
+impl From<&TransmittedNoteCiphertextEx> for CompactNoteCiphertextEx
+{
+    fn from(n: &TransmittedNoteCiphertextEx) -> Self
+    {
+        CompactNoteCiphertextEx{
+            id: n.id,
+            block_number: n.block_number,
+            leaf_index: n.leaf_index,
+            encrypted_note: CompactNoteCiphertext::from(&n.encrypted_note)
+        }
+    }
+}
+
+impl CompactNoteCiphertextEx
+{
+    /// Returns this notes global ID
+    pub fn id(&self) -> u64
+    {
+        self.id
+    }
+
+    /// Try to decrypt note as receiver (without memo and unauthenticated, see: 'try_compact_note_decryption')
+    pub fn try_decrypt_as_receiver(
+        &self,
+        ivk: &PreparedIncomingViewingKey
+    ) -> Option<NoteEx>
+    {
+        try_compact_note_decryption(ivk, &self.encrypted_note).map(|note| NoteEx {
+            id: self.id,
+            block_number: self.block_number,
+            note
+        })
+    }
+
+    /// Try to decrypt note as sender (without memo and unauthenticated, see: 'try_compact_output_recovery_with_ovk')
+    pub fn try_decrypt_as_sender(
+        &self,
+        ovk: &OutgoingViewingKey
+    ) -> Option<NoteEx>
+    {
+        try_compact_output_recovery_with_ovk(ovk, &self.encrypted_note).map(|note| NoteEx {
+            id: self.id,
+            block_number: self.block_number,
+            note
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NoteEx
 {
@@ -398,7 +535,7 @@ pub struct EOSGetTableRowsResponse
 
 // Required to de-/serialize u64 <-> String for use in JSON strings
 // From: https://github.com/serde-rs/json/issues/329#issuecomment-305608405
-mod string
+pub(crate) mod string
 {
     use std::fmt::Display;
     use std::str::FromStr;
@@ -443,11 +580,43 @@ pub struct Global
     pub tree_depth: u64,
 }
 
+impl Global
+{
+    /// Returns true if this is the state of a contract without row in the global table (a fresh deployment, see:
+    /// 'TokenContract::get_global_state'): it has neither notes nor a merkle tree yet
+    pub fn is_empty(&self) -> bool
+    {
+        self.note_count == 0 && self.leaf_count == 0 && self.tree_depth == 0
+    }
+
+    /// Checks that the contract's merkle tree depth matches the depth of the circuit (see: check_tree_depth). The
+    /// depth of a contract without merkle tree (see: 'Global::is_empty') is not known yet and not checked.
+    pub fn check_tree_depth(&self) -> Result<(), TreeDepthError>
+    {
+        if self.is_empty()
+        {
+            return Ok(());
+        }
+        check_tree_depth(self.tree_depth)
+    }
+}
+
+/// The subset of the chain info returned by the 'get_info' endpoint of the chain API which is required to tell
+/// reversible from irreversible blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainInfo
+{
+    pub chain_id: String,
+    pub head_block_num: u64,
+    pub last_irreversible_block_num: u64,
+}
+
 /// Represents the ZEOS token contract
 /// See also: thezeostoken
 #[derive(Debug)]
 pub struct TokenContract
 {
+    network: Network,
     endpoints: NonEmpty<String>,
     node_buffer: HashMap<u64, MerkleHashOrchard>
 }
@@ -458,7 +627,7 @@ impl HasMerkleTree for TokenContract
         &mut self,
         array_index: u64,
         leaf_count: u64,
-    ) -> MerklePath
+    ) -> Result<MerklePath, String>
     {
         // only merkle trees with depth up to 32 are supported by the circuit design
         assert!(MERKLE_DEPTH_ORCHARD <= 32);
@@ -500,7 +669,7 @@ impl HasMerkleTree for TokenContract
                 let v = if sis_idx > last_node_in_row {
                     EMPTY_ROOTS[d]
                 } else {
-                    self.get_merkle_hash(sis_idx_tos).await.unwrap()
+                    self.get_merkle_hash(sis_idx_tos).await?.ok_or_else(|| format!("merkle node {} not found", sis_idx_tos))?
                 };
                 self.node_buffer.insert(sis_idx_tos, v);
                 v
@@ -512,13 +681,13 @@ impl HasMerkleTree for TokenContract
         }
 
         assert_eq!(auth_path.len(), MERKLE_DEPTH_ORCHARD);
-        MerklePath::from_parts(position, auth_path.try_into().unwrap())
+        Ok(MerklePath::from_parts(position, auth_path.try_into().unwrap()))
     }
 
     async fn get_merkle_index(
         &self,
         hash: ExtractedNoteCommitment
-    ) -> Option<u64>
+    ) -> Result<Option<u64>, String>
     {
         let hash_str = format!("{}{}{}{}",
             hex::encode(hash.inner().0[0].to_le_bytes()),
@@ -528,9 +697,9 @@ impl HasMerkleTree for TokenContract
         );
         // prepare POST request to fetch from EOSIO multiindex table
         let payload = EOSGetTableRowsPayload{
-            code: "thezeostoken".to_string(),
-            table: "mteosram".to_string(),
-            scope: "thezeostoken".to_string(),
+            code: self.network.token_contract().to_string(),
+            table: MERKLE_TABLE.to_string(),
+            scope: self.network.token_contract().to_string(),
             index_position: "secondary".to_string(),
             key_type: "sha256".to_string(),
             encode_type: "hex".to_string(),
@@ -541,34 +710,50 @@ impl HasMerkleTree for TokenContract
             show_payer: false
         };
 
-        let res = self.get_table_rows(&mut payload.clone()).await;
+        let res = self.get_table_rows(&mut payload.clone()).await?;
         if res.rows.len() == 0
         {
-            return None;
+            return Ok(None);
         }
         // extract serialized node data and parse 'index' (ignore 'MerkleHash')
         let mut arr = [0; 40];
         assert!(hex::decode_to_slice(res.rows[0].clone(), &mut arr).is_ok());
         let index = u64::from_le_bytes(arr[0..8].try_into().unwrap());
 
-        Some(index)
+        Ok(Some(index))
     }
 }
 
 impl TokenContract
 {
-    pub fn new(endpoints: NonEmpty<String>) -> Self
+    /// Creates the token contract of 'network' which is queried using 'endpoints'
+    pub fn new(network: Network, endpoints: NonEmpty<String>) -> Self
     {
         TokenContract {
+            network,
             endpoints,
             node_buffer: HashMap::new()
         }
     }
 
+    /// Creates the token contract of 'network' which is queried using the default endpoints of 'network'
+    pub fn from_network(network: Network) -> Self
+    {
+        Self::new(network, NonEmpty::from_vec(network.endpoints()).expect("network without endpoints"))
+    }
+
+    /// Returns the network of this token contract
+    pub fn network(&self) -> Network
+    {
+        self.network
+    }
+
+    /// Fetches the rows of the table described by 'payload' (following 'more' if required). Fails if the request fails
+    /// or the response is not a valid 'get_table_rows' response.
     pub async fn get_table_rows(
         &self,
         payload: &mut EOSGetTableRowsPayload
-    ) -> EOSGetTableRowsResponse
+    ) -> Result<EOSGetTableRowsResponse, String>
     {
         let mut res = EOSGetTableRowsResponse{
             rows: Vec::new(),
@@ -577,34 +762,13 @@ impl TokenContract
         };
         loop
         {
-            // prepare POST request to fetch from EOSIO multiindex table
-            let mut opts = RequestInit::new();
-            opts.method("POST");
-            opts.mode(RequestMode::Cors);
-            opts.body(Some(&JsValue::from_str(&serde_json::to_string(payload).unwrap())));
-
+            // send POST request to fetch from EOSIO multiindex table
             let url = format!("{}/v1/chain/get_table_rows", self.endpoints[0]);
-            let request = Request::new_with_str_and_init(&url, &opts).unwrap();
-            request
-                .headers()
-                .set("Accept", "application/json").unwrap();
-            
-            // send http request using browser window's fetch
-            let window = web_sys::window().unwrap();
-            let resp_value = JsFuture::from(window.fetch_with_request(&request)).await.unwrap();
-
-            // `resp_value` is a `Response` object.
-            assert!(resp_value.is_instance_of::<Response>());
-            let resp: Response = resp_value.dyn_into().unwrap();
-            let str = resp.text()
-                .map(JsFuture::from).unwrap()
-                .await.unwrap()
-                .as_string()
-                .expect("fetch: Response expected `String` after .text()");
+            let str = http::post_json(&url, &serde_json::to_string(payload).unwrap()).await?;
 
             // str has the following format:
             // {"rows":["", "", ...], "more": false, "next_key": ""}
-            let tmp: EOSGetTableRowsResponse = serde_json::from_str(&str).unwrap();
+            let tmp: EOSGetTableRowsResponse = serde_json::from_str(&str).map_err(|e| format!("get_table_rows: {}", e))?;
             res.rows.extend(tmp.rows);
 
             // if there's more update payload struct and repeat
@@ -628,19 +792,20 @@ impl TokenContract
             }
         }
         
-        res
+        Ok(res)
     }
 
+    /// Fetches the merkle tree node at array index 'index'. Returns 'None' if the node doesn't exist.
     pub async fn get_merkle_hash(
         &self,
         index: u64
-    ) -> Option<MerkleHashOrchard>
+    ) -> Result<Option<MerkleHashOrchard>, String>
     {
         // prepare POST request to fetch from EOSIO multiindex table
         let payload = EOSGetTableRowsPayload{
-            code: "thezeostoken".to_string(),
-            table: "mteosram".to_string(),
-            scope: "thezeostoken".to_string(),
+            code: self.network.token_contract().to_string(),
+            table: MERKLE_TABLE.to_string(),
+            scope: self.network.token_contract().to_string(),
             index_position: "primary".to_string(),
             key_type: "uint64_t".to_string(),
             encode_type: "hex".to_string(),
@@ -651,10 +816,10 @@ impl TokenContract
             show_payer: false
         };
         
-        let res = self.get_table_rows(&mut payload.clone()).await;
+        let res = self.get_table_rows(&mut payload.clone()).await?;
         if res.rows.len() == 0
         {
-            return None;
+            return Ok(None);
         }
         // extract serialized node data and parse 'MerkleHash' (ignore 'index')
         let mut arr = [0; 40];
@@ -666,72 +831,63 @@ impl TokenContract
             u64::from_le_bytes(arr[32..40].try_into().unwrap())
         ]));
         
-        Some(value)
+        Ok(Some(value))
     }
 
-    pub async fn get_global_state(&self) -> Global
+    /// Returns the chain info (head block and last irreversible block) of the chain the contract is deployed on
+    pub async fn get_info(&self) -> Result<ChainInfo, String>
     {
-        // prepare POST request to fetch from EOSIO singleton table
-        let mut opts = RequestInit::new();
-        opts.method("POST");
-        opts.mode(RequestMode::Cors);
-        opts.body(Some(&JsValue::from_str("{\"code\":\"thezeostoken\",\"table\":\"global\",\"scope\":\"thezeostoken\"}")));
+        let url = format!("{}/v1/chain/get_info", self.endpoints[0]);
+        let str = http::post_json(&url, "{}").await?;
+        serde_json::from_str(&str).map_err(|e| e.to_string())
+    }
 
+    /// Fetches the global state of the contract. A fresh deployment has no row in the global table yet: its state is
+    /// empty (see: 'Global::is_empty').
+    pub async fn get_global_state(&self) -> Result<Global, String>
+    {
+        // send POST request to fetch from EOSIO singleton table
         let url = format!("{}/v1/chain/get_table_rows", self.endpoints[0]);
-        let request = Request::new_with_str_and_init(&url, &opts).unwrap();
-        request
-            .headers()
-            .set("Accept", "application/json").unwrap();
-
-        // send http request using browser window's fetch
-        let window = web_sys::window().unwrap();
-        let resp_value = JsFuture::from(window.fetch_with_request(&request)).await.unwrap();
-
-        // `resp_value` is a `Response` object.
-        assert!(resp_value.is_instance_of::<Response>());
-        let resp: Response = resp_value.dyn_into().unwrap();
-        let str = resp.text()
-            .map(JsFuture::from).unwrap()
-            .await.unwrap()
-            .as_string()
-            .expect("fetch: Response expected `String` after .text()");
-
-        let res: EOSGetTableRowsResponse = serde_json::from_str(&str).unwrap();
+        let body = format!("{{\"code\":\"{0}\",\"table\":\"{1}\",\"scope\":\"{0}\"}}", self.network.token_contract(), GLOBAL_TABLE);
+        let str = http::post_json(&url, &body).await?;
+
+        let res: EOSGetTableRowsResponse = serde_json::from_str(&str).map_err(|e| format!("get_table_rows: {}", e))?;
         if res.rows.is_empty()
         {
-            return Global{
+            return Ok(Global{
                 note_count: 0,
                 leaf_count: 0,
                 tree_depth: 0,
-            }
+            })
         }
 
         // parse serialized EOS data
         let mut arr = [0; 8+8+8];
         let str: String = res.rows[0].clone().chars().take((8+8+8)*2).collect();
-        assert!(hex::decode_to_slice(str, &mut arr).is_ok());
+        hex::decode_to_slice(str, &mut arr).map_err(|e| format!("invalid global state: {}", e))?;
         let note_count = u64::from_le_bytes(arr[0..8].try_into().unwrap());
         let leaf_count = u64::from_le_bytes(arr[8..16].try_into().unwrap());
         let tree_depth = u64::from_le_bytes(arr[16..24].try_into().unwrap());
         
-        Global{
+        Ok(Global{
             note_count,
             leaf_count,
             tree_depth,
-        }
+        })
     }
 
+    /// Fetches the encrypted notes with ids 'from' to 'to' (inclusive)
     pub async fn get_encrypted_notes(
         &self,
         from: u64,
         to: u64
-    ) -> Vec<TransmittedNoteCiphertextEx>
+    ) -> Result<Vec<TransmittedNoteCiphertextEx>, String>
     {
         // prepare POST request to fetch from EOSIO multiindex table
         let payload = EOSGetTableRowsPayload{
-            code: "thezeostoken".to_string(),
-            table: "noteseosram".to_string(),
-            scope: "thezeostoken".to_string(),
+            code: self.network.token_contract().to_string(),
+            table: NOTES_TABLE.to_string(),
+            scope: self.network.token_contract().to_string(),
             index_position: "primary".to_string(),
             key_type: "uint64_t".to_string(),
             encode_type: "dec".to_string(),
@@ -741,16 +897,17 @@ impl TokenContract
             reverse: false,
             show_payer: false
         };
-        let res = self.get_table_rows(&mut payload.clone()).await;
+        let res = self.get_table_rows(&mut payload.clone()).await?;
         
         let mut v = Vec::new();
         for str in res.rows
         {
-            // parse serialized EOS data
-            let mut arr = [0; 8+8+1+32*2+2+ENC_CIPHERTEXT_SIZE*2+2+OUT_CIPHERTEXT_SIZE*2];
+            // parse serialized EOS data (id, block number, leaf index, epk, encrypted note, encrypted outgoing plaintext)
+            let mut arr = [0; 8+8+8+1+32*2+2+ENC_CIPHERTEXT_SIZE*2+2+OUT_CIPHERTEXT_SIZE*2];
             assert!(hex::decode_to_slice(str, &mut arr).is_ok());
             let id = u64::from_le_bytes(arr[0..8].try_into().unwrap());
             let block_number = u64::from_le_bytes(arr[8..16].try_into().unwrap());
+            let leaf_index = u64::from_le_bytes(arr[16..24].try_into().unwrap());
             // skip reading string sizes since we already know the exact length
             let epk_bytes: [u8; 32*2] = arr[24+1..24+1+32*2].try_into().unwrap();
             let enc_ciphertext: [u8; ENC_CIPHERTEXT_SIZE*2] = arr[24+1+32*2+2..24+1+32*2+2+ENC_CIPHERTEXT_SIZE*2].try_into().unwrap();
@@ -767,6 +924,7 @@ impl TokenContract
             v.push(TransmittedNoteCiphertextEx{
                 id,
                 block_number,
+                leaf_index,
                 encrypted_note: TransmittedNoteCiphertext{
                     epk_bytes,
                     enc_ciphertext,
@@ -774,59 +932,119 @@ impl TokenContract
                 }
             });
         } 
-        v
+        Ok(v)
     }
 
+    /// Fetches the nullifiers which have been added to the nullifiers table starting at id 'from'. Returns id, nullifier
+    /// and block number of each row.
+    pub async fn get_nullifiers(
+        &self,
+        from: u64
+    ) -> Result<Vec<(u64, [u8; 32], u64)>, String>
+    {
+        let payload = EOSGetTableRowsPayload{
+            code: self.network.token_contract().to_string(),
+            table: NULLIFIERS_TABLE.to_string(),
+            scope: self.network.token_contract().to_string(),
+            index_position: "primary".to_string(),
+            key_type: "uint64_t".to_string(),
+            encode_type: "hex".to_string(),
+            lower_bound: from.to_string(),
+            upper_bound: String::new(),
+            limit: 100,
+            reverse: false,
+            show_payer: false
+        };
+        let res = self.get_table_rows(&mut payload.clone()).await?;
+
+        let mut v = Vec::new();
+        for str in res.rows
+        {
+            // parse serialized EOS data (id, block number, nullifier)
+            let mut arr = [0; 8+8+32];
+            assert!(hex::decode_to_slice(str, &mut arr).is_ok());
+            let id = u64::from_le_bytes(arr[0..8].try_into().unwrap());
+            let block_number = u64::from_le_bytes(arr[8..16].try_into().unwrap());
+            v.push((id, arr[16..48].try_into().unwrap(), block_number));
+        }
+        Ok(v)
+    }
+
+    /// Fetches the merkle roots which have been added to the roots table starting at id 'from'. Returns id and root of
+    /// each row.
+    pub async fn get_roots(
+        &self,
+        from: u64
+    ) -> Result<Vec<(u64, MerkleHashOrchard)>, String>
+    {
+        let payload = EOSGetTableRowsPayload{
+            code: self.network.token_contract().to_string(),
+            table: ROOTS_TABLE.to_string(),
+            scope: self.network.token_contract().to_string(),
+            index_position: "primary".to_string(),
+            key_type: "uint64_t".to_string(),
+            encode_type: "hex".to_string(),
+            lower_bound: from.to_string(),
+            upper_bound: String::new(),
+            limit: 100,
+            reverse: false,
+            show_payer: false
+        };
+        let res = self.get_table_rows(&mut payload.clone()).await?;
+
+        let mut v = Vec::new();
+        for str in res.rows
+        {
+            // parse serialized EOS data (id, root) like the rows of the merkle tree table
+            let mut arr = [0; 40];
+            assert!(hex::decode_to_slice(str, &mut arr).is_ok());
+            let id = u64::from_le_bytes(arr[0..8].try_into().unwrap());
+            v.push((id, MerkleHashOrchard::from(Fp([
+                u64::from_le_bytes(arr[ 8..16].try_into().unwrap()),
+                u64::from_le_bytes(arr[16..24].try_into().unwrap()),
+                u64::from_le_bytes(arr[24..32].try_into().unwrap()),
+                u64::from_le_bytes(arr[32..40].try_into().unwrap())
+            ]))));
+        }
+        Ok(v)
+    }
+
+    /// Fetches the balance of 'symbol' of token contract 'code' of 'account'. Returns amount and precision.
     pub async fn get_currency_balance(
         &self,
         code: &String,
         account: &String,
         symbol: &String
-    ) -> (u64, u8)
+    ) -> Result<(u64, u8), String>
     {
-        // prepare POST request for this API call
-        let mut opts = RequestInit::new();
-        opts.method("POST");
-        opts.mode(RequestMode::Cors);
-        opts.body(Some(&JsValue::from_str(&format!("{{\"code\":\"{}\",\"account\":\"{}\",\"symbol\":\"{}\"}}", code, account, symbol))));
-
+        // send POST request for this API call
         let url = format!("{}/v1/chain/get_currency_balance", self.endpoints[0]);
-        let request = Request::new_with_str_and_init(&url, &opts).unwrap();
-        request
-            .headers()
-            .set("Accept", "application/json").unwrap();
-        
-        // send http request using browser window's fetch
-        let window = web_sys::window().unwrap();
-        let resp_value = JsFuture::from(window.fetch_with_request(&request)).await.unwrap();
-
-        // `resp_value` is a `Response` object.
-        assert!(resp_value.is_instance_of::<Response>());
-        let resp: Response = resp_value.dyn_into().unwrap();
-        let str = resp.text()
-            .map(JsFuture::from).unwrap()
-            .await.unwrap()
-            .as_string()
-            .expect("fetch: Response expected `String` after .text()");
+        let body = format!("{{\"code\":\"{}\",\"account\":\"{}\",\"symbol\":\"{}\"}}", code, account, symbol);
+        let str = http::post_json(&url, &body).await?;
 
         // str has the following format:
         // ["257.2000 SYM"] or []
-        if str == "[]" { return (0, 0); }
-        let str = str.chars().skip(2).take(str.len()-4).collect::<String>();
-        let dot = str.find('.');
-        let ws = str.find(' ').unwrap(); // must contain whitespace
-        let dec_len = if dot.is_some() { dot.unwrap() } else { ws };
-        let frac_len = if dot.is_some() { ws-1 - dot.unwrap() } else { 0 };
-        let dec = str.chars().take(dec_len).collect::<String>().parse::<u64>().unwrap() * 10_u64.pow(frac_len as u32);
-        let frac = if dot.is_some() { str.chars().skip(dot.unwrap()+1).take(frac_len).collect::<String>().parse::<u64>().unwrap() } else { 0 };
-        (dec + frac, frac_len as u8)
+        let balances: Vec<String> = serde_json::from_str(&str).map_err(|e| format!("get_currency_balance: {}", e))?;
+        let str = match balances.first() {
+            Some(str) => str,
+            None => return Ok((0, 0))
+        };
+        let invalid = || format!("get_currency_balance: invalid quantity '{}'", str);
+        let (quantity, _) = str.split_once(' ').ok_or_else(invalid)?; // must contain whitespace
+        let (dec, frac) = quantity.split_once('.').unwrap_or((quantity, ""));
+        let frac_len = frac.len();
+        let dec = dec.parse::<u64>().map_err(|_| invalid())?;
+        let frac = if frac.is_empty() { 0 } else { frac.parse::<u64>().map_err(|_| invalid())? };
+        let amount = dec.checked_mul(10_u64.pow(frac_len as u32)).and_then(|d| d.checked_add(frac)).ok_or_else(invalid)?;
+        Ok((amount, frac_len as u8))
     }
 
+    /// Fetches the NFTs of 'account' of NFT contract 'code'. Returns id and collection of each NFT.
     pub async fn get_nfts(
         &self,
         code: &String,
         account: &String
-    ) -> Vec<(u64, String)>
+    ) -> Result<Vec<(u64, String)>, String>
     {
         // prepare POST request to fetch from EOSIO multiindex table
         let payload = EOSGetTableRowsPayload{
@@ -842,7 +1060,7 @@ impl TokenContract
             reverse: false,
             show_payer: false
         };
-        let res = self.get_table_rows(&mut payload.clone()).await;
+        let res = self.get_table_rows(&mut payload.clone()).await?;
         
         let mut v = Vec::new();
         for str in res.rows
@@ -855,59 +1073,111 @@ impl TokenContract
             let collection = value_to_name(u64::from_le_bytes(arr[8..16].try_into().unwrap()));
             v.push((id, collection));
         }
-        v
+        Ok(v)
     }
 
+    /// Uploads 'proof' to liquidstorage which makes it available to the validators. Fails if the upload fails.
     pub async fn upload_proof_to_liquidstorage(
         &self,
         proof: &String
-    )
+    ) -> Result<(), String>
     {
-        let fd = FormData::new().unwrap();
-        assert!(fd.append_with_str("strupload", proof).is_ok());
-
-        // prepare POST request to fetch from EOSIO multiindex table
-        let mut opts = RequestInit::new();
-        opts.method("POST");
-        opts.mode(RequestMode::NoCors); // TODO: should be CORS
-        opts.body(Some(&fd));
-        
-        // TODO: Change to endpoints (need to have the 'web3uploader' service running)
-        let url = "http://web3.zeos.one/uploadstr"; // TODO: should be DSP/ZEOS Validator
-        let request = Request::new_with_str_and_init(&url, &opts).unwrap();
-        
-        // send http request using browser window's fetch
-        let window = web_sys::window().unwrap();
-        let _resp_value = JsFuture::from(window.fetch_with_request(&request)).await.unwrap();
-
-        // 'no-cors' mode doesn't allow the browser to read any response content.
-        // see: https://stackoverflow.com/a/54906434/2340535
+        // a local chain (see: simulator) accepts the uploads itself
+        let url = match self.network {
+            Network::Local => format!("{}/uploadstr", self.endpoints[0]),
+            _ => self.network.liquidstorage_upload_url().to_string() // TODO: should be DSP/ZEOS Validator
+        };
+        http::post_form(&url, "strupload", proof).await.map_err(|e| format!("liquidstorage upload: {}", e))
     }
 }
 
 #[cfg(test)]
 mod tests
 {
-    use crate::tree::EMPTY_ROOTS;
+    use crate::constants::{MT_ARR_FULL_TREE_OFFSET, MT_ARR_LEAF_ROW_OFFSET, MT_NUM_LEAVES};
+    use crate::tree::{ContractTree, EMPTY_ROOTS, TreeDepthError};
 
-    use super::MERKLE_DEPTH_ORCHARD;
+    use super::{Global, TokenContract, MERKLE_DEPTH_ORCHARD};
+    use crate::network::Network;
+    use nonempty::NonEmpty;
 
     #[test]
     fn test_macros()
     {
-        println!("{}", MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD));
-        println!("{}", MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD));
-        println!("{}", MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD));
-
-        let array_index = 77;
+        assert_eq!(MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD), 1u64 << MERKLE_DEPTH_ORCHARD);
+        assert_eq!(MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD), MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD) - 1);
+        assert_eq!(MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD), 2 * MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD) - 1);
+        // the deepest trees supported by the circuit design do not overflow
+        assert_eq!(MT_NUM_LEAVES!(32), 1 << 32);
+        assert_eq!(MT_ARR_FULL_TREE_OFFSET!(32), (1 << 33) - 1);
+
+        // the first leaf of the second tree
+        let array_index = MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD) + MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD);
         let tree_index = array_index / MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD);
-        let leaf_index = array_index - (tree_index + 1) * MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD) + (tree_index + 1) * MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD);
-        println!("{}", leaf_index);
+        let leaf_index = tree_index * MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD) + array_index % MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD) - MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD);
+        assert_eq!(leaf_index, MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD));
+        assert_eq!(ContractTree::leaf_position(array_index), Some(leaf_index));
+        assert_eq!(EMPTY_ROOTS.len(), MERKLE_DEPTH_ORCHARD + 1);
+    }
+
+    #[test]
+    fn tree_depth()
+    {
+        let global = Global{ note_count: 0, leaf_count: 0, tree_depth: MERKLE_DEPTH_ORCHARD as u64 };
+        assert!(global.check_tree_depth().is_ok());
+        let global = Global{ note_count: 0, leaf_count: 0, tree_depth: MERKLE_DEPTH_ORCHARD as u64 + 1 };
+        assert_eq!(global.check_tree_depth(), Err(TreeDepthError::Mismatch{ contract: MERKLE_DEPTH_ORCHARD as u64 + 1, circuit: MERKLE_DEPTH_ORCHARD }));
+        let global = Global{ note_count: 1, leaf_count: 1, tree_depth: 0 };
+        assert_eq!(global.check_tree_depth(), Err(TreeDepthError::Mismatch{ contract: 0, circuit: MERKLE_DEPTH_ORCHARD }));
+    }
+
+    #[tokio::test]
+    async fn fresh_deployment()
+    {
+        // the global table of a fresh deployment has no row yet
+        let server = std::sync::Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}", server.server_addr());
+        {
+            let server = server.clone();
+            std::thread::spawn(move || {
+                for req in server.incoming_requests()
+                {
+                    req.respond(tiny_http::Response::from_string(r#"{"rows":[],"more":false,"next_key":""}"#)).unwrap();
+                }
+            });
+        }
+        let contract = TokenContract::new(Network::Local, NonEmpty::new(url));
+        let global = contract.get_global_state().await.unwrap();
+        assert!(global.is_empty());
+        assert!(global.check_tree_depth().is_ok());
+    }
 
-        println!("empty roots:");
-        for r in EMPTY_ROOTS.iter()
+    #[tokio::test]
+    async fn rpc_errors()
+    {
+        // an endpoint which answers every request with an error page
+        let server = std::sync::Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}", server.server_addr());
         {
-            println!("{}", hex::encode(r.inner().0[0].to_le_bytes()));
+            let server = server.clone();
+            std::thread::spawn(move || {
+                for req in server.incoming_requests()
+                {
+                    let status = if req.url() == "/v1/chain/get_currency_balance" { 200 } else { 502 };
+                    req.respond(tiny_http::Response::from_string("<html>bad gateway</html>").with_status_code(status)).unwrap();
+                }
+            });
         }
+        let contract = TokenContract::new(Network::Local, NonEmpty::new(url));
+        assert!(contract.get_global_state().await.unwrap_err().starts_with("HTTP 502"));
+        assert!(contract.get_encrypted_notes(0, 10).await.is_err());
+        assert!(contract.get_merkle_hash(0).await.is_err());
+        assert!(contract.get_currency_balance(&"eosio.token".to_string(), &"alice".to_string(), &"EOS".to_string()).await.unwrap_err().starts_with("get_currency_balance"));
+        assert!(contract.upload_proof_to_liquidstorage(&"00".to_string()).await.unwrap_err().starts_with("liquidstorage upload"));
+
+        // no endpoint at all
+        let contract = TokenContract::new(Network::Local, NonEmpty::new(String::from("http://127.0.0.1:1")));
+        assert!(contract.get_roots(0).await.is_err());
+        assert!(contract.get_nullifiers(0).await.is_err());
     }
-}
\ No newline at end of file
+}
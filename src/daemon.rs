@@ -0,0 +1,558 @@
+//! Wallet daemon for backend integrations (e.g. exchanges and payment processors).
+//!
+//! The daemon holds one or more wallets, synchronizes them on a schedule and exposes a JSON-RPC 2.0 API via HTTP POST.
+//! Each request addresses a wallet by name with the parameter "wallet" (optional if the daemon holds only one):
+//!
+//! getstatus                                   network, contract state, last sync and last error of each wallet
+//! getbalance                                  fungible token balances (symbol => quantity)
+//! listnotes                                   unspent notes
+//! gethistory                                  received and sent notes
+//! newaddress                                  derives a new shielded address
+//! sendmany    payments, auth, [limits]        pays many shielded recipients ({"to", "asset", "memo"})
+//! shield      account, asset, [to]            shields an asset of an EOS account
+//! unshield    asset, account, auth, [memo]    unshields an asset into an EOS account (authorized by 'auth')
+//! getevents   [since]                         notifications with a sequence number greater than 'since'
+//!
+//! Assets are quantities of known tokens (e.g. "1.0000 EOS") or NFTs as "<id>@<contract>" (see: 'Wallet::parse_asset').
+//! Transactions are returned as lists of EOS actions ready to be signed and pushed. The notes spent by a returned
+//! transaction are reserved until synchronization finds them spent or the transaction has expired: later calls don't
+//! spend them again. For each incoming note found during synchronization an "incoming_note" event is recorded and
+//! posted to all configured webhooks.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::builder::{BatchLimits, EOSAuthorization, Payment};
+use crate::contract::NoteEx;
+use crate::http;
+use crate::wallet::{HistoryEntry, Wallet, WalletError};
+use crate::wallet_file;
+
+/// The maximum number of events kept in memory
+const MAX_EVENTS: usize = 10000;
+
+/// The number of seconds the notes spent by a returned transaction are reserved (the maximum expiration of EOS
+/// transactions)
+const RESERVATION_SECS: u64 = 3600;
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const WALLET_ERROR: i64 = -32000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError
+{
+    pub code: i64,
+    pub message: String
+}
+
+impl RpcError
+{
+    fn new(code: i64, message: String) -> Self
+    {
+        RpcError{ code, message }
+    }
+}
+
+impl From<WalletError> for RpcError
+{
+    fn from(err: WalletError) -> Self
+    {
+        RpcError::new(WALLET_ERROR, err.to_string())
+    }
+}
+
+/// A notification of the daemon
+#[derive(Debug, Clone, Serialize)]
+pub struct Event
+{
+    /// The sequence number of this event
+    pub seq: u64,
+    /// The name of the wallet
+    pub wallet: String,
+    /// The type of this event ("incoming_note")
+    pub event: &'static str,
+    /// The incoming note
+    pub note: HistoryEntry
+}
+
+/// A wallet held by the daemon
+struct ManagedWallet
+{
+    wallet: Wallet,
+    /// the wallet file and its password (the wallet is saved after each change)
+    file: Option<(PathBuf, String)>,
+    /// unix time of the last successful synchronization
+    last_sync: Option<u64>,
+    /// the error of the last synchronization or save
+    last_error: Option<String>,
+    /// the ids of the notes spent by pending transactions and the unix time until which they are reserved
+    reserved: HashMap<u64, u64>
+}
+
+impl ManagedWallet
+{
+    /// Returns the spendable notes which are not reserved by pending transactions. Reservations of notes which are not
+    /// spendable anymore (i.e. synchronization has found them spent) or which have expired are released.
+    fn unreserved_notes(&mut self) -> Result<Vec<NoteEx>, RpcError>
+    {
+        let notes = self.wallet.spendable_notes()?;
+        let ids: HashSet<u64> = notes.iter().map(|n| n.id).collect();
+        let now = now();
+        self.reserved.retain(|id, until| ids.contains(id) && *until > now);
+        Ok(notes.into_iter().filter(|n| !self.reserved.contains_key(&n.id)).collect())
+    }
+
+    /// Reserves the notes of 'available' which are missing from 'remaining', i.e. the notes spent by the transactions
+    /// which have been built from 'available'
+    fn reserve(&mut self, available: &[NoteEx], remaining: &[NoteEx])
+    {
+        let remaining: HashSet<u64> = remaining.iter().map(|n| n.id).collect();
+        let until = now() + RESERVATION_SECS;
+        self.reserved.extend(available.iter().filter(|n| !remaining.contains(&n.id)).map(|n| (n.id, until)));
+    }
+
+    fn save(&mut self) -> Result<(), RpcError>
+    {
+        if let Some((path, password)) = &self.file
+        {
+            if let Err(e) = wallet_file::save(path, &self.wallet, password)
+            {
+                self.last_error = Some(e.to_string());
+                return Err(RpcError::new(WALLET_ERROR, e.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The wallet daemon
+pub struct Daemon
+{
+    wallets: BTreeMap<String, Mutex<ManagedWallet>>,
+    webhooks: Vec<String>,
+    events: Mutex<Vec<Event>>
+}
+
+fn now() -> u64
+{
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn param(params: &Value, name: &str) -> Result<String, RpcError>
+{
+    params[name].as_str().map(|s| s.to_string()).ok_or(RpcError::new(INVALID_PARAMS, format!("missing parameter: {}", name)))
+}
+
+fn opt_param(params: &Value, name: &str) -> Option<String>
+{
+    params[name].as_str().map(|s| s.to_string())
+}
+
+/// Locks the wallet 'mw'. Fails if an operation on the wallet has panicked while holding the lock (the wallet may be
+/// in an inconsistent state).
+fn lock(mw: &Mutex<ManagedWallet>) -> Result<MutexGuard<'_, ManagedWallet>, RpcError>
+{
+    mw.lock().map_err(|_| RpcError::new(WALLET_ERROR, String::from("wallet unavailable: an earlier operation has panicked")))
+}
+
+fn transactions(txs: Vec<String>) -> Result<Value, RpcError>
+{
+    let txs: Result<Vec<Value>, _> = txs.iter().map(|tx| serde_json::from_str(tx)).collect();
+    txs.map(|txs| json!({ "transactions": txs })).map_err(|e| RpcError::new(WALLET_ERROR, e.to_string()))
+}
+
+impl Daemon
+{
+    /// Creates a daemon without wallets which posts its events to 'webhooks'
+    pub fn new(webhooks: Vec<String>) -> Self
+    {
+        Daemon {
+            wallets: BTreeMap::new(),
+            webhooks,
+            events: Mutex::new(Vec::new())
+        }
+    }
+
+    /// Adds 'wallet' by 'name'. If 'file' (path and password) is set the wallet is saved after each change.
+    pub fn add_wallet(&mut self, name: &str, wallet: Wallet, file: Option<(PathBuf, String)>)
+    {
+        self.wallets.insert(name.to_string(), Mutex::new(ManagedWallet{ wallet, file, last_sync: None, last_error: None, reserved: HashMap::new() }));
+    }
+
+    /// Synchronizes all wallets and records (and posts) an event for each incoming note
+    pub fn sync_all(&self)
+    {
+        for (name, mw) in self.wallets.iter()
+        {
+            let incoming = {
+                let mut mw = match lock(mw) {
+                    Ok(mw) => mw,
+                    Err(e) => {
+                        eprintln!("wallet {}: {}", name, e.message);
+                        continue;
+                    }
+                };
+                let known: HashSet<u64> = match mw.wallet.history() {
+                    Ok(history) => history.iter().filter(|e| e.direction == "received").map(|e| e.id).collect(),
+                    Err(e) => {
+                        mw.last_error = Some(e.to_string());
+                        continue;
+                    }
+                };
+                match pollster::block_on(mw.wallet.sync()) {
+                    Ok(()) => {
+                        mw.last_sync = Some(now());
+                        mw.last_error = None;
+                        let _ = mw.save();
+                    }
+                    Err(e) => mw.last_error = Some(e.to_string())
+                }
+                mw.wallet.history().unwrap_or_default().into_iter().filter(|e| e.direction == "received" && !known.contains(&e.id)).collect::<Vec<HistoryEntry>>()
+            };
+            for note in incoming
+            {
+                self.notify(name, note);
+            }
+        }
+    }
+
+    /// Records an "incoming_note" event and posts it to all webhooks
+    fn notify(&self, wallet: &str, note: HistoryEntry)
+    {
+        let event = {
+            let mut events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+            let seq = events.last().map(|e| e.seq + 1).unwrap_or(1);
+            let event = Event{ seq, wallet: wallet.to_string(), event: "incoming_note", note };
+            events.push(event.clone());
+            if events.len() > MAX_EVENTS
+            {
+                events.remove(0);
+            }
+            event
+        };
+        let body = serde_json::to_string(&event).unwrap();
+        for url in self.webhooks.iter()
+        {
+            if let Err(e) = pollster::block_on(http::post_json(url, &body))
+            {
+                eprintln!("webhook {} failed: {}", url, e);
+            }
+        }
+    }
+
+    /// Returns the wallet addressed by the parameter "wallet" (optional if there is only one wallet)
+    fn wallet(&self, params: &Value) -> Result<&Mutex<ManagedWallet>, RpcError>
+    {
+        match opt_param(params, "wallet") {
+            Some(name) => self.wallets.get(&name).ok_or(RpcError::new(INVALID_PARAMS, format!("unknown wallet: {}", name))),
+            None if self.wallets.len() == 1 => Ok(self.wallets.values().next().unwrap()),
+            None => Err(RpcError::new(INVALID_PARAMS, String::from("missing parameter: wallet")))
+        }
+    }
+
+    /// Executes the JSON-RPC method 'method' with 'params'
+    pub fn call(&self, method: &str, params: &Value) -> Result<Value, RpcError>
+    {
+        match method
+        {
+            "getstatus" => {
+                let wallets: Vec<Value> = self.wallets.iter().map(|(name, mw)| {
+                    match lock(mw) {
+                        Ok(mw) => json!({
+                            "wallet": name,
+                            "network": mw.wallet.network(),
+                            "state": mw.wallet.state(),
+                            "watch_only": mw.wallet.is_watch_only(),
+                            "last_sync": mw.last_sync,
+                            "last_error": mw.last_error,
+                            "reserved_notes": mw.reserved.len()
+                        }),
+                        Err(e) => json!({ "wallet": name, "last_error": e.message })
+                    }
+                }).collect();
+                let last_event = self.events.lock().unwrap_or_else(PoisonError::into_inner).last().map(|e| e.seq).unwrap_or(0);
+                Ok(json!({ "wallets": wallets, "last_event": last_event }))
+            }
+            "getbalance" => {
+                let mw = lock(self.wallet(params)?)?;
+                let balances: BTreeMap<String, String> = mw.wallet.balances()?.into_iter().map(|(symbol, amount)| {
+                    let quantity = match mw.wallet.settings().ft_contract(&symbol) {
+                        Some((contract, sym)) => crate::eosio::Asset::ft(amount, *sym, contract).to_quantity_string(),
+                        None => format!("{} {}", amount, symbol)
+                    };
+                    (symbol, quantity)
+                }).collect();
+                Ok(json!(balances))
+            }
+            "listnotes" => {
+                let mw = lock(self.wallet(params)?)?;
+                let notes: Vec<HistoryEntry> = mw.wallet.history()?.into_iter().filter(|e| e.direction == "received").collect();
+                Ok(json!(notes))
+            }
+            "gethistory" => {
+                let mw = lock(self.wallet(params)?)?;
+                Ok(json!(mw.wallet.history()?))
+            }
+            "newaddress" => {
+                let mut mw = lock(self.wallet(params)?)?;
+                let address = mw.wallet.derive_new_wallet_address();
+                mw.save()?;
+                Ok(json!({ "index": mw.wallet.diversifier_index(), "address": address }))
+            }
+            "sendmany" => {
+                let mut mw = lock(self.wallet(params)?)?;
+                let auth = param(params, "auth")?;
+                let (actor, permission) = auth.split_once('@').unwrap_or((&auth, "active"));
+                let limits: BatchLimits = match params.get("limits") {
+                    Some(l) => serde_json::from_value(l.clone()).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?,
+                    None => BatchLimits::default()
+                };
+                let mut payments = Vec::new();
+                for p in params["payments"].as_array().ok_or(RpcError::new(INVALID_PARAMS, String::from("missing parameter: payments")))?
+                {
+                    let asset = mw.wallet.parse_asset(&param(p, "asset")?)?;
+                    payments.push(Payment::new(&param(p, "to")?, &asset, &opt_param(p, "memo").unwrap_or_default()));
+                }
+                let available = mw.unreserved_notes()?;
+                let mut notes = available.clone();
+                let txs = pollster::block_on(mw.wallet.send_payments_from(&mut notes, &payments, &limits, &vec![EOSAuthorization::new(actor, permission)]))?;
+                mw.reserve(&available, &notes);
+                transactions(txs)
+            }
+            "shield" => {
+                let mw = lock(self.wallet(params)?)?;
+                let asset = mw.wallet.parse_asset(&param(params, "asset")?)?;
+                let to = opt_param(params, "to").unwrap_or_else(|| mw.wallet.address(mw.wallet.diversifier_index()));
+                let tx = pollster::block_on(mw.wallet.shield_asset(&param(params, "account")?, &asset, &to))?;
+                transactions(vec![tx])
+            }
+            "unshield" => {
+                let mut mw = lock(self.wallet(params)?)?;
+                let asset = mw.wallet.parse_asset(&param(params, "asset")?)?;
+                let auth = param(params, "auth")?;
+                let (actor, permission) = auth.split_once('@').unwrap_or((&auth, "active"));
+                let eos_auth = vec![EOSAuthorization::new(actor, permission)];
+                let available = mw.unreserved_notes()?;
+                let mut notes = available.clone();
+                let tx = pollster::block_on(mw.wallet.unshield_asset_from(&mut notes, &asset, &param(params, "account")?, &opt_param(params, "memo").unwrap_or_default(), &eos_auth))?;
+                mw.reserve(&available, &notes);
+                transactions(vec![tx])
+            }
+            "getevents" => {
+                let since = params["since"].as_u64().unwrap_or(0);
+                let events: Vec<Event> = self.events.lock().unwrap_or_else(PoisonError::into_inner).iter().filter(|e| e.seq > since).cloned().collect();
+                Ok(json!(events))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("method not found: {}", method)))
+        }
+    }
+
+    /// Handles a JSON-RPC 2.0 request (or batch of requests) and returns the response
+    pub fn handle(&self, body: &str) -> String
+    {
+        let req: Value = match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(e) => return json!({ "jsonrpc": "2.0", "id": null, "error": { "code": PARSE_ERROR, "message": e.to_string() } }).to_string()
+        };
+        match req {
+            Value::Array(reqs) => Value::Array(reqs.iter().map(|r| self.handle_request(r)).collect()).to_string(),
+            req => self.handle_request(&req).to_string()
+        }
+    }
+
+    fn handle_request(&self, req: &Value) -> Value
+    {
+        let id = req.get("id").cloned().unwrap_or(Value::Null);
+        let res = match req["method"].as_str() {
+            Some(method) => self.call(method, req.get("params").unwrap_or(&json!({}))),
+            None => Err(RpcError::new(INVALID_REQUEST, String::from("missing method")))
+        };
+        match res {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": e.code, "message": e.message } })
+        }
+    }
+
+    /// Serves the JSON-RPC API until the server is shut down
+    pub fn serve(&self, server: &tiny_http::Server)
+    {
+        for mut request in server.incoming_requests()
+        {
+            let mut body = String::new();
+            let (status, body) = if *request.method() != tiny_http::Method::Post {
+                (405, String::new())
+            } else {
+                match request.as_reader().read_to_string(&mut body) {
+                    Ok(_) => (200, self.handle(&body)),
+                    Err(e) => (400, e.to_string())
+                }
+            };
+            let response = tiny_http::Response::from_string(body)
+                .with_status_code(status)
+                .with_header("Content-Type: application/json".parse::<tiny_http::Header>().unwrap());
+            let _ = request.respond(response);
+        }
+    }
+
+    /// Synchronizes all wallets every 'interval' in a background thread and serves the JSON-RPC API. A panicking
+    /// synchronization is reported and doesn't stop the synchronization of later intervals.
+    pub fn run(self: Arc<Self>, server: &tiny_http::Server, interval: Duration)
+    {
+        let daemon = self.clone();
+        std::thread::spawn(move || loop
+        {
+            if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| daemon.sync_all())).is_err()
+            {
+                eprintln!("synchronization panicked");
+            }
+            std::thread::sleep(interval);
+        });
+        self.serve(server);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{Daemon, METHOD_NOT_FOUND, PARSE_ERROR, INVALID_PARAMS, WALLET_ERROR};
+    use crate::eosio::{name_to_value, string_to_symbol, Asset};
+    use crate::keys::{FullViewingKey, Scope, SpendingKey};
+    use crate::note::{Note, NT_FT};
+    use crate::value::NoteValue;
+    use rand::rngs::OsRng;
+    use crate::network::Network;
+    use crate::simulator::SimulatedChain;
+    use crate::wallet::Wallet;
+    use serde_json::{json, Value};
+    use std::io::Read;
+    use std::time::Duration;
+
+    #[test]
+    fn daemon()
+    {
+        // simulated chain
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        // webhook receiver
+        let webhook = tiny_http::Server::http("127.0.0.1:0").unwrap();
+
+        let mut wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        wallet.set_endpoints(vec![url], vec![]);
+        let address = wallet.address(0);
+        let mut daemon = Daemon::new(vec![format!("http://{}", webhook.server_addr())]);
+        daemon.add_wallet("hot", wallet, None);
+
+        let call = |method: &str, params: Value| -> Value {
+            serde_json::from_str(&daemon.handle(&json!({ "jsonrpc": "2.0", "id": 7, "method": method, "params": params }).to_string())).unwrap()
+        };
+
+        // an incoming payment
+        let eos = Asset::ft(125000, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        chain.mint(crate::address::Address::from_bech32m(&address, Network::Local).unwrap(), &eos, "INV-42");
+        daemon.sync_all();
+
+        let res = call("getbalance", json!({}));
+        assert_eq!(res["id"], 7);
+        assert_eq!(res["result"], json!({ "EOS": "12.5000 EOS" }));
+        let res = call("listnotes", json!({ "wallet": "hot" }));
+        assert_eq!(res["result"].as_array().unwrap().len(), 1);
+        assert_eq!(res["result"][0]["memo"], "INV-42");
+        let res = call("getstatus", json!({}));
+        assert_eq!(res["result"]["wallets"][0]["network"], "local");
+        assert_eq!(res["result"]["wallets"][0]["state"]["note_count"], "1");
+        assert_eq!(res["result"]["last_event"], 1);
+
+        // the event is recorded and posted to the webhook
+        let res = call("getevents", json!({ "since": 0 }));
+        assert_eq!(res["result"][0]["event"], "incoming_note");
+        assert_eq!(res["result"][0]["wallet"], "hot");
+        let mut request = webhook.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        let mut body = String::new();
+        request.as_reader().read_to_string(&mut body).unwrap();
+        let event: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(event["note"]["memo"], "INV-42");
+        assert!(call("getevents", json!({ "since": 1 }))["result"].as_array().unwrap().is_empty());
+
+        // nothing new
+        daemon.sync_all();
+        assert_eq!(call("getstatus", json!({}))["result"]["last_event"], 1);
+
+        let res = call("newaddress", json!({}));
+        assert_eq!(res["result"]["index"], 1);
+        assert!(res["result"]["address"].as_str().unwrap().starts_with("zalocal1"));
+
+        // errors
+        assert_eq!(call("getbalances", json!({}))["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(call("getbalance", json!({ "wallet": "cold" }))["error"]["code"], INVALID_PARAMS);
+        assert_eq!(call("shield", json!({ "asset": "1.0000 EOS" }))["error"]["code"], INVALID_PARAMS);
+        // an unshield must be authorized by the payer
+        assert_eq!(call("unshield", json!({ "asset": "1.0000 EOS", "account": "bob" }))["error"]["code"], INVALID_PARAMS);
+        let res: Value = serde_json::from_str(&daemon.handle("{")).unwrap();
+        assert_eq!(res["error"]["code"], PARSE_ERROR);
+        let res: Value = serde_json::from_str(&daemon.handle(r#"[{"id":1,"method":"getstatus"},{"id":2,"method":"getevents"}]"#)).unwrap();
+        assert_eq!(res.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reservations_and_poisoning()
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        let seed = "miau seed miau 123 Der seed muss lang genug sein...";
+        let fvk = FullViewingKey::from(&SpendingKey::from_zip32_seed(seed.as_bytes(), 0, 0).unwrap());
+        let mut wallet = Wallet::new_for_network(seed.to_string(), "local".to_string()).unwrap();
+        wallet.set_endpoints(vec![url], vec![]);
+        let address = wallet.address(0);
+        let mut daemon = Daemon::new(vec![]);
+        daemon.add_wallet("hot", wallet, None);
+        let call = |method: &str, params: Value| -> Value {
+            serde_json::from_str(&daemon.handle(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string())).unwrap()
+        };
+
+        let eos = Asset::ft(100000, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        let recipient = crate::address::Address::from_bech32m(&address, Network::Local).unwrap();
+        for _ in 0..2
+        {
+            chain.mint(recipient, &eos, "");
+        }
+        daemon.sync_all();
+
+        // each transaction spends another note until the notes of pending transactions are all there is
+        let sendmany = json!({ "payments": [{ "to": address, "asset": "5.0000 EOS" }], "auth": "alice@active" });
+        assert_eq!(call("sendmany", sendmany.clone())["result"]["transactions"].as_array().unwrap().len(), 1);
+        assert_eq!(call("getstatus", json!({}))["result"]["wallets"][0]["reserved_notes"], 1);
+        assert_eq!(call("unshield", json!({ "asset": "5.0000 EOS", "account": "bob", "auth": "alice" }))["result"]["transactions"].as_array().unwrap().len(), 1);
+        assert_eq!(call("getstatus", json!({}))["result"]["wallets"][0]["reserved_notes"], 2);
+        assert_eq!(call("sendmany", sendmany.clone())["error"]["code"], WALLET_ERROR);
+
+        // the reservation of a note is released once synchronization finds it spent (by a transaction which returns
+        // 5 EOS as change): the change is not reserved
+        let nf = daemon.wallets["hot"].lock().unwrap().wallet.spendable_notes().unwrap()[0].note.nullifier(&fvk);
+        let change = Note::new(NT_FT, recipient, NoteValue::from_raw(50000), NoteValue::from_raw(eos.d2()), NoteValue::from_raw(name_to_value(&"eosio.token".to_string())), NoteValue::from_raw(0), nf, OsRng, [0; 512]);
+        chain.add_notes(&[change], Some(fvk.to_ovk(Scope::External)));
+        daemon.sync_all();
+        assert_eq!(call("getbalance", json!({}))["result"], json!({ "EOS": "15.0000 EOS" }));
+        assert_eq!(call("getstatus", json!({}))["result"]["wallets"][0]["reserved_notes"], 1);
+        assert!(call("sendmany", sendmany.clone())["result"].is_object());
+        assert_eq!(call("getstatus", json!({}))["result"]["wallets"][0]["reserved_notes"], 2);
+
+        // a wallet whose lock has been poisoned by a panic returns errors instead of panicking
+        std::thread::scope(|s| {
+            assert!(s.spawn(|| {
+                let _mw = daemon.wallets["hot"].lock().unwrap();
+                panic!("poison");
+            }).join().is_err());
+        });
+        assert_eq!(call("getbalance", json!({}))["error"]["code"], WALLET_ERROR);
+        assert_eq!(call("sendmany", sendmany)["error"]["code"], WALLET_ERROR);
+        assert!(call("getstatus", json!({}))["result"]["wallets"][0]["last_error"].as_str().unwrap().contains("panicked"));
+        daemon.sync_all();
+    }
+}
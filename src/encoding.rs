@@ -0,0 +1,240 @@
+//! Bech32m encodings of spending and viewing keys.
+//!
+//! Each key type has its own human readable part (HRP) per network (see: 'Network::key_hrp'), e.g. a full viewing key
+//! on mainnet is encoded as "zfvk1..." and on Kylin as "zfvkkylin1...". Decoding is strict: the checksum, the Bech32m
+//! variant, the key type, the network and the length of the payload are checked before the key itself is parsed.
+
+use std::fmt;
+
+use bech32::{FromBase32, ToBase32, Variant};
+
+use crate::keys::{SpendingKey, FullViewingKey, IncomingViewingKey, OutgoingViewingKey};
+use crate::network::Network;
+
+/// The types of keys which have a Bech32m encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType
+{
+    SpendingKey,
+    FullViewingKey,
+    IncomingViewingKey,
+    OutgoingViewingKey
+}
+
+impl KeyType
+{
+    /// All key types
+    pub const ALL: [KeyType; 4] = [KeyType::SpendingKey, KeyType::FullViewingKey, KeyType::IncomingViewingKey, KeyType::OutgoingViewingKey];
+
+    /// Returns the prefix of the human readable part (which is followed by the network suffix)
+    pub fn hrp_prefix(&self) -> &'static str
+    {
+        match self
+        {
+            KeyType::SpendingKey => "zsk",
+            KeyType::FullViewingKey => "zfvk",
+            KeyType::IncomingViewingKey => "zivk",
+            KeyType::OutgoingViewingKey => "zovk"
+        }
+    }
+
+    /// Returns the length of the raw encoding of keys of this type
+    pub fn raw_len(&self) -> usize
+    {
+        match self
+        {
+            KeyType::SpendingKey => 32,
+            KeyType::FullViewingKey => 96,
+            KeyType::IncomingViewingKey => 64,
+            KeyType::OutgoingViewingKey => 32
+        }
+    }
+}
+
+impl fmt::Display for KeyType
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            KeyType::SpendingKey => write!(f, "spending key"),
+            KeyType::FullViewingKey => write!(f, "full viewing key"),
+            KeyType::IncomingViewingKey => write!(f, "incoming viewing key"),
+            KeyType::OutgoingViewingKey => write!(f, "outgoing viewing key")
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum KeyEncodingError
+{
+    #[error("invalid bech32m encoding: {0}")]
+    InvalidEncoding(String),
+    #[error("expected bech32m but found bech32 encoding")]
+    WrongVariant,
+    #[error("unknown prefix: {0}")]
+    UnknownPrefix(String),
+    #[error("expected a {expected} but found a {found}")]
+    WrongKeyType{ expected: KeyType, found: KeyType },
+    #[error("expected a key of network {expected} but found a key of network {found}")]
+    WrongNetwork{ expected: Network, found: Network },
+    #[error("expected {expected} bytes but found {found}")]
+    InvalidLength{ expected: usize, found: usize },
+    #[error("invalid {0}")]
+    InvalidKey(KeyType)
+}
+
+/// Encodes the raw 'bytes' of a key of type 'key_type' of 'network' as Bech32m
+pub fn encode_key(network: Network, key_type: KeyType, bytes: &[u8]) -> String
+{
+    bech32::encode(&network.key_hrp(key_type), bytes.to_base32(), Variant::Bech32m).unwrap()
+}
+
+/// Decodes a Bech32m encoded key of type 'key_type' of 'network' and returns its raw bytes
+pub fn decode_key(str: &str, network: Network, key_type: KeyType) -> Result<Vec<u8>, KeyEncodingError>
+{
+    let (hrp, data, variant) = bech32::decode(str).map_err(|e| KeyEncodingError::InvalidEncoding(e.to_string()))?;
+    if variant != Variant::Bech32m
+    {
+        return Err(KeyEncodingError::WrongVariant);
+    }
+    let (found_network, found_type) = Network::from_key_hrp(&hrp).ok_or(KeyEncodingError::UnknownPrefix(hrp))?;
+    if found_type != key_type
+    {
+        return Err(KeyEncodingError::WrongKeyType{ expected: key_type, found: found_type });
+    }
+    if found_network != network
+    {
+        return Err(KeyEncodingError::WrongNetwork{ expected: network, found: found_network });
+    }
+    let bytes = Vec::<u8>::from_base32(&data).map_err(|e| KeyEncodingError::InvalidEncoding(e.to_string()))?;
+    if bytes.len() != key_type.raw_len()
+    {
+        return Err(KeyEncodingError::InvalidLength{ expected: key_type.raw_len(), found: bytes.len() });
+    }
+    Ok(bytes)
+}
+
+impl SpendingKey
+{
+    /// Encodes this spending key as Bech32m using the human readable part of 'network'
+    pub fn to_bech32m(&self, network: Network) -> String
+    {
+        encode_key(network, KeyType::SpendingKey, self.to_bytes())
+    }
+
+    /// Parses a Bech32m encoded spending key of 'network'
+    pub fn from_bech32m(str: &str, network: Network) -> Result<Self, KeyEncodingError>
+    {
+        let bytes = decode_key(str, network, KeyType::SpendingKey)?;
+        Option::from(SpendingKey::from_bytes(bytes.try_into().unwrap())).ok_or(KeyEncodingError::InvalidKey(KeyType::SpendingKey))
+    }
+}
+
+impl FullViewingKey
+{
+    /// Encodes this full viewing key as Bech32m using the human readable part of 'network'
+    pub fn to_bech32m(&self, network: Network) -> String
+    {
+        encode_key(network, KeyType::FullViewingKey, &self.to_bytes())
+    }
+
+    /// Parses a Bech32m encoded full viewing key of 'network'
+    pub fn from_bech32m(str: &str, network: Network) -> Result<Self, KeyEncodingError>
+    {
+        let bytes = decode_key(str, network, KeyType::FullViewingKey)?;
+        FullViewingKey::from_bytes(&bytes.try_into().unwrap()).ok_or(KeyEncodingError::InvalidKey(KeyType::FullViewingKey))
+    }
+}
+
+impl IncomingViewingKey
+{
+    /// Encodes this incoming viewing key as Bech32m using the human readable part of 'network'
+    pub fn to_bech32m(&self, network: Network) -> String
+    {
+        encode_key(network, KeyType::IncomingViewingKey, &self.to_bytes())
+    }
+
+    /// Parses a Bech32m encoded incoming viewing key of 'network'
+    pub fn from_bech32m(str: &str, network: Network) -> Result<Self, KeyEncodingError>
+    {
+        let bytes = decode_key(str, network, KeyType::IncomingViewingKey)?;
+        Option::from(IncomingViewingKey::from_bytes(&bytes.try_into().unwrap())).ok_or(KeyEncodingError::InvalidKey(KeyType::IncomingViewingKey))
+    }
+}
+
+impl OutgoingViewingKey
+{
+    /// Encodes this outgoing viewing key as Bech32m using the human readable part of 'network'
+    pub fn to_bech32m(&self, network: Network) -> String
+    {
+        encode_key(network, KeyType::OutgoingViewingKey, self.as_ref())
+    }
+
+    /// Parses a Bech32m encoded outgoing viewing key of 'network'
+    pub fn from_bech32m(str: &str, network: Network) -> Result<Self, KeyEncodingError>
+    {
+        let bytes: [u8; 32] = decode_key(str, network, KeyType::OutgoingViewingKey)?.try_into().unwrap();
+        Ok(OutgoingViewingKey::from(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{encode_key, KeyEncodingError, KeyType};
+    use crate::keys::{SpendingKey, FullViewingKey, IncomingViewingKey, OutgoingViewingKey, Scope};
+    use crate::network::Network;
+    use bech32::{ToBase32, Variant};
+
+    #[test]
+    fn key_encodings()
+    {
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk = FullViewingKey::from(&sk);
+        let ivk = fvk.to_ivk(Scope::External);
+        let ovk = fvk.to_ovk(Scope::External);
+
+        for network in Network::ALL
+        {
+            let str = sk.to_bech32m(network);
+            assert!(str.starts_with(&format!("{}1", network.key_hrp(KeyType::SpendingKey))));
+            assert_eq!(SpendingKey::from_bech32m(&str, network).unwrap().to_bytes(), sk.to_bytes());
+            let str = fvk.to_bech32m(network);
+            assert_eq!(FullViewingKey::from_bech32m(&str, network).unwrap(), fvk);
+            let str = ivk.to_bech32m(network);
+            assert_eq!(IncomingViewingKey::from_bech32m(&str, network).unwrap(), ivk);
+            let str = ovk.to_bech32m(network);
+            assert_eq!(OutgoingViewingKey::from_bech32m(&str, network).unwrap().as_ref(), ovk.as_ref());
+        }
+
+        // wrong key type
+        let str = fvk.to_bech32m(Network::Mainnet);
+        assert_eq!(IncomingViewingKey::from_bech32m(&str, Network::Mainnet), Err(KeyEncodingError::WrongKeyType{ expected: KeyType::IncomingViewingKey, found: KeyType::FullViewingKey }));
+        assert!(matches!(SpendingKey::from_bech32m(&str, Network::Mainnet), Err(KeyEncodingError::WrongKeyType{ .. })));
+
+        // wrong network
+        assert!(matches!(FullViewingKey::from_bech32m(&str, Network::Kylin), Err(KeyEncodingError::WrongNetwork{ expected: Network::Kylin, found: Network::Mainnet })));
+
+        // bad checksum
+        let mut tampered = str.clone();
+        let last = if tampered.ends_with('q') { 'p' } else { 'q' };
+        tampered.pop();
+        tampered.push(last);
+        assert!(matches!(FullViewingKey::from_bech32m(&tampered, Network::Mainnet), Err(KeyEncodingError::InvalidEncoding(_))));
+
+        // bech32 instead of bech32m
+        let bech32 = bech32::encode("zfvk", fvk.to_bytes().to_base32(), Variant::Bech32).unwrap();
+        assert_eq!(FullViewingKey::from_bech32m(&bech32, Network::Mainnet), Err(KeyEncodingError::WrongVariant));
+
+        // unknown prefix and wrong length
+        let unknown = bech32::encode("zxvk", fvk.to_bytes().to_base32(), Variant::Bech32m).unwrap();
+        assert!(matches!(FullViewingKey::from_bech32m(&unknown, Network::Mainnet), Err(KeyEncodingError::UnknownPrefix(_))));
+        let short = encode_key(Network::Mainnet, KeyType::FullViewingKey, &fvk.to_bytes()[0..64]);
+        assert_eq!(FullViewingKey::from_bech32m(&short, Network::Mainnet), Err(KeyEncodingError::InvalidLength{ expected: 96, found: 64 }));
+
+        // invalid key
+        let invalid = encode_key(Network::Mainnet, KeyType::FullViewingKey, &[0xff; 96]);
+        assert_eq!(FullViewingKey::from_bech32m(&invalid, Network::Mainnet), Err(KeyEncodingError::InvalidKey(KeyType::FullViewingKey)));
+    }
+}
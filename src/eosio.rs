@@ -1,6 +1,7 @@
 //! Helper functions to deal with EOSIO/Antelope related stuff.
 
 use std::cmp::min;
+use crate::note::Note;
 
 /// Rust equivalent of: cdt/libraries/eosiolib/core/eosio/name.hpp -> name.char_to_value()
 /// See also: https://github.com/AntelopeIO/cdt/blob/c010d6fae2656f212f78d01c41812734934eb54c/libraries/eosiolib/core/eosio/name.hpp#L108
@@ -123,6 +124,106 @@ pub fn symbol_to_string_precision(raw: u64) -> (String, u8)
     (symbol_code_to_string(raw >> 8), (raw & 0xFF) as u8)
 }
 
+/// A (non-)fungible asset as it is represented by the fields d1, d2, sc and nft of a ZEOS note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Asset
+{
+    /// The amount (fungible) or the asset id (non-fungible)
+    #[serde(with = "crate::contract::string")]
+    pub(crate) d1: u64,
+    /// The symbol (fungible) or zero (non-fungible)
+    #[serde(with = "crate::contract::string")]
+    pub(crate) d2: u64,
+    /// The EOSIO/Antelope account name of the token contract
+    pub(crate) contract: String,
+    /// Indicates a non-fungible asset
+    pub(crate) nft: bool,
+}
+
+impl Asset
+{
+    /// Creates a fungible asset
+    pub fn ft(amount: u64, symbol: u64, contract: &String) -> Self
+    {
+        Asset { d1: amount, d2: symbol, contract: contract.clone(), nft: false }
+    }
+
+    /// Creates a non-fungible asset
+    pub fn nft(id: u64, contract: &String) -> Self
+    {
+        Asset { d1: id, d2: 0, contract: contract.clone(), nft: true }
+    }
+
+    /// Returns the asset a note represents
+    pub fn from_note(note: &Note) -> Self
+    {
+        Asset {
+            d1: note.d1().inner(),
+            d2: note.d2().inner(),
+            contract: value_to_name(note.sc().inner()),
+            nft: note.nft().inner() != 0
+        }
+    }
+
+    /// Returns the amount (fungible) or the asset id (non-fungible)
+    pub fn d1(&self) -> u64
+    {
+        self.d1
+    }
+
+    /// Returns the symbol (fungible) or zero (non-fungible)
+    pub fn d2(&self) -> u64
+    {
+        self.d2
+    }
+
+    /// Returns the account name of the token contract
+    pub fn contract(&self) -> &String
+    {
+        &self.contract
+    }
+
+    /// Returns true if this is a non-fungible asset
+    pub fn is_nft(&self) -> bool
+    {
+        self.nft
+    }
+
+    /// Parses the EOSIO/Antelope asset string of a fungible asset of token contract 'contract', e.g. "1.0000 EOS".
+    /// The precision of the symbol is the number of decimal places of the amount.
+    pub fn from_quantity_string(str: &str, contract: &String) -> Option<Self>
+    {
+        let (amount, symbol) = str.trim().split_once(' ')?;
+        let (int, frac) = amount.split_once('.').unwrap_or((amount, ""));
+        if int.is_empty() || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) || frac.len() > 18
+        {
+            return None;
+        }
+        let precision = frac.len() as u8;
+        let d1 = int.parse::<u64>().ok()?
+            .checked_mul(10_u64.pow(precision as u32))?
+            .checked_add(if frac.is_empty() { 0 } else { frac.parse::<u64>().ok()? })?;
+        let symbol = symbol.trim().to_string();
+        if string_to_symbol_code(&symbol) == 0
+        {
+            return None;
+        }
+        Some(Asset::ft(d1, string_to_symbol(&symbol, precision), contract))
+    }
+
+    /// Returns the EOSIO/Antelope asset string of a fungible asset, e.g. "1.0000 EOS"
+    pub fn to_quantity_string(&self) -> String
+    {
+        let (symbol, precision) = symbol_to_string_precision(self.d2);
+        if precision == 0
+        {
+            return format!("{} {}", self.d1, symbol);
+        }
+        let p = 10_u64.pow(precision as u32);
+        format!("{}.{:0width$} {}", self.d1 / p, self.d1 % p, symbol, width = precision as usize)
+    }
+}
+
 #[cfg(test)]
 mod tests
 {
@@ -144,6 +245,17 @@ mod tests
         assert_eq!(value_to_name(6138663577826885632), "eosio".to_string());
         assert_eq!(value_to_name(6138663587900751872), "eosio.msig".to_string());
         assert_eq!(value_to_name(6138663591592764928), "eosio.token".to_string());
+        assert_eq!(Asset::ft(10000, 1397703940, &"eosio.token".to_string()).to_quantity_string(), "1.0000 EOS".to_string());
+        assert_eq!(Asset::ft(123, 1397703938, &"eosio.token".to_string()).to_quantity_string(), "1.23 EOS".to_string());
+        assert_eq!(Asset::ft(5, string_to_symbol(&"ZEOS".to_string(), 0), &"thezeostoken".to_string()).to_quantity_string(), "5 ZEOS".to_string());
+        let contract = "eosio.token".to_string();
+        assert_eq!(Asset::from_quantity_string("1.0000 EOS", &contract), Some(Asset::ft(10000, 1397703940, &contract)));
+        assert_eq!(Asset::from_quantity_string("1.23 EOS", &contract), Some(Asset::ft(123, 1397703938, &contract)));
+        assert_eq!(Asset::from_quantity_string("5 ZEOS", &contract), Some(Asset::ft(5, string_to_symbol(&"ZEOS".to_string(), 0), &contract)));
+        assert_eq!(Asset::from_quantity_string("1.0000", &contract), None);
+        assert_eq!(Asset::from_quantity_string("-1.0000 EOS", &contract), None);
+        assert_eq!(Asset::from_quantity_string("1.0000 eos", &contract), None);
+        assert_eq!(Asset::from_quantity_string("99999999999999999999 EOS", &contract), None);
     }
 
     #[test]
@@ -0,0 +1,213 @@
+//! Minimal HTTP client: uses the browser's fetch API in WASM builds and a blocking native client otherwise. Native
+//! requests run on their own threads so that awaiting them doesn't block the executor.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::future::Future;
+#[cfg(not(target_arch = "wasm32"))]
+use std::pin::Pin;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Arc, Mutex};
+#[cfg(not(target_arch = "wasm32"))]
+use std::task::{Context, Poll, Waker};
+
+/// Sends 'body' as JSON via HTTP POST to 'url' and returns the response body. Returns an error message if the request
+/// fails or the response status is not 2xx.
+#[cfg(target_arch = "wasm32")]
+pub async fn post_json(url: &str, body: &str) -> Result<String, String>
+{
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{Request, RequestInit, RequestMode, Response};
+
+    let mut opts = RequestInit::new();
+    opts.method("POST");
+    opts.mode(RequestMode::Cors);
+    opts.body(Some(&JsValue::from_str(body)));
+
+    let request = Request::new_with_str_and_init(url, &opts).map_err(|e| format!("{:?}", e))?;
+    request.headers().set("Content-Type", "application/json").map_err(|e| format!("{:?}", e))?;
+    request.headers().set("Accept", "application/json").map_err(|e| format!("{:?}", e))?;
+
+    // send http request using browser window's fetch
+    let window = web_sys::window().ok_or(String::from("no window"))?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await.map_err(|e| format!("{:?}", e))?;
+    let resp: Response = resp_value.dyn_into().map_err(|e| format!("{:?}", e))?;
+    let str = JsFuture::from(resp.text().map_err(|e| format!("{:?}", e))?)
+        .await.map_err(|e| format!("{:?}", e))?
+        .as_string()
+        .ok_or(String::from("fetch: Response expected `String` after .text()"))?;
+    if !resp.ok()
+    {
+        return Err(format!("HTTP {}: {}", resp.status(), str));
+    }
+    Ok(str)
+}
+
+/// Sends 'body' as JSON via HTTP POST to 'url' and returns the response body. Returns an error message if the request
+/// fails or the response status is not 2xx.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn post_json(url: &str, body: &str) -> Result<String, String>
+{
+    let (url, body) = (url.to_string(), body.to_string());
+    Unblock::spawn(move || {
+        match ureq::post(&url).set("Content-Type", "application/json").send_string(&body) {
+            Ok(resp) => resp.into_string().map_err(|e| e.to_string()),
+            Err(ureq::Error::Status(code, resp)) => Err(format!("HTTP {}: {}", code, resp.into_string().unwrap_or_default())),
+            Err(e) => Err(e.to_string())
+        }
+    }).await
+}
+
+/// Fetches 'url' via HTTP GET and returns the response body (native builds only, e.g. for the validator). Returns an
+/// error message if the request fails or the response status is not 2xx.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn get(url: &str) -> Result<String, String>
+{
+    let url = url.to_string();
+    Unblock::spawn(move || {
+        match ureq::get(&url).call() {
+            Ok(resp) => resp.into_string().map_err(|e| e.to_string()),
+            Err(ureq::Error::Status(code, resp)) => Err(format!("HTTP {}: {}", code, resp.into_string().unwrap_or_default())),
+            Err(e) => Err(e.to_string())
+        }
+    }).await
+}
+
+/// Sends 'value' as multipart form field 'field' via HTTP POST to 'url'. The request is sent in 'no-cors' mode in WASM
+/// builds which doesn't allow the browser to read any response content (see: https://stackoverflow.com/a/54906434/2340535).
+#[cfg(target_arch = "wasm32")]
+pub async fn post_form(url: &str, field: &str, value: &str) -> Result<(), String>
+{
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{Request, RequestInit, RequestMode, FormData};
+
+    let fd = FormData::new().map_err(|e| format!("{:?}", e))?;
+    fd.append_with_str(field, value).map_err(|e| format!("{:?}", e))?;
+
+    let mut opts = RequestInit::new();
+    opts.method("POST");
+    opts.mode(RequestMode::NoCors); // TODO: should be CORS
+    opts.body(Some(&fd));
+
+    let request = Request::new_with_str_and_init(url, &opts).map_err(|e| format!("{:?}", e))?;
+    let window = web_sys::window().ok_or(String::from("no window"))?;
+    JsFuture::from(window.fetch_with_request(&request)).await.map_err(|e| format!("{:?}", e))?;
+    Ok(())
+}
+
+/// The boundary of the parts of multipart form data (see: 'post_form')
+#[cfg(not(target_arch = "wasm32"))]
+const FORM_BOUNDARY: &str = "----ZeosFormBoundary7MA4YWxkTrZu0gW";
+
+/// Sends 'value' as multipart form field 'field' via HTTP POST to 'url' (the same encoding as the browser's 'FormData'
+/// in WASM builds). Returns an error message if the request fails or the response status is not 2xx.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn post_form(url: &str, field: &str, value: &str) -> Result<(), String>
+{
+    let body = multipart_form(field, value)?;
+    let content_type = format!("multipart/form-data; boundary={}", FORM_BOUNDARY);
+    let url = url.to_string();
+    Unblock::spawn(move || {
+        match ureq::post(&url).set("Content-Type", &content_type).send_string(&body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, resp)) => Err(format!("HTTP {}: {}", code, resp.into_string().unwrap_or_default())),
+            Err(e) => Err(e.to_string())
+        }
+    }).await
+}
+
+/// Encodes 'value' as the only field 'field' of multipart form data (RFC 7578)
+#[cfg(not(target_arch = "wasm32"))]
+fn multipart_form(field: &str, value: &str) -> Result<String, String>
+{
+    if value.contains(FORM_BOUNDARY) || field.contains('"') || field.contains('\r') || field.contains('\n')
+    {
+        return Err(format!("form field '{}' cannot be encoded", field));
+    }
+    Ok(format!("--{b}\r\nContent-Disposition: form-data; name=\"{field}\"\r\n\r\n{value}\r\n--{b}--\r\n", b = FORM_BOUNDARY, field = field, value = value))
+}
+
+/// The result of a blocking request which runs on its own thread. Awaiting it doesn't block the executor. The native
+/// builds are driven by different executors (tokio in the tests, 'pollster' in the binaries) hence no 'spawn_blocking'.
+#[cfg(not(target_arch = "wasm32"))]
+struct Unblock<T>
+{
+    /// The result once the request has finished and the waker of the task awaiting it
+    state: Arc<Mutex<(Option<Result<T, String>>, Option<Waker>)>>
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + 'static> Unblock<T>
+{
+    /// Runs the blocking request 'f' on a new thread. A panicking request results in an error message.
+    fn spawn(f: impl FnOnce() -> Result<T, String> + Send + 'static) -> Self
+    {
+        let state = Arc::new(Mutex::new((None, None)));
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or_else(|_| Err(String::from("HTTP request panicked")));
+            let waker = {
+                let mut state = shared.lock().unwrap();
+                state.0 = Some(res);
+                state.1.take()
+            };
+            if let Some(waker) = waker
+            {
+                waker.wake();
+            }
+        });
+        Unblock{ state }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<T> Future for Unblock<T>
+{
+    type Output = Result<T, String>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output>
+    {
+        let mut state = self.state.lock().unwrap();
+        match state.0.take() {
+            Some(res) => Poll::Ready(res),
+            None => {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{get, post_json};
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn requests_do_not_block_the_executor()
+    {
+        // the server runs on the same (single threaded) executor as the requests: blocking requests would deadlock
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr());
+        let serve = tokio::spawn(async move {
+            for _ in 0..2
+            {
+                let mut req = loop {
+                    match server.try_recv().unwrap() {
+                        Some(req) => break req,
+                        None => tokio::time::sleep(std::time::Duration::from_millis(10)).await
+                    }
+                };
+                let mut body = String::new();
+                req.as_reader().read_to_string(&mut body).unwrap();
+                let status = if body.is_empty() { 404 } else { 200 };
+                req.respond(tiny_http::Response::from_string(body).with_status_code(status)).unwrap();
+            }
+        });
+        assert_eq!(post_json(&url, "{\"a\":1}").await, Ok(String::from("{\"a\":1}")));
+        assert_eq!(get(&url).await, Err(String::from("HTTP 404: ")));
+        serve.await.unwrap();
+    }
+}
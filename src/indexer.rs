@@ -0,0 +1,937 @@
+//! Chain indexer: mirrors the global data sets of the ZEOS token contract into a local database and serves them to
+//! wallets.
+//!
+//! The indexer follows either the tables of the token contract (the list of encrypted notes, the merkle tree, the
+//! merkle roots and the nullifiers, see: 'Index::follow') or the action traces of executed transactions from a local
+//! recording (see: 'Index::index_fixture' and 'validator::Fixture'). It stores the encrypted notes, the note
+//! commitments, the nodes of the merkle tree, the merkle roots and the nullifiers in a SQLite database. Every root is
+//! indexed: the root after each transaction when replaying action traces and each row of the roots table (checked
+//! against the mirrored merkle tree) when following the contract tables. A followed index compares its notes of
+//! reversible blocks with the chain on each update and discards the entries of all reversible blocks if the chain has
+//! forked (they are indexed again from the new fork).
+//!
+//! Wallets synchronize with an indexer instead of the contract tables if it is set as ZEOS endpoint (see:
+//! 'Wallet::set_zeos_endpoints'). Notes and nullifiers are fetched in bulk in pages of up to 'MAX_PAGE_SIZE' rows and
+//! none of these requests depends on the keys of the wallet. Light wallets trial-decrypt compact notes (without the
+//! encrypted memos, see: 'CompactNoteCiphertext') and fetch the full notes of those which decrypt only. A compact note
+//! takes 228 instead of 740 bytes (the outgoing ciphertext is kept), i.e. about a third.
+//!
+//! The sync API (JSON via HTTP POST, errors are returned as '{"error":"..."}' with a non-2xx status code):
+//!
+//! POST /v1/zeos/get_info            {}                                  => 'IndexerInfo'
+//! POST /v1/zeos/get_notes           {"from": 0, "limit": 1000}          => 'NotesResponse'
+//! POST /v1/zeos/get_compact_notes   {"from": 0, "limit": 1000}          => 'CompactNotesResponse'
+//! POST /v1/zeos/get_notes_by_id     {"ids": [3, 17]}                    => 'NotesResponse'
+//! POST /v1/zeos/get_nullifiers      {"from": 0, "limit": 1000}          => 'NullifiersResponse'
+//! POST /v1/zeos/get_merkle_index    {"cmx": "<hex>"}                    => {"array_index": 4294967295} (or null)
+//! POST /v1/zeos/get_path            {"array_index": 4294967295}         => 'MerklePath'
+
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::builder::HasMerkleTree;
+use crate::contract::{CompactNoteCiphertextEx, Global, TransmittedNoteCiphertextEx};
+use crate::http;
+use crate::network::Network;
+use crate::note::ExtractedNoteCommitment;
+use crate::tree::MerklePath;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::{BTreeSet, HashMap};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
+use rusqlite::{params, Connection, OptionalExtension};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::constants::MERKLE_DEPTH_ORCHARD;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::contract::TokenContract;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::note::TransmittedNoteCiphertext;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::sighash::is_spend;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transaction::{DecodedTransaction, TransactionDecodeError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tree::{Anchor, ContractTree, MerkleHashOrchard, TreeDepthError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::validator::{commitments, Fixture, RecordedTransaction};
+
+/// The maximum number of rows per page of notes or nullifiers
+pub const MAX_PAGE_SIZE: u64 = 1000;
+
+/// The state of an indexer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexerInfo
+{
+    pub network: Network,
+    /// The global state of the token contract as far as it is indexed
+    pub state: Global,
+    /// The number of indexed nullifiers
+    pub nullifier_count: u64,
+    /// The latest merkle root (hex, empty if there are no leaves yet)
+    pub root: String,
+    /// The last irreversible block as of the latest update of the index: the indexed entries of later blocks have not
+    /// been checked against the chain since they became irreversible and may be discarded by a fork
+    #[serde(default)]
+    pub last_irreversible_block: u64
+}
+
+/// A request for a page of notes (by id) or nullifiers (by position)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageRequest
+{
+    #[serde(default)]
+    pub from: u64,
+    /// The maximum number of rows (at most 'MAX_PAGE_SIZE' which is the default)
+    #[serde(default)]
+    pub limit: Option<u64>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotesResponse
+{
+    pub notes: Vec<TransmittedNoteCiphertextEx>,
+    /// True if there are more notes after this page
+    pub more: bool
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactNotesResponse
+{
+    pub notes: Vec<CompactNoteCiphertextEx>,
+    /// True if there are more notes after this page
+    pub more: bool
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NullifiersResponse
+{
+    /// The nullifiers (hex) in the order they were revealed
+    pub nullifiers: Vec<String>,
+    /// True if there are more nullifiers after this page
+    pub more: bool
+}
+
+/// A client of the sync API of an indexer
+#[derive(Debug, Clone)]
+pub struct IndexerClient
+{
+    url: String
+}
+
+impl IndexerClient
+{
+    /// Creates a client of the indexer at 'url' (e.g. "http://127.0.0.1:8790")
+    pub fn new(url: &str) -> Self
+    {
+        IndexerClient{ url: url.trim_end_matches('/').to_string() }
+    }
+
+    async fn post<T: DeserializeOwned>(&self, endpoint: &str, body: serde_json::Value) -> Result<T, String>
+    {
+        let res = http::post_json(&format!("{}/v1/zeos/{}", self.url, endpoint), &body.to_string()).await?;
+        serde_json::from_str(&res).map_err(|e| e.to_string())
+    }
+
+    /// Fetches the state of the indexer
+    pub async fn get_info(&self) -> Result<IndexerInfo, String>
+    {
+        self.post("get_info", json!({})).await
+    }
+
+    /// Fetches the encrypted notes with ids from 'from' up to (excluding) 'to'
+    pub async fn get_encrypted_notes(&self, from: u64, to: u64) -> Result<Vec<TransmittedNoteCiphertextEx>, String>
+    {
+        let mut notes = Vec::new();
+        let mut from = from;
+        while from < to
+        {
+            let res: NotesResponse = self.post("get_notes", json!({ "from": from, "limit": (to - from).min(MAX_PAGE_SIZE) })).await?;
+            match res.notes.last() {
+                Some(n) => from = n.id + 1,
+                None => break
+            }
+            notes.extend(res.notes.into_iter().filter(|n| n.id < to));
+        }
+        Ok(notes)
+    }
+
+    /// Fetches the compact representations of the encrypted notes with ids from 'from' up to (excluding) 'to'
+    pub async fn get_compact_notes(&self, from: u64, to: u64) -> Result<Vec<CompactNoteCiphertextEx>, String>
+    {
+        let mut notes = Vec::new();
+        let mut from = from;
+        while from < to
+        {
+            let res: CompactNotesResponse = self.post("get_compact_notes", json!({ "from": from, "limit": (to - from).min(MAX_PAGE_SIZE) })).await?;
+            match res.notes.last() {
+                Some(n) => from = n.id + 1,
+                None => break
+            }
+            notes.extend(res.notes.into_iter().filter(|n| n.id < to));
+        }
+        Ok(notes)
+    }
+
+    /// Fetches the encrypted notes with 'ids' (e.g. the full notes of compact notes which decrypt)
+    pub async fn get_encrypted_notes_by_id(&self, ids: &[u64]) -> Result<Vec<TransmittedNoteCiphertextEx>, String>
+    {
+        let mut notes = Vec::new();
+        for ids in ids.chunks(MAX_PAGE_SIZE as usize)
+        {
+            let res: NotesResponse = self.post("get_notes_by_id", json!({ "ids": ids })).await?;
+            notes.extend(res.notes);
+        }
+        Ok(notes)
+    }
+}
+
+impl HasMerkleTree for IndexerClient
+{
+    /// fetches the path to the latest root of the indexer (which is a root of the contract as well, regardless of
+    /// 'leaf_count')
+    async fn get_sister_path(
+        &mut self,
+        array_index: u64,
+        _leaf_count: u64,
+    ) -> Result<MerklePath, String>
+    {
+        self.post("get_path", json!({ "array_index": array_index })).await
+    }
+
+    async fn get_merkle_index(
+        &self,
+        hash: ExtractedNoteCommitment
+    ) -> Result<Option<u64>, String>
+    {
+        let res: serde_json::Value = self.post("get_merkle_index", json!({ "cmx": hex::encode(hash.to_bytes()) })).await?;
+        Ok(res["array_index"].as_u64())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(thiserror::Error, Debug)]
+pub enum IndexerError
+{
+    #[error(transparent)]
+    Database(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Decode(#[from] TransactionDecodeError),
+    #[error(transparent)]
+    TreeDepth(#[from] TreeDepthError),
+    #[error("the index belongs to network {0}")]
+    NetworkMismatch(Network),
+    #[error("batch does not continue the index (expected note {0} and leaf {1})")]
+    OutOfOrder(u64, u64),
+    #[error("merkle root mismatch after {0} leaves")]
+    RootMismatch(u64),
+    #[error("{0}")]
+    Source(String)
+}
+
+/// New entries of the global data sets which continue an index (see: 'Index::apply')
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Default)]
+pub struct Batch
+{
+    /// the id of the first note and the position of the first leaf of this batch
+    first_note: u64,
+    first_leaf: u64,
+    notes: Vec<TransmittedNoteCiphertextEx>,
+    leaves: Vec<MerkleHashOrchard>,
+    /// the numbers of leaves of this batch after which the merkle root is indexed
+    roots: Vec<usize>,
+    /// the new rows of the roots table of the contract in order: each is indexed after the leaf it is the root of
+    contract_roots: Vec<MerkleHashOrchard>,
+    nullifiers: Vec<([u8; 32], u64)>,
+    /// the root of the latest tree after this batch as reported by the contract (checked if set)
+    expected_root: Option<MerkleHashOrchard>,
+    /// the number of recorded transactions this batch covers
+    transactions: u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Batch
+{
+    /// Returns true if this batch adds nothing to an index
+    pub fn is_empty(&self) -> bool
+    {
+        self.notes.is_empty() && self.leaves.is_empty() && self.nullifiers.is_empty() && self.transactions == 0
+    }
+
+    /// Fetches the notes, leaves, roots and nullifiers which the tables of 'contract' contain beyond 'note_count'
+    /// notes, 'leaf_count' leaves, 'root_count' roots and 'nullifier_count' nullifiers
+    pub async fn poll(contract: &TokenContract, note_count: u64, leaf_count: u64, root_count: u64, nullifier_count: u64) -> Result<Self, IndexerError>
+    {
+        let global = contract.get_global_state().await.map_err(IndexerError::Source)?;
+        global.check_tree_depth()?;
+        let mut batch = Batch{ first_note: note_count, first_leaf: leaf_count, ..Default::default() };
+
+        if global.note_count > note_count
+        {
+            batch.notes = contract.get_encrypted_notes(note_count, global.note_count - 1).await.map_err(IndexerError::Source)?;
+            if batch.notes.iter().enumerate().any(|(i, n)| n.id != note_count + i as u64)
+            {
+                return Err(IndexerError::Source(format!("notes {} to {} are not contiguous", note_count, global.note_count)));
+            }
+        }
+        for position in leaf_count..global.leaf_count
+        {
+            let leaf = contract.get_merkle_hash(ContractTree::branch_indices(position)[0]).await.map_err(IndexerError::Source)?;
+            batch.leaves.push(leaf.ok_or_else(|| IndexerError::Source(format!("leaf {} not found", position)))?);
+        }
+        if !batch.leaves.is_empty()
+        {
+            batch.roots.push(batch.leaves.len());
+            // the contract may have moved on since its global state was fetched which is detected as mismatch (and
+            // resolved by the next poll)
+            batch.expected_root = contract.get_merkle_hash(*ContractTree::branch_indices(global.leaf_count - 1).last().unwrap()).await.map_err(IndexerError::Source)?;
+        }
+
+        // roots and nullifiers are fetched after the leaves: the roots table thus contains the roots of all leaves of
+        // this batch (later roots are not matched by any leaf and fetched again by the next poll)
+        let roots = contract.get_roots(root_count).await.map_err(IndexerError::Source)?;
+        if roots.iter().enumerate().any(|(i, (id, _))| *id != root_count + i as u64)
+        {
+            return Err(IndexerError::Source(format!("roots from {} are not contiguous", root_count)));
+        }
+        batch.contract_roots = roots.into_iter().map(|(_, root)| root).collect();
+        let nullifiers = contract.get_nullifiers(nullifier_count).await.map_err(IndexerError::Source)?;
+        if nullifiers.iter().enumerate().any(|(i, (id, _, _))| *id != nullifier_count + i as u64)
+        {
+            return Err(IndexerError::Source(format!("nullifiers from {} are not contiguous", nullifier_count)));
+        }
+        batch.nullifiers = nullifiers.into_iter().map(|(_, nf, block_number)| (nf, block_number)).collect();
+        Ok(batch)
+    }
+
+    /// Decodes the recorded 'transactions' of 'network' which continue an index of 'note_count' notes and
+    /// 'leaf_count' leaves. The transactions are not validated (see: 'validator::Validator').
+    pub fn from_transactions(network: Network, transactions: &[RecordedTransaction], note_count: u64, leaf_count: u64) -> Result<Self, IndexerError>
+    {
+        let mut batch = Batch{ first_note: note_count, first_leaf: leaf_count, ..Default::default() };
+        for tx in transactions
+        {
+            let decoded = DecodedTransaction::decode(network, &tx.actions)?;
+            let leaves = batch.leaves.len();
+            for encrypted_note in decoded.encrypted_notes.iter()
+            {
+                batch.notes.push(TransmittedNoteCiphertextEx {
+                    id: note_count + batch.notes.len() as u64,
+                    block_number: tx.block_number,
+                    leaf_index: leaf_count + leaves as u64,
+                    encrypted_note: encrypted_note.clone()
+                });
+            }
+            for za in decoded.zactions()
+            {
+                if is_spend(za.za_type())
+                {
+                    batch.nullifiers.push((za.instance().nf.to_bytes(), tx.block_number));
+                }
+                batch.leaves.extend(commitments(&za).iter().map(MerkleHashOrchard::from_cmx));
+            }
+            if batch.leaves.len() > leaves
+            {
+                batch.roots.push(batch.leaves.len());
+            }
+            batch.transactions += 1;
+        }
+        Ok(batch)
+    }
+}
+
+/// A local index of the global data sets of the ZEOS token contract, stored in a SQLite database
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Index
+{
+    network: Network,
+    db: Connection,
+    /// the merkle tree (a copy of the nodes table)
+    tree: ContractTree,
+    note_count: u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Index
+{
+    /// Opens (or creates) the index of 'network' stored in the database at 'path'
+    pub fn open(path: impl AsRef<Path>, network: Network) -> Result<Self, IndexerError>
+    {
+        Self::init(Connection::open(path)?, network)
+    }
+
+    /// Creates an index of 'network' which is kept in memory only
+    pub fn open_in_memory(network: Network) -> Result<Self, IndexerError>
+    {
+        Self::init(Connection::open_in_memory()?, network)
+    }
+
+    fn init(db: Connection, network: Network) -> Result<Self, IndexerError>
+    {
+        db.execute_batch("
+            CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS notes (id INTEGER PRIMARY KEY, block_number INTEGER NOT NULL, leaf_index INTEGER NOT NULL, epk BLOB NOT NULL, enc BLOB NOT NULL, out BLOB NOT NULL);
+            CREATE TABLE IF NOT EXISTS leaves (position INTEGER PRIMARY KEY, cmx BLOB NOT NULL);
+            CREATE INDEX IF NOT EXISTS leaves_cmx ON leaves (cmx);
+            CREATE TABLE IF NOT EXISTS nodes (idx INTEGER PRIMARY KEY, hash BLOB NOT NULL);
+            CREATE TABLE IF NOT EXISTS roots (root BLOB PRIMARY KEY, leaf_count INTEGER NOT NULL);
+            CREATE TABLE IF NOT EXISTS nullifiers (nf BLOB PRIMARY KEY, block_number INTEGER NOT NULL);
+        ")?;
+        let stored: Option<String> = db.query_row("SELECT value FROM meta WHERE key = 'network'", [], |r| r.get(0)).optional()?;
+        match stored {
+            Some(n) if n != network.to_string() => return Err(IndexerError::NetworkMismatch(n.parse().map_err(|_| IndexerError::Source(format!("unknown network: {}", n)))?)),
+            Some(_) => (),
+            None => { db.execute("INSERT INTO meta (key, value) VALUES ('network', ?1)", [network.to_string()])?; }
+        }
+        let note_count = db.query_row("SELECT COALESCE(MAX(id) + 1, 0) FROM notes", [], |r| r.get(0))?;
+        let tree = Self::load_tree(&db)?;
+        Ok(Index{ network, db, tree, note_count })
+    }
+
+    fn load_tree(db: &Connection) -> Result<ContractTree, IndexerError>
+    {
+        let leaf_count = db.query_row("SELECT COUNT(*) FROM leaves", [], |r| r.get(0))?;
+        let mut nodes = HashMap::new();
+        let mut stmt = db.prepare("SELECT idx, hash FROM nodes")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            let hash: [u8; 32] = row.get(1)?;
+            let hash = Option::from(MerkleHashOrchard::from_bytes(&hash)).ok_or_else(|| IndexerError::Source(String::from("invalid merkle node")))?;
+            nodes.insert(row.get(0)?, hash);
+        }
+        Ok(ContractTree::from_parts(leaf_count, nodes))
+    }
+
+    /// Returns the network of this index
+    pub fn network(&self) -> Network
+    {
+        self.network
+    }
+
+    /// Returns the merkle tree of note commitments
+    pub fn tree(&self) -> &ContractTree
+    {
+        &self.tree
+    }
+
+    /// Returns the number of indexed notes
+    pub fn note_count(&self) -> u64
+    {
+        self.note_count
+    }
+
+    /// Returns the latest merkle root or 'None' if there are no leaves yet
+    pub fn root(&self) -> Option<Anchor>
+    {
+        let n = self.tree.leaf_count();
+        if n == 0
+        {
+            return None;
+        }
+        let root = *ContractTree::branch_indices(n - 1).last().unwrap();
+        Some(self.tree.nodes()[&root].into())
+    }
+
+    /// Returns the state of this index
+    pub fn info(&self) -> Result<IndexerInfo, IndexerError>
+    {
+        Ok(IndexerInfo {
+            network: self.network,
+            state: Global{ note_count: self.note_count, leaf_count: self.tree.leaf_count(), tree_depth: MERKLE_DEPTH_ORCHARD as u64 },
+            nullifier_count: self.db.query_row("SELECT COUNT(*) FROM nullifiers", [], |r| r.get(0))?,
+            root: self.root().map(|r| hex::encode(r.to_bytes())).unwrap_or_default(),
+            last_irreversible_block: self.last_irreversible_block()?
+        })
+    }
+
+    /// Returns the last irreversible block of the chain as of the latest update of this index (see: 'Index::follow')
+    pub fn last_irreversible_block(&self) -> Result<u64, IndexerError>
+    {
+        let lib: Option<String> = self.db.query_row("SELECT value FROM meta WHERE key = 'last_irreversible_block'", [], |r| r.get(0)).optional()?;
+        Ok(lib.and_then(|v| v.parse().ok()).unwrap_or(0))
+    }
+
+    fn set_last_irreversible_block(&self, block_number: u64) -> Result<(), IndexerError>
+    {
+        let lib = block_number.max(self.last_irreversible_block()?);
+        self.db.execute("INSERT INTO meta (key, value) VALUES ('last_irreversible_block', ?1) ON CONFLICT (key) DO UPDATE SET value = ?1", [lib.to_string()])?;
+        Ok(())
+    }
+
+    /// Returns true if 'anchor' is an indexed merkle root
+    pub fn is_root(&self, anchor: &Anchor) -> Result<bool, IndexerError>
+    {
+        Ok(self.db.query_row("SELECT 1 FROM roots WHERE root = ?1", [&anchor.to_bytes()[..]], |_| Ok(())).optional()?.is_some())
+    }
+
+    /// Returns up to 'limit' notes with ids from 'from' and whether there are more
+    pub fn notes(&self, from: u64, limit: u64) -> Result<(Vec<TransmittedNoteCiphertextEx>, bool), IndexerError>
+    {
+        let mut stmt = self.db.prepare("SELECT id, block_number, leaf_index, epk, enc, out FROM notes WHERE id >= ?1 ORDER BY id LIMIT ?2")?;
+        let mut notes = stmt.query_map(params![from, limit + 1], |r| Ok(TransmittedNoteCiphertextEx {
+            id: r.get(0)?,
+            block_number: r.get(1)?,
+            leaf_index: r.get(2)?,
+            encrypted_note: TransmittedNoteCiphertext{ epk_bytes: r.get(3)?, enc_ciphertext: r.get(4)?, out_ciphertext: r.get(5)? }
+        }))?.collect::<Result<Vec<_>, _>>()?;
+        let more = notes.len() as u64 > limit;
+        notes.truncate(limit as usize);
+        Ok((notes, more))
+    }
+
+    /// Returns the notes with 'ids' (unknown ids are skipped)
+    pub fn notes_by_id(&self, ids: &[u64]) -> Result<Vec<TransmittedNoteCiphertextEx>, IndexerError>
+    {
+        let mut notes = Vec::new();
+        for id in ids
+        {
+            notes.extend(self.notes(*id, 1)?.0.into_iter().filter(|n| n.id == *id));
+        }
+        Ok(notes)
+    }
+
+    /// Returns up to 'limit' nullifiers from position 'from' (in the order they were revealed) and whether there are
+    /// more
+    pub fn nullifiers(&self, from: u64, limit: u64) -> Result<(Vec<[u8; 32]>, bool), IndexerError>
+    {
+        let mut stmt = self.db.prepare("SELECT nf FROM nullifiers ORDER BY rowid LIMIT ?1 OFFSET ?2")?;
+        let mut nullifiers = stmt.query_map(params![limit + 1, from], |r| r.get(0))?.collect::<Result<Vec<[u8; 32]>, _>>()?;
+        let more = nullifiers.len() as u64 > limit;
+        nullifiers.truncate(limit as usize);
+        Ok((nullifiers, more))
+    }
+
+    /// Returns the array index of the leaf of note commitment 'cmx' (see: 'HasMerkleTree::get_merkle_index')
+    pub fn merkle_index(&self, cmx: &[u8; 32]) -> Result<Option<u64>, IndexerError>
+    {
+        let position: Option<u64> = self.db.query_row("SELECT position FROM leaves WHERE cmx = ?1 ORDER BY position LIMIT 1", [&cmx[..]], |r| r.get(0)).optional()?;
+        Ok(position.map(|p| ContractTree::branch_indices(p)[0]))
+    }
+
+    /// Returns the merkle path of the leaf with array index 'array_index' to the latest root of its tree
+    pub fn path(&self, array_index: u64) -> Option<MerklePath>
+    {
+        ContractTree::leaf_position(array_index).filter(|p| *p < self.tree.leaf_count()).map(|p| self.tree.path(p))
+    }
+
+    /// Adds 'batch' to this index. Returns the number of new notes.
+    pub fn apply(&mut self, batch: Batch) -> Result<usize, IndexerError>
+    {
+        if batch.first_note != self.note_count || batch.first_leaf != self.tree.leaf_count()
+        {
+            return Err(IndexerError::OutOfOrder(self.note_count, self.tree.leaf_count()));
+        }
+        let res = self.write(&batch);
+        if res.is_err()
+        {
+            // the database transaction is rolled back: restore the tree as well
+            self.tree = Self::load_tree(&self.db)?;
+        }
+        res?;
+        self.note_count += batch.notes.len() as u64;
+        Ok(batch.notes.len())
+    }
+
+    fn write(&mut self, batch: &Batch) -> Result<(), IndexerError>
+    {
+        let tx = self.db.transaction()?;
+        for n in batch.notes.iter()
+        {
+            let en = &n.encrypted_note;
+            tx.execute("INSERT INTO notes (id, block_number, leaf_index, epk, enc, out) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![n.id, n.block_number, n.leaf_index, &en.epk_bytes[..], &en.enc_ciphertext[..], &en.out_ciphertext[..]])?;
+        }
+
+        let mut nodes = BTreeSet::new();
+        let mut root = None;
+        let mut contract_roots = batch.contract_roots.iter().peekable();
+        for (i, leaf) in batch.leaves.iter().enumerate()
+        {
+            let position = self.tree.leaf_count();
+            root = Some(self.tree.append(*leaf));
+            nodes.extend(ContractTree::branch_indices(position));
+            tx.execute("INSERT INTO leaves (position, cmx) VALUES (?1, ?2)", params![position, &leaf.to_bytes()[..]])?;
+            let is_contract_root = contract_roots.next_if(|r| Anchor::from(**r) == root.unwrap()).is_some();
+            if batch.roots.contains(&(i + 1)) || is_contract_root
+            {
+                tx.execute("INSERT OR IGNORE INTO roots (root, leaf_count) VALUES (?1, ?2)", params![&root.unwrap().to_bytes()[..], position + 1])?;
+            }
+        }
+        if let (Some(root), Some(expected)) = (root, batch.expected_root)
+        {
+            if root != expected.into()
+            {
+                return Err(IndexerError::RootMismatch(self.tree.leaf_count()));
+            }
+        }
+        for idx in nodes
+        {
+            tx.execute("INSERT OR REPLACE INTO nodes (idx, hash) VALUES (?1, ?2)", params![idx, &self.tree.nodes()[&idx].to_bytes()[..]])?;
+        }
+
+        for (nf, block_number) in batch.nullifiers.iter()
+        {
+            tx.execute("INSERT OR IGNORE INTO nullifiers (nf, block_number) VALUES (?1, ?2)", params![&nf[..], block_number])?;
+        }
+        if batch.transactions > 0
+        {
+            tx.execute("INSERT INTO meta (key, value) VALUES ('transactions', ?1) ON CONFLICT (key) DO UPDATE SET value = value + ?1", [batch.transactions])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Indexes the notes, leaves, roots and nullifiers which have been added to the tables of 'contract' after
+    /// discarding those of forked blocks. Returns the number of new notes.
+    pub async fn follow(&mut self, contract: &TokenContract) -> Result<usize, IndexerError>
+    {
+        // the chain is final up to 'lib' once it has been fetched: the entries of the blocks after the previous last
+        // irreversible block are checked against the chain before it is advanced
+        let lib = contract.get_info().await.map_err(IndexerError::Source)?.last_irreversible_block_num;
+        self.discard_fork(contract).await?;
+        self.set_last_irreversible_block(lib)?;
+        let root_count = self.db.query_row("SELECT COUNT(*) FROM roots", [], |r| r.get(0))?;
+        let nullifier_count = self.db.query_row("SELECT COUNT(*) FROM nullifiers", [], |r| r.get(0))?;
+        let batch = Batch::poll(contract, self.note_count, self.tree.leaf_count(), root_count, nullifier_count).await?;
+        self.apply(batch)
+    }
+
+    /// Compares the indexed notes of reversible blocks with the notes of 'contract'. If the chain has forked, all
+    /// entries of reversible blocks are discarded (and indexed again by the next poll). Returns true if the chain has
+    /// forked.
+    async fn discard_fork(&mut self, contract: &TokenContract) -> Result<bool, IndexerError>
+    {
+        let lib = self.last_irreversible_block()?;
+        let first: Option<u64> = self.db.query_row("SELECT MIN(id) FROM notes WHERE block_number > ?1", [lib], |r| r.get(0))?;
+        let first = match first {
+            Some(first) => first,
+            None => return Ok(false)
+        };
+        let (indexed, _) = self.notes(first, self.note_count - first)?;
+        let global = contract.get_global_state().await.map_err(IndexerError::Source)?;
+        // the upper bound is inclusive
+        let notes = match global.note_count.min(self.note_count) {
+            to if to > first => contract.get_encrypted_notes(first, to - 1).await.map_err(IndexerError::Source)?,
+            _ => Vec::new()
+        };
+        let key = |n: &TransmittedNoteCiphertextEx| (n.id, n.block_number, n.leaf_index, n.encrypted_note.epk_bytes);
+        if notes.iter().map(key).eq(indexed.iter().map(key))
+        {
+            return Ok(false);
+        }
+        self.truncate(first, indexed[0].leaf_index, lib)?;
+        Ok(true)
+    }
+
+    /// Discards the notes from id 'note_count', the leaves from position 'leaf_count' (and the roots of the discarded
+    /// leaves) and the nullifiers revealed after block 'block_number'
+    fn truncate(&mut self, note_count: u64, leaf_count: u64, block_number: u64) -> Result<(), IndexerError>
+    {
+        let tx = self.db.transaction()?;
+        tx.execute("DELETE FROM notes WHERE id >= ?1", [note_count])?;
+        tx.execute("DELETE FROM leaves WHERE position >= ?1", [leaf_count])?;
+        tx.execute("DELETE FROM roots WHERE leaf_count > ?1", [leaf_count])?;
+        tx.execute("DELETE FROM nullifiers WHERE block_number > ?1", [block_number])?;
+        // the nodes of the merkle tree are rebuilt from the remaining leaves
+        let mut tree = ContractTree::new();
+        {
+            let mut stmt = tx.prepare("SELECT cmx FROM leaves ORDER BY position")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()?
+            {
+                let cmx: [u8; 32] = row.get(0)?;
+                tree.append(Option::from(MerkleHashOrchard::from_bytes(&cmx)).ok_or_else(|| IndexerError::Source(String::from("invalid leaf")))?);
+            }
+        }
+        tx.execute("DELETE FROM nodes", [])?;
+        for (idx, hash) in tree.nodes().iter()
+        {
+            tx.execute("INSERT INTO nodes (idx, hash) VALUES (?1, ?2)", params![idx, &hash.to_bytes()[..]])?;
+        }
+        tx.commit()?;
+        self.tree = tree;
+        self.note_count = note_count;
+        Ok(())
+    }
+
+    /// Indexes the transactions of 'fixture' which have not been indexed yet. Returns the number of new
+    /// transactions.
+    pub fn index_fixture(&mut self, fixture: &Fixture) -> Result<usize, IndexerError>
+    {
+        if fixture.network != self.network
+        {
+            return Err(IndexerError::NetworkMismatch(self.network));
+        }
+        let indexed: Option<String> = self.db.query_row("SELECT value FROM meta WHERE key = 'transactions'", [], |r| r.get(0)).optional()?;
+        let indexed = indexed.and_then(|v| v.parse().ok()).unwrap_or(0usize).min(fixture.transactions.len());
+        let batch = Batch::from_transactions(self.network, &fixture.transactions[indexed..], self.note_count, self.tree.leaf_count())?;
+        let n = batch.transactions as usize;
+        self.apply(batch)?;
+        // recorded transactions are replayed, not followed: they are never discarded by a fork
+        if let Some(tx) = fixture.transactions.last()
+        {
+            self.set_last_irreversible_block(tx.block_number)?;
+        }
+        Ok(n)
+    }
+
+    /// Handles a request of the sync API and returns HTTP status and response body
+    pub fn handle(&self, url: &str, body: &str) -> (u16, String)
+    {
+        let page = || serde_json::from_str::<PageRequest>(if body.trim().is_empty() { "{}" } else { body })
+            .map(|p| (p.from, p.limit.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE)))
+            .map_err(|e| (400, e.to_string()));
+        let res: Result<String, (u16, String)> = match url
+        {
+            "/v1/zeos/get_info" => self.info().map(|info| serde_json::to_string(&info).unwrap()).map_err(|e| (500, e.to_string())),
+            "/v1/zeos/get_notes" => page().and_then(|(from, limit)| self.notes(from, limit).map_err(|e| (500, e.to_string())))
+                .map(|(notes, more)| serde_json::to_string(&NotesResponse{ notes, more }).unwrap()),
+            "/v1/zeos/get_compact_notes" => page().and_then(|(from, limit)| self.notes(from, limit).map_err(|e| (500, e.to_string())))
+                .map(|(notes, more)| serde_json::to_string(&CompactNotesResponse{ notes: notes.iter().map(CompactNoteCiphertextEx::from).collect(), more }).unwrap()),
+            "/v1/zeos/get_notes_by_id" => match serde_json::from_str::<serde_json::Value>(body).ok().and_then(|v| serde_json::from_value::<Vec<u64>>(v["ids"].clone()).ok()) {
+                Some(ids) if ids.len() as u64 <= MAX_PAGE_SIZE => self.notes_by_id(&ids)
+                    .map(|notes| serde_json::to_string(&NotesResponse{ notes, more: false }).unwrap())
+                    .map_err(|e| (500, e.to_string())),
+                Some(_) => Err((400, format!("at most {} ids per request", MAX_PAGE_SIZE))),
+                None => Err((400, String::from("invalid ids")))
+            },
+            "/v1/zeos/get_nullifiers" => page().and_then(|(from, limit)| self.nullifiers(from, limit).map_err(|e| (500, e.to_string())))
+                .map(|(nfs, more)| serde_json::to_string(&NullifiersResponse{ nullifiers: nfs.iter().map(hex::encode).collect(), more }).unwrap()),
+            "/v1/zeos/get_merkle_index" => {
+                let cmx = serde_json::from_str::<serde_json::Value>(body).ok()
+                    .and_then(|v| v["cmx"].as_str().and_then(|s| hex::decode(s).ok()))
+                    .and_then(|v| <[u8; 32]>::try_from(v).ok());
+                match cmx {
+                    Some(cmx) => self.merkle_index(&cmx).map(|idx| json!({ "array_index": idx }).to_string()).map_err(|e| (500, e.to_string())),
+                    None => Err((400, String::from("invalid cmx")))
+                }
+            }
+            "/v1/zeos/get_path" => match serde_json::from_str::<serde_json::Value>(body).ok().and_then(|v| v["array_index"].as_u64()) {
+                Some(idx) => self.path(idx).map(|p| serde_json::to_string(&p).unwrap()).ok_or((404, format!("no leaf at array index {}", idx))),
+                None => Err((400, String::from("invalid array_index")))
+            },
+            _ => Err((404, format!("not found: {}", url)))
+        };
+        match res {
+            Ok(body) => (200, body),
+            Err((status, e)) => (status, json!({ "error": e }).to_string())
+        }
+    }
+}
+
+/// Serves the sync API of 'index' until the server is shut down
+#[cfg(not(target_arch = "wasm32"))]
+pub fn serve(index: &Mutex<Index>, server: &tiny_http::Server)
+{
+    use std::io::Read;
+
+    for mut request in server.incoming_requests()
+    {
+        let mut body = String::new();
+        let (status, body) = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => index.lock().unwrap().handle(request.url(), &body),
+            Err(e) => (400, json!({ "error": e.to_string() }).to_string())
+        };
+        let response = tiny_http::Response::from_string(body)
+            .with_status_code(status)
+            .with_header("Content-Type: application/json".parse::<tiny_http::Header>().unwrap())
+            .with_header("Access-Control-Allow-Origin: *".parse::<tiny_http::Header>().unwrap());
+        let _ = request.respond(response);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::{Arc, Mutex};
+    use nonempty::NonEmpty;
+    use rand::rngs::OsRng;
+    use rustzeos::halo2::Proof;
+    use super::{serve, Index, IndexerClient, IndexerError};
+    use crate::action::{RawZAction, ZA_MINTFT, ZA_TRANSFERFT};
+    use crate::address::Address;
+    use crate::builder::{exec_action, HasMerkleTree};
+    use crate::constants::{MERKLE_DEPTH_ORCHARD, MT_ARR_LEAF_ROW_OFFSET};
+    use crate::contract::TokenContract;
+    use crate::eosio::{string_to_symbol, Asset};
+    use crate::keys::{FullViewingKey, SpendingKey};
+    use crate::network::Network;
+    use crate::note::{ExtractedNoteCommitment, Nullifier};
+    use crate::simulator::SimulatedChain;
+    use crate::tree::{ContractTree, MerkleHashOrchard};
+    use crate::validator::testing::{ft_note, transaction};
+    use crate::validator::{Fixture, RecordedTransaction};
+    use crate::wallet::Wallet;
+
+    #[tokio::test]
+    async fn follow_contract()
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        let mut wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        let eos = Asset::ft(10000, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        for _ in 0..12
+        {
+            chain.mint(Address::from_bech32m(&wallet.address(0), Network::Local).unwrap(), &eos, "");
+        }
+
+        // the index mirrors the contract tables: notes, leaves and the root
+        let mut contract = TokenContract::new(Network::Local, NonEmpty::new(url.clone()));
+        let index = Arc::new(Mutex::new(Index::open_in_memory(Network::Local).unwrap()));
+        assert_eq!(index.lock().unwrap().follow(&contract).await.unwrap(), 12);
+        assert_eq!(index.lock().unwrap().follow(&contract).await.unwrap(), 0);
+        chain.mint(Address::from_bech32m(&wallet.address(0), Network::Local).unwrap(), &eos, "lazy memo");
+        assert_eq!(index.lock().unwrap().follow(&contract).await.unwrap(), 1);
+        // every root and nullifier of the contract tables is indexed, not only the latest root
+        let nf = Nullifier::dummy(&mut OsRng);
+        chain.spend(&[nf]);
+        assert_eq!(index.lock().unwrap().follow(&contract).await.unwrap(), 0);
+        let roots = contract.get_roots(0).await.unwrap();
+        assert_eq!(roots.len(), 13);
+        assert!(roots.iter().all(|(_, r)| index.lock().unwrap().is_root(&(*r).into()).unwrap()));
+        assert_eq!(index.lock().unwrap().nullifiers(0, 10).unwrap().0, vec![nf.to_bytes()]);
+        let info = index.lock().unwrap().info().unwrap();
+        assert_eq!((info.state.note_count, info.state.leaf_count), (13, 13));
+        assert_eq!(info.root, hex::encode(contract.get_merkle_hash(0).await.unwrap().unwrap().to_bytes()));
+
+        let index_server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let index_url = format!("http://{}", index_server.server_addr());
+        {
+            let (index, index_server) = (index.clone(), index_server.clone());
+            std::thread::spawn(move || serve(&index, &index_server));
+        }
+
+        // the sync API serves the same notes and merkle paths as the contract tables
+        let mut client = IndexerClient::new(&index_url);
+        let notes = client.get_encrypted_notes(0, 13).await.unwrap();
+        assert_eq!(notes.len(), 13);
+        assert_eq!(notes[12].leaf_index, 12);
+        // compact notes take less than half the bandwidth
+        let compact_notes = client.get_compact_notes(0, 13).await.unwrap();
+        assert_eq!(compact_notes.len(), 13);
+        assert!(serde_json::to_string(&compact_notes).unwrap().len() * 2 < serde_json::to_string(&notes).unwrap().len());
+        assert_eq!(client.get_encrypted_notes_by_id(&[12, 3, 99]).await.unwrap().iter().map(|n| n.id).collect::<Vec<_>>(), vec![12, 3]);
+        let leaf = contract.get_merkle_hash(ContractTree::branch_indices(12)[0]).await.unwrap().unwrap();
+        let cmx = ExtractedNoteCommitment::from_bytes(&leaf.to_bytes()).unwrap();
+        let idx = client.get_merkle_index(cmx).await.unwrap().unwrap();
+        assert_eq!(Some(idx), contract.get_merkle_index(cmx).await.unwrap());
+        assert_eq!(client.get_sister_path(idx, 13).await.unwrap().root(cmx), contract.get_sister_path(idx, 13).await.unwrap().root(cmx));
+
+        // a wallet synchronizes with the indexer
+        wallet.set_endpoints(vec![url], vec![]);
+        wallet.set_zeos_endpoints(vec![index_url]);
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.state().note_count, 13);
+        let notes = wallet.spendable_notes().unwrap();
+        assert_eq!(notes.len(), 13);
+        // the memos are fetched with the full notes of the compact notes which decrypt
+        assert_eq!(&notes[12].note.memo()[..9], b"lazy memo");
+    }
+
+    #[tokio::test]
+    async fn follow_fork()
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        let contract = TokenContract::new(Network::Local, NonEmpty::new(url.clone()));
+        let index = Arc::new(Mutex::new(Index::open_in_memory(Network::Local).unwrap()));
+        let index_server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let index_url = format!("http://{}", index_server.server_addr());
+        {
+            let (index, index_server) = (index.clone(), index_server.clone());
+            std::thread::spawn(move || serve(&index, &index_server));
+        }
+        let mut wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        wallet.set_endpoints(vec![url], vec![]);
+        wallet.set_zeos_endpoints(vec![index_url]);
+        let address = Address::from_bech32m(&wallet.address(0), Network::Local).unwrap();
+        let eos = |amount: u64| Asset::ft(amount, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        let amounts = |wallet: &Wallet| wallet.spendable_notes().unwrap().iter().map(|n| n.note.d1().inner()).collect::<Vec<u64>>();
+        let confirmed = |wallet: &Wallet| wallet.history().unwrap().iter().map(|e| e.confirmed).collect::<Vec<bool>>();
+
+        // the two most recent blocks are reversible: note 2 and the nullifier are unconfirmed
+        chain.set_reversible_blocks(2);
+        for amount in [1, 2, 3]
+        {
+            chain.mint(address, &eos(amount), "");
+        }
+        chain.spend(&[Nullifier::dummy(&mut OsRng)]);
+        assert_eq!(index.lock().unwrap().follow(&contract).await.unwrap(), 3);
+        assert_eq!(index.lock().unwrap().info().unwrap().last_irreversible_block, 2);
+        wallet.sync().await.unwrap();
+        assert_eq!(confirmed(&wallet), vec![true, true, false]);
+        let root = index.lock().unwrap().root().unwrap();
+
+        // block 3 is replaced by a fork: the index discards the notes, leaves, roots and nullifiers of all reversible
+        // blocks and indexes them again from the new fork
+        chain.fork(2).unwrap();
+        chain.mint(address, &eos(4), "");
+        chain.mint(address, &eos(5), "");
+        assert_eq!(index.lock().unwrap().follow(&contract).await.unwrap(), 2);
+        let contract_root = contract.get_merkle_hash(0).await.unwrap().unwrap();
+        {
+            let index = index.lock().unwrap();
+            assert_eq!(index.note_count(), 4);
+            assert_eq!(index.root(), Some(contract_root.into()));
+            assert!(!index.is_root(&root).unwrap());
+            assert!(index.nullifiers(0, 10).unwrap().0.is_empty());
+        }
+        assert_eq!(index.lock().unwrap().follow(&contract).await.unwrap(), 0);
+
+        // the wallet detects the fork through the indexer and rolls back
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.state().note_count, 4);
+        assert_eq!(amounts(&wallet), vec![1, 2, 4, 5]);
+        assert_eq!(confirmed(&wallet), vec![true, true, false, false]);
+    }
+
+    #[test]
+    fn index_fixture()
+    {
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        // the indexer does not verify proofs
+        let proof = Proof::new(vec![0; 32]);
+        let tx = |rzactions: Vec<RawZAction>| transaction(&sk, rzactions, &proof);
+        let note = |d1: u64, i: u32| ft_note(&fvk, d1, i);
+
+        let path = std::env::temp_dir().join(format!("zeos-indexer-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut index = Index::open(&path, Network::Kylin).unwrap();
+
+        // mint a note, then spend it
+        let note_a = note(10, 0);
+        let mint = tx(vec![RawZAction::from_parts(ZA_MINTFT, &fvk, None, None, Some(note_a), None, String::new(), OsRng)]);
+        let mut fixture = Fixture{ network: Network::Kylin, transactions: vec![RecordedTransaction{ id: String::from("a"), block_number: 1, actions: mint }], proofs: Default::default() };
+        assert_eq!(index.index_fixture(&fixture).unwrap(), 1);
+        let cm: ExtractedNoteCommitment = note_a.commitment().into();
+        assert_eq!(index.merkle_index(&cm.to_bytes()).unwrap(), Some(MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD)));
+        let anchor = index.path(index.merkle_index(&cm.to_bytes()).unwrap().unwrap()).unwrap().root(cm);
+        assert!(index.is_root(&anchor).unwrap());
+
+        let transfer = tx(vec![RawZAction::from_parts(ZA_TRANSFERFT, &fvk, Some(index.tree().path(0)), Some(note_a), Some(note(7, 1)), Some(note(3, 0)), String::new(), OsRng)]);
+        fixture.transactions.push(RecordedTransaction{ id: String::from("b"), block_number: 2, actions: transfer });
+        fixture.transactions.push(RecordedTransaction{ id: String::from("c"), block_number: 3, actions: vec![exec_action(Network::Kylin)] });
+        // only the new transactions are indexed
+        assert_eq!(index.index_fixture(&fixture).unwrap(), 2);
+        assert_eq!(index.index_fixture(&fixture).unwrap(), 0);
+        let info = index.info().unwrap();
+        assert_eq!((info.state.note_count, info.state.leaf_count, info.nullifier_count), (3, 3, 1));
+        let (notes, more) = index.notes(1, 1).unwrap();
+        assert_eq!((notes[0].id, notes[0].block_number, notes[0].leaf_index, more), (1, 2, 1, true));
+        assert_eq!(index.nullifiers(0, 10).unwrap().0, vec![note_a.nullifier(&fvk).to_bytes()]);
+
+        // the index is persistent and bound to its network
+        let root = index.root();
+        drop(index);
+        let index = Index::open(&path, Network::Kylin).unwrap();
+        assert_eq!((index.note_count(), index.tree().leaf_count(), index.root()), (3, 3, root));
+        assert_eq!(index.tree().path(2).auth_path(), index.path(ContractTree::branch_indices(2)[0]).unwrap().auth_path());
+        assert!(matches!(Index::open(&path, Network::Mainnet), Err(IndexerError::NetworkMismatch(Network::Kylin))));
+        assert!(!index.is_root(&MerkleHashOrchard::from_cmx(&cm).into()).unwrap());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -21,32 +21,54 @@
 
 mod action;
 mod address;
+mod base85;
 pub mod builder;
 pub mod bundle;
 pub mod circuit;
 pub mod contract;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod daemon;
+pub mod encoding;
+mod http;
+pub mod indexer;
 pub mod wallet;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod wallet_file;
+pub mod wallet_format;
+pub mod wallet_store;
 mod eosio;
 mod constants;
 pub mod keys;
+pub mod network;
+pub mod payment_request;
 pub mod note;
 pub mod note_encryption;
 pub mod primitives;
+pub mod sighash;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod simulator;
+pub mod prover;
+pub mod relayer;
 mod spec;
+pub mod transaction;
 pub mod tree;
+pub mod unproven;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod validator;
 pub mod value;
 pub mod zip32;
 
 #[cfg(test)]
 mod test_vectors;
 
-pub use action::RawZAction;
-pub use address::Address;
+pub use action::{RawZAction, ZAction};
+pub use address::{Address, AddressError};
 pub use bundle::Bundle;
-extern crate nonempty;
-use nonempty::{NonEmpty, nonempty};
+pub use eosio::Asset;
 pub use note::Note;
 pub use tree::Anchor;
+pub use constants::MERKLE_DEPTH_ORCHARD;
+pub use network::Network;
 
 use crate::keys::SpendingKey;
 use crate::keys::FullViewingKey;
@@ -58,16 +80,22 @@ use wasm_bindgen::prelude::*;
 extern crate console_error_panic_hook;
 extern crate serde_json;
 
-use crate::contract::{TokenContract, EOSGetTableRowsPayload};
+use crate::contract::{TokenContract, EOSGetTableRowsPayload, MERKLE_TABLE};
 
 #[macro_use]
 extern crate serde_derive;
 
-const ENDPOINTS: NonEmpty<&'static str> = nonempty![
-    //"http://eos.api.eosnation.io"
-    //"https://kylin.eosn.io"
-    "https://kylin-dsp-1.liquidapps.io"
-];
+/// Checks if 'address' is a valid shielded address of 'network' (e.g. "kylin"). Returns 'undefined' if it is valid and
+/// a message describing the problem otherwise.
+#[wasm_bindgen]
+pub fn validate_address(address: String, network: String) -> Option<String>
+{
+    let network = match network.parse::<Network>() {
+        Ok(n) => n,
+        Err(e) => return Some(e.to_string())
+    };
+    Address::from_bech32m(&address, network).err().map(|e| e.to_string())
+}
 
 #[wasm_bindgen]
 pub async fn test1(_js_objects: JsValue) -> String
@@ -84,10 +112,11 @@ pub async fn test1(_js_objects: JsValue) -> String
 pub async fn test_get_table_rows() -> JsValue
 {
     // prepare POST request to fetch from EOSIO multiindex table
+    let network = Network::default();
     let payload = EOSGetTableRowsPayload{
-        code: "thezeostoken".to_string(),
-        table: "mteosram".to_string(),
-        scope: "thezeostoken".to_string(),
+        code: network.token_contract().to_string(),
+        table: MERKLE_TABLE.to_string(),
+        scope: network.token_contract().to_string(),
         index_position: "primary".to_string(),
         key_type: "uint64_t".to_string(),
         encode_type: "dec".to_string(),
@@ -98,19 +127,22 @@ pub async fn test_get_table_rows() -> JsValue
         show_payer: false
     };
     
-    let thezeostoken = TokenContract::new(ENDPOINTS.map(String::from));
-    let res = thezeostoken.get_table_rows(&mut payload.clone()).await;
-    JsValue::from_str(&serde_json::to_string(&res).unwrap())
+    let thezeostoken = TokenContract::from_network(Network::default());
+    match thezeostoken.get_table_rows(&mut payload.clone()).await {
+        Ok(res) => JsValue::from_str(&serde_json::to_string(&res).unwrap()),
+        Err(e) => JsValue::from_str(&e)
+    }
 }
 
 #[wasm_bindgen]
 pub async fn test_merkle_hash_fetch(index: String) -> JsValue
 {
-    let thezeostoken = TokenContract::new(ENDPOINTS.map(String::from));
+    let thezeostoken = TokenContract::from_network(Network::default());
     let mh = thezeostoken.get_merkle_hash(index.parse::<u64>().unwrap()).await;
     match mh {
-        None => JsValue::NULL,
-        Some(x) => JsValue::from_str(&hex::encode(x.inner().0[0].to_le_bytes()))
+        Ok(None) => JsValue::NULL,
+        Ok(Some(x)) => JsValue::from_str(&hex::encode(x.inner().0[0].to_le_bytes())),
+        Err(e) => JsValue::from_str(&e)
     }
 }
 
@@ -125,45 +157,48 @@ pub async fn test_merkle_index_fetch(hash: String) -> JsValue
         u64::from_le_bytes(arr[16..24].try_into().unwrap()),
         u64::from_le_bytes(arr[24..32].try_into().unwrap())
     ]));
-    let thezeostoken = TokenContract::new(ENDPOINTS.map(String::from));
+    let thezeostoken = TokenContract::from_network(Network::default());
     let index = thezeostoken.get_merkle_index(value).await;
     match index {
-        None => JsValue::NULL,
-        Some(x) => JsValue::from_str(&x.to_string())
+        Ok(None) => JsValue::NULL,
+        Ok(Some(x)) => JsValue::from_str(&x.to_string()),
+        Err(e) => JsValue::from_str(&e)
     }
 }
 
 #[wasm_bindgen]
 pub async fn test_merkle_path_fetch(array_index: String, leaf_count: String) -> JsValue
 {
-    // remember to set the correct merkle tree depth in constants.rs
-    let mut thezeostoken = TokenContract::new(ENDPOINTS.map(String::from));
-    let path = thezeostoken.get_sister_path(array_index.parse::<u64>().unwrap(), leaf_count.parse::<u64>().unwrap()).await;
-
-    let str = format!("{}, [({}), ({}), ({}), ({})]",
-        path.position(),
-        hex::encode(path.auth_path()[0].inner().0[0].to_le_bytes()),
-        hex::encode(path.auth_path()[1].inner().0[0].to_le_bytes()),
-        hex::encode(path.auth_path()[2].inner().0[0].to_le_bytes()),
-        hex::encode(path.auth_path()[3].inner().0[0].to_le_bytes())
-    );
+    // the merkle tree depth is selected at build time (ZEOS_MERKLE_DEPTH) and must match the contract's tree depth
+    let mut thezeostoken = TokenContract::from_network(Network::default());
+    let path = match thezeostoken.get_sister_path(array_index.parse::<u64>().unwrap(), leaf_count.parse::<u64>().unwrap()).await {
+        Ok(path) => path,
+        Err(e) => return JsValue::from_str(&e)
+    };
+
+    let nodes: Vec<String> = path.auth_path().iter().map(|n| format!("({})", hex::encode(n.inner().0[0].to_le_bytes()))).collect();
+    let str = format!("{}, [{}]", path.position(), nodes.join(", "));
     JsValue::from_str(&str)
 }
 
 #[wasm_bindgen]
 pub async fn test_get_global() -> JsValue
 {
-    let thezeostoken = TokenContract::new(ENDPOINTS.map(String::from));
-    let res = thezeostoken.get_global_state().await;
-    JsValue::from_str(&serde_json::to_string(&res).unwrap())
+    let thezeostoken = TokenContract::from_network(Network::default());
+    match thezeostoken.get_global_state().await {
+        Ok(res) => JsValue::from_str(&serde_json::to_string(&res).unwrap()),
+        Err(e) => JsValue::from_str(&e)
+    }
 }
 
 #[wasm_bindgen]
 pub async fn test_fetch_notes() -> JsValue
 {   
-    let thezeostoken = TokenContract::new(ENDPOINTS.map(String::from));
-    let res = thezeostoken.get_encrypted_notes(0, 10).await;
-    JsValue::from_str(&serde_json::to_string(&res).unwrap())
+    let thezeostoken = TokenContract::from_network(Network::default());
+    match thezeostoken.get_encrypted_notes(0, 10).await {
+        Ok(res) => JsValue::from_str(&serde_json::to_string(&res).unwrap()),
+        Err(e) => JsValue::from_str(&e)
+    }
 }
 
 use wasm_bindgen_futures::JsFuture;
@@ -180,7 +215,7 @@ pub async fn test_proof_upload()
     opts.mode(RequestMode::NoCors);
     opts.body(Some(&fd));
     
-    let url = "http://web3.zeos.one/uploadstr";
+    let url = Network::default().liquidstorage_upload_url();
     let request = Request::new_with_str_and_init(&url, &opts).unwrap();
     
     // send http request using browser window's fetch
@@ -235,3 +270,25 @@ extern "C" {
     #[wasm_bindgen(js_namespace = console, js_name = log)]
     fn log_many(a: &str, b: &str);
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::validate_address;
+    use crate::keys::{FullViewingKey, Scope, SpendingKey};
+    use crate::Network;
+
+    #[test]
+    fn address_validation()
+    {
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let address = FullViewingKey::from(&sk).address_at(0u32, Scope::External).to_bech32m(Network::Kylin);
+        assert_eq!(validate_address(address.clone(), "kylin".to_string()), None);
+        assert!(validate_address(address.clone(), "mainnet".to_string()).unwrap().starts_with("expected an address of network"));
+        assert_eq!(validate_address(address.clone(), "moon".to_string()), Some(String::from("unknown network: moon")));
+        let mut typo = address.clone();
+        typo.replace_range(10..11, if &address[10..11] == "q" { "p" } else { "q" });
+        assert_eq!(validate_address(typo, "kylin".to_string()), Some(String::from("invalid checksum")));
+        assert!(validate_address("mschoenebeck".to_string(), "kylin".to_string()).is_some());
+    }
+}
@@ -0,0 +1,219 @@
+//! Deployment profiles of the ZEOS protocol.
+//!
+//! A 'Network' identifies the chain the ZEOS token contract is deployed to and carries everything which differs between
+//! deployments: the chain id, the contract account, the human readable parts (HRP) of the bech32m encodings of addresses
+//! and keys (see: encoding) and the default API endpoints. The table names are part of the contract code and thus the
+//! same on every network (see: contract). Since the HRP of shielded addresses differs for each network, an address of a
+//! testnet can never be decoded (and thus never be paid) by a mainnet wallet and vice versa. Kylin keeps the HRP 'za'
+//! all addresses had before network profiles existed, so existing Kylin addresses remain valid.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::encoding::KeyType;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum NetworkError
+{
+    #[error("unknown network: {0}")]
+    Unknown(String)
+}
+
+/// The networks ZEOS is deployed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Network
+{
+    /// EOS Mainnet
+    Mainnet,
+    /// Kylin Testnet (the default since this is where the protocol is currently deployed)
+    #[default]
+    Kylin,
+    /// Jungle4 Testnet
+    Jungle,
+    /// A local single node chain (e.g. for development and integration tests)
+    Local
+}
+
+impl Network
+{
+    /// All networks
+    pub const ALL: [Network; 4] = [Network::Mainnet, Network::Kylin, Network::Jungle, Network::Local];
+
+    /// Returns the name of this network
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Network::Mainnet => "mainnet",
+            Network::Kylin => "kylin",
+            Network::Jungle => "jungle",
+            Network::Local => "local"
+        }
+    }
+
+    /// Returns the chain id of this network (hex)
+    pub fn chain_id(&self) -> &'static str
+    {
+        match self
+        {
+            Network::Mainnet => "aca376f206b8fc25a6ed44dbdc66547c36c6c33e3a119ffbeaef943642f0e906",
+            Network::Kylin => "5fff1dae8dc8e2fc4d5b23b2c7665c97f9e9d8edf2b6485a86ba311c25639191",
+            Network::Jungle => "73e4385a2708e6d7048834fbc1079f2fabb17b3c125b146af438971e90716c4d",
+            // chain id of a local nodeos with the default genesis.json
+            Network::Local => "cf057bbfb72640471fd910bcb67639c22df9f92470936cddc1ade0e2f2e7dc4f"
+        }
+    }
+
+    /// Returns the account the ZEOS token contract is deployed to on this network
+    pub fn token_contract(&self) -> &'static str
+    {
+        match self
+        {
+            Network::Mainnet => "thezeostoken",
+            Network::Kylin => "thezeostoken",
+            Network::Jungle => "thezeostoken",
+            Network::Local => "thezeostoken"
+        }
+    }
+
+    /// Returns the human readable part of bech32m encoded shielded addresses
+    pub fn address_hrp(&self) -> &'static str
+    {
+        match self
+        {
+            Network::Mainnet => "zamain",
+            // Kylin keeps the HRP of all addresses created before network profiles existed
+            Network::Kylin => "za",
+            Network::Jungle => "zajungle",
+            Network::Local => "zalocal"
+        }
+    }
+
+    /// Returns the network of the human readable part 'hrp' of a bech32m encoded shielded address
+    pub fn from_address_hrp(hrp: &str) -> Option<Network>
+    {
+        Self::ALL.into_iter().find(|n| n.address_hrp() == hrp)
+    }
+
+    /// Returns the human readable part of bech32m encoded keys of type 'key_type'
+    pub fn key_hrp(&self, key_type: KeyType) -> String
+    {
+        let suffix = match self
+        {
+            Network::Mainnet => "",
+            Network::Kylin => "kylin",
+            Network::Jungle => "jungle",
+            Network::Local => "local"
+        };
+        format!("{}{}", key_type.hrp_prefix(), suffix)
+    }
+
+    /// Returns the network and key type of the human readable part 'hrp' of a bech32m encoded key
+    pub fn from_key_hrp(hrp: &str) -> Option<(Network, KeyType)>
+    {
+        Self::ALL.into_iter()
+            .flat_map(|n| KeyType::ALL.into_iter().map(move |t| (n, t)))
+            .find(|(n, t)| n.key_hrp(*t) == hrp)
+    }
+
+    /// Returns the default EOSIO/Antelope API endpoints
+    pub fn eos_endpoints(&self) -> Vec<String>
+    {
+        match self
+        {
+            Network::Mainnet => vec!["https://eos.api.eosnation.io".to_string()],
+            Network::Kylin => vec!["https://kylin.eosn.io".to_string()],
+            Network::Jungle => vec!["https://jungle4.api.eosnation.io".to_string()],
+            Network::Local => vec!["http://127.0.0.1:8888".to_string()]
+        }
+    }
+
+    /// Returns the default API endpoints of DAPP service providers (DSP) which serve the vRAM tables of the contract.
+    /// Without a DSP the vRAM tables cannot be queried, so every network must have at least one.
+    pub fn dsp_endpoints(&self) -> Vec<String>
+    {
+        match self
+        {
+            // the DSP of the ZEOS team which also serves the LiquidStorage uploads (see: liquidstorage_upload_url)
+            Network::Mainnet => vec!["http://web3.zeos.one".to_string()],
+            Network::Kylin => vec!["https://kylin-dsp-1.liquidapps.io".to_string()],
+            Network::Jungle => vec!["http://web3.zeos.one".to_string()],
+            Network::Local => vec!["http://127.0.0.1:13015".to_string()]
+        }
+    }
+
+    /// Returns the URL of the service which uploads proofs to LiquidStorage
+    pub fn liquidstorage_upload_url(&self) -> &'static str
+    {
+        match self
+        {
+            Network::Local => "http://127.0.0.1:13015/uploadstr",
+            _ => "http://web3.zeos.one/uploadstr"
+        }
+    }
+
+    /// Returns the list of endpoints to query the contract tables: the DSP endpoints first (only they serve the vRAM
+    /// tables) followed by the EOSIO/Antelope API endpoints.
+    pub fn endpoints(&self) -> Vec<String>
+    {
+        let mut v = self.dsp_endpoints();
+        v.extend(self.eos_endpoints());
+        v
+    }
+}
+
+impl fmt::Display for Network
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Network
+{
+    type Err = NetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        Self::ALL.into_iter().find(|n| n.name() == s.to_lowercase()).ok_or(NetworkError::Unknown(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::Network;
+    use crate::encoding::KeyType;
+    use std::collections::HashSet;
+
+    #[test]
+    fn profiles()
+    {
+        // every network must have its own chain id, address HRP and key HRPs
+        assert_eq!(Network::ALL.iter().map(|n| n.chain_id()).collect::<HashSet<_>>().len(), Network::ALL.len());
+        assert_eq!(Network::ALL.iter().map(|n| n.address_hrp()).collect::<HashSet<_>>().len(), Network::ALL.len());
+        let key_hrps: HashSet<String> = Network::ALL.iter().flat_map(|n| KeyType::ALL.map(|t| n.key_hrp(t))).collect();
+        assert_eq!(key_hrps.len(), Network::ALL.len() * KeyType::ALL.len());
+        for n in Network::ALL
+        {
+            assert_eq!(n.chain_id().len(), 64);
+            assert_eq!(Network::from_address_hrp(n.address_hrp()), Some(n));
+            for t in KeyType::ALL
+            {
+                assert_eq!(Network::from_key_hrp(&n.key_hrp(t)), Some((n, t)));
+            }
+            assert_eq!(n.name().parse::<Network>().unwrap(), n);
+            assert!(!n.dsp_endpoints().is_empty());
+            assert!(!n.eos_endpoints().is_empty());
+        }
+        assert_eq!("Mainnet".parse::<Network>().unwrap(), Network::Mainnet);
+        assert!("testnet".parse::<Network>().is_err());
+        assert_eq!(Network::from_address_hrp("zs"), None);
+        // addresses created before network profiles existed belong to Kylin
+        assert_eq!(Network::default(), Network::Kylin);
+        assert_eq!(Network::from_address_hrp("za"), Some(Network::Kylin));
+        assert_eq!(serde_json::to_string(&Network::Jungle).unwrap(), "\"jungle\"");
+    }
+}
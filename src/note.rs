@@ -11,7 +11,7 @@ use crate::{
     spec::{to_base, to_scalar, NonZeroPallasScalar, PrfExpand},
     value::NoteValue,
     Address,
-    note_encryption::ENC_CIPHERTEXT_SIZE
+    note_encryption::{COMPACT_NOTE_SIZE, ENC_CIPHERTEXT_SIZE}
 };
 
 pub(crate) mod commitment;
@@ -341,6 +341,45 @@ impl fmt::Debug for TransmittedNoteCiphertext {
     }
 }
 
+/// The compact representation of an encrypted note: everything but the encrypted memo and
+/// the authentication tag of the note ciphertext.
+///
+/// Sufficient to trial-decrypt (and validate) a note without its memo. Unlike Zcash's
+/// 52 byte compact ciphertext the prefix covers `rho` which precedes `rseed` in the note
+/// plaintext (see: [`COMPACT_NOTE_SIZE`]).
+#[derive(Clone)]
+pub struct CompactNoteCiphertext {
+    /// The serialization of the ephemeral public key
+    pub epk_bytes: [u8; 32],
+    /// The first [`COMPACT_NOTE_SIZE`] bytes of the encrypted note ciphertext
+    pub enc_ciphertext: [u8; COMPACT_NOTE_SIZE],
+    /// An encrypted value that allows the holder of the outgoing cipher
+    /// key for the note to recover the note plaintext.
+    pub out_ciphertext: [u8; 80],
+}
+
+impl From<&TransmittedNoteCiphertext> for CompactNoteCiphertext {
+    fn from(encrypted_note: &TransmittedNoteCiphertext) -> Self {
+        CompactNoteCiphertext {
+            epk_bytes: encrypted_note.epk_bytes,
+            enc_ciphertext: encrypted_note.enc_ciphertext[..COMPACT_NOTE_SIZE]
+                .try_into()
+                .unwrap(),
+            out_ciphertext: encrypted_note.out_ciphertext,
+        }
+    }
+}
+
+impl fmt::Debug for CompactNoteCiphertext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompactNoteCiphertext")
+            .field("epk_bytes", &self.epk_bytes)
+            .field("enc_ciphertext", &hex::encode(self.enc_ciphertext))
+            .field("out_ciphertext", &hex::encode(self.out_ciphertext))
+            .finish()
+    }
+}
+
 /// Generators for property testing.
 #[cfg(any(test, feature = "test-dependencies"))]
 #[cfg_attr(docsrs, doc(cfg(feature = "test-dependencies")))]
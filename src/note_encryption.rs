@@ -21,12 +21,16 @@
 
 use core::convert::TryInto;
 
+use chacha20::{
+    cipher::{NewCipher, StreamCipher, StreamCipherSeek},
+    ChaCha20,
+};
 use chacha20poly1305::{
     aead::{AeadInPlace, NewAead},
     ChaCha20Poly1305,
 };
 
-use crate::note::TransmittedNoteCiphertext;
+use crate::note::{CompactNoteCiphertext, TransmittedNoteCiphertext};
 
 use rand_core::RngCore;
 use subtle::{Choice, ConstantTimeEq};
@@ -42,6 +46,18 @@ pub const NOTE_PLAINTEXT_SIZE: usize = 1 + // version
     32 + // rho
     32 + // rseed (or rcm prior to ZIP 212)
     512; // memo
+/// The size of the prefix of a note plaintext which is decrypted by compact trial decryption:
+/// everything but the memo. Zcash's compact size (52 bytes) does not suffice since the ZEOS
+/// note plaintext places `rho` (required to validate the note) before `rseed`.
+pub const COMPACT_NOTE_SIZE: usize = 1 + // version
+    8  + // header
+    11 + // diversifier
+    8  + // d1
+    8  + // d2
+    8  + // sc
+    8  + // nft
+    32 + // rho
+    32; // rseed
 /// The size of [`OutPlaintextBytes`].
 pub const OUT_PLAINTEXT_SIZE: usize = 32 + // pk_d
     32; // esk
@@ -251,6 +267,45 @@ fn try_note_decryption_inner(
     Some(note)
 }
 
+/// Trial decryption of the compact note plaintext by the recipient.
+///
+/// Attempts to decrypt and validate the compact representation of an encrypted note using
+/// the given `ivk`. The ciphertext is not authenticated (the tag covers the memo as well):
+/// notes which decrypt must be decrypted in full with [`try_note_decryption`] before they
+/// are trusted. The memo of the returned note is empty. Plaintexts which do not parse (e.g.
+/// a non-canonical `rho` decrypted from another recipient's ciphertext) yield `None`.
+///
+/// Implements the procedure specified in [`ZIP 307`].
+///
+/// [`ZIP 307`]: https://zips.z.cash/zip-0307
+pub fn try_compact_note_decryption(
+    ivk: &PreparedIncomingViewingKey,
+    encrypted_note: &CompactNoteCiphertext,
+) -> Option<Note> {
+    let ephemeral_key = EphemeralKeyBytes(encrypted_note.epk_bytes);
+
+    let epk = OrchardDomain::prepare_epk(OrchardDomain::epk(&ephemeral_key)?);
+    let shared_secret = OrchardDomain::ka_agree_dec(ivk, &epk);
+    let key = OrchardDomain::kdf(shared_secret, &ephemeral_key);
+
+    let plaintext = decrypt_compact_plaintext(&key, &encrypted_note.enc_ciphertext);
+    parse_note_plaintext_ivk(ivk, &ephemeral_key, &plaintext)
+}
+
+/// Decrypts the compact prefix of a note ciphertext with the ChaCha20 keystream of `key`.
+/// The first block of the keystream is skipped since ChaCha20Poly1305 uses it for the
+/// authentication key.
+fn decrypt_compact_plaintext(
+    key: &Hash,
+    enc_ciphertext: &[u8; COMPACT_NOTE_SIZE],
+) -> [u8; COMPACT_NOTE_SIZE] {
+    let mut plaintext = *enc_ciphertext;
+    let mut keystream = ChaCha20::new(key.as_ref().into(), [0u8; 12][..].into());
+    keystream.seek(64);
+    keystream.apply_keystream(&mut plaintext);
+    plaintext
+}
+
 fn parse_note_plaintext_ivk(
     ivk: &PreparedIncomingViewingKey,
     ephemeral_key: &EphemeralKeyBytes,
@@ -317,6 +372,52 @@ pub fn try_output_recovery_with_ock(
 ) -> Option<Note> {
     let enc_ciphertext = encrypted_note.enc_ciphertext;
 
+    let ephemeral_key = EphemeralKeyBytes(encrypted_note.epk_bytes);
+    let (pk_d, esk, key) = decrypt_out_ciphertext(ock, &ephemeral_key, out_ciphertext)?;
+
+    let mut plaintext = NotePlaintextBytes([0; NOTE_PLAINTEXT_SIZE]);
+    plaintext
+        .0
+        .copy_from_slice(&enc_ciphertext[..NOTE_PLAINTEXT_SIZE]);
+
+    ChaCha20Poly1305::new(key.as_ref().into())
+        .decrypt_in_place_detached(
+            [0u8; 12][..].into(),
+            &[],
+            &mut plaintext.0,
+            enc_ciphertext[NOTE_PLAINTEXT_SIZE..].into(),
+        )
+        .ok()?;
+
+    let note = OrchardDomain::parse_note_plaintext_ovk(&pk_d, &esk, &ephemeral_key, &plaintext.0)?;
+    check_recovered_note(note, &esk, &ephemeral_key)
+}
+
+/// Recovery of the compact note plaintext by the sender.
+///
+/// Attempts to decrypt and validate the compact representation of an encrypted note using
+/// the given `ovk`. As with [`try_compact_note_decryption`] the note ciphertext is not
+/// authenticated and the memo of the returned note is empty.
+pub fn try_compact_output_recovery_with_ovk(
+    ovk: &OutgoingViewingKey,
+    encrypted_note: &CompactNoteCiphertext,
+) -> Option<Note> {
+    let ephemeral_key = EphemeralKeyBytes(encrypted_note.epk_bytes);
+    let ock = OrchardDomain::derive_ock(ovk, &ephemeral_key);
+    let (pk_d, esk, key) = decrypt_out_ciphertext(&ock, &ephemeral_key, &encrypted_note.out_ciphertext)?;
+
+    let plaintext = decrypt_compact_plaintext(&key, &encrypted_note.enc_ciphertext);
+    let note = OrchardDomain::parse_note_plaintext_ovk(&pk_d, &esk, &ephemeral_key, &plaintext)?;
+    check_recovered_note(note, &esk, &ephemeral_key)
+}
+
+/// Decrypts `out_ciphertext` using `ock` and derives the symmetric key of the note ciphertext
+/// from the recovered `pk_d` and `esk`.
+fn decrypt_out_ciphertext(
+    ock: &OutgoingCipherKey,
+    ephemeral_key: &EphemeralKeyBytes,
+    out_ciphertext: &[u8; OUT_CIPHERTEXT_SIZE],
+) -> Option<(DiversifiedTransmissionKey, EphemeralSecretKey, Hash)> {
     let mut op = OutPlaintextBytes([0; OUT_PLAINTEXT_SIZE]);
     op.0.copy_from_slice(&out_ciphertext[..OUT_PLAINTEXT_SIZE]);
 
@@ -332,39 +433,30 @@ pub fn try_output_recovery_with_ock(
     let pk_d = OrchardDomain::extract_pk_d(&op)?;
     let esk = OrchardDomain::extract_esk(&op)?;
 
-    let ephemeral_key = EphemeralKeyBytes(encrypted_note.epk_bytes);
     let shared_secret = OrchardDomain::ka_agree_enc(&esk, &pk_d);
     // The small-order point check at the point of output parsing rejects
     // non-canonical encodings, so reencoding here for the KDF should
     // be okay.
-    let key = OrchardDomain::kdf(shared_secret, &ephemeral_key);
+    let key = OrchardDomain::kdf(shared_secret, ephemeral_key);
 
-    let mut plaintext = NotePlaintextBytes([0; NOTE_PLAINTEXT_SIZE]);
-    plaintext
-        .0
-        .copy_from_slice(&enc_ciphertext[..NOTE_PLAINTEXT_SIZE]);
-
-    ChaCha20Poly1305::new(key.as_ref().into())
-        .decrypt_in_place_detached(
-            [0u8; 12][..].into(),
-            &[],
-            &mut plaintext.0,
-            enc_ciphertext[NOTE_PLAINTEXT_SIZE..].into(),
-        )
-        .ok()?;
-
-    let note = OrchardDomain::parse_note_plaintext_ovk(&pk_d, &esk, &ephemeral_key, &plaintext)?;
+    Some((pk_d, esk, key))
+}
 
+fn check_recovered_note(
+    note: Note,
+    esk: &EphemeralSecretKey,
+    ephemeral_key: &EphemeralKeyBytes,
+) -> Option<Note> {
     // ZIP 212: Check that the esk provided to this function is consistent with the esk we
     // can derive from the note.
     if let Some(derived_esk) = OrchardDomain::derive_esk(&note) {
-        if (!derived_esk.ct_eq(&esk)).into() {
+        if (!derived_esk.ct_eq(esk)).into() {
             return None;
         }
     }
 
     if let NoteValidity::Valid =
-        check_note_validity(&note, &ephemeral_key)
+        check_note_validity(&note, ephemeral_key)
     {
         Some(note)
     } else {
@@ -420,7 +512,7 @@ fn orchard_parse_note_plaintext<F>(
 where
     F: FnOnce(&Diversifier) -> Option<DiversifiedTransmissionKey>,
 {
-    assert!(plaintext.len() == NOTE_PLAINTEXT_SIZE);
+    assert!(plaintext.len() == NOTE_PLAINTEXT_SIZE || plaintext.len() == COMPACT_NOTE_SIZE);
 
     // Check note plaintext version
     if plaintext[0] != 0x02 {
@@ -434,16 +526,21 @@ where
     let d2 = NoteValue::from_bytes(plaintext[28..36].try_into().unwrap());
     let sc = NoteValue::from_bytes(plaintext[36..44].try_into().unwrap());
     let nft = NoteValue::from_bytes(plaintext[44..52].try_into().unwrap());
-    let rho = Nullifier::from_bytes(plaintext[52..84].try_into().unwrap()).unwrap();
+    // compact plaintexts are not authenticated: rho and the note may be invalid
+    let rho = Option::from(Nullifier::from_bytes(plaintext[52..84].try_into().unwrap()))?;
     let rseed = Option::from(RandomSeed::from_bytes(
         plaintext[84..116].try_into().unwrap(),
         &rho,
     ))?;
-    let memo = plaintext[116..NOTE_PLAINTEXT_SIZE].try_into().unwrap();
+    // compact plaintexts do not contain the memo
+    let memo = match plaintext.len() {
+        NOTE_PLAINTEXT_SIZE => plaintext[COMPACT_NOTE_SIZE..].try_into().unwrap(),
+        _ => [0; 512],
+    };
 
     let pk_d = get_validated_pk_d(&diversifier)?;
     let recipient = Address::from_parts(diversifier, pk_d);
-    Some(Note::from_parts(header, recipient, d1, d2, sc, nft, rho, rseed, memo).unwrap())
+    Option::from(Note::from_parts(header, recipient, d1, d2, sc, nft, rho, rseed, memo))
 }
 
 /// Orchard-specific note encryption logic.
@@ -611,9 +708,9 @@ impl OrchardDomain {
         pk_d: &DiversifiedTransmissionKey,
         esk: &EphemeralSecretKey,
         ephemeral_key: &EphemeralKeyBytes,
-        plaintext: &NotePlaintextBytes,
+        plaintext: &[u8],
     ) -> Option<Note> {
-        orchard_parse_note_plaintext(&plaintext.0, |diversifier| {
+        orchard_parse_note_plaintext(plaintext, |diversifier| {
             if esk
                 .derive_public(diversify_hash(diversifier.as_array()))
                 .to_bytes()
@@ -648,7 +745,9 @@ impl OrchardDomain {
 #[cfg(test)]
 mod tests {
     use rand::rngs::OsRng;
+    use rand::RngCore;
     use super::{
+        try_compact_note_decryption, try_compact_output_recovery_with_ovk,
         try_note_decryption, try_output_recovery_with_ovk,
         EphemeralKeyBytes
     };
@@ -659,7 +758,7 @@ mod tests {
             DiversifiedTransmissionKey, Diversifier, EphemeralSecretKey, IncomingViewingKey,
             OutgoingViewingKey, PreparedIncomingViewingKey, SpendingKey, FullViewingKey, Scope::External
         },
-        note::{NT_FT, Nullifier, RandomSeed, TransmittedNoteCiphertext},
+        note::{CompactNoteCiphertext, ExtractedNoteCommitment, NT_FT, Nullifier, RandomSeed, TransmittedNoteCiphertext},
         value::{NoteValue},
         Address, Note
     };
@@ -796,4 +895,64 @@ mod tests {
         }
 
     }
+
+    #[test]
+    fn compact_decryption()
+    {
+        let mut rng = OsRng.clone();
+        let fvk_alice = FullViewingKey::from(&SpendingKey::from_zip32_seed("This is Alice seed string! Usually this is just a listing of words. Here we just use sentences.".as_bytes(), 0, 0).unwrap());
+        let fvk_bob = FullViewingKey::from(&SpendingKey::from_zip32_seed("This is Bob's seed string. His seed is a little shorter...".as_bytes(), 0, 0).unwrap());
+
+        let mut memo = [0; 512];
+        memo[..5].copy_from_slice(b"hello");
+        let note = Note::new(
+            NT_FT,
+            fvk_bob.address_at(0u32, External),
+            NoteValue::from_raw(100000),
+            NoteValue::from_raw(357812230660),
+            NoteValue::from_raw(123456789),
+            NoteValue::from_raw(0),
+            Nullifier::from_bytes(&[1; 32]).unwrap(),
+            rng,
+            memo
+        );
+        let ne = NoteEncryption::new(Some(fvk_alice.to_ovk(External)), note);
+        let encrypted_note = TransmittedNoteCiphertext {
+            epk_bytes: ne.epk().to_bytes().0,
+            enc_ciphertext: ne.encrypt_note_plaintext(),
+            out_ciphertext: ne.encrypt_outgoing_plaintext(&mut rng),
+        };
+        let compact = CompactNoteCiphertext::from(&encrypted_note);
+
+        // the compact plaintext yields the note without its memo
+        let ivk_bob = PreparedIncomingViewingKey::new(&fvk_bob.to_ivk(External));
+        let decrypted = try_compact_note_decryption(&ivk_bob, &compact).unwrap();
+        assert_eq!(ExtractedNoteCommitment::from(decrypted.commitment()), ExtractedNoteCommitment::from(note.commitment()));
+        assert_eq!(decrypted.memo(), [0; 512]);
+        assert_eq!(try_note_decryption(&ivk_bob, &encrypted_note).unwrap().memo(), memo);
+        let recovered = try_compact_output_recovery_with_ovk(&fvk_alice.to_ovk(External), &compact).unwrap();
+        assert_eq!(ExtractedNoteCommitment::from(recovered.commitment()), ExtractedNoteCommitment::from(note.commitment()));
+
+        // other keys do not decrypt
+        let ivk_alice = PreparedIncomingViewingKey::new(&fvk_alice.to_ivk(External));
+        assert!(try_compact_note_decryption(&ivk_alice, &compact).is_none());
+        assert!(try_compact_output_recovery_with_ovk(&fvk_bob.to_ovk(External), &compact).is_none());
+
+        // a tampered ciphertext which passes the version check but yields a non-canonical rho is rejected
+        let mut tampered = compact.clone();
+        tampered.enc_ciphertext[52..84].iter_mut().for_each(|b| *b ^= 0xff);
+        assert!(try_compact_note_decryption(&ivk_bob, &tampered).is_none());
+
+        // trial decryption of garbage (of which about 1/256 passes the version check) does not panic
+        for _ in 0..4096
+        {
+            let mut garbage = compact.clone();
+            rng.fill_bytes(&mut garbage.enc_ciphertext);
+            rng.fill_bytes(&mut garbage.out_ciphertext);
+            let _ = try_compact_note_decryption(&ivk_bob, &garbage);
+            let _ = try_compact_output_recovery_with_ovk(&fvk_alice.to_ovk(External), &garbage);
+            rng.fill_bytes(&mut garbage.epk_bytes);
+            let _ = try_compact_note_decryption(&ivk_bob, &garbage);
+        }
+    }
 }
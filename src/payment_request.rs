@@ -0,0 +1,513 @@
+//! Payment request URIs (modelled on ZIP-321).
+//!
+//! A payment request asks for one or more payments to shielded addresses, e.g. a merchant's QR code:
+//!
+//!     zeos:za1...?amount=12.5000%20EOS&contract=eosio.token&memo=INV-42&label=Coffee%20Shop
+//!
+//! The address of the first payment is the path of the URI, its parameters are not indexed. The parameters of further
+//! payments carry the index of the payment (1 to 9999), e.g. 'address.1=za1...&nft.1=1099511627776&contract.1=atomicassets'.
+//! The parameters are:
+//!
+//! - 'address': the Bech32m encoded shielded address of the recipient
+//! - 'amount': the quantity of a fungible asset including its precision and symbol (e.g. "12.5000 EOS")
+//! - 'nft': the id of a non-fungible asset (instead of 'amount')
+//! - 'contract': the EOSIO/Antelope account of the token contract of the asset
+//! - 'memo': the memo of the payment (UTF-8, less than 512 bytes)
+//! - 'label': a description of the recipient (e.g. the name of the shop), not part of the transaction
+//!
+//! Values are percent encoded. Unknown parameters are ignored unless they start with "req-" (i.e. they are required to
+//! be understood). The payments of a request are passed to 'Wallet::send_payments' (see: 'PaymentRequest::to_payments').
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::address::{Address, AddressError};
+use crate::builder::Payment;
+use crate::eosio::{name_to_value, symbol_to_string_precision, value_to_name, Asset};
+use crate::network::Network;
+
+/// The URI scheme of payment requests
+pub const PAYMENT_REQUEST_SCHEME: &str = "zeos";
+
+/// The highest index of a payment of a payment request
+const MAX_PAYMENT_INDEX: u32 = 9999;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum PaymentRequestError
+{
+    #[error("not a ZEOS payment request: expected 'zeos:' but found '{0}'")]
+    InvalidScheme(String),
+    #[error("the payment request contains no payment")]
+    NoPayments,
+    #[error("the payment request contains {0} payments (at most 10000 allowed)")]
+    TooManyPayments(usize),
+    #[error("invalid parameter: '{0}'")]
+    InvalidParameter(String),
+    #[error("invalid payment index in parameter '{0}' (expected 1 to 9999)")]
+    InvalidIndex(String),
+    #[error("parameter '{0}' occurs more than once")]
+    DuplicateParameter(String),
+    #[error("unsupported required parameter: '{0}'")]
+    UnsupportedRequirement(String),
+    #[error("invalid percent encoding of parameter '{0}'")]
+    InvalidEncoding(String),
+    #[error("payment {index}: missing address")]
+    MissingAddress{ index: u32 },
+    #[error("payment {index}: invalid address: {error}")]
+    InvalidAddress{ index: u32, error: AddressError },
+    #[error("payment {index}: missing amount or NFT id")]
+    MissingAsset{ index: u32 },
+    #[error("payment {index}: either an amount or an NFT id must be given, not both")]
+    AmountAndNft{ index: u32 },
+    #[error("payment {index}: invalid amount: '{amount}' (expected {expected})")]
+    InvalidAmount{ index: u32, amount: String, expected: String },
+    #[error("payment {index}: invalid NFT id: '{id}'")]
+    InvalidNftId{ index: u32, id: String },
+    #[error("payment {index}: missing token contract")]
+    MissingContract{ index: u32 },
+    #[error("payment {index}: invalid token contract: '{contract}'")]
+    InvalidContract{ index: u32, contract: String },
+    #[error("payment {index}: memo too long: {len} bytes (less than 512 allowed)")]
+    MemoTooLong{ index: u32, len: usize },
+}
+
+/// A payment of a payment request: 'asset' is requested to be sent to the shielded address 'to' using 'memo'
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestedPayment
+{
+    /// The Bech32m encoded shielded address of the recipient
+    pub(crate) to: String,
+    /// The requested asset
+    pub(crate) asset: Asset,
+    /// The memo of the payment
+    pub(crate) memo: String,
+    /// A description of the recipient
+    pub(crate) label: Option<String>,
+}
+
+impl RequestedPayment
+{
+    pub fn new(to: &str, asset: &Asset, memo: &str) -> Self
+    {
+        RequestedPayment{ to: to.to_string(), asset: asset.clone(), memo: memo.to_string(), label: None }
+    }
+
+    /// Sets the description of the recipient (e.g. the name of the shop)
+    pub fn with_label(mut self, label: &str) -> Self
+    {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    pub fn to(&self) -> &str
+    {
+        &self.to
+    }
+
+    pub fn asset(&self) -> &Asset
+    {
+        &self.asset
+    }
+
+    pub fn memo(&self) -> &str
+    {
+        &self.memo
+    }
+
+    pub fn label(&self) -> Option<&str>
+    {
+        self.label.as_deref()
+    }
+
+    /// Checks the payment with index 'index' of a payment request of 'network'
+    fn check(&self, index: u32, network: Network) -> Result<(), PaymentRequestError>
+    {
+        Address::from_bech32m(&self.to, network).map_err(|error| PaymentRequestError::InvalidAddress{ index, error })?;
+        if !is_name(self.asset.contract())
+        {
+            return Err(PaymentRequestError::InvalidContract{ index, contract: self.asset.contract().clone() });
+        }
+        if self.memo.len() >= 512
+        {
+            return Err(PaymentRequestError::MemoTooLong{ index, len: self.memo.len() });
+        }
+        Ok(())
+    }
+}
+
+/// A request for one or more payments to shielded addresses of a network (see: module documentation)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PaymentRequest
+{
+    network: Network,
+    payments: Vec<RequestedPayment>,
+}
+
+impl PaymentRequest
+{
+    /// Creates a payment request for 'payments' on 'network'. Fails if a payment is invalid, e.g. if its address
+    /// belongs to another network.
+    pub fn new(network: Network, payments: Vec<RequestedPayment>) -> Result<Self, PaymentRequestError>
+    {
+        if payments.is_empty()
+        {
+            return Err(PaymentRequestError::NoPayments);
+        }
+        if payments.len() > MAX_PAYMENT_INDEX as usize + 1
+        {
+            return Err(PaymentRequestError::TooManyPayments(payments.len()));
+        }
+        for (i, p) in payments.iter().enumerate()
+        {
+            p.check(i as u32, network)?;
+        }
+        Ok(PaymentRequest{ network, payments })
+    }
+
+    /// Parses the payment request URI 'uri' of 'network'
+    pub fn parse(uri: &str, network: Network) -> Result<Self, PaymentRequestError>
+    {
+        Self::parse_impl(uri, network, None)
+    }
+
+    /// Parses the payment request URI 'uri' of 'network' like 'parse' and checks the requested fungible tokens against
+    /// the known tokens 'ft_contracts' (symbol code => token contract and raw symbol, e.g. "EOS" => "eosio.token" and
+    /// "4,EOS"): the token must be known and the amount must have the precision of its symbol.
+    pub fn parse_known_tokens(uri: &str, network: Network, ft_contracts: &HashMap<String, (String, u64)>) -> Result<Self, PaymentRequestError>
+    {
+        Self::parse_impl(uri, network, Some(ft_contracts))
+    }
+
+    /// Parses the payment request URI 'uri' of 'network' and checks the fungible tokens against 'ft_contracts' if given
+    fn parse_impl(uri: &str, network: Network, ft_contracts: Option<&HashMap<String, (String, u64)>>) -> Result<Self, PaymentRequestError>
+    {
+        let (scheme, rest) = uri.trim().split_once(':').ok_or_else(|| PaymentRequestError::InvalidScheme(uri.chars().take(16).collect()))?;
+        if !scheme.eq_ignore_ascii_case(PAYMENT_REQUEST_SCHEME)
+        {
+            return Err(PaymentRequestError::InvalidScheme(scheme.to_string()));
+        }
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        // the parameters of all payments by payment index
+        let mut params: BTreeMap<u32, BTreeMap<&str, String>> = BTreeMap::new();
+        if !path.is_empty()
+        {
+            params.entry(0).or_default().insert("address", percent_decode(path, "address")?);
+        }
+        for param in query.split('&').filter(|_| !query.is_empty())
+        {
+            let (key, value) = param.split_once('=').ok_or_else(|| PaymentRequestError::InvalidParameter(param.to_string()))?;
+            let (name, index) = match key.split_once('.') {
+                Some((name, index)) => (name, parse_index(index).ok_or_else(|| PaymentRequestError::InvalidIndex(key.to_string()))?),
+                None => (key, 0)
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err(PaymentRequestError::InvalidParameter(param.to_string()));
+            }
+            let name = match name {
+                "address" => "address",
+                "amount" => "amount",
+                "nft" => "nft",
+                "contract" => "contract",
+                "memo" => "memo",
+                "label" => "label",
+                _ if name.starts_with("req-") => return Err(PaymentRequestError::UnsupportedRequirement(key.to_string())),
+                _ => continue
+            };
+            let value = percent_decode(value, key)?;
+            if params.entry(index).or_default().insert(name, value).is_some()
+            {
+                return Err(PaymentRequestError::DuplicateParameter(key.to_string()));
+            }
+        }
+        if params.is_empty()
+        {
+            return Err(PaymentRequestError::NoPayments);
+        }
+
+        let mut payments = Vec::new();
+        for (index, mut p) in params
+        {
+            let to = p.remove("address").ok_or(PaymentRequestError::MissingAddress{ index })?;
+            let contract = p.remove("contract").ok_or(PaymentRequestError::MissingContract{ index })?;
+            if !is_name(&contract)
+            {
+                return Err(PaymentRequestError::InvalidContract{ index, contract });
+            }
+            let asset = match (p.remove("amount"), p.remove("nft")) {
+                (Some(_), Some(_)) => return Err(PaymentRequestError::AmountAndNft{ index }),
+                (None, None) => return Err(PaymentRequestError::MissingAsset{ index }),
+                (Some(amount), None) => match Asset::from_quantity_string(&amount, &contract) {
+                    Some(asset) if asset.d1() > 0 && amount.trim() == amount => {
+                        if let Some(ft_contracts) = ft_contracts
+                        {
+                            check_known_token(index, &amount, &asset, ft_contracts)?;
+                        }
+                        asset
+                    }
+                    _ => return Err(PaymentRequestError::InvalidAmount{ index, amount, expected: String::from("a quantity like \"12.5000 EOS\"") })
+                },
+                (None, Some(id)) => match id.parse::<u64>() {
+                    Ok(n) if id.chars().all(|c| c.is_ascii_digit()) => Asset::nft(n, &contract),
+                    _ => return Err(PaymentRequestError::InvalidNftId{ index, id })
+                }
+            };
+            let payment = RequestedPayment{ to, asset, memo: p.remove("memo").unwrap_or_default(), label: p.remove("label") };
+            payment.check(index, network)?;
+            payments.push(payment);
+        }
+        Ok(PaymentRequest{ network, payments })
+    }
+
+    /// Returns the URI of this payment request
+    pub fn to_uri(&self) -> String
+    {
+        let mut uri = format!("{}:{}", PAYMENT_REQUEST_SCHEME, self.payments[0].to);
+        let mut params = Vec::new();
+        for (i, p) in self.payments.iter().enumerate()
+        {
+            let suffix = if i == 0 { String::new() } else { format!(".{}", i) };
+            if i > 0
+            {
+                params.push(format!("address{}={}", suffix, p.to));
+            }
+            if p.asset.is_nft()
+            {
+                params.push(format!("nft{}={}", suffix, p.asset.d1()));
+            }
+            else
+            {
+                params.push(format!("amount{}={}", suffix, percent_encode(&p.asset.to_quantity_string())));
+            }
+            params.push(format!("contract{}={}", suffix, percent_encode(p.asset.contract())));
+            if !p.memo.is_empty()
+            {
+                params.push(format!("memo{}={}", suffix, percent_encode(&p.memo)));
+            }
+            if let Some(label) = &p.label
+            {
+                params.push(format!("label{}={}", suffix, percent_encode(label)));
+            }
+        }
+        uri.push('?');
+        uri.push_str(&params.join("&"));
+        uri
+    }
+
+    /// Returns the network of this payment request
+    pub fn network(&self) -> Network
+    {
+        self.network
+    }
+
+    /// Returns the requested payments
+    pub fn payments(&self) -> &[RequestedPayment]
+    {
+        &self.payments
+    }
+
+    /// Returns the requested payments to be executed by 'Wallet::send_payments'
+    pub fn to_payments(&self) -> Vec<Payment>
+    {
+        self.payments.iter().map(|p| Payment::new(&p.to, &p.asset, &p.memo)).collect()
+    }
+}
+
+impl fmt::Display for PaymentRequest
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        f.write_str(&self.to_uri())
+    }
+}
+
+/// Returns true if 'str' is a valid EOSIO/Antelope account name
+fn is_name(str: &String) -> bool
+{
+    !str.is_empty() && value_to_name(name_to_value(str)) == *str
+}
+
+/// Parses the payment index 'str': a number from 1 to 'MAX_PAYMENT_INDEX' without leading zeros
+fn parse_index(str: &str) -> Option<u32>
+{
+    if str.starts_with('0') || !str.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    str.parse::<u32>().ok().filter(|i| (1..=MAX_PAYMENT_INDEX).contains(i))
+}
+
+/// Percent encodes all characters of 'str' except the unreserved characters of RFC 3986
+fn percent_encode(str: &str) -> String
+{
+    let mut res = String::new();
+    for b in str.bytes()
+    {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b)
+        {
+            res.push(b as char);
+        }
+        else
+        {
+            res.push_str(&format!("%{:02X}", b));
+        }
+    }
+    res
+}
+
+/// Checks the fungible 'asset' (parsed from 'amount') of the payment with index 'index' against the known tokens
+/// 'ft_contracts': fails if the token is unknown, belongs to another contract or 'amount' has another precision
+fn check_known_token(index: u32, amount: &str, asset: &Asset, ft_contracts: &HashMap<String, (String, u64)>) -> Result<(), PaymentRequestError>
+{
+    let (code, _) = symbol_to_string_precision(asset.d2());
+    match ft_contracts.get(&code) {
+        Some((contract, symbol)) if contract == asset.contract() => {
+            if *symbol != asset.d2()
+            {
+                let precision = symbol_to_string_precision(*symbol).1;
+                return Err(PaymentRequestError::InvalidAmount{ index, amount: amount.to_string(), expected: format!("{} with precision {}", code, precision) });
+            }
+            Ok(())
+        }
+        Some(_) => Err(PaymentRequestError::InvalidContract{ index, contract: asset.contract().clone() }),
+        None => Err(PaymentRequestError::InvalidAmount{ index, amount: amount.to_string(), expected: String::from("a known token") })
+    }
+}
+
+/// Decodes the percent encoded value 'str' of parameter 'param'
+fn percent_decode(str: &str, param: &str) -> Result<String, PaymentRequestError>
+{
+    let err = || PaymentRequestError::InvalidEncoding(param.to_string());
+    let mut bytes = Vec::new();
+    let mut it = str.bytes();
+    while let Some(b) = it.next()
+    {
+        match b {
+            b'%' => {
+                let hex = [it.next().ok_or_else(err)?, it.next().ok_or_else(err)?];
+                let hex = std::str::from_utf8(&hex).map_err(|_| err())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| err())?);
+            }
+            // characters which must be encoded
+            b' ' | b'"' | b'<' | b'>' | b'\\' | b'^' | b'`' | b'{' | b'|' | b'}' | b'#' => return Err(err()),
+            b if b.is_ascii_control() || !b.is_ascii() => return Err(err()),
+            b => bytes.push(b)
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| err())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{PaymentRequest, PaymentRequestError, RequestedPayment};
+    use crate::address::AddressError;
+    use crate::eosio::{string_to_symbol, Asset};
+    use crate::keys::{FullViewingKey, Scope, SpendingKey};
+    use crate::network::Network;
+    use std::collections::HashMap;
+
+    fn addresses(network: Network) -> (String, String)
+    {
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk = FullViewingKey::from(&sk);
+        (fvk.address_at(0u32, Scope::External).to_bech32m(network), fvk.address_at(1u32, Scope::External).to_bech32m(network))
+    }
+
+    #[test]
+    fn round_trip()
+    {
+        let (shop, other) = addresses(Network::Mainnet);
+        let eos = Asset::ft(125000, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        let nft = Asset::nft(1099511627776, &"atomicassets".to_string());
+
+        // a single payment
+        let req = PaymentRequest::new(Network::Mainnet, vec![RequestedPayment::new(&shop, &eos, "INV-42").with_label("Coffee Shop")]).unwrap();
+        let uri = req.to_uri();
+        assert_eq!(uri, format!("zeos:{}?amount=12.5000%20EOS&contract=eosio.token&memo=INV-42&label=Coffee%20Shop", shop));
+        assert_eq!(PaymentRequest::parse(&uri, Network::Mainnet).unwrap(), req);
+
+        // many payments, UTF-8 memos and reserved characters
+        let req = PaymentRequest::new(Network::Mainnet, vec![
+            RequestedPayment::new(&shop, &eos, ""),
+            RequestedPayment::new(&other, &nft, "für dich & mich: 100%?"),
+        ]).unwrap();
+        let uri = req.to_uri();
+        assert!(uri.contains(&format!("&address.1={}&nft.1=1099511627776&contract.1=atomicassets&memo.1=", other)));
+        let parsed = PaymentRequest::parse(&uri, Network::Mainnet).unwrap();
+        assert_eq!(parsed, req);
+        assert_eq!(parsed.payments()[1].memo(), "für dich & mich: 100%?");
+
+        // the payments feed the transfer builder
+        let payments = parsed.to_payments();
+        assert_eq!(payments.len(), 2);
+        assert_eq!(payments[0].asset, eos);
+        assert_eq!(payments[1].to, other);
+
+        // the scheme is case insensitive, payment indices need not be consecutive and unknown parameters are ignored
+        let uri = format!("ZEOS:?address.7={}&amount.7=1.0000%20EOS&contract.7=eosio.token&message.7=hi&address.2={}&nft.2=5&contract.2=atomicassets", shop, other);
+        let parsed = PaymentRequest::parse(&uri, Network::Mainnet).unwrap();
+        assert_eq!(parsed.payments().iter().map(|p| p.to()).collect::<Vec<_>>(), vec![other.as_str(), shop.as_str()]);
+        assert_eq!(parsed.payments()[1].asset().to_quantity_string(), "1.0000 EOS");
+    }
+
+    #[test]
+    fn errors()
+    {
+        let (shop, _) = addresses(Network::Mainnet);
+        let (kylin, _) = addresses(Network::Kylin);
+        let parse = |uri: String| PaymentRequest::parse(&uri, Network::Mainnet).unwrap_err();
+        let ok = "amount=1.0000%20EOS&contract=eosio.token";
+        let quantity = String::from("a quantity like \"12.5000 EOS\"");
+
+        assert_eq!(parse(format!("zcash:{}?{}", shop, ok)), PaymentRequestError::InvalidScheme("zcash".to_string()));
+        assert_eq!(parse(String::from("zeos:")), PaymentRequestError::NoPayments);
+        assert_eq!(parse(format!("zeos:{}?{}&memo", shop, ok)), PaymentRequestError::InvalidParameter("memo".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&", shop, ok)), PaymentRequestError::InvalidParameter("".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&memo.01=a", shop, ok)), PaymentRequestError::InvalidIndex("memo.01".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&memo.10000=a", shop, ok)), PaymentRequestError::InvalidIndex("memo.10000".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&address={}", shop, ok, shop)), PaymentRequestError::DuplicateParameter("address".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&memo=a&memo=b", shop, ok)), PaymentRequestError::DuplicateParameter("memo".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&req-expires=1", shop, ok)), PaymentRequestError::UnsupportedRequirement("req-expires".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&memo=%E", shop, ok)), PaymentRequestError::InvalidEncoding("memo".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&memo=a b", shop, ok)), PaymentRequestError::InvalidEncoding("memo".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&memo=%FF", shop, ok)), PaymentRequestError::InvalidEncoding("memo".to_string()));
+        assert_eq!(parse(format!("zeos:{}?{}&amount.1=1.0000%20EOS&contract.1=eosio.token", shop, ok)), PaymentRequestError::MissingAddress{ index: 1 });
+        assert_eq!(parse(format!("zeos:{}?{}", kylin, ok)), PaymentRequestError::InvalidAddress{ index: 0, error: AddressError::WrongNetwork{ expected: Network::Mainnet, found: Network::Kylin } });
+        assert!(matches!(parse(format!("zeos:{}x?{}", shop, ok)), PaymentRequestError::InvalidAddress{ index: 0, .. }));
+        assert_eq!(parse(format!("zeos:{}?contract=eosio.token", shop)), PaymentRequestError::MissingAsset{ index: 0 });
+        assert_eq!(parse(format!("zeos:{}?{}&nft=1", shop, ok)), PaymentRequestError::AmountAndNft{ index: 0 });
+        assert_eq!(parse(format!("zeos:{}?amount=1.0.0%20EOS&contract=eosio.token", shop)), PaymentRequestError::InvalidAmount{ index: 0, amount: "1.0.0 EOS".to_string(), expected: quantity.clone() });
+        assert_eq!(parse(format!("zeos:{}?amount=0.0000%20EOS&contract=eosio.token", shop)), PaymentRequestError::InvalidAmount{ index: 0, amount: "0.0000 EOS".to_string(), expected: quantity.clone() });
+        assert_eq!(parse(format!("zeos:{}?nft=-1&contract=atomicassets", shop)), PaymentRequestError::InvalidNftId{ index: 0, id: "-1".to_string() });
+        assert_eq!(parse(format!("zeos:{}?amount=1.0000%20EOS", shop)), PaymentRequestError::MissingContract{ index: 0 });
+        assert_eq!(parse(format!("zeos:{}?amount=1.0000%20EOS&contract=Eosio.token", shop)), PaymentRequestError::InvalidContract{ index: 0, contract: "Eosio.token".to_string() });
+        assert_eq!(parse(format!("zeos:{}?{}&memo={}", shop, ok, "a".repeat(512))), PaymentRequestError::MemoTooLong{ index: 0, len: 512 });
+
+        // the errors are shown to users
+        assert_eq!(PaymentRequestError::InvalidAmount{ index: 2, amount: "1.0.0 EOS".to_string(), expected: quantity }.to_string(), "payment 2: invalid amount: '1.0.0 EOS' (expected a quantity like \"12.5000 EOS\")");
+        assert_eq!(PaymentRequest::new(Network::Mainnet, vec![]), Err(PaymentRequestError::NoPayments));
+        let eos = Asset::ft(10000, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        let payments = vec![RequestedPayment::new(&shop, &eos, ""); 10001];
+        assert_eq!(PaymentRequest::new(Network::Mainnet, payments.clone()), Err(PaymentRequestError::TooManyPayments(10001)));
+        assert!(PaymentRequest::new(Network::Mainnet, payments[..10000].to_vec()).is_ok());
+    }
+
+    #[test]
+    fn known_tokens()
+    {
+        let (shop, other) = addresses(Network::Mainnet);
+        let ft_contracts = HashMap::from([("EOS".to_string(), ("eosio.token".to_string(), string_to_symbol(&"EOS".to_string(), 4)))]);
+        let parse = |query: &str| PaymentRequest::parse_known_tokens(&format!("zeos:{}?{}", shop, query), Network::Mainnet, &ft_contracts);
+
+        assert!(parse("amount=12.5000%20EOS&contract=eosio.token").is_ok());
+        assert!(parse(&format!("amount=1.0000%20EOS&contract=eosio.token&address.1={}&nft.1=5&contract.1=atomicassets", other)).is_ok());
+        assert_eq!(parse("amount=12.5%20EOS&contract=eosio.token"), Err(PaymentRequestError::InvalidAmount{ index: 0, amount: "12.5 EOS".to_string(), expected: "EOS with precision 4".to_string() }));
+        assert_eq!(parse("amount=1.0000%20FOO&contract=foo.token"), Err(PaymentRequestError::InvalidAmount{ index: 0, amount: "1.0000 FOO".to_string(), expected: "a known token".to_string() }));
+        assert_eq!(parse("amount=1.0000%20EOS&contract=fake.token"), Err(PaymentRequestError::InvalidContract{ index: 0, contract: "fake.token".to_string() }));
+        assert_eq!(parse(&format!("amount=1.0000%20EOS&contract=eosio.token&address.3={}&amount.3=1.00%20EOS&contract.3=eosio.token", other)).unwrap_err().to_string(), "payment 3: invalid amount: '1.00 EOS' (expected EOS with precision 4)");
+        // without known tokens the precision is not checked
+        assert!(PaymentRequest::parse(&format!("zeos:{}?amount=12.5%20EOS&contract=eosio.token", shop), Network::Mainnet).is_ok());
+    }
+}
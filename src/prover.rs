@@ -0,0 +1,360 @@
+//! Remote proving: serializable circuit witnesses and instances of a bundle, the client which submits them to a prover
+//! service and the request handler of that service (see: src/bin/zeos-prover.rs).
+//!
+//! The prover service exposes a single endpoint: `POST /prove` with a JSON encoded 'ProveRequest' as body. It responds
+//! with a JSON encoded 'ProveResponse' or with '{"error":"..."}' and a non-2xx status code.
+
+use crate::circuit::{Circuit, Instance};
+use crate::keys::{FullViewingKey, SpendValidatingKey};
+use crate::note::{Note, Nullifier, ExtractedNoteCommitment};
+use crate::primitives::redpallas::{SpendAuth, VerificationKey};
+use crate::tree::{Anchor, MerklePath};
+use crate::value::NoteValue;
+use crate::http;
+
+use ff::PrimeField;
+use halo2_proofs::circuit::Value;
+use pasta_curves::pallas;
+use rand::rngs::OsRng;
+use rustzeos::halo2::{Proof, ProvingKey};
+use serde::{Serialize, Deserialize};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::Read;
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum ProverError
+{
+    #[error("invalid witness: {0}")]
+    InvalidWitness(String),
+    #[error("invalid instance: {0}")]
+    InvalidInstance(String),
+    #[error("proving failed: {0}")]
+    ProvingError(String),
+    #[error("remote prover: {0}")]
+    RemoteError(String)
+}
+
+impl From<halo2_proofs::plonk::Error> for ProverError
+{
+    fn from(err: halo2_proofs::plonk::Error) -> Self
+    {
+        Self::ProvingError(err.to_string())
+    }
+}
+
+fn decode_32(str: &String, field: &str) -> Result<[u8; 32], String>
+{
+    let mut arr = [0; 32];
+    hex::decode_to_slice(str, &mut arr).map_err(|e| format!("{}: {}", field, e))?;
+    Ok(arr)
+}
+
+/// The witness of a single circuit: everything required to construct a 'Circuit'. In case of zactions without
+/// spent note (mint) or without note_b/note_c the dummy notes (and dummy full viewing key) are already filled in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitWitness
+{
+    path: MerklePath,
+    fvk: String,    // hex
+    alpha: String,  // hex
+    note_a: Note,
+    note_b: Note,
+    note_c: Note,
+    acc_b: u64,
+    acc_c: u64
+}
+
+impl CircuitWitness
+{
+    /// Constructs a circuit witness from its constituent parts
+    pub fn from_parts(
+        path: MerklePath,
+        fvk: &FullViewingKey,
+        alpha: pallas::Scalar,
+        note_a: Note,
+        note_b: Note,
+        note_c: Note,
+        acc_b: NoteValue,
+        acc_c: NoteValue
+    ) -> Self
+    {
+        CircuitWitness {
+            path,
+            fvk: hex::encode(fvk.to_bytes()),
+            alpha: hex::encode(alpha.to_repr()),
+            note_a,
+            note_b,
+            note_c,
+            acc_b: acc_b.inner(),
+            acc_c: acc_c.inner()
+        }
+    }
+
+    /// Constructs the circuit of this witness
+    pub fn to_circuit(&self) -> Result<Circuit, ProverError>
+    {
+        let mut fvk = [0; 96];
+        hex::decode_to_slice(&self.fvk, &mut fvk).map_err(|e| ProverError::InvalidWitness(format!("fvk: {}", e)))?;
+        let fvk = FullViewingKey::from_bytes(&fvk).ok_or(ProverError::InvalidWitness(String::from("fvk")))?;
+        let alpha = decode_32(&self.alpha, "alpha").map_err(ProverError::InvalidWitness)?;
+        let alpha: Option<pallas::Scalar> = pallas::Scalar::from_repr(alpha).into();
+        let alpha = alpha.ok_or(ProverError::InvalidWitness(String::from("alpha")))?;
+
+        let (note_a, note_b, note_c) = (self.note_a, self.note_b, self.note_c);
+        let ak: SpendValidatingKey = fvk.clone().into();
+        let nk = *fvk.nk();
+        // if this fails the spending authority (derived from fvk) for note a is wrong:
+        // note_a's address was not derived from this fvk
+        let scope = fvk.scope_for_address(&note_a.recipient()).ok_or(ProverError::InvalidWitness(String::from("note_a does not belong to fvk")))?;
+        let rivk = fvk.rivk(scope);
+
+        Ok(Circuit {
+            path: Value::known(self.path.auth_path()),
+            pos: Value::known(self.path.position()),
+            g_d_a: Value::known(note_a.recipient().g_d()),
+            pk_d_a: Value::known(*note_a.recipient().pk_d()),
+            d1_a: Value::known(note_a.d1()),
+            d2_a: Value::known(note_a.d2()),
+            rho_a: Value::known(note_a.rho()),
+            psi_a: Value::known(note_a.rseed().psi(&note_a.rho())),
+            rcm_a: Value::known(note_a.rseed().rcm(&note_a.rho())),
+            cm_a: Value::known(note_a.commitment()),
+            alpha: Value::known(alpha),
+            ak: Value::known(ak),
+            nk: Value::known(nk),
+            rivk: Value::known(rivk),
+            g_d_b: Value::known(note_b.recipient().g_d()),
+            pk_d_b: Value::known(*note_b.recipient().pk_d()),
+            d1_b: Value::known(note_b.d1()),
+            d2_b: Value::known(note_b.d2()),
+            sc_b: Value::known(note_b.sc()),
+            rho_b: Value::known(note_b.rho()),
+            psi_b: Value::known(note_b.rseed().psi(&note_b.rho())),
+            rcm_b: Value::known(note_b.rseed().rcm(&note_b.rho())),
+            acc_b: Value::known(NoteValue::from_raw(self.acc_b)),
+            g_d_c: Value::known(note_c.recipient().g_d()),
+            pk_d_c: Value::known(*note_c.recipient().pk_d()),
+            d1_c: Value::known(note_c.d1()),
+            psi_c: Value::known(note_c.rseed().psi(&note_c.rho())),
+            rcm_c: Value::known(note_c.rseed().rcm(&note_c.rho())),
+            acc_c: Value::known(NoteValue::from_raw(self.acc_c)),
+        })
+    }
+}
+
+/// The serialized form of an 'Instance' (public inputs of a circuit)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceData
+{
+    anchor: String, // hex
+    nf: String,     // hex
+    rk: String,     // hex
+    nft: bool,
+    b_d1: u64,
+    b_d2: u64,
+    b_sc: u64,
+    c_d1: u64,
+    cmb: String,    // hex
+    cmc: String,    // hex
+    accb: u64,
+    accc: u64
+}
+
+impl From<&Instance> for InstanceData
+{
+    fn from(ins: &Instance) -> Self
+    {
+        let rk: [u8; 32] = ins.rk.clone().into();
+        InstanceData {
+            anchor: hex::encode(ins.anchor.to_bytes()),
+            nf: hex::encode(ins.nf.to_bytes()),
+            rk: hex::encode(rk),
+            nft: ins.nft,
+            b_d1: ins.b_d1.inner(),
+            b_d2: ins.b_d2.inner(),
+            b_sc: ins.b_sc.inner(),
+            c_d1: ins.c_d1.inner(),
+            cmb: hex::encode(ins.cmb.to_bytes()),
+            cmc: hex::encode(ins.cmc.to_bytes()),
+            accb: ins.accb.inner(),
+            accc: ins.accc.inner()
+        }
+    }
+}
+
+impl InstanceData
+{
+    /// Restores the instance
+    pub fn to_instance(&self) -> Result<Instance, ProverError>
+    {
+        let anchor: Option<Anchor> = Anchor::from_bytes(decode_32(&self.anchor, "anchor").map_err(ProverError::InvalidInstance)?).into();
+        let nf: Option<Nullifier> = Nullifier::from_bytes(&decode_32(&self.nf, "nf").map_err(ProverError::InvalidInstance)?).into();
+        let rk = VerificationKey::<SpendAuth>::try_from(decode_32(&self.rk, "rk").map_err(ProverError::InvalidInstance)?).ok();
+        let cmb: Option<ExtractedNoteCommitment> = ExtractedNoteCommitment::from_bytes(&decode_32(&self.cmb, "cmb").map_err(ProverError::InvalidInstance)?).into();
+        let cmc: Option<ExtractedNoteCommitment> = ExtractedNoteCommitment::from_bytes(&decode_32(&self.cmc, "cmc").map_err(ProverError::InvalidInstance)?).into();
+        Ok(Instance::from_parts(
+            anchor.ok_or(ProverError::InvalidInstance(String::from("anchor")))?,
+            nf.ok_or(ProverError::InvalidInstance(String::from("nf")))?,
+            rk.ok_or(ProverError::InvalidInstance(String::from("rk")))?,
+            self.nft,
+            NoteValue::from_raw(self.b_d1),
+            NoteValue::from_raw(self.b_d2),
+            NoteValue::from_raw(self.b_sc),
+            NoteValue::from_raw(self.c_d1),
+            cmb.ok_or(ProverError::InvalidInstance(String::from("cmb")))?,
+            cmc.ok_or(ProverError::InvalidInstance(String::from("cmc")))?,
+            NoteValue::from_raw(self.accb),
+            NoteValue::from_raw(self.accc)
+        ))
+    }
+}
+
+/// The body of a 'POST /prove' request: the witnesses and instances of all circuits of a bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProveRequest
+{
+    pub witnesses: Vec<CircuitWitness>,
+    pub instances: Vec<InstanceData>
+}
+
+/// The response of a successful 'POST /prove' request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProveResponse
+{
+    /// hex encoded proof bytes
+    pub proof: String
+}
+
+/// Calculates the proof of all circuits of 'req'
+pub fn prove(pk: &ProvingKey, req: &ProveRequest) -> Result<Proof, ProverError>
+{
+    if req.witnesses.is_empty() || req.witnesses.len() != req.instances.len()
+    {
+        return Err(ProverError::InvalidWitness(format!("{} witnesses for {} instances", req.witnesses.len(), req.instances.len())));
+    }
+    let circuits = req.witnesses.iter().map(|w| w.to_circuit()).collect::<Result<Vec<_>, _>>()?;
+    let instances = req.instances.iter().map(|i| i.to_instance()).collect::<Result<Vec<_>, _>>()?;
+    Ok(Proof::create(pk, &circuits, &instances, OsRng)?)
+}
+
+/// A prover service reachable at 'url' (e.g. "http://127.0.0.1:8787")
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteProver
+{
+    url: String
+}
+
+impl RemoteProver
+{
+    pub fn new(url: &String) -> Self
+    {
+        RemoteProver { url: url.trim_end_matches('/').to_string() }
+    }
+
+    /// Submits the witnesses and instances of all circuits of a bundle to the prover service and returns the proof
+    pub async fn prove(&self, witnesses: Vec<CircuitWitness>, instances: &Vec<Instance>) -> Result<Proof, ProverError>
+    {
+        let req = ProveRequest {
+            witnesses,
+            instances: instances.iter().map(|i| i.into()).collect()
+        };
+        let body = serde_json::to_string(&req).unwrap();
+        let res = http::post_json(&format!("{}/prove", self.url), &body).await.map_err(ProverError::RemoteError)?;
+        let res: ProveResponse = serde_json::from_str(&res).map_err(|e| ProverError::RemoteError(e.to_string()))?;
+        let proof = hex::decode(res.proof).map_err(|e| ProverError::RemoteError(e.to_string()))?;
+        Ok(Proof::new(proof))
+    }
+}
+
+/// Handles all incoming requests of 'server' using the proving key 'pk'. Blocks forever.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn serve(server: &tiny_http::Server, pk: &ProvingKey)
+{
+    for mut request in server.incoming_requests()
+    {
+        let (status, body) = handle_request(pk, &mut request);
+        let response = tiny_http::Response::from_string(body)
+            .with_status_code(status)
+            .with_header("Content-Type: application/json".parse::<tiny_http::Header>().unwrap())
+            .with_header("Access-Control-Allow-Origin: *".parse::<tiny_http::Header>().unwrap());
+        let _ = request.respond(response);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn handle_request(pk: &ProvingKey, request: &mut tiny_http::Request) -> (u16, String)
+{
+    let error = |msg: String| serde_json::json!({ "error": msg }).to_string();
+    if *request.method() != tiny_http::Method::Post || request.url() != "/prove"
+    {
+        return (404, error(format!("not found: {} {}", request.method(), request.url())));
+    }
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().read_to_string(&mut body)
+    {
+        return (400, error(e.to_string()));
+    }
+    let req: ProveRequest = match serde_json::from_str(&body) {
+        Ok(req) => req,
+        Err(e) => return (400, error(e.to_string()))
+    };
+    match prove(pk, &req) {
+        Ok(proof) => (200, serde_json::to_string(&ProveResponse{ proof: hex::encode(proof.as_ref()) }).unwrap()),
+        Err(e @ ProverError::ProvingError(_)) => (500, error(e.to_string())),
+        Err(e) => (400, error(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::rngs::OsRng;
+    use super::{serve, RemoteProver, InstanceData};
+    use crate::action::{RawZAction, ZA_MINTFT, ZA_TRANSFERFT};
+    use crate::bundle::Bundle;
+    use crate::circuit::{Circuit, K};
+    use crate::keys::{SpendingKey, FullViewingKey, Scope::External};
+    use crate::note::{Note, Nullifier, NT_FT};
+    use crate::tree::MerklePath;
+    use crate::value::NoteValue;
+    use rustzeos::halo2::{ProvingKey, VerifyingKey, Instance as ConcreteInstance};
+
+    #[tokio::test]
+    async fn remote_proving()
+    {
+        let mut rng = OsRng.clone();
+
+        // run the prover service on localhost (random port)
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr());
+        std::thread::spawn(move || {
+            let pk = ProvingKey::build(Circuit::default(), K);
+            serve(&server, &pk);
+        });
+
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        let note_a = Note::new(NT_FT, fvk.address_at(0u32, External), NoteValue::from_raw(10), NoteValue::from_raw(1), NoteValue::from_raw(1), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512]);
+        let note_b = Note::new(NT_FT, fvk.address_at(1u32, External), NoteValue::from_raw(7), NoteValue::from_raw(1), NoteValue::from_raw(1), NoteValue::from_raw(0), note_a.nullifier(&fvk), rng, [0; 512]);
+        let note_c = Note::new(NT_FT, fvk.address_at(0u32, External), NoteValue::from_raw(3), NoteValue::from_raw(1), NoteValue::from_raw(1), NoteValue::from_raw(0), note_a.nullifier(&fvk), rng, [0; 512]);
+        let note_m = Note::new(NT_FT, fvk.address_at(0u32, External), NoteValue::from_raw(5), NoteValue::from_raw(1), NoteValue::from_raw(1), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512]);
+        let bundle = Bundle::from_parts(vec![
+            RawZAction::from_parts(ZA_TRANSFERFT, &fvk, Some(MerklePath::dummy(&mut rng)), Some(note_a), Some(note_b), Some(note_c), String::from(""), rng),
+            RawZAction::from_parts(ZA_MINTFT, &fvk, None, None, Some(note_m), None, String::from(""), rng)
+        ]);
+
+        // instances survive the serialization
+        let (_, instances) = bundle.witnesses(&mut rng);
+        let restored = InstanceData::from(&instances[0]).to_instance().unwrap();
+        assert_eq!(restored.to_halo2_instance(), instances[0].to_halo2_instance());
+
+        let prover = RemoteProver::new(&url);
+        let (proof, zactions, encrypted_notes) = bundle.prepare_remote(&prover, &mut rng).await.unwrap();
+        assert_eq!(zactions.len(), 2);
+        assert_eq!(encrypted_notes.len(), 3);
+
+        let vk = VerifyingKey::build(Circuit::default(), K);
+        let instances: Vec<_> = zactions.iter().map(|za| za.instance().to_halo2_instance_vec()).collect();
+        assert!(proof.verify(&vk, &instances).is_ok());
+    }
+}
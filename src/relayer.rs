@@ -0,0 +1,347 @@
+//! Relayer protocol: allows fully private users (who hold shielded notes only and no EOS account) to transact.
+//!
+//! A relayer publishes its network, a shielded address, its fee and the EOS authorization it signs with. The wallet
+//! builds a transaction which is authorized by the relayer and pays the fee to the relayer's address via a TRANSFERFT
+//! zaction (see: 'Wallet::relay_transfer'). The transaction and its proof are sent to the relayer which checks that
+//!
+//! - the transaction consists of the privacy sequence only and nothing but its 'begin' and 'step' actions are
+//!   authorized by the relayer,
+//! - the spend authorization signatures and the proof are valid,
+//! - there is exactly one encrypted note per note created by the zactions and the fee is paid: distinct notes of the
+//!   fee token which decrypt with the relayer's viewing key and are created by TRANSFERFT zactions of the transaction
+//!   sum up to at least the fee
+//!
+//! before it uploads the proof, adds its signature and pushes the transaction.
+//!
+//! The relayer service exposes two endpoints (JSON via HTTP POST, errors are returned as '{"error":"..."}' with a
+//! non-2xx status code):
+//!
+//! POST /v1/relayer/get_info     {}                                  => 'RelayerInfo'
+//! POST /v1/relayer/relay        'RelayRequest'                      => 'RelayResponse'
+
+use std::collections::HashSet;
+
+use rustzeos::halo2::VerifyingKey;
+
+use crate::action::ZA_TRANSFERFT;
+use crate::builder::{get_liquidstorage_uri, EOSAction, EOSAuthorization};
+use crate::bundle::{verify_zactions, VerificationError};
+use crate::eosio::{name_to_value, Asset};
+use crate::http;
+use crate::keys::{FullViewingKey, PreparedIncomingViewingKey, Scope::External};
+use crate::network::Network;
+use crate::note::ExtractedNoteCommitment;
+use crate::note_encryption::try_note_decryption;
+use crate::sighash::SpendAuthError;
+use crate::transaction::{DecodedTransaction, TransactionDecodeError};
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum RelayError
+{
+    #[error(transparent)]
+    Decode(#[from] TransactionDecodeError),
+    #[error("no zactions to relay")]
+    NoPrivacySequence,
+    #[error("expected {expected} encrypted notes but found {found}")]
+    NoteCountMismatch{ expected: usize, found: usize },
+    #[error("unexpected action: {0}")]
+    UnexpectedAction(String),
+    #[error("action {0} is not authorized by the relayer only")]
+    Unauthorized(String),
+    #[error(transparent)]
+    SpendAuth(#[from] SpendAuthError),
+    #[error("proof does not match its URI")]
+    ProofMismatch,
+    #[error(transparent)]
+    InvalidProof(#[from] VerificationError),
+    #[error("insufficient relayer fee: {paid} paid but {required} required")]
+    InsufficientFee{ required: u64, paid: u64 },
+    #[error("failed to push transaction: {0}")]
+    Push(String)
+}
+
+/// The terms of a relayer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayerInfo
+{
+    pub network: Network,
+    /// The shielded address the fee is paid to
+    pub address: String,
+    /// The fee per transaction (a fungible token)
+    pub fee: Asset,
+    /// The EOS authorization the relayer signs with
+    pub auth: EOSAuthorization
+}
+
+/// A transaction to be relayed: the EOS actions (authorized by the relayer) and the proof (hex)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayRequest
+{
+    pub actions: Vec<EOSAction>,
+    pub proof: String
+}
+
+/// The answer of a relayer to a 'RelayRequest'
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayResponse
+{
+    /// The fee paid (amount of the fee token)
+    #[serde(with = "crate::contract::string")]
+    pub fee: u64,
+    /// The output of the push command (e.g. the transaction id) or the approved actions if nothing was pushed
+    pub result: String
+}
+
+/// Checks relay requests against the terms of a relayer
+pub struct Relayer
+{
+    network: Network,
+    fvk: FullViewingKey,
+    auth: EOSAuthorization,
+    fee: Asset,
+    vk: VerifyingKey
+}
+
+impl Relayer
+{
+    /// Creates a relayer on 'network' which receives 'fee' at the addresses of 'fvk' and signs with 'auth'
+    pub fn new(network: Network, fvk: FullViewingKey, auth: EOSAuthorization, fee: Asset, vk: VerifyingKey) -> Self
+    {
+        Relayer{ network, fvk, auth, fee, vk }
+    }
+
+    /// Returns the terms of this relayer
+    pub fn info(&self) -> RelayerInfo
+    {
+        RelayerInfo {
+            network: self.network,
+            address: self.fvk.address_at(0u32, External).to_bech32m(self.network),
+            fee: self.fee.clone(),
+            auth: self.auth.clone()
+        }
+    }
+
+    /// Checks 'req' and returns the fee it pays. The transaction is safe to be signed by the relayer if this succeeds.
+    pub fn check(&self, req: &RelayRequest) -> Result<u64, RelayError>
+    {
+        let contract = self.network.token_contract();
+        let tx = DecodedTransaction::decode(self.network, &req.actions)?;
+        let uri = tx.proof.as_ref().ok_or(RelayError::NoPrivacySequence)?;
+        let expected = tx.expected_note_count();
+        if tx.encrypted_notes.len() != expected
+        {
+            return Err(RelayError::NoteCountMismatch{ expected, found: tx.encrypted_notes.len() });
+        }
+
+        // the relayer signs the privacy sequence only
+        if let Some(a) = tx.pre.iter().chain(tx.post.iter()).next()
+        {
+            return Err(RelayError::UnexpectedAction(format!("{}::{}", a.account, a.name)));
+        }
+        for a in req.actions.iter()
+        {
+            if a.authorization.len() != 1 || a.authorization[0].actor != self.auth.actor || a.authorization[0].permission != self.auth.permission
+            {
+                return Err(RelayError::Unauthorized(format!("{}::{}", a.account, a.name)));
+            }
+        }
+        for a in tx.steps.iter().map(|s| &s.action)
+        {
+            if a.account != contract && a.authorization.iter().any(|auth| auth.actor == self.auth.actor)
+            {
+                return Err(RelayError::Unauthorized(format!("{}::{}", a.account, a.name)));
+            }
+        }
+
+        tx.verify_spend_auth()?;
+        let zactions = tx.zactions();
+        if get_liquidstorage_uri(req.proof.clone(), uri.starts_with("z")) != *uri
+        {
+            return Err(RelayError::ProofMismatch);
+        }
+        let proof = hex::decode(&req.proof).map_err(|_| RelayError::ProofMismatch)?;
+        verify_zactions(&proof, &zactions, &self.vk)?;
+
+        let paid = self.fee_paid(&tx);
+        if paid < self.fee.d1()
+        {
+            return Err(RelayError::InsufficientFee{ required: self.fee.d1(), paid });
+        }
+        Ok(paid)
+    }
+
+    /// Sums up all fee notes which are actually created by the TRANSFERFT zactions of 'tx'. Each note counts once even
+    /// if its ciphertext is repeated.
+    fn fee_paid(&self, tx: &DecodedTransaction) -> u64
+    {
+        let commitments: HashSet<[u8; 32]> = tx.zactions().iter()
+            .filter(|za| za.za_type() == ZA_TRANSFERFT)
+            .flat_map(|za| [za.instance().cmb.to_bytes(), za.instance().cmc.to_bytes()])
+            .collect();
+        let ivk = PreparedIncomingViewingKey::new(&self.fvk.to_ivk(External));
+        let mut counted = HashSet::new();
+        tx.encrypted_notes.iter()
+            .filter_map(|en| try_note_decryption(&ivk, en))
+            .filter(|n| n.nft().inner() == 0 && n.d2().inner() == self.fee.d2() && n.sc().inner() == name_to_value(self.fee.contract()))
+            .filter(|n| {
+                let cm = ExtractedNoteCommitment::from(n.commitment()).to_bytes();
+                commitments.contains(&cm) && counted.insert(cm)
+            })
+            .fold(0u64, |sum, n| sum.saturating_add(n.d1().inner()))
+    }
+
+    /// Checks 'req', uploads its proof and pushes it with 'push_cmd' (a shell command which reads the JSON list of
+    /// actions from stdin, signs and pushes them, e.g. using cleos). Without 'push_cmd' the approved actions are
+    /// returned instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn relay(&self, req: &RelayRequest, push_cmd: Option<&str>) -> Result<RelayResponse, RelayError>
+    {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let fee = self.check(req)?;
+        let actions = crate::builder::actions_json(&req.actions).map_err(|e| RelayError::Push(e.to_string()))?;
+        let cmd = match push_cmd {
+            Some(cmd) => cmd,
+            None => return Ok(RelayResponse{ fee, result: actions })
+        };
+        pollster::block_on(http::post_form(self.network.liquidstorage_upload_url(), "strupload", &req.proof)).map_err(RelayError::Push)?;
+
+        let mut child = Command::new("sh").arg("-c").arg(cmd)
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+            .spawn().map_err(|e| RelayError::Push(e.to_string()))?;
+        child.stdin.take().unwrap().write_all(actions.as_bytes()).map_err(|e| RelayError::Push(e.to_string()))?;
+        let output = child.wait_with_output().map_err(|e| RelayError::Push(e.to_string()))?;
+        if !output.status.success()
+        {
+            return Err(RelayError::Push(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+        }
+        Ok(RelayResponse{ fee, result: String::from_utf8_lossy(&output.stdout).trim().to_string() })
+    }
+
+    /// Serves the relayer endpoints until the server is shut down (see: 'Relayer::relay')
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn serve(&self, server: &tiny_http::Server, push_cmd: Option<&str>)
+    {
+        use std::io::Read;
+
+        for mut request in server.incoming_requests()
+        {
+            let mut body = String::new();
+            let res = match request.as_reader().read_to_string(&mut body) {
+                Err(e) => Err((400, e.to_string())),
+                Ok(_) => match (request.method(), request.url()) {
+                    (tiny_http::Method::Post, "/v1/relayer/get_info") => Ok(serde_json::to_string(&self.info()).unwrap()),
+                    (tiny_http::Method::Post, "/v1/relayer/relay") => match serde_json::from_str::<RelayRequest>(&body) {
+                        Ok(req) => self.relay(&req, push_cmd).map(|res| serde_json::to_string(&res).unwrap()).map_err(|e| match e {
+                            RelayError::Push(_) => (500, e.to_string()),
+                            _ => (400, e.to_string())
+                        }),
+                        Err(e) => Err((400, e.to_string()))
+                    },
+                    _ => Err((404, String::from("not found")))
+                }
+            };
+            let (status, body) = match res {
+                Ok(body) => (200, body),
+                Err((status, e)) => (status, serde_json::json!({ "error": e }).to_string())
+            };
+            let response = tiny_http::Response::from_string(body)
+                .with_status_code(status)
+                .with_header("Content-Type: application/json".parse::<tiny_http::Header>().unwrap());
+            let _ = request.respond(response);
+        }
+    }
+}
+
+/// Fetches the terms of the relayer at 'url'
+pub async fn get_relayer_info(url: &str) -> Result<RelayerInfo, String>
+{
+    let res = http::post_json(&format!("{}/v1/relayer/get_info", url.trim_end_matches('/')), "{}").await?;
+    serde_json::from_str(&res).map_err(|e| e.to_string())
+}
+
+/// Sends 'req' to the relayer at 'url'
+pub async fn relay(url: &str, req: &RelayRequest) -> Result<RelayResponse, String>
+{
+    let body = serde_json::to_string(req).map_err(|e| e.to_string())?;
+    let res = http::post_json(&format!("{}/v1/relayer/relay", url.trim_end_matches('/')), &body).await?;
+    serde_json::from_str(&res).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+    use rustzeos::halo2::VerifyingKey;
+    use super::{get_relayer_info, relay, RelayError, Relayer};
+    use crate::address::Address;
+    use crate::builder::EOSAuthorization;
+    use crate::circuit::{Circuit, K};
+    use crate::eosio::{string_to_symbol, Asset};
+    use crate::keys::{FullViewingKey, PreparedIncomingViewingKey, Scope::External, SpendingKey};
+    use crate::network::Network;
+    use crate::note_encryption::try_note_decryption;
+    use crate::simulator::SimulatedChain;
+    use crate::transaction::DecodedTransaction;
+    use crate::wallet::Wallet;
+
+    #[tokio::test]
+    async fn relay_transfer()
+    {
+        // simulated chain
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+
+        // relayer
+        let sk = SpendingKey::from_zip32_seed(b"relayer seed relayer seed relayer seed relayer seed", 0, 0).unwrap();
+        let eos = |amount: u64| Asset::ft(amount, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        let relayer = Arc::new(Relayer::new(Network::Local, FullViewingKey::from(&sk), EOSAuthorization::new("relayer", "active"), eos(1000), VerifyingKey::build(Circuit::default(), K)));
+        let relayer_server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let relayer_url = format!("http://{}", relayer_server.server_addr());
+        {
+            let (relayer, relayer_server) = (relayer.clone(), relayer_server.clone());
+            std::thread::spawn(move || relayer.serve(&relayer_server, None));
+        }
+
+        // a private user who holds shielded EOS only
+        let mut wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        wallet.set_endpoints(vec![url], vec![]);
+        chain.mint(Address::from_bech32m(&wallet.address(0), Network::Local).unwrap(), &eos(50000), "");
+        wallet.sync().await.unwrap();
+
+        let info = get_relayer_info(&relayer_url).await.unwrap();
+        assert_eq!(info.fee.d1(), 1000);
+        let req = wallet.relay_transfer(&info, &wallet.address(1), &eos(20000), &"thanks".to_string()).await.unwrap();
+        assert_eq!(relayer.check(&req).unwrap(), 1000);
+        let res = relay(&relayer_url, &req).await.unwrap();
+        assert_eq!(res.fee, 1000);
+        assert!(res.result.contains("\"relayer\""));
+
+        // the relayer rejects transactions which pay less than its fee
+        let greedy = Relayer::new(Network::Local, FullViewingKey::from(&sk), EOSAuthorization::new("relayer", "active"), eos(1001), VerifyingKey::build(Circuit::default(), K));
+        assert!(matches!(greedy.check(&req), Err(RelayError::InsufficientFee{ required: 1001, paid: 1000 })));
+        // ... which are not authorized by the relayer
+        let other = Relayer::new(Network::Local, FullViewingKey::from(&sk), EOSAuthorization::new("relayer2", "active"), eos(1000), VerifyingKey::build(Circuit::default(), K));
+        assert!(matches!(other.check(&req), Err(RelayError::Unauthorized(_))));
+        // ... or whose proof does not match
+        let mut forged = req.clone();
+        forged.proof = String::from("00");
+        assert!(matches!(relayer.check(&forged), Err(RelayError::ProofMismatch)));
+        assert!(relay(&relayer_url, &forged).await.unwrap_err().contains("proof does not match"));
+
+        // a repeated fee note counts once: replace the other ciphertexts by copies of the fee note ...
+        let ivk = PreparedIncomingViewingKey::new(&FullViewingKey::from(&sk).to_ivk(External));
+        let mut tx = DecodedTransaction::decode(Network::Local, &req.actions).unwrap();
+        let fee_note = tx.encrypted_notes.iter().find(|en| try_note_decryption(&ivk, en).is_some()).unwrap().clone();
+        assert_eq!(relayer.fee_paid(&tx), 1000);
+        tx.encrypted_notes.iter_mut().for_each(|en| *en = fee_note.clone());
+        assert_eq!(relayer.fee_paid(&tx), 1000);
+        // ... and ciphertexts in addition to one per created note are rejected
+        let mut repeated = req.clone();
+        let begin = repeated.actions.iter_mut().find(|a| a.name == "begin").unwrap();
+        let mut data: serde_json::Value = serde_json::from_str(&begin.data).unwrap();
+        data["notes"].as_array_mut().unwrap().push(serde_json::to_value(&fee_note).unwrap());
+        begin.data = data.to_string();
+        assert!(matches!(relayer.check(&repeated), Err(RelayError::NoteCountMismatch{ .. })));
+    }
+}
@@ -0,0 +1,229 @@
+//! Spend authorization signatures which bind the spent notes to the whole transaction.
+//!
+//! The sighash of a transaction is a BLAKE2b-256 hash over the EOS actions before the privacy sequence ('pre'), the
+//! 'step' actions of the privacy sequence (including their serialized zactions), the EOS actions after the privacy
+//! sequence ('post'), all zactions and all encrypted notes. Each zaction which spends a note carries a RedPallas
+//! SpendAuth signature of the sighash which must be valid under the randomized verification key 'rk' of the zaction.
+//! Since 'rk' is a public input of the proof a relayer can neither modify the surrounding EOS actions nor the encrypted
+//! notes of a proved bundle without invalidating the signatures.
+//!
+//! The public memo of a zaction (e.g. the memo of the transfer a third party contract receives from a burn) is not a
+//! public input of the circuit. Instead, the hash of each memo (see: 'memo_hash') is part of the sighash, so a relayer
+//! cannot rewrite it (e.g. the encoded auth token commitment of a private deposit). Burn recipients are bound by the
+//! proof already since they are public inputs ('accb' and 'accc').
+//!
+//! The 'begin' and 'step' actions themselves (and their authorizations) are not part of the sighash. This allows a
+//! relayer to pay for the execution of the transaction.
+
+use crate::action::{RawZAction, ZAction, ZActionError, ZA_TRANSFERFT, ZA_TRANSFERNFT, ZA_BURNFT, ZA_BURNFT2, ZA_BURNNFT};
+use crate::builder::EOSAction;
+use crate::keys::{SpendingKey, SpendAuthorizingKey};
+use crate::note::TransmittedNoteCiphertext;
+use crate::primitives::redpallas::{Signature, SpendAuth};
+
+use blake2b_simd::{Params as Blake2bParams, State};
+use rand::{CryptoRng, RngCore};
+
+/// Personalization of the transaction sighash
+pub const ZEOS_SIGHASH_PERSONALIZATION: &[u8; 16] = b"ZEOS_TxSigHash__";
+/// Personalization of the hash of a zaction memo
+pub const ZEOS_MEMO_HASH_PERSONALIZATION: &[u8; 16] = b"ZEOS_ZActMemoH__";
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum SpendAuthError
+{
+    #[error("expected {expected} spend authorization signatures but found {found}")]
+    WrongSignatureCount{ expected: usize, found: usize },
+    #[error("invalid spend authorization signature of zaction {0}")]
+    InvalidSignature(usize),
+    #[error("invalid encoding: {0}")]
+    InvalidEncoding(String)
+}
+
+/// Returns true if zactions of type 'za_type' spend a note (note a) and thus require a spend authorization signature
+pub fn is_spend(za_type: u64) -> bool
+{
+    za_type == ZA_TRANSFERFT || za_type == ZA_TRANSFERNFT || za_type == ZA_BURNFT || za_type == ZA_BURNFT2 || za_type == ZA_BURNNFT
+}
+
+/// Returns the hash of the public memo of a zaction
+pub fn memo_hash(memo: &str) -> [u8; 32]
+{
+    let mut h = Blake2bParams::new()
+        .hash_length(32)
+        .personal(ZEOS_MEMO_HASH_PERSONALIZATION)
+        .to_state();
+    h.update(memo.as_bytes());
+    let mut res = [0; 32];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+/// Calculates the sighash of a transaction. 'steps' are the 'step' actions of the privacy sequence with the serialized
+/// zactions already prepended to their 'data' (see: builder::step_actions). Fails if a zaction cannot be serialized.
+pub fn sighash(
+    pre: &[EOSAction],
+    steps: &[EOSAction],
+    post: &[EOSAction],
+    zactions: &[ZAction],
+    encrypted_notes: &[TransmittedNoteCiphertext]
+) -> Result<[u8; 32], ZActionError>
+{
+    let mut h = Blake2bParams::new()
+        .hash_length(32)
+        .personal(ZEOS_SIGHASH_PERSONALIZATION)
+        .to_state();
+
+    update_actions(&mut h, pre);
+    update_actions(&mut h, steps);
+    update_actions(&mut h, post);
+
+    h.update(&(zactions.len() as u64).to_le_bytes());
+    for za in zactions
+    {
+        update_bytes(&mut h, &hex::decode(za.serialize_eos()?).unwrap());
+        h.update(&memo_hash(za.memo()));
+    }
+
+    h.update(&(encrypted_notes.len() as u64).to_le_bytes());
+    for note in encrypted_notes
+    {
+        h.update(&note.epk_bytes);
+        h.update(&note.enc_ciphertext);
+        h.update(&note.out_ciphertext);
+    }
+
+    let mut res = [0; 32];
+    res.copy_from_slice(h.finalize().as_bytes());
+    Ok(res)
+}
+
+/// Signs 'sighash' for each raw zaction which spends a note using the spending key 'sk' randomized by the zaction's
+/// 'alpha_a'. Returns the signatures in the order of the spending zactions.
+pub fn sign_spends<R: RngCore + CryptoRng>(
+    sk: &SpendingKey,
+    raw_zactions: &[RawZAction],
+    sighash: &[u8; 32],
+    mut rng: R
+) -> Vec<Signature<SpendAuth>>
+{
+    let ask = SpendAuthorizingKey::from(sk);
+    raw_zactions.iter()
+        .filter(|rza| is_spend(rza.za_type()))
+        .map(|rza| ask.randomize(&rza.alpha_a()).sign(&mut rng, sighash))
+        .collect()
+}
+
+/// Verifies the spend authorization signatures 'sigs' of all 'zactions' which spend a note against 'sighash'. This is
+/// the check a contract or validator has to perform in addition to verifying the proof.
+pub fn verify_spend_auth(
+    sighash: &[u8; 32],
+    zactions: &[ZAction],
+    sigs: &[Signature<SpendAuth>]
+) -> Result<(), SpendAuthError>
+{
+    let spends: Vec<(usize, &ZAction)> = zactions.iter().enumerate().filter(|(_, za)| is_spend(za.za_type())).collect();
+    if spends.len() != sigs.len()
+    {
+        return Err(SpendAuthError::WrongSignatureCount{ expected: spends.len(), found: sigs.len() });
+    }
+    for ((i, za), sig) in spends.iter().zip(sigs.iter())
+    {
+        za.instance().rk.verify(sighash, sig).map_err(|_| SpendAuthError::InvalidSignature(*i))?;
+    }
+    Ok(())
+}
+
+/// Returns the hex encoding of a spend authorization signature
+pub fn signature_to_hex(sig: &Signature<SpendAuth>) -> String
+{
+    hex::encode(<[u8; 64]>::from(sig))
+}
+
+/// Decodes a hex encoded spend authorization signature
+pub fn signature_from_hex(str: &str) -> Result<Signature<SpendAuth>, SpendAuthError>
+{
+    let mut bytes = [0; 64];
+    hex::decode_to_slice(str, &mut bytes).map_err(|e| SpendAuthError::InvalidEncoding(e.to_string()))?;
+    Ok(Signature::from(bytes))
+}
+
+fn update_bytes(h: &mut State, bytes: &[u8])
+{
+    h.update(&(bytes.len() as u64).to_le_bytes());
+    h.update(bytes);
+}
+
+fn update_actions(h: &mut State, actions: &[EOSAction])
+{
+    h.update(&(actions.len() as u64).to_le_bytes());
+    for a in actions
+    {
+        update_bytes(h, a.account.as_bytes());
+        update_bytes(h, a.name.as_bytes());
+        h.update(&(a.authorization.len() as u64).to_le_bytes());
+        for auth in &a.authorization
+        {
+            update_bytes(h, auth.actor.as_bytes());
+            update_bytes(h, auth.permission.as_bytes());
+        }
+        update_bytes(h, a.data.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::rngs::OsRng;
+    use super::{sighash, sign_spends, verify_spend_auth, SpendAuthError};
+    use crate::action::{RawZAction, ZAction, ZA_TRANSFERFT, ZA_MINTFT};
+    use crate::builder::exec_action;
+    use crate::network::Network;
+    use crate::keys::{SpendingKey, FullViewingKey, Scope};
+    use crate::note::{Note, Nullifier, NT_FT};
+    use crate::tree::MerklePath;
+    use crate::value::NoteValue;
+
+    #[test]
+    fn spend_authorization()
+    {
+        let mut rng = OsRng.clone();
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+
+        let note_a = Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(10), NoteValue::from_raw(1), NoteValue::from_raw(0), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512]);
+        let note_b = Note::new(NT_FT, fvk.address_at(1u32, Scope::External), NoteValue::from_raw(10), NoteValue::from_raw(1), NoteValue::from_raw(0), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512]);
+        let rzactions = vec![
+            RawZAction::from_parts(ZA_MINTFT, &fvk, None, None, Some(note_b), None, String::from(""), rng),
+            RawZAction::from_parts(ZA_TRANSFERFT, &fvk, Some(MerklePath::dummy(&mut rng)), Some(note_a), Some(note_b), None, String::from(""), rng)
+        ];
+        let zactions: Vec<ZAction> = rzactions.iter().map(|rza| rza.zaction()).collect();
+
+        let pre = vec![exec_action(Network::Kylin)];
+        let h = sighash(&pre, &Vec::new(), &Vec::new(), &zactions, &Vec::new()).unwrap();
+        let sigs = sign_spends(&sk, &rzactions, &h, &mut rng);
+        assert_eq!(sigs.len(), 1);
+        assert!(verify_spend_auth(&h, &zactions, &sigs).is_ok());
+
+        // tampering with the surrounding EOS actions invalidates the signature
+        let mut tampered = exec_action(Network::Kylin);
+        tampered.data = String::from("00");
+        let h2 = sighash(&vec![tampered], &Vec::new(), &Vec::new(), &zactions, &Vec::new()).unwrap();
+        assert_eq!(verify_spend_auth(&h2, &zactions, &sigs), Err(SpendAuthError::InvalidSignature(1)));
+
+        // tampering with the public memo of a zaction invalidates the signature
+        let mut tampered_zactions: Vec<ZAction> = rzactions.iter().map(|rza| rza.zaction()).collect();
+        let za = tampered_zactions.pop().unwrap();
+        tampered_zactions.push(ZAction::from_parts(za.za_type(), za.instance(), String::from("rewritten memo")));
+        let h3 = sighash(&pre, &Vec::new(), &Vec::new(), &tampered_zactions, &Vec::new()).unwrap();
+        assert_eq!(verify_spend_auth(&h3, &tampered_zactions, &sigs), Err(SpendAuthError::InvalidSignature(1)));
+
+        // signatures of another spending key are rejected
+        let other = SpendingKey::from_zip32_seed(b"another seed which is long enough to derive a spending key", 0, 0).unwrap();
+        let sigs2 = sign_spends(&other, &rzactions, &h, &mut rng);
+        assert_eq!(verify_spend_auth(&h, &zactions, &sigs2), Err(SpendAuthError::InvalidSignature(1)));
+
+        // missing signatures are rejected
+        assert_eq!(verify_spend_auth(&h, &zactions, &Vec::new()), Err(SpendAuthError::WrongSignatureCount{ expected: 1, found: 0 }));
+    }
+}
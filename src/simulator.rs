@@ -0,0 +1,454 @@
+//! A simulated local chain for integration tests of native applications (e.g. the wallet daemon).
+//!
+//! Serves the subset of the EOSIO/Antelope chain API which is used by 'TokenContract' from an in-memory state of the
+//! ZEOS token contract: the global singleton, the list of encrypted notes, the merkle tree of note commitments, the
+//! merkle roots and the nullifiers. The table rows are encoded exactly like the tables of the deployed contract.
+//! Notes are added to the chain by 'SimulatedChain::mint' or via HTTP, nullifiers by 'SimulatedChain::spend':
+//!
+//! POST /sim/mint    {"address": "zalocal1...", "asset": {"d1": "10000", "d2": "1397703940", "contract": "eosio.token", "nft": false}, "memo": ""}
+//! POST /sim/fork    {"block_number": 42}
+//!
+//! Every block is irreversible immediately unless a number of reversible blocks is set (see:
+//! 'SimulatedChain::set_reversible_blocks'). Reversible blocks can be discarded by a fork (see: 'SimulatedChain::fork').
+//!
+//! The default endpoints of 'Network::Local' point to a simulated chain served at 127.0.0.1:13015.
+
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+use rand::rngs::OsRng;
+use serde_json::json;
+
+use crate::address::Address;
+use crate::constants::MERKLE_DEPTH_ORCHARD;
+use crate::contract::{Global, GLOBAL_TABLE, MERKLE_TABLE, NOTES_TABLE, NULLIFIERS_TABLE, ROOTS_TABLE};
+use crate::eosio::{name_to_value, Asset};
+use crate::keys::OutgoingViewingKey;
+use crate::network::Network;
+use crate::note::{Note, Nullifier, ExtractedNoteCommitment, TransmittedNoteCiphertext, NT_FT, NT_NFT};
+use crate::note_encryption::NoteEncryption;
+use crate::tree::{ContractTree, MerkleHashOrchard};
+use crate::value::NoteValue;
+
+/// The default address of a simulated chain (see: 'Network::Local')
+pub const DEFAULT_SIMULATOR_ADDRESS: &str = "127.0.0.1:13015";
+
+#[derive(Debug, Deserialize)]
+struct MintRequest
+{
+    address: String,
+    asset: Asset,
+    #[serde(default)]
+    memo: String
+}
+
+#[derive(Debug, Deserialize)]
+struct ForkRequest
+{
+    block_number: u64
+}
+
+/// The in-memory state of the ZEOS token contract
+#[derive(Debug, Default)]
+struct ChainState
+{
+    /// the current block number (incremented with each block of notes)
+    block_number: u64,
+    /// the number of most recent blocks which are reversible
+    reversible_blocks: u64,
+    /// the rows of the table of encrypted notes (hex)
+    notes: Vec<String>,
+    /// the block number of each note
+    note_blocks: Vec<u64>,
+    /// the block number and the commitment of each leaf (to rebuild the merkle tree after a fork)
+    leaves: Vec<(u64, MerkleHashOrchard)>,
+    /// the merkle tree of note commitments
+    tree: ContractTree,
+    /// the block number and the merkle root after each block of notes
+    roots: Vec<(u64, MerkleHashOrchard)>,
+    /// the block number and the value of each nullifier
+    nullifiers: Vec<(u64, [u8; 32])>
+}
+
+impl ChainState
+{
+    fn last_irreversible_block(&self) -> u64
+    {
+        self.block_number.saturating_sub(self.reversible_blocks)
+    }
+}
+
+/// A simulated chain with a deployed ZEOS token contract
+#[derive(Debug)]
+pub struct SimulatedChain
+{
+    network: Network,
+    state: Mutex<ChainState>
+}
+
+/// Encodes the four limbs of 'hash' like the contract does (hex)
+fn hash_to_hex(hash: &MerkleHashOrchard) -> String
+{
+    hash.inner().0.iter().map(|l| hex::encode(l.to_le_bytes())).collect()
+}
+
+/// Encodes the length of a string as unsigned LEB128 (EOSIO varuint32)
+fn varuint(mut len: usize) -> Vec<u8>
+{
+    let mut v = Vec::new();
+    loop
+    {
+        let b = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0
+        {
+            v.push(b);
+            return v;
+        }
+        v.push(b | 0x80);
+    }
+}
+
+impl SimulatedChain
+{
+    /// Creates an empty chain of 'network'
+    pub fn new(network: Network) -> Self
+    {
+        SimulatedChain {
+            network,
+            state: Mutex::new(ChainState::default())
+        }
+    }
+
+    /// Returns the global state of the token contract
+    pub fn global(&self) -> Global
+    {
+        let state = self.state.lock().unwrap();
+        Global{ note_count: state.notes.len() as u64, leaf_count: state.tree.leaf_count(), tree_depth: MERKLE_DEPTH_ORCHARD as u64 }
+    }
+
+    /// Returns the number of the last irreversible block
+    pub fn last_irreversible_block(&self) -> u64
+    {
+        self.state.lock().unwrap().last_irreversible_block()
+    }
+
+    /// Sets the number of most recent blocks which are reversible (0 by default: every block is irreversible as soon
+    /// as it is produced)
+    pub fn set_reversible_blocks(&self, blocks: u64)
+    {
+        self.state.lock().unwrap().reversible_blocks = blocks;
+    }
+
+    /// Simulates a fork: discards all blocks after 'block_number' including their notes and leaves. New blocks
+    /// continue at 'block_number' + 1. Fails if an irreversible block would be discarded.
+    pub fn fork(&self, block_number: u64) -> Result<(), String>
+    {
+        let mut state = self.state.lock().unwrap();
+        if block_number < state.last_irreversible_block()
+        {
+            return Err(format!("block {} is irreversible", state.last_irreversible_block()));
+        }
+        let note_count = state.note_blocks.iter().take_while(|b| **b <= block_number).count();
+        state.notes.truncate(note_count);
+        state.note_blocks.truncate(note_count);
+        state.leaves.retain(|(b, _)| *b <= block_number);
+        state.roots.retain(|(b, _)| *b <= block_number);
+        state.nullifiers.retain(|(b, _)| *b <= block_number);
+        let mut tree = ContractTree::default();
+        for (_, leaf) in state.leaves.iter()
+        {
+            tree.append(*leaf);
+        }
+        state.tree = tree;
+        state.block_number = state.block_number.min(block_number);
+        Ok(())
+    }
+
+    /// Mints 'asset' into the shielded address 'recipient' with 'memo' (as a MINTFT or MINTNFT zaction would) in a new
+    /// block. Returns the id of the note.
+    pub fn mint(&self, recipient: Address, asset: &Asset, memo: &str) -> u64
+    {
+        let mut rng = OsRng;
+        let mut memo_arr = [0; 512];
+        let len = memo.len().min(511);
+        memo_arr[0..len].copy_from_slice(&memo.as_bytes()[0..len]);
+        let note = Note::new(
+            if asset.is_nft() { NT_NFT } else { NT_FT },
+            recipient,
+            NoteValue::from_raw(asset.d1()),
+            NoteValue::from_raw(asset.d2()),
+            NoteValue::from_raw(name_to_value(asset.contract())),
+            NoteValue::from_raw(if asset.is_nft() { 1 } else { 0 }),
+            Nullifier::dummy(&mut rng),
+            rng,
+            memo_arr
+        );
+        self.add_notes(&[note], None)[0]
+    }
+
+    /// Adds 'notes' encrypted with 'ovk' to the chain in a new block and inserts their commitments into the merkle
+    /// tree. Returns the ids of the notes.
+    pub fn add_notes(&self, notes: &[Note], ovk: Option<OutgoingViewingKey>) -> Vec<u64>
+    {
+        let mut rng = OsRng;
+        let mut state = self.state.lock().unwrap();
+        state.block_number += 1;
+        let mut ids = Vec::new();
+        let mut root = None;
+        for note in notes
+        {
+            let ne = NoteEncryption::new(ovk.clone(), *note);
+            let encrypted_note = TransmittedNoteCiphertext {
+                epk_bytes: ne.epk().to_bytes().0,
+                enc_ciphertext: ne.encrypt_note_plaintext(),
+                out_ciphertext: ne.encrypt_outgoing_plaintext(&mut rng),
+            };
+            let id = state.notes.len() as u64;
+            let row = Self::note_row(id, state.block_number, state.tree.leaf_count(), &encrypted_note);
+            let block_number = state.block_number;
+            state.notes.push(row);
+            state.note_blocks.push(block_number);
+            let cm: ExtractedNoteCommitment = note.commitment().into();
+            let leaf = MerkleHashOrchard::from_cmx(&cm);
+            state.leaves.push((block_number, leaf));
+            root = Some(state.tree.append(leaf));
+            ids.push(id);
+        }
+        if let Some(root) = root
+        {
+            let block_number = state.block_number;
+            state.roots.push((block_number, MerkleHashOrchard::from(root.inner())));
+        }
+        ids
+    }
+
+    /// Adds 'nullifiers' to the chain in a new block (as the zactions spending the notes would)
+    pub fn spend(&self, nullifiers: &[Nullifier])
+    {
+        let mut state = self.state.lock().unwrap();
+        state.block_number += 1;
+        let block_number = state.block_number;
+        state.nullifiers.extend(nullifiers.iter().map(|nf| (block_number, nf.to_bytes())));
+    }
+
+    /// Encodes a row of the table of encrypted notes: id, block number, leaf index and the strings of epk, encrypted
+    /// note and encrypted outgoing plaintext (hex)
+    fn note_row(id: u64, block_number: u64, leaf_index: u64, en: &TransmittedNoteCiphertext) -> String
+    {
+        let mut row = Vec::new();
+        row.extend(id.to_le_bytes());
+        row.extend(block_number.to_le_bytes());
+        row.extend(leaf_index.to_le_bytes());
+        for str in [hex::encode(en.epk_bytes), hex::encode(en.enc_ciphertext), hex::encode(en.out_ciphertext)]
+        {
+            row.extend(varuint(str.len()));
+            row.extend(str.as_bytes());
+        }
+        hex::encode(row)
+    }
+
+    /// Answers a 'get_table_rows' request for one of the tables of the token contract
+    fn get_table_rows(&self, payload: &serde_json::Value) -> Result<serde_json::Value, String>
+    {
+        let str = |key: &str, default: &str| payload[key].as_str().unwrap_or(default).to_string();
+        if str("code", "") != self.network.token_contract()
+        {
+            return Ok(json!({ "rows": [], "more": false, "next_key": "" }));
+        }
+        let state = self.state.lock().unwrap();
+        let table = str("table", "");
+        let limit = payload["limit"].as_u64().unwrap_or(10) as usize;
+        let bound = |key: &str, default: u64| str(key, "").parse::<u64>().unwrap_or(default);
+
+        if table == GLOBAL_TABLE
+        {
+            let mut row = Vec::new();
+            row.extend((state.notes.len() as u64).to_le_bytes());
+            row.extend(state.tree.leaf_count().to_le_bytes());
+            row.extend((MERKLE_DEPTH_ORCHARD as u64).to_le_bytes());
+            return Ok(json!({ "rows": [hex::encode(row)], "more": false, "next_key": "" }));
+        }
+        if table == NOTES_TABLE
+        {
+            let (lower, upper) = (bound("lower_bound", 0), bound("upper_bound", u64::MAX));
+            let ids: Vec<u64> = (lower..state.notes.len() as u64).take_while(|id| *id <= upper).collect();
+            let rows: Vec<&String> = ids.iter().take(limit).map(|id| &state.notes[*id as usize]).collect();
+            let more = ids.len() > limit;
+            let next_key = if more { ids[limit].to_string() } else { String::new() };
+            return Ok(json!({ "rows": rows, "more": more, "next_key": next_key }));
+        }
+        if table == MERKLE_TABLE
+        {
+            // the secondary index is the hash of a node
+            if str("index_position", "primary") == "secondary"
+            {
+                let hash = str("lower_bound", "");
+                let node = state.tree.nodes().iter().filter(|(_, h)| hash_to_hex(h) == hash).map(|(i, _)| *i).min();
+                let rows: Vec<String> = node.into_iter().map(|i| format!("{}{}", hex::encode(i.to_le_bytes()), hash_to_hex(&state.tree.nodes()[&i]))).collect();
+                return Ok(json!({ "rows": rows, "more": false, "next_key": "" }));
+            }
+            let (lower, upper) = (bound("lower_bound", 0), bound("upper_bound", u64::MAX));
+            let mut indices: Vec<u64> = state.tree.nodes().keys().copied().filter(|i| lower <= *i && *i <= upper).collect();
+            indices.sort();
+            let rows: Vec<String> = indices.iter().take(limit).map(|i| format!("{}{}", hex::encode(i.to_le_bytes()), hash_to_hex(&state.tree.nodes()[i]))).collect();
+            let more = indices.len() > limit;
+            let next_key = if more { indices[limit].to_string() } else { String::new() };
+            return Ok(json!({ "rows": rows, "more": more, "next_key": next_key }));
+        }
+        if table == ROOTS_TABLE || table == NULLIFIERS_TABLE
+        {
+            let rows: Vec<String> = if table == ROOTS_TABLE {
+                state.roots.iter().enumerate().map(|(i, (_, root))| format!("{}{}", hex::encode((i as u64).to_le_bytes()), hash_to_hex(root))).collect()
+            } else {
+                state.nullifiers.iter().enumerate().map(|(i, (b, nf))| format!("{}{}{}", hex::encode((i as u64).to_le_bytes()), hex::encode(b.to_le_bytes()), hex::encode(nf))).collect()
+            };
+            let (lower, upper) = (bound("lower_bound", 0), bound("upper_bound", u64::MAX));
+            let ids: Vec<u64> = (lower..rows.len() as u64).take_while(|id| *id <= upper).collect();
+            let more = ids.len() > limit;
+            let next_key = if more { ids[limit].to_string() } else { String::new() };
+            let rows: Vec<&String> = ids.iter().take(limit).map(|id| &rows[*id as usize]).collect();
+            return Ok(json!({ "rows": rows, "more": more, "next_key": next_key }));
+        }
+        Err(format!("unknown table: {}", table))
+    }
+
+    /// Handles a request of the chain API or the simulation API and returns HTTP status and response body
+    pub fn handle(&self, url: &str, body: &str) -> (u16, String)
+    {
+        let error = |code: u16, msg: String| (code, json!({ "error": msg }).to_string());
+        match url
+        {
+            "/v1/chain/get_info" => {
+                let state = self.state.lock().unwrap();
+                (200, json!({
+                    "chain_id": self.network.chain_id(),
+                    "head_block_num": state.block_number,
+                    "last_irreversible_block_num": state.last_irreversible_block()
+                }).to_string())
+            }
+            "/v1/chain/get_table_rows" => {
+                let payload: serde_json::Value = match serde_json::from_str(body) {
+                    Ok(p) => p,
+                    Err(e) => return error(400, e.to_string())
+                };
+                match self.get_table_rows(&payload) {
+                    Ok(res) => (200, res.to_string()),
+                    Err(e) => error(400, e)
+                }
+            }
+            // there are no EOS accounts on the simulated chain
+            "/v1/chain/get_currency_balance" => (200, String::from("[]")),
+            // proofs are accepted but not stored
+            "/uploadstr" => (200, String::new()),
+            "/sim/mint" => {
+                let req: MintRequest = match serde_json::from_str(body) {
+                    Ok(r) => r,
+                    Err(e) => return error(400, e.to_string())
+                };
+                let recipient = match Address::from_bech32m(&req.address, self.network) {
+                    Ok(a) => a,
+                    Err(e) => return error(400, e.to_string())
+                };
+                let id = self.mint(recipient, &req.asset, &req.memo);
+                (200, json!({ "id": id }).to_string())
+            }
+            "/sim/fork" => {
+                let req: ForkRequest = match serde_json::from_str(body) {
+                    Ok(r) => r,
+                    Err(e) => return error(400, e.to_string())
+                };
+                match self.fork(req.block_number) {
+                    Ok(()) => (200, json!(self.global()).to_string()),
+                    Err(e) => error(400, e)
+                }
+            }
+            _ => error(404, format!("not found: {}", url))
+        }
+    }
+
+    /// Serves the chain API of this simulated chain until the server is shut down
+    pub fn serve(&self, server: &tiny_http::Server)
+    {
+        for mut request in server.incoming_requests()
+        {
+            let mut body = String::new();
+            let (status, body) = match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => self.handle(request.url(), &body),
+                Err(e) => (400, json!({ "error": e.to_string() }).to_string())
+            };
+            let response = tiny_http::Response::from_string(body)
+                .with_status_code(status)
+                .with_header("Content-Type: application/json".parse::<tiny_http::Header>().unwrap())
+                .with_header("Access-Control-Allow-Origin: *".parse::<tiny_http::Header>().unwrap());
+            let _ = request.respond(response);
+        }
+    }
+
+    /// Creates an empty chain of 'network' which is served at a free local port in a background thread (e.g. for
+    /// tests). Returns the chain and its URL. Panics if no local port can be bound.
+    pub fn spawn(network: Network) -> (Arc<SimulatedChain>, String)
+    {
+        let chain = Arc::new(SimulatedChain::new(network));
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr());
+        {
+            let chain = chain.clone();
+            std::thread::spawn(move || chain.serve(&server));
+        }
+        (chain, url)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::SimulatedChain;
+    use crate::builder::HasMerkleTree;
+    use crate::contract::TokenContract;
+    use crate::eosio::{string_to_symbol, Asset};
+    use crate::keys::{FullViewingKey, PreparedIncomingViewingKey, Scope, SpendingKey};
+    use crate::network::Network;
+    use crate::note::ExtractedNoteCommitment;
+    use crate::tree::MerkleHashOrchard;
+    use nonempty::NonEmpty;
+
+    #[tokio::test]
+    async fn simulated_chain()
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk = FullViewingKey::from(&sk);
+        let eos = Asset::ft(10000, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        for i in 0..12
+        {
+            chain.mint(fvk.address_at(i as u32, Scope::External), &eos, "hi");
+        }
+
+        let mut contract = TokenContract::new(Network::Local, NonEmpty::new(url));
+        let global = contract.get_global_state().await.unwrap();
+        assert_eq!((global.note_count, global.leaf_count), (12, 12));
+        assert!(global.check_tree_depth().is_ok());
+
+        // all notes are fetched (in pages of 10) and decrypt
+        let notes = contract.get_encrypted_notes(0, global.note_count).await.unwrap();
+        assert_eq!(notes.len(), 12);
+        let ivk = PreparedIncomingViewingKey::new(&fvk.to_ivk(Scope::External));
+        let decrypted: Vec<_> = notes.iter().map(|n| n.try_decrypt_as_receiver(&ivk).unwrap()).collect();
+        assert_eq!(decrypted[11].id, 11);
+        assert_eq!(decrypted[11].note.d1().inner(), 10000);
+
+        // the merkle path of each note leads to the same root
+        let mut roots = Vec::new();
+        for n in decrypted.iter().take(3)
+        {
+            let cm: ExtractedNoteCommitment = n.note.commitment().into();
+            let idx = contract.get_merkle_index(cm).await.unwrap().unwrap();
+            let path = contract.get_sister_path(idx, global.leaf_count).await.unwrap();
+            assert_eq!(MerkleHashOrchard::from_cmx(&cm), contract.get_merkle_hash(idx).await.unwrap().unwrap());
+            roots.push(path.root(cm));
+        }
+        assert!(roots.iter().all(|r| *r == roots[0]));
+    }
+}
@@ -0,0 +1,253 @@
+//! Decoding of executed (or ready to execute) EOS transactions back into their typed parts.
+//!
+//! This is the inverse of 'builder::assemble_transaction': the 'begin' action contains the proof URI, the encrypted
+//! notes, the spend authorization signatures and the list of 'step' actions. The 'data' of each 'step' action with
+//! zactions starts with the number of zactions (including the dummy zaction) followed by the serialized zactions.
+
+use crate::action::{ZAction, ZActionError, ZACTION_SIZE, ZA_NULL, ZA_TRANSFERFT, ZA_BURNFT, ZA_BURNFT2};
+use crate::builder::EOSAction;
+use crate::bundle::instances;
+use crate::circuit::Instance;
+use crate::network::Network;
+use crate::note::TransmittedNoteCiphertext;
+use crate::primitives::redpallas::{Signature, SpendAuth};
+use crate::sighash::{sighash, verify_spend_auth, signature_from_hex, SpendAuthError};
+
+/// The serialized type of the dummy zaction (ZA_DUMMY) which is the first zaction of each 'step' action with zactions
+const ZA_DUMMY_HEX: &str = "efbeaddeefbeadde";
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum TransactionDecodeError
+{
+    #[error("invalid 'begin' action: {0}")]
+    InvalidBegin(String),
+    #[error("expected {expected} 'step' actions but found {found}")]
+    MissingSteps{ expected: usize, found: usize },
+    #[error("invalid zactions of step {0}: {1}")]
+    InvalidZActions(usize, ZActionError),
+    #[error("invalid signature: {0}")]
+    InvalidSignature(SpendAuthError)
+}
+
+/// A decoded 'step' action of the privacy sequence
+#[derive(Debug, Clone)]
+pub struct DecodedStep
+{
+    /// The EOS action as it is executed (i.e. the serialized zactions are still part of its 'data')
+    pub action: EOSAction,
+    /// The 'data' of the EOS action without the serialized zactions
+    pub data: String,
+    /// The zactions of this step (without the dummy zaction)
+    pub zactions: Vec<ZAction>
+}
+
+/// A decoded EOS transaction
+#[derive(Debug, Clone)]
+pub struct DecodedTransaction
+{
+    /// The EOS actions before the privacy sequence (all actions if there is no privacy sequence)
+    pub pre: Vec<EOSAction>,
+    /// The LiquidStorage URI of the proof ('None' if there is no privacy sequence)
+    pub proof: Option<String>,
+    /// The encrypted notes of all zactions
+    pub encrypted_notes: Vec<TransmittedNoteCiphertext>,
+    /// The spend authorization signatures of all zactions which spend a note
+    pub sigs: Vec<Signature<SpendAuth>>,
+    /// The 'step' actions of the privacy sequence
+    pub steps: Vec<DecodedStep>,
+    /// The EOS actions after the privacy sequence
+    pub post: Vec<EOSAction>
+}
+
+impl DecodedTransaction
+{
+    /// Decodes the list of EOS 'actions' of a transaction on 'network'. The privacy sequence starts with the 'begin'
+    /// action of the ZEOS token contract which must be followed by one 'step' action per entry of its step list.
+    pub fn decode(network: Network, actions: &[EOSAction]) -> Result<Self, TransactionDecodeError>
+    {
+        let contract = network.token_contract();
+        let begin = actions.iter().position(|a| a.account == contract && a.name == "begin");
+        let begin = match begin {
+            Some(i) => i,
+            None => return Ok(DecodedTransaction {
+                pre: actions.to_vec(),
+                proof: None,
+                encrypted_notes: Vec::new(),
+                sigs: Vec::new(),
+                steps: Vec::new(),
+                post: Vec::new()
+            })
+        };
+
+        let data: serde_json::Value = serde_json::from_str(&actions[begin].data).map_err(|e| TransactionDecodeError::InvalidBegin(e.to_string()))?;
+        let proof = data["proof"].as_str().ok_or(TransactionDecodeError::InvalidBegin(String::from("proof")))?.to_string();
+        let encrypted_notes: Vec<TransmittedNoteCiphertext> = serde_json::from_value(data["notes"].clone()).map_err(|e| TransactionDecodeError::InvalidBegin(e.to_string()))?;
+        let sigs = match data["sigs"].as_array() {
+            Some(arr) => arr.iter()
+                .map(|sig| signature_from_hex(sig.as_str().unwrap_or("")))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(TransactionDecodeError::InvalidSignature)?,
+            None => Vec::new()
+        };
+        let list: Vec<EOSAction> = serde_json::from_value(data["tx"].clone()).map_err(|e| TransactionDecodeError::InvalidBegin(e.to_string()))?;
+
+        // the 'begin' action is followed by one 'step' action per entry of the list
+        let found = actions[begin+1..].iter().take(list.len()).take_while(|a| a.account == contract && a.name == "step").count();
+        if found != list.len()
+        {
+            return Err(TransactionDecodeError::MissingSteps{ expected: list.len(), found });
+        }
+
+        let mut steps = Vec::new();
+        for (i, action) in list.into_iter().enumerate()
+        {
+            let (zactions, data) = decode_step_data(&action.data).map_err(|e| TransactionDecodeError::InvalidZActions(i, e))?;
+            steps.push(DecodedStep{ action, data, zactions });
+        }
+
+        Ok(DecodedTransaction {
+            pre: actions[0..begin].to_vec(),
+            proof: Some(proof),
+            encrypted_notes,
+            sigs,
+            post: actions[begin+1+steps.len()..].to_vec(),
+            steps
+        })
+    }
+
+    /// Returns the zactions of all 'step' actions
+    pub fn zactions(&self) -> Vec<ZAction>
+    {
+        self.steps.iter().flat_map(|step| step.zactions.iter().cloned()).collect()
+    }
+
+    /// Returns the number of encrypted notes which must be transmitted with the zactions of this transaction
+    pub fn expected_note_count(&self) -> usize
+    {
+        self.steps.iter().flat_map(|step| step.zactions.iter()).map(|za| encrypted_note_count(za.za_type())).sum()
+    }
+
+    /// Returns the instances of all zactions in the order of the circuits of the proof
+    pub fn instances(&self) -> Vec<Instance>
+    {
+        instances(&self.zactions())
+    }
+
+    /// Calculates the sighash of this transaction (see: sighash)
+    pub fn sighash(&self) -> Result<[u8; 32], ZActionError>
+    {
+        let list: Vec<EOSAction> = self.steps.iter().map(|step| step.action.clone()).collect();
+        sighash(&self.pre, &list, &self.post, &self.zactions(), &self.encrypted_notes)
+    }
+
+    /// Verifies the spend authorization signatures of this transaction
+    pub fn verify_spend_auth(&self) -> Result<(), SpendAuthError>
+    {
+        let sighash = self.sighash().map_err(|e| SpendAuthError::InvalidEncoding(e.to_string()))?;
+        verify_spend_auth(&sighash, &self.zactions(), &self.sigs)
+    }
+}
+
+/// Returns the number of encrypted notes transmitted with a zaction of type 'za_type' (see:
+/// 'RawZAction::encrypted_notes')
+fn encrypted_note_count(za_type: u64) -> usize
+{
+    match za_type {
+        ZA_NULL => 0,
+        ZA_TRANSFERFT | ZA_BURNFT | ZA_BURNFT2 => 2,
+        _ => 1
+    }
+}
+
+/// Splits the 'data' of a 'step' action into its zactions and the original data. Returns no zactions if the data does
+/// not start with serialized zactions.
+fn decode_step_data(data: &str) -> Result<(Vec<ZAction>, String), ZActionError>
+{
+    if data.get(2..2+ZA_DUMMY_HEX.len()).map(|t| t.eq_ignore_ascii_case(ZA_DUMMY_HEX)) != Some(true)
+    {
+        return Ok((Vec::new(), data.to_string()));
+    }
+    let count = u8::from_str_radix(&data[0..2], 16).map_err(|e| ZActionError::InvalidEncoding(e.to_string()))? as usize;
+    if count == 0
+    {
+        return Err(ZActionError::InvalidEncoding(String::from("zaction count")));
+    }
+
+    // skip the dummy zaction
+    let mut pos = 2 + ZACTION_SIZE * 2;
+    let mut zactions = Vec::new();
+    for _ in 1..count
+    {
+        let rest = data.get(pos..).ok_or(ZActionError::InvalidEncoding(String::from("too short")))?;
+        let (za, len) = ZAction::deserialize_eos(rest)?;
+        zactions.push(za);
+        pos += len;
+    }
+    let rest = data.get(pos..).ok_or(ZActionError::InvalidEncoding(String::from("too short")))?;
+    Ok((zactions, rest.to_string()))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::rngs::OsRng;
+    use super::DecodedTransaction;
+    use crate::action::{RawZAction, ZA_TRANSFERFT, ZA_MINTFT};
+    use crate::builder::{EOSAuthorization, exec_action, assemble_transaction, transaction_sighash};
+    use crate::bundle::Bundle;
+    use crate::keys::{SpendingKey, FullViewingKey, Scope};
+    use crate::network::Network;
+    use crate::note::{Note, Nullifier, NT_FT};
+    use crate::sighash::sign_spends;
+    use crate::tree::MerklePath;
+    use crate::value::NoteValue;
+    use rustzeos::halo2::Proof;
+
+    #[test]
+    fn decode_transaction()
+    {
+        let mut rng = OsRng.clone();
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+
+        let note_a = Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(10), NoteValue::from_raw(1), NoteValue::from_raw(0), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512]);
+        let note_b = Note::new(NT_FT, fvk.address_at(1u32, Scope::External), NoteValue::from_raw(7), NoteValue::from_raw(1), NoteValue::from_raw(0), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512]);
+        let note_c = Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(3), NoteValue::from_raw(1), NoteValue::from_raw(0), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512]);
+        let rzactions = vec![
+            RawZAction::from_parts(ZA_MINTFT, &fvk, None, None, Some(note_b), None, String::from(""), rng),
+            RawZAction::from_parts(ZA_TRANSFERFT, &fvk, Some(MerklePath::dummy(&mut rng)), Some(note_a), Some(note_b), Some(note_c), String::from(""), rng)
+        ];
+
+        let mut action = exec_action(Network::Kylin);
+        action.data = String::from("cafe");
+        let steps = vec![(action, rzactions.clone())];
+        let pre = vec![exec_action(Network::Kylin)];
+        let encrypted_notes = Bundle::from_parts(rzactions.clone()).encrypted_notes(&mut rng);
+        let h = transaction_sighash(&pre, &steps, &Vec::new(), &encrypted_notes).unwrap();
+        let sigs = sign_spends(&sk, &rzactions, &h, &mut rng);
+        let auth = vec![EOSAuthorization{actor: "mschoenebeck".to_string(), permission: "active".to_string()}];
+        // the proof itself is not decoded, so any bytes will do
+        let tx = assemble_transaction(Network::Kylin, Some(&Proof::new(vec![0; 32])), &encrypted_notes, &sigs, pre, steps, Vec::new(), &auth).unwrap();
+
+        let decoded = DecodedTransaction::decode(Network::Kylin, &tx).unwrap();
+        assert_eq!(decoded.pre.len(), 1);
+        assert!(decoded.proof.is_some());
+        assert_eq!(decoded.encrypted_notes.len(), 3);
+        assert_eq!(decoded.sigs.len(), 1);
+        assert_eq!(decoded.steps.len(), 1);
+        assert_eq!(decoded.steps[0].data, "cafe");
+        assert_eq!(decoded.post.len(), 0);
+
+        let zactions = decoded.zactions();
+        assert_eq!(zactions.len(), 2);
+        assert_eq!(zactions[1].za_type(), ZA_TRANSFERFT);
+        assert_eq!(zactions[1].instance().to_halo2_instance(), rzactions[1].zaction().instance().to_halo2_instance());
+        assert_eq!(decoded.instances().len(), 2);
+        assert!(decoded.verify_spend_auth().is_ok());
+
+        // transactions without privacy sequence
+        let decoded = DecodedTransaction::decode(Network::Kylin, &vec![exec_action(Network::Kylin)]).unwrap();
+        assert_eq!(decoded.pre.len(), 1);
+        assert!(decoded.proof.is_none());
+    }
+}
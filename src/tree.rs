@@ -1,11 +1,12 @@
 //! Types related to Orchard note commitment trees and anchors.
 
 use core::iter;
+use std::collections::HashMap;
 
 use crate::{
     constants::{
         sinsemilla::{i2lebsp_k, L_ORCHARD_MERKLE, MERKLE_CRH_PERSONALIZATION},
-        MERKLE_DEPTH_ORCHARD,
+        MERKLE_DEPTH_ORCHARD, MT_ARR_FULL_TREE_OFFSET, MT_ARR_LEAF_ROW_OFFSET, MT_NUM_LEAVES,
     },
     note::commitment::ExtractedNoteCommitment,
 };
@@ -40,6 +41,24 @@ lazy_static! {
     };
 }
 
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum TreeDepthError
+{
+    #[error("merkle tree depth mismatch: the contract uses depth {contract} but this build (circuit and keys) uses depth {circuit} (see: ZEOS_MERKLE_DEPTH)")]
+    Mismatch{ contract: u64, circuit: usize }
+}
+
+/// Checks that the depth of the merkle tree of a deployment ('tree_depth' of the contract) equals the depth this crate
+/// was built for. The depth determines the circuit and thus the proving and verifying keys.
+pub fn check_tree_depth(tree_depth: u64) -> Result<(), TreeDepthError>
+{
+    if tree_depth != MERKLE_DEPTH_ORCHARD as u64
+    {
+        return Err(TreeDepthError::Mismatch{ contract: tree_depth, circuit: MERKLE_DEPTH_ORCHARD });
+    }
+    Ok(())
+}
+
 /// The root of an Orchard commitment tree. This must be a value
 /// in the range {0..=q_ℙ-1}
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
@@ -77,7 +96,7 @@ impl Anchor {
 
 /// The Merkle path from a leaf of the note commitment tree
 /// to its anchor.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MerklePath {
     position: u32,
     auth_path: [MerkleHashOrchard; MERKLE_DEPTH_ORCHARD],
@@ -249,6 +268,104 @@ impl<'de> Deserialize<'de> for MerkleHashOrchard {
     }
 }
 
+/// The note commitment tree as it is stored by the ZEOS token contract. The nodes of a tree are stored as an array
+/// (the root has index 0, the children of node `i` have the indices `2i+1` and `2i+2`). When a tree is full a new
+/// tree is started whose array is offset by `2^(d+1)-1`.
+#[derive(Debug, Clone, Default)]
+pub struct ContractTree {
+    leaf_count: u64,
+    nodes: HashMap<u64, MerkleHashOrchard>,
+}
+
+impl ContractTree {
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a tree of `leaf_count` leaves from its `nodes` by array index (e.g. as stored by an indexer).
+    pub fn from_parts(leaf_count: u64, nodes: HashMap<u64, MerkleHashOrchard>) -> Self {
+        ContractTree { leaf_count, nodes }
+    }
+
+    /// Returns the array indices of the leaf at `position` and of all its ancestors (from the leaf to the root).
+    pub fn branch_indices(position: u64) -> Vec<u64> {
+        let tos = (position / MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD)) * MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD);
+        let mut idx = MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD) + position % MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD);
+        let mut indices = vec![tos + idx];
+        while idx > 0 {
+            idx = (idx - 1) / 2;
+            indices.push(tos + idx);
+        }
+        indices
+    }
+
+    /// Returns the position of the leaf with array index `array_index` or `None` if it is not the index of a leaf.
+    pub fn leaf_position(array_index: u64) -> Option<u64> {
+        let idx = array_index % MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD);
+        if idx < MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD) {
+            return None;
+        }
+        Some(
+            (array_index / MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD)) * MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD)
+                + idx
+                - MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD),
+        )
+    }
+
+    /// Returns the number of leaves (of all trees).
+    pub fn leaf_count(&self) -> u64 {
+        self.leaf_count
+    }
+
+    /// Returns the nodes by array index.
+    pub fn nodes(&self) -> &HashMap<u64, MerkleHashOrchard> {
+        &self.nodes
+    }
+
+    /// Appends `leaf` and updates all nodes on the path to the root. Returns the root of the tree the leaf was
+    /// appended to.
+    pub fn append(&mut self, leaf: MerkleHashOrchard) -> Anchor {
+        let tos = (self.leaf_count / MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD)) * MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD);
+        let mut idx = MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD) + self.leaf_count % MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD);
+        self.nodes.insert(tos + idx, leaf);
+        for l in 0..MERKLE_DEPTH_ORCHARD {
+            // if the array index of a node is uneven it is always the left child
+            let left_idx = if idx % 2 == 1 { idx } else { idx - 1 };
+            let left = self.nodes[&(tos + left_idx)];
+            let right = self
+                .nodes
+                .get(&(tos + left_idx + 1))
+                .copied()
+                .unwrap_or(EMPTY_ROOTS[l]);
+            idx = left_idx / 2;
+            self.nodes.insert(
+                tos + idx,
+                MerkleHashOrchard::combine((l as u8).into(), &left, &right),
+            );
+        }
+        self.leaf_count += 1;
+        self.nodes[&tos].into()
+    }
+
+    /// Returns the Merkle path of the leaf at `position` to the root of the tree the leaf belongs to.
+    pub fn path(&self, position: u64) -> MerklePath {
+        let tos = (position / MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD)) * MT_ARR_FULL_TREE_OFFSET!(MERKLE_DEPTH_ORCHARD);
+        let mut idx = MT_ARR_LEAF_ROW_OFFSET!(MERKLE_DEPTH_ORCHARD) + position % MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD);
+        let mut auth_path = [MerkleHashOrchard::empty_leaf(); MERKLE_DEPTH_ORCHARD];
+        for (l, sibling) in auth_path.iter_mut().enumerate() {
+            let sibling_idx = if idx % 2 == 1 { idx + 1 } else { idx - 1 };
+            *sibling = self
+                .nodes
+                .get(&(tos + sibling_idx))
+                .copied()
+                .unwrap_or(EMPTY_ROOTS[l]);
+            idx = (idx - 1) / 2;
+        }
+        MerklePath::from_parts((position % MT_NUM_LEAVES!(MERKLE_DEPTH_ORCHARD)) as u32, auth_path)
+    }
+}
+
 /// Generators for property testing.
 #[cfg(any(test, feature = "test-dependencies"))]
 #[cfg_attr(docsrs, doc(cfg(feature = "test-dependencies")))]
@@ -370,4 +487,42 @@ pub mod testing {
         }
         assert_eq!(frontier.root().0, pallas::Base::from_repr(anchor).unwrap());
     }
+
+    #[test]
+    fn contract_tree() {
+        use crate::note::ExtractedNoteCommitment;
+        use crate::constants::MERKLE_DEPTH_ORCHARD;
+        use crate::tree::ContractTree;
+        use group::ff::Field;
+        use rand::rngs::OsRng;
+
+        let mut tree = ContractTree::new();
+        let cmxs: Vec<ExtractedNoteCommitment> = (0..5)
+            .map(|_| ExtractedNoteCommitment::from(pallas::Base::random(OsRng)))
+            .collect();
+        let mut root = None;
+        for cmx in cmxs.iter() {
+            root = Some(tree.append(MerkleHashOrchard::from_cmx(cmx)));
+        }
+        assert_eq!(tree.leaf_count(), 5);
+
+        // the path of each leaf leads to the current root
+        for (i, cmx) in cmxs.iter().enumerate() {
+            assert_eq!(Some(tree.path(i as u64).root(*cmx)), root);
+        }
+
+        // the branch of a leaf covers exactly the nodes its append has written
+        let branch = ContractTree::branch_indices(4);
+        assert_eq!(branch.len(), MERKLE_DEPTH_ORCHARD + 1);
+        assert_eq!(*branch.last().unwrap(), 0);
+        assert!(branch.iter().all(|i| tree.nodes().contains_key(i)));
+        assert_eq!(ContractTree::leaf_position(branch[0]), Some(4));
+        assert_eq!(ContractTree::leaf_position(branch[1]), None);
+        let second = ContractTree::branch_indices(1 << MERKLE_DEPTH_ORCHARD);
+        assert_eq!(ContractTree::leaf_position(second[0]), Some(1 << MERKLE_DEPTH_ORCHARD));
+
+        // a tree restored from its nodes continues like the original
+        let mut restored = ContractTree::from_parts(tree.leaf_count(), tree.nodes().clone());
+        let leaf = MerkleHashOrchard::from_cmx(&ExtractedNoteCommitment::from(pallas::Base::random(OsRng)));
+        assert_eq!(restored.append(leaf), tree.append(leaf));
 }
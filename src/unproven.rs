@@ -0,0 +1,389 @@
+//! A portable format for transactions which are fully planned but not yet authorized or proved.
+//!
+//! Similar to Zcash's PCZT an unproven transaction holds all raw zactions (notes, merkle paths, alpha randomizers and
+//! memos) of a transaction together with the surrounding EOS actions. It can be passed between devices which play
+//! different roles:
+//!
+//! - Creator: selects notes and fetches merkle paths using the full viewing key only (see: 'from_parts' and
+//!   'TransactionBuilder::build_unproven_transaction').
+//! - Signer: authorizes the transaction by signing its sighash for each spent note using the spending key (see:
+//!   'authorize').
+//! - Prover: calculates the proof of all zactions (see: 'prove').
+//! - Finalizer: assembles the final EOS actions ready to execute (see: 'finalize').
+
+use crate::action::RawZAction;
+use crate::builder::{EOSAction, EOSAuthorization, assemble_transaction, transaction_sighash};
+use crate::bundle::Bundle;
+use crate::keys::{FullViewingKey, SpendingKey};
+use crate::network::Network;
+use crate::note::{Note, TransmittedNoteCiphertext};
+use crate::sighash::{sign_spends, verify_spend_auth, signature_to_hex, signature_from_hex};
+use crate::tree::MerklePath;
+
+use ff::PrimeField;
+use pasta_curves::pallas;
+use rand::rngs::OsRng;
+use rustzeos::halo2::{Proof, ProvingKey};
+use serde::{Serialize, Deserialize};
+
+/// The current version of the unproven transaction format
+pub const UNPROVEN_TX_VERSION: u32 = 3;
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum UnprovenTransactionError
+{
+    #[error("unsupported unproven transaction version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("invalid encoding: {0}")]
+    InvalidEncoding(String),
+    #[error("spending key does not match the full viewing key of this transaction")]
+    WrongSpendingKey,
+    #[error("spent note {0} does not belong to the full viewing key of this transaction")]
+    ForeignNote(usize),
+    #[error("transaction is not authorized")]
+    NotAuthorized,
+    #[error("transaction is not proved")]
+    NotProved,
+    #[error("invalid spend authorization: {0}")]
+    InvalidSpendAuthorization(String),
+    #[error("invalid zaction: {0}")]
+    InvalidZAction(String),
+    #[error("proving failed: {0}")]
+    ProvingError(String)
+}
+
+/// The serialized form of a raw zaction. The full viewing key is stored only once per transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UnprovenZAction
+{
+    za_type: u64,
+    auth_path_a: Option<MerklePath>,
+    alpha_a: String,    // hex
+    note_a: Option<Note>,
+    note_b: Option<Note>,
+    note_c: Option<Note>,
+    memo: String
+}
+
+impl From<&RawZAction> for UnprovenZAction
+{
+    fn from(rza: &RawZAction) -> Self
+    {
+        UnprovenZAction {
+            za_type: rza.za_type(),
+            auth_path_a: rza.auth_path_a().cloned(),
+            alpha_a: hex::encode(rza.alpha_a().to_repr()),
+            note_a: rza.note_a(),
+            note_b: rza.note_b(),
+            note_c: rza.note_c(),
+            memo: rza.memo().clone()
+        }
+    }
+}
+
+impl UnprovenZAction
+{
+    fn to_raw_zaction(&self, fvk: &FullViewingKey) -> Result<RawZAction, UnprovenTransactionError>
+    {
+        let mut alpha_a = [0; 32];
+        hex::decode_to_slice(&self.alpha_a, &mut alpha_a).map_err(|e| UnprovenTransactionError::InvalidEncoding(e.to_string()))?;
+        let alpha_a: Option<pallas::Scalar> = pallas::Scalar::from_repr(alpha_a).into();
+        if alpha_a.is_none()
+        {
+            return Err(UnprovenTransactionError::InvalidEncoding(String::from("alpha_a")));
+        }
+        Ok(RawZAction::from_parts_with_alpha(
+            self.za_type,
+            fvk,
+            self.auth_path_a.clone(),
+            alpha_a.unwrap(),
+            self.note_a,
+            self.note_b,
+            self.note_c,
+            self.memo.clone()
+        ))
+    }
+}
+
+/// A 'step' action of the privacy sequence with the zactions it depends on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UnprovenStep
+{
+    action: EOSAction,
+    zactions: Vec<UnprovenZAction>
+}
+
+/// A transaction whose zactions are fully determined but which is not yet authorized and proved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnprovenTransaction
+{
+    version: u32,
+    network: Network,
+    fvk: String,    // hex
+    pre: Vec<EOSAction>,
+    steps: Vec<UnprovenStep>,
+    post: Vec<EOSAction>,
+    eos_auth: Vec<EOSAuthorization>,
+    notes: Vec<TransmittedNoteCiphertext>,
+    sigs: Vec<String>,      // hex
+    authorized: bool,
+    proof: Option<String>   // hex
+}
+
+impl UnprovenTransaction
+{
+    /// Creator: creates an unproven transaction from the sequence of 'step' actions (each with the raw zactions it
+    /// depends on) and the EOS actions before ('pre') and after ('post') the privacy sequence. All raw zactions must
+    /// have been created using 'fvk'. The encrypted notes are created here since they are part of the sighash.
+    pub fn from_parts(
+        network: Network,
+        fvk: &FullViewingKey,
+        pre: Vec<EOSAction>,
+        steps: Vec<(EOSAction, Vec<RawZAction>)>,
+        post: Vec<EOSAction>,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Self
+    {
+        let mut rng = OsRng.clone();
+        let raw_zactions: Vec<RawZAction> = steps.iter().flat_map(|(_, rzas)| rzas.iter().cloned()).collect();
+        let notes = if raw_zactions.is_empty() { Vec::new() } else { Bundle::from_parts(raw_zactions).encrypted_notes(&mut rng) };
+        UnprovenTransaction {
+            version: UNPROVEN_TX_VERSION,
+            network,
+            fvk: hex::encode(fvk.to_bytes()),
+            pre,
+            steps: steps.iter().map(|(action, rzas)| UnprovenStep{ action: action.clone(), zactions: rzas.iter().map(|rza| rza.into()).collect() }).collect(),
+            post,
+            eos_auth: eos_auth.clone(),
+            notes,
+            sigs: Vec::new(),
+            authorized: false,
+            proof: None
+        }
+    }
+
+    /// Restores an unproven transaction from its JSON encoding. Fails if the version is not supported.
+    pub fn from_json(json: &str) -> Result<Self, UnprovenTransactionError>
+    {
+        let v: serde_json::Value = serde_json::from_str(json).map_err(|e| UnprovenTransactionError::InvalidEncoding(e.to_string()))?;
+        let version = v["version"].as_u64().unwrap_or(0) as u32;
+        if version != UNPROVEN_TX_VERSION
+        {
+            return Err(UnprovenTransactionError::UnsupportedVersion(version));
+        }
+        serde_json::from_value(v).map_err(|e| UnprovenTransactionError::InvalidEncoding(e.to_string()))
+    }
+
+    /// Returns the JSON encoding of this unproven transaction
+    pub fn to_json(&self) -> String
+    {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Returns the version of the format of this unproven transaction
+    pub fn version(&self) -> u32
+    {
+        self.version
+    }
+
+    /// Returns the network this transaction is executed on
+    pub fn network(&self) -> Network
+    {
+        self.network
+    }
+
+    /// Returns true if this transaction has been authorized by the Signer
+    pub fn is_authorized(&self) -> bool
+    {
+        self.authorized
+    }
+
+    /// Returns true if this transaction has been proved by the Prover
+    pub fn is_proved(&self) -> bool
+    {
+        self.proof.is_some()
+    }
+
+    /// Returns the full viewing key of this transaction
+    pub fn fvk(&self) -> Result<FullViewingKey, UnprovenTransactionError>
+    {
+        let mut bytes = [0; 96];
+        hex::decode_to_slice(&self.fvk, &mut bytes).map_err(|e| UnprovenTransactionError::InvalidEncoding(e.to_string()))?;
+        FullViewingKey::from_bytes(&bytes).ok_or(UnprovenTransactionError::InvalidEncoding(String::from("fvk")))
+    }
+
+    /// Returns the raw zactions of all 'step' actions
+    pub fn raw_zactions(&self) -> Result<Vec<(EOSAction, Vec<RawZAction>)>, UnprovenTransactionError>
+    {
+        let fvk = self.fvk()?;
+        let mut res = Vec::new();
+        for step in self.steps.iter()
+        {
+            let rzas = step.zactions.iter().map(|uza| uza.to_raw_zaction(&fvk)).collect::<Result<Vec<_>, _>>()?;
+            res.push((step.action.clone(), rzas));
+        }
+        Ok(res)
+    }
+
+    /// Signer: authorizes this transaction by signing its sighash for each spent note using the spending key 'sk'. Fails
+    /// if 'sk' does not match the full viewing key of this transaction or if any spent note does not belong to it.
+    pub fn authorize(&mut self, sk: &SpendingKey) -> Result<(), UnprovenTransactionError>
+    {
+        let fvk = self.fvk()?;
+        if FullViewingKey::from(sk) != fvk
+        {
+            return Err(UnprovenTransactionError::WrongSpendingKey);
+        }
+        for (i, uza) in self.steps.iter().flat_map(|step| step.zactions.iter()).enumerate()
+        {
+            if uza.note_a.is_some() && fvk.scope_for_address(&uza.note_a.unwrap().recipient()).is_none()
+            {
+                return Err(UnprovenTransactionError::ForeignNote(i));
+            }
+        }
+        let steps = self.raw_zactions()?;
+        let sighash = transaction_sighash(&self.pre, &steps, &self.post, &self.notes).map_err(|e| UnprovenTransactionError::InvalidZAction(e.to_string()))?;
+        let raw_zactions: Vec<RawZAction> = steps.into_iter().flat_map(|(_, rzas)| rzas).collect();
+        self.sigs = sign_spends(sk, &raw_zactions, &sighash, OsRng.clone()).iter().map(signature_to_hex).collect();
+        self.authorized = true;
+        Ok(())
+    }
+
+    /// Prover: calculates the proof of all zactions of this (authorized) transaction. Does nothing if there are no
+    /// zactions.
+    pub fn prove(&mut self, pk: &ProvingKey) -> Result<(), UnprovenTransactionError>
+    {
+        if !self.authorized
+        {
+            return Err(UnprovenTransactionError::NotAuthorized);
+        }
+        let raw_zactions: Vec<RawZAction> = self.raw_zactions()?.into_iter().flat_map(|(_, rzas)| rzas).collect();
+        if raw_zactions.is_empty()
+        {
+            return Ok(());
+        }
+        let mut rng = OsRng.clone();
+        let (proof, _, _) = Bundle::from_parts(raw_zactions).proof(pk, &mut rng).map_err(|e| UnprovenTransactionError::ProvingError(e.to_string()))?;
+        self.proof = Some(hex::encode(proof.as_ref()));
+        Ok(())
+    }
+
+    /// Finalizer: verifies the spend authorization signatures and assembles the final EOS actions of this (proved)
+    /// transaction. Returns the proof (if any) and the EOS actions ready to execute.
+    pub fn finalize(self) -> Result<(Option<Proof>, Vec<EOSAction>), UnprovenTransactionError>
+    {
+        let steps = self.raw_zactions()?;
+        let has_zactions = steps.iter().any(|(_, rzas)| !rzas.is_empty());
+        if has_zactions && self.proof.is_none()
+        {
+            return Err(UnprovenTransactionError::NotProved);
+        }
+        let proof = match &self.proof {
+            Some(p) => Some(Proof::new(hex::decode(p).map_err(|e| UnprovenTransactionError::InvalidEncoding(e.to_string()))?)),
+            None => None
+        };
+        let sigs = self.sigs.iter().map(|sig| signature_from_hex(sig)).collect::<Result<Vec<_>, _>>()
+            .map_err(|e| UnprovenTransactionError::InvalidEncoding(e.to_string()))?;
+        let sighash = transaction_sighash(&self.pre, &steps, &self.post, &self.notes).map_err(|e| UnprovenTransactionError::InvalidZAction(e.to_string()))?;
+        let zactions: Vec<_> = steps.iter().flat_map(|(_, rzas)| rzas.iter().map(|rza| rza.zaction())).collect();
+        verify_spend_auth(&sighash, &zactions, &sigs).map_err(|e| UnprovenTransactionError::InvalidSpendAuthorization(e.to_string()))?;
+        let tx = assemble_transaction(self.network, proof.as_ref(), &self.notes, &sigs, self.pre, steps, self.post, &self.eos_auth)
+            .map_err(|e| UnprovenTransactionError::InvalidZAction(e.to_string()))?;
+        Ok((proof, tx))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::rngs::OsRng;
+    use super::{UnprovenTransaction, UnprovenTransactionError, UNPROVEN_TX_VERSION};
+    use crate::builder::{TransactionBuilder, EOSActionDesc, EOSAuthorization, ZActionDesc, HasMerkleTree, exec_action};
+    use crate::action::ZA_TRANSFERFT;
+    use crate::contract::NoteEx;
+    use crate::eosio::{name_to_value, Asset};
+    use crate::keys::{SpendingKey, FullViewingKey, Scope};
+    use crate::note::{Note, Nullifier, ExtractedNoteCommitment, NT_FT};
+    use crate::tree::MerklePath;
+    use crate::value::NoteValue;
+    use crate::circuit::{Circuit, K};
+    use crate::network::Network;
+    use rustzeos::halo2::ProvingKey;
+
+    struct DummyContract;
+    impl HasMerkleTree for DummyContract
+    {
+        async fn get_sister_path(&mut self, _array_index: u64, _leaf_count: u64) -> Result<MerklePath, String>
+        {
+            let mut rng = OsRng.clone();
+            Ok(MerklePath::dummy(&mut rng))
+        }
+
+        async fn get_merkle_index(&self, _hash: ExtractedNoteCommitment) -> Result<Option<u64>, String>
+        {
+            Ok(Some(0))
+        }
+    }
+
+    #[tokio::test]
+    async fn roles()
+    {
+        let mut rng = OsRng.clone();
+
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+
+        let mut notes = Vec::new();
+        notes.push(NoteEx{id: 0, block_number: 0, note: Note::new(NT_FT, fvk.address_at(0u32, Scope::External), NoteValue::from_raw(10), NoteValue::from_raw(1), NoteValue::from_raw(name_to_value(&"thezeostoken".to_string())), NoteValue::from_raw(0), Nullifier::dummy(&mut rng), rng, [0; 512])});
+
+        let descs = vec![EOSActionDesc{
+            action: exec_action(Network::Jungle),
+            zaction_descs: vec![ZActionDesc::from_asset(ZA_TRANSFERFT, &fvk.address_at(1u32, Scope::External).to_bech32m(Network::Jungle), &Asset::ft(7, 1, &"thezeostoken".to_string()), &"".to_string())]
+        }];
+        let auth = vec![EOSAuthorization{actor: "mschoenebeck".to_string(), permission: "active".to_string()}];
+
+        // Creator (watch-only)
+        let tb = TransactionBuilder::new(Network::Jungle, 0); // leaf_count not required for DummyContract's get_merkle_path()
+        let mut dc = DummyContract;
+        let utx = tb.build_unproven_transaction(&fvk, &mut notes, &descs, &mut dc, &auth).await.unwrap();
+        let json = utx.to_json();
+
+        // the format is versioned
+        let wrong_version = json.replacen(&format!("\"version\":{}", UNPROVEN_TX_VERSION), "\"version\":999", 1);
+        assert!(matches!(UnprovenTransaction::from_json(&wrong_version), Err(UnprovenTransactionError::UnsupportedVersion(999))));
+
+        // Prover refuses to prove an unauthorized transaction
+        let pk = ProvingKey::build(Circuit::default(), K);
+        let mut utx = UnprovenTransaction::from_json(&json).unwrap();
+        assert!(matches!(utx.prove(&pk), Err(UnprovenTransactionError::NotAuthorized)));
+
+        // Signer (offline)
+        let other = SpendingKey::from_zip32_seed(b"another seed which is long enough to derive a spending key", 0, 0).unwrap();
+        assert!(matches!(utx.authorize(&other), Err(UnprovenTransactionError::WrongSpendingKey)));
+        utx.authorize(&sk).unwrap();
+        let json = utx.to_json();
+
+        // Prover
+        let mut utx = UnprovenTransaction::from_json(&json).unwrap();
+        assert!(utx.is_authorized());
+        utx.prove(&pk).unwrap();
+        let json = utx.to_json();
+
+        // Finalizer
+        let utx = UnprovenTransaction::from_json(&json).unwrap();
+        assert!(utx.is_proved());
+        assert_eq!(utx.network(), Network::Jungle);
+
+        // a relayer cannot add EOS actions to the authorized transaction
+        let mut tampered = utx.clone();
+        tampered.pre.push(exec_action(Network::Jungle));
+        assert!(matches!(tampered.finalize(), Err(UnprovenTransactionError::InvalidSpendAuthorization(_))));
+
+        let (proof, actions) = utx.finalize().unwrap();
+        assert!(proof.is_some());
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].account, Network::Jungle.token_contract());
+        assert_eq!(actions[0].name, "begin");
+        assert_eq!(actions[1].name, "step");
+    }
+}
@@ -0,0 +1,547 @@
+//! Off-chain validator of ZEOS transactions.
+//!
+//! Independently checks the privacy sequences ('begin' and 'step' actions) of executed EOS transactions: the proof is
+//! fetched by its LiquidStorage URI, checked against the URI (content addressing) and verified against the instances
+//! reconstructed from the zactions. The zactions are replayed against a local copy of the global data sets of the ZEOS
+//! token contract (the merkle tree of note commitments, the set of merkle roots and the set of nullifiers) which
+//! detects spends of notes under unknown roots and double spends. Transactions must be validated in the order they
+//! were executed. Only valid transactions change the local state.
+//!
+//! The validator runs offline against recorded fixtures (see: 'Fixture') or against the transactions fetched from a
+//! Hyperion history API (see: 'HistoryApi') and fetches proofs from an IPFS gateway (see: 'IpfsGateway'). The local
+//! state is empty (i.e. validation starts with the first transaction of the contract) or a snapshot of the contract
+//! tables (see: 'Ledger::from_contract').
+
+use std::collections::{HashMap, HashSet};
+
+use rustzeos::halo2::VerifyingKey;
+
+use crate::action::{ZAction, ZA_MINTFT, ZA_MINTNFT, ZA_MINTAUTH, ZA_TRANSFERFT, ZA_TRANSFERNFT, ZA_BURNFT};
+use crate::builder::{get_liquidstorage_uri, EOSAction, EOSAuthorization};
+use crate::bundle::{verify_zactions, VerificationError};
+use crate::contract::TokenContract;
+use crate::http;
+use crate::network::Network;
+use crate::note::ExtractedNoteCommitment;
+use crate::sighash::{is_spend, SpendAuthError};
+use crate::transaction::{DecodedTransaction, TransactionDecodeError};
+use crate::tree::{Anchor, ContractTree, MerkleHashOrchard};
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum ValidationError
+{
+    #[error(transparent)]
+    Decode(#[from] TransactionDecodeError),
+    #[error("expected {expected} encrypted notes but found {found}")]
+    NoteCountMismatch{ expected: usize, found: usize },
+    #[error("zaction {0} spends a note under an unknown merkle root")]
+    UnknownAnchor(usize),
+    #[error("zaction {0} spends an already spent note (nullifier: {1})")]
+    DoubleSpend(usize, String),
+    #[error(transparent)]
+    SpendAuth(#[from] SpendAuthError),
+    #[error("proof not found: {0}")]
+    ProofNotFound(String),
+    #[error("proof does not match its URI: {0}")]
+    ProofMismatch(String),
+    #[error(transparent)]
+    InvalidProof(#[from] VerificationError)
+}
+
+/// A source of proofs stored in LiquidStorage
+pub trait ProofSource
+{
+    /// Returns the proof (hex) stored under the LiquidStorage 'uri' or 'None' if it is not available
+    fn fetch(&self, uri: &str) -> Option<String>;
+}
+
+/// Fetches proofs from an IPFS gateway (e.g. "https://ipfs.io")
+#[derive(Debug, Clone)]
+pub struct IpfsGateway
+{
+    pub url: String
+}
+
+impl ProofSource for IpfsGateway
+{
+    fn fetch(&self, uri: &str) -> Option<String>
+    {
+        let cid = uri.trim_start_matches("ipfs://");
+        pollster::block_on(http::get(&format!("{}/ipfs/{}", self.url.trim_end_matches('/'), cid))).ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryActions
+{
+    actions: Vec<HistoryAction>
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryAction
+{
+    #[serde(default)]
+    block_num: u64,
+    #[serde(default)]
+    trx_id: String,
+    #[serde(default)]
+    action_ordinal: u32,
+    #[serde(default)]
+    creator_action_ordinal: u32,
+    act: HistoryAct
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryAct
+{
+    account: String,
+    name: String,
+    #[serde(default)]
+    authorization: Vec<EOSAuthorization>,
+    data: serde_json::Value
+}
+
+/// Fetches executed transactions from a Hyperion history API (e.g. "https://kylin.eosn.io")
+#[derive(Debug, Clone)]
+pub struct HistoryApi
+{
+    pub url: String
+}
+
+impl HistoryApi
+{
+    /// Fetches up to 'limit' transactions of 'network' which executed a 'begin' action of the token contract in a
+    /// block after 'after_block' (in the order of execution). The recording contains the top level actions of each
+    /// transaction but no proofs (see: 'IpfsGateway').
+    pub async fn fetch(&self, network: Network, after_block: u64, limit: u64) -> Result<Fixture, String>
+    {
+        let url = self.url.trim_end_matches('/');
+        let contract = network.token_contract();
+        let res = http::get(&format!("{}/v2/history/get_actions?account={1}&filter={1}:begin&sort=asc&after={2}&limit={3}", url, contract, after_block, limit)).await?;
+        let res: HistoryActions = serde_json::from_str(&res).map_err(|e| e.to_string())?;
+
+        let mut transactions: Vec<RecordedTransaction> = Vec::new();
+        for begin in res.actions.into_iter().filter(|a| a.block_num > after_block)
+        {
+            // a transaction may contain more than one privacy sequence
+            if transactions.iter().any(|tx| tx.id == begin.trx_id)
+            {
+                continue;
+            }
+            let res = http::get(&format!("{}/v2/history/get_transaction?id={}", url, begin.trx_id)).await?;
+            let mut res: HistoryActions = serde_json::from_str(&res).map_err(|e| e.to_string())?;
+            res.actions.retain(|a| a.creator_action_ordinal == 0);
+            res.actions.sort_by_key(|a| a.action_ordinal);
+            transactions.push(RecordedTransaction {
+                id: begin.trx_id,
+                block_number: begin.block_num,
+                actions: res.actions.into_iter().map(|a| EOSAction {
+                    account: a.act.account,
+                    name: a.act.name,
+                    authorization: a.act.authorization,
+                    data: match a.act.data {
+                        serde_json::Value::String(data) => data,
+                        data => data.to_string()
+                    }
+                }).collect()
+            });
+        }
+        Ok(Fixture{ network, transactions, proofs: HashMap::new() })
+    }
+}
+
+/// An executed EOS transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedTransaction
+{
+    /// The EOS transaction id
+    #[serde(default)]
+    pub id: String,
+    /// The EOS block number the transaction was executed in
+    #[serde(default)]
+    pub block_number: u64,
+    /// The actions of the transaction
+    pub actions: Vec<EOSAction>
+}
+
+/// A recording of executed transactions (in the order of execution) and the proofs they refer to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture
+{
+    pub network: Network,
+    pub transactions: Vec<RecordedTransaction>,
+    /// The proofs (hex) by LiquidStorage URI
+    #[serde(default)]
+    pub proofs: HashMap<String, String>
+}
+
+impl ProofSource for Fixture
+{
+    fn fetch(&self, uri: &str) -> Option<String>
+    {
+        self.proofs.get(uri).cloned()
+    }
+}
+
+/// The result of the validation of a recorded transaction
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionReport
+{
+    pub id: String,
+    pub block_number: u64,
+    pub valid: bool,
+    /// The number of zactions of the transaction
+    pub zactions: usize,
+    pub error: Option<String>
+}
+
+/// A local copy of the global data sets of the ZEOS token contract
+#[derive(Debug, Clone, Default)]
+pub struct Ledger
+{
+    tree: ContractTree,
+    roots: HashSet<[u8; 32]>,
+    nullifiers: HashSet<[u8; 32]>
+}
+
+/// Returns the note commitments a zaction adds to the merkle tree
+pub(crate) fn commitments(za: &ZAction) -> Vec<ExtractedNoteCommitment>
+{
+    let ins = za.instance();
+    match za.za_type() {
+        ZA_MINTFT | ZA_MINTNFT | ZA_MINTAUTH | ZA_TRANSFERNFT => vec![ins.cmb],
+        ZA_TRANSFERFT => vec![ins.cmb, ins.cmc],
+        ZA_BURNFT => vec![ins.cmc],
+        _ => Vec::new()
+    }
+}
+
+impl Ledger
+{
+    /// Fetches a snapshot of the tables of 'contract': the merkle tree, the merkle roots and the nullifiers. The
+    /// tables are read one after the other, thus the contract must not execute transactions meanwhile for the
+    /// snapshot to be consistent.
+    pub async fn from_contract(contract: &TokenContract) -> Result<Self, String>
+    {
+        let global = contract.get_global_state().await?;
+        global.check_tree_depth().map_err(|e| e.to_string())?;
+        let mut ledger = Ledger::default();
+        let mut root = None;
+        for position in 0..global.leaf_count
+        {
+            let leaf = contract.get_merkle_hash(ContractTree::branch_indices(position)[0]).await?;
+            root = Some(ledger.tree.append(leaf.ok_or_else(|| format!("leaf {} not found", position))?));
+        }
+        if let Some(root) = root
+        {
+            let expected = contract.get_merkle_hash(*ContractTree::branch_indices(global.leaf_count - 1).last().unwrap()).await?;
+            if expected.map(Anchor::from) != Some(root)
+            {
+                return Err(format!("merkle root mismatch after {} leaves", global.leaf_count));
+            }
+        }
+        ledger.roots = contract.get_roots(0).await?.iter().map(|(_, root)| root.to_bytes()).collect();
+        ledger.nullifiers = contract.get_nullifiers(0).await?.iter().map(|(_, nf, _)| *nf).collect();
+        Ok(ledger)
+    }
+
+    /// Returns the merkle tree of note commitments
+    pub fn tree(&self) -> &ContractTree
+    {
+        &self.tree
+    }
+
+    /// Returns true if 'anchor' is a known merkle root
+    pub fn is_root(&self, anchor: &Anchor) -> bool
+    {
+        self.roots.contains(&anchor.to_bytes())
+    }
+
+    /// Returns the number of revealed nullifiers
+    pub fn nullifier_count(&self) -> usize
+    {
+        self.nullifiers.len()
+    }
+
+    /// Checks that all notes spent by 'zactions' exist under a known root and are not spent yet (neither before nor
+    /// by another of the 'zactions')
+    pub fn check(&self, zactions: &[ZAction]) -> Result<(), ValidationError>
+    {
+        let mut nullifiers = HashSet::new();
+        for (i, za) in zactions.iter().enumerate().filter(|(_, za)| is_spend(za.za_type()))
+        {
+            let ins = za.instance();
+            if !self.is_root(&ins.anchor)
+            {
+                return Err(ValidationError::UnknownAnchor(i));
+            }
+            let nf = ins.nf.to_bytes();
+            if self.nullifiers.contains(&nf) || !nullifiers.insert(nf)
+            {
+                return Err(ValidationError::DoubleSpend(i, hex::encode(nf)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies 'zactions' (which must have been checked before): reveals their nullifiers, appends their note
+    /// commitments to the merkle tree and adds the resulting merkle root to the set of roots
+    pub fn apply(&mut self, zactions: &[ZAction])
+    {
+        let mut root = None;
+        for za in zactions
+        {
+            if is_spend(za.za_type())
+            {
+                self.nullifiers.insert(za.instance().nf.to_bytes());
+            }
+            for cm in commitments(za)
+            {
+                root = Some(self.tree.append(MerkleHashOrchard::from_cmx(&cm)));
+            }
+        }
+        if let Some(root) = root
+        {
+            self.roots.insert(root.to_bytes());
+        }
+    }
+}
+
+/// Validates transactions and replays them against its local ledger
+pub struct Validator
+{
+    network: Network,
+    vk: VerifyingKey,
+    ledger: Ledger
+}
+
+impl Validator
+{
+    /// Creates a validator for 'network' with an empty ledger
+    pub fn new(network: Network, vk: VerifyingKey) -> Self
+    {
+        Self::with_ledger(network, vk, Ledger::default())
+    }
+
+    /// Creates a validator for 'network' which continues 'ledger' (see: 'Ledger::from_contract')
+    pub fn with_ledger(network: Network, vk: VerifyingKey, ledger: Ledger) -> Self
+    {
+        Validator {
+            network,
+            vk,
+            ledger
+        }
+    }
+
+    /// Returns the local ledger
+    pub fn ledger(&self) -> &Ledger
+    {
+        &self.ledger
+    }
+
+    /// Validates the EOS transaction given by its 'actions' and applies it to the local ledger if it is valid.
+    /// Returns the number of zactions of the transaction (zero if it has no privacy sequence).
+    pub fn validate(&mut self, actions: &[EOSAction], proofs: &dyn ProofSource) -> Result<usize, ValidationError>
+    {
+        let tx = DecodedTransaction::decode(self.network, actions)?;
+        let uri = match &tx.proof {
+            Some(uri) => uri,
+            None => return Ok(0)
+        };
+        let zactions = tx.zactions();
+
+        let expected = tx.expected_note_count();
+        if tx.encrypted_notes.len() != expected
+        {
+            return Err(ValidationError::NoteCountMismatch{ expected, found: tx.encrypted_notes.len() });
+        }
+        self.ledger.check(&zactions)?;
+        tx.verify_spend_auth()?;
+
+        // there is no circuit for ZA_MINTAUTH (i.e. nothing to prove)
+        if !tx.instances().is_empty()
+        {
+            let proof = proofs.fetch(uri).ok_or(ValidationError::ProofNotFound(uri.clone()))?;
+            if get_liquidstorage_uri(proof.clone(), uri.starts_with("z")) != *uri
+            {
+                return Err(ValidationError::ProofMismatch(uri.clone()));
+            }
+            let proof = hex::decode(proof).map_err(|_| ValidationError::ProofMismatch(uri.clone()))?;
+            verify_zactions(&proof, &zactions, &self.vk)?;
+        }
+
+        self.ledger.apply(&zactions);
+        Ok(zactions.len())
+    }
+
+    /// Validates all transactions of 'fixture' in order and returns a report for each. Proofs are taken from the
+    /// fixture or else from 'proofs'.
+    pub fn run(&mut self, fixture: &Fixture, proofs: Option<&dyn ProofSource>) -> Vec<TransactionReport>
+    {
+        struct Sources<'a>(&'a Fixture, Option<&'a dyn ProofSource>);
+        impl ProofSource for Sources<'_>
+        {
+            fn fetch(&self, uri: &str) -> Option<String>
+            {
+                self.0.fetch(uri).or_else(|| self.1.and_then(|p| p.fetch(uri)))
+            }
+        }
+        let sources = Sources(fixture, proofs);
+
+        fixture.transactions.iter().map(|tx| {
+            let res = self.validate(&tx.actions, &sources);
+            TransactionReport {
+                id: tx.id.clone(),
+                block_number: tx.block_number,
+                valid: res.is_ok(),
+                zactions: *res.as_ref().unwrap_or(&0),
+                error: res.err().map(|e| e.to_string())
+            }
+        }).collect()
+    }
+}
+
+/// Helpers to create the recorded transactions of tests
+#[cfg(test)]
+pub(crate) mod testing
+{
+    use rand::rngs::OsRng;
+    use rustzeos::halo2::Proof;
+    use crate::action::RawZAction;
+    use crate::builder::{assemble_transaction, exec_action, transaction_sighash, EOSAction, EOSAuthorization};
+    use crate::bundle::Bundle;
+    use crate::keys::{FullViewingKey, Scope, SpendingKey};
+    use crate::network::Network;
+    use crate::note::{Note, Nullifier, NT_FT};
+    use crate::sighash::sign_spends;
+    use crate::value::NoteValue;
+
+    /// Creates a fungible token note of 'd1' units for the 'i'-th address of 'fvk'
+    pub fn ft_note(fvk: &FullViewingKey, d1: u64, i: u32) -> Note
+    {
+        Note::new(NT_FT, fvk.address_at(i, Scope::External), NoteValue::from_raw(d1), NoteValue::from_raw(1), NoteValue::from_raw(0), NoteValue::from_raw(0), Nullifier::dummy(&mut OsRng), OsRng, [0; 512])
+    }
+
+    /// Assembles the actions of a Kylin transaction of 'rzactions' with 'proof' whose spends are signed by 'sk'
+    pub fn transaction(sk: &SpendingKey, rzactions: Vec<RawZAction>, proof: &Proof) -> Vec<EOSAction>
+    {
+        let auth = vec![EOSAuthorization::new("mschoenebeck", "active")];
+        let encrypted_notes = Bundle::from_parts(rzactions.clone()).encrypted_notes(OsRng);
+        let steps = vec![(exec_action(Network::Kylin), rzactions.clone())];
+        let h = transaction_sighash(&Vec::new(), &steps, &Vec::new(), &encrypted_notes).unwrap();
+        let sigs = sign_spends(sk, &rzactions, &h, &mut OsRng);
+        assemble_transaction(Network::Kylin, Some(proof), &encrypted_notes, &sigs, Vec::new(), steps, Vec::new(), &auth).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashMap;
+    use nonempty::NonEmpty;
+    use rand::rngs::OsRng;
+    use rustzeos::halo2::{Proof, ProvingKey, VerifyingKey};
+    use super::testing::{ft_note, transaction};
+    use super::{Fixture, Ledger, RecordedTransaction, ValidationError, Validator};
+    use crate::action::{RawZAction, ZA_MINTFT, ZA_TRANSFERFT};
+    use crate::builder::{exec_action, get_liquidstorage_uri, EOSAction};
+    use crate::bundle::{Bundle, VerificationError};
+    use crate::circuit::{Circuit, K};
+    use crate::contract::TokenContract;
+    use crate::keys::{FullViewingKey, SpendingKey};
+    use crate::network::Network;
+    use crate::note::{ExtractedNoteCommitment, Note, Nullifier};
+    use crate::simulator::SimulatedChain;
+    use crate::tree::{ContractTree, MerkleHashOrchard, MerklePath};
+
+    #[test]
+    fn validator()
+    {
+        let mut rng = OsRng;
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        let pk = ProvingKey::build(Circuit::default(), K);
+        let vk = VerifyingKey::build(Circuit::default(), K);
+
+        // assembles a transaction of 'rzactions' with 'proof' (or a new proof which is added to 'proofs')
+        let tx = |rzactions: Vec<RawZAction>, proof: Option<&Proof>, proofs: &mut HashMap<String, String>| -> Vec<EOSAction> {
+            let proof = match proof {
+                Some(p) => Proof::new(p.as_ref().to_vec()),
+                None => {
+                    let ((proof, _, _), _, _) = Bundle::from_parts(rzactions.clone()).prepare(&pk, OsRng).unwrap();
+                    let hex = hex::encode(proof.as_ref());
+                    proofs.insert(get_liquidstorage_uri(hex.clone(), true), hex);
+                    proof
+                }
+            };
+            transaction(&sk, rzactions, &proof)
+        };
+        let note = |d1: u64, i: u32| ft_note(&fvk, d1, i);
+        let fixture = |proofs: &HashMap<String, String>| Fixture{ network: Network::Kylin, transactions: Vec::new(), proofs: proofs.clone() };
+
+        // mint a note and spend it
+        let note_a = note(10, 0);
+        let mut proofs = HashMap::new();
+        let mint = tx(vec![RawZAction::from_parts(ZA_MINTFT, &fvk, None, None, Some(note_a), None, String::new(), rng)], None, &mut proofs);
+        let mint_uri = proofs.keys().next().unwrap().clone();
+        let mut validator = Validator::new(Network::Kylin, VerifyingKey::build(Circuit::default(), K));
+        assert_eq!(validator.validate(&mint, &fixture(&proofs)).unwrap(), 1);
+        assert_eq!(validator.ledger().tree().leaf_count(), 1);
+
+        let path = validator.ledger().tree().path(0);
+        let transfer = tx(vec![RawZAction::from_parts(ZA_TRANSFERFT, &fvk, Some(path), Some(note_a), Some(note(7, 1)), Some(note(3, 0)), String::new(), rng)], None, &mut proofs);
+        assert_eq!(validator.validate(&transfer, &fixture(&proofs)).unwrap(), 1);
+        assert_eq!(validator.ledger().tree().leaf_count(), 3);
+        assert_eq!(validator.ledger().nullifier_count(), 1);
+
+        // double spend
+        assert!(matches!(validator.validate(&transfer, &fixture(&proofs)), Err(ValidationError::DoubleSpend(0, _))));
+        // unknown root (the ledger is checked before the proof)
+        let dummy = Proof::new(vec![0; 32]);
+        let forged = tx(vec![RawZAction::from_parts(ZA_TRANSFERFT, &fvk, Some(MerklePath::dummy(&mut rng)), Some(note(10, 0)), Some(note(7, 1)), Some(note(3, 0)), String::new(), rng)], Some(&dummy), &mut proofs);
+        assert!(matches!(validator.validate(&forged, &fixture(&proofs)), Err(ValidationError::UnknownAnchor(0))));
+        // the proof of another transaction
+        let mint_proof = Proof::new(hex::decode(&proofs[&mint_uri]).unwrap());
+        let forged = tx(vec![RawZAction::from_parts(ZA_MINTFT, &fvk, None, None, Some(note(1000, 0)), None, String::new(), rng)], Some(&mint_proof), &mut proofs);
+        assert!(matches!(validator.validate(&forged, &fixture(&proofs)), Err(ValidationError::InvalidProof(VerificationError::InvalidProof))));
+        // a missing proof or a proof which does not match its URI
+        let mut wrong = HashMap::new();
+        assert!(matches!(validator.validate(&forged, &fixture(&wrong)), Err(ValidationError::ProofNotFound(_))));
+        wrong.insert(mint_uri, String::from("00"));
+        assert!(matches!(validator.validate(&forged, &fixture(&wrong)), Err(ValidationError::ProofMismatch(_))));
+        assert_eq!(validator.ledger().tree().leaf_count(), 3);
+
+        // replay the recorded transactions offline
+        let recorded = |id: &str, block_number: u64, actions: &Vec<EOSAction>| RecordedTransaction{ id: id.to_string(), block_number, actions: actions.clone() };
+        let fixture = Fixture {
+            network: Network::Kylin,
+            transactions: vec![recorded("a", 1, &mint), recorded("b", 2, &transfer), recorded("c", 3, &transfer), recorded("d", 4, &vec![exec_action(Network::Kylin)])],
+            proofs
+        };
+        let fixture: Fixture = serde_json::from_str(&serde_json::to_string(&fixture).unwrap()).unwrap();
+        let reports = Validator::new(Network::Kylin, vk).run(&fixture, None);
+        assert_eq!(reports.iter().map(|r| r.valid).collect::<Vec<_>>(), vec![true, true, false, true]);
+        assert_eq!(reports[1].zactions, 1);
+        assert!(reports[2].error.as_ref().unwrap().contains("already spent"));
+        assert_eq!(reports[3].zactions, 0);
+    }
+
+    #[tokio::test]
+    async fn ledger_from_contract()
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk: FullViewingKey = (&sk).into();
+        let notes: Vec<Note> = (0..3).map(|i| ft_note(&fvk, 10, i)).collect();
+        chain.add_notes(&notes[0..1], None);
+        chain.add_notes(&notes[1..3], None);
+        chain.spend(&[Nullifier::dummy(&mut OsRng)]);
+
+        let ledger = Ledger::from_contract(&TokenContract::new(Network::Local, NonEmpty::new(url))).await.unwrap();
+        assert_eq!((ledger.tree().leaf_count(), ledger.nullifier_count()), (3, 1));
+        // the roots after each block are known, the root in the middle of the second block is not
+        let mut tree = ContractTree::default();
+        let roots: Vec<_> = notes.iter().map(|n| tree.append(MerkleHashOrchard::from_cmx(&ExtractedNoteCommitment::from(n.commitment())))).collect();
+        assert_eq!(roots.iter().map(|r| ledger.is_root(r)).collect::<Vec<_>>(), vec![true, false, true]);
+    }
+}
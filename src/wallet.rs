@@ -1,18 +1,29 @@
 //! Logic for everything wallet related.
 
-use crate::builder::TransactionBuilder;
+use crate::unproven::{UnprovenTransaction, UnprovenTransactionError};
+use crate::prover::RemoteProver;
+use crate::builder::{TransactionBuilder, TransactionBuilderError, EOSAction, EOSActionDesc, EOSAuthorization, BatchLimits, Payment};
+use crate::builder::{actions_json, plan_transaction, shield_action_descs, transfer_action_descs, unshield_action_descs};
 use crate::constants::MERKLE_DEPTH_ORCHARD;
-use crate::keys::{PreparedIncomingViewingKey, SpendingKey, FullViewingKey, Scope::External};
-use crate::contract::{Global, NoteEx, TokenContract};
-use crate::ENDPOINTS;
+use crate::relayer::{self, RelayerInfo, RelayRequest};
+use crate::indexer::IndexerClient;
+use crate::keys::{PreparedIncomingViewingKey, IncomingViewingKey, SpendingKey, FullViewingKey, Scope::External};
+use crate::encoding::{KeyEncodingError, KeyType};
+use crate::contract::{Global, NoteEx, TokenContract, TransmittedNoteCiphertextEx, CompactNoteCiphertextEx};
+use crate::network::{Network, NetworkError};
+use crate::payment_request::{PaymentRequest, PaymentRequestError, RequestedPayment};
+use crate::tree::TreeDepthError;
 use crate::circuit::{Circuit, K};
-use crate::eosio::{symbol_to_string_precision, string_to_symbol, value_to_name};
+use crate::eosio::{name_to_value, symbol_to_string_precision, string_to_symbol, value_to_name, Asset};
+use crate::note::{ExtractedNoteCommitment, Note, NT_AT, NH_BURN_FLAG};
+use crate::wallet_store::{join_wallet_json, split_wallet_json, MemoryStore, NoteQuery, NoteStatus, StoreBatch, WalletStore, WalletStoreError};
 
-use rustzeos::halo2::ProvingKey;
+use rustzeos::halo2::{Proof, ProvingKey};
 use wasm_bindgen::prelude::*;
 extern crate console_error_panic_hook;
 extern crate serde_json;
 use std::collections::HashMap;
+use nonempty::NonEmpty;
 use std::fmt::Debug;
 use serde::Serialize;
 use serde_wasm_bindgen::Serializer;
@@ -21,48 +32,80 @@ use serde_wasm_bindgen::Serializer;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Settings
 {
+    /// the network this wallet operates on (wallets created before network profiles existed operate on Kylin)
+    #[serde(default)]
+    network: Network,
     /// list of API endpoints
     eos_endpoints: Vec<String>,
     dsp_endpoints: Vec<String>,
+    /// ZEOS indexer endpoints (see: indexer) the wallet synchronizes with instead of the contract tables if not empty
+    #[serde(default)]
     zeos_endpoints: Vec<String>,
     /// maps token symbol to contract name and decimals of known fungible tokens
     ft_contracts: HashMap<String, (String, u64)>,
     /// list of known NFT contracts
-    nft_contracts: Vec<String>
+    nft_contracts: Vec<String>,
+    /// URL of a remote prover service (proofs are calculated locally if not set)
+    #[serde(default)]
+    prover_url: Option<String>
 }
 
 impl Default for Settings
 {
     fn default() -> Self
+    {
+        Settings::for_network(Network::default())
+    }
+}
+
+impl Settings
+{
+    /// Returns the default settings of 'network'
+    pub fn for_network(network: Network) -> Self
     {
         Settings {
-            eos_endpoints: vec![
-                "https://kylin.eosn.io".to_string()
-            ],
-            dsp_endpoints: vec![
-                "https://kylin-dsp-1.liquidapps.io".to_string()
-            ],
+            network,
+            eos_endpoints: network.eos_endpoints(),
+            dsp_endpoints: network.dsp_endpoints(),
             zeos_endpoints: vec![
             ],
             ft_contracts: HashMap::from([
                 ("EOS".to_string(), ("eosio.token".to_string(), string_to_symbol(&"EOS".to_string(), 4))),
                 ("DAPP".to_string(), ("dappservices".to_string(), string_to_symbol(&"DAPP".to_string(), 4))),
-                ("ZEOS".to_string(), ("thezeostoken".to_string(), string_to_symbol(&"ZEOS".to_string(), 4))),
+                ("ZEOS".to_string(), (network.token_contract().to_string(), string_to_symbol(&"ZEOS".to_string(), 4))),
             ]),
             nft_contracts: vec![
                 "atomicassets".to_string()
             ],
+            prover_url: None,
         }
     }
+
+    /// Returns the network of these settings
+    pub fn network(&self) -> Network
+    {
+        self.network
+    }
+
+    /// Returns the token contract and the symbol of the known fungible token 'symbol' (e.g. "EOS")
+    pub fn ft_contract(&self, symbol: &str) -> Option<&(String, u64)>
+    {
+        self.ft_contracts.get(symbol)
+    }
 }
 
-/// A ZEOS wallet.
-#[wasm_bindgen]
+/// A ZEOS wallet. Its notes are kept by the store 'S' (see: wallet_store), the serialization of a wallet contains
+/// everything else.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Wallet
+#[serde(bound(serialize = "", deserialize = "S: Default"))]
+pub struct Wallet<S: WalletStore = MemoryStore>
 {
-    /// The seed phrase
+    /// The seed phrase (empty if the wallet has been imported from a key)
     pub(crate) seed: String,
+    /// The Bech32m encoded spending key, full viewing key (watch-only) or incoming viewing key (receive-only) of a
+    /// wallet imported from a key
+    #[serde(default)]
+    pub(crate) imported_key: Option<String>,
     /// The state of this wallet
     pub(crate) state: Global,
     /// The settings of this wallet
@@ -73,10 +116,109 @@ pub struct Wallet
     #[serde(skip)]
     #[serde(default = "default_proving_key")]
     pk: ProvingKey,
-    /// The received/spendable notes of this wallet
-    pub(crate) spendable_notes: Vec<NoteEx>,
-    /// The notes that have been sent from this wallet
-    pub(crate) sent_notes: Vec<NoteEx>,
+    /// The private deposits into third party smart contracts made from this wallet
+    #[serde(default)]
+    pub(crate) private_deposits: Vec<PrivateDeposit>,
+    /// The last irreversible block of the chain as of the latest synchronization
+    #[serde(default)]
+    pub(crate) last_irreversible_block: u64,
+    /// The notes (of all users) synchronized from reversible blocks ordered by id. They are compared with the chain on
+    /// each synchronization in order to detect forks.
+    #[serde(default)]
+    pub(crate) reversible_notes: Vec<ReversibleNote>,
+    /// The notes of this wallet spent by notes from reversible blocks (restored if the chain forks)
+    #[serde(default)]
+    pub(crate) reversible_spends: Vec<ReversibleSpend>,
+    /// The store of the received/spendable notes and the notes that have been sent from this wallet
+    #[serde(skip)]
+    store: S,
+}
+
+/// A private deposit of an asset into a third party smart contract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivateDeposit
+{
+    /// The EOSIO/Antelope account of the third party smart contract
+    pub(crate) contract: String,
+    /// The deposited asset
+    pub(crate) asset: Asset,
+    /// The note commitment of the auth token (hex) which is required to withdraw the deposit
+    pub(crate) cm_auth: String,
+    /// The auth token minted by the deposit
+    #[serde(default)]
+    pub(crate) auth_note: Option<Note>,
+    /// Indicates that the deposit has been executed: set during sync once the auth token has been received. The
+    /// deposit of a transaction which is never executed remains unconfirmed and cannot be withdrawn.
+    #[serde(default)]
+    pub(crate) confirmed: bool,
+    /// The memo of the deposit (without the base85 encoded auth token commitment)
+    pub(crate) memo: String,
+    /// The global id of the note the withdrawn asset has been minted into (set during sync)
+    #[serde(default)]
+    pub(crate) withdrawn_note_id: Option<u64>,
+}
+
+/// A received or sent note of the transaction history of a wallet
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry
+{
+    /// The global id of the note
+    pub(crate) id: u64,
+    /// The EOS block number when the note was added to the global list of encrypted notes
+    pub(crate) block_number: u64,
+    /// "received" or "sent"
+    pub(crate) direction: &'static str,
+    /// The transferred asset
+    pub(crate) asset: Asset,
+    /// The shielded address of the recipient
+    pub(crate) address: String,
+    /// The memo of the note
+    pub(crate) memo: String,
+    /// Indicates that the note has been added in an irreversible block (notes from reversible blocks are rolled back
+    /// if the chain forks)
+    pub(crate) confirmed: bool,
+}
+
+/// A note (of any user) which has been synchronized from a reversible block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReversibleNote
+{
+    /// The global id of the note
+    id: u64,
+    /// The EOS block number when the note was added to the global list of encrypted notes
+    block_number: u64,
+    /// The number of leaves of the merkle tree when the note was added
+    leaf_index: u64,
+    /// The ephemeral public key of the note (hex) which identifies the note
+    epk: String,
+}
+
+impl From<&TransmittedNoteCiphertextEx> for ReversibleNote
+{
+    fn from(n: &TransmittedNoteCiphertextEx) -> Self
+    {
+        ReversibleNote{ id: n.id, block_number: n.block_number, leaf_index: n.leaf_index, epk: hex::encode(n.encrypted_note.epk_bytes) }
+    }
+}
+
+impl From<&CompactNoteCiphertextEx> for ReversibleNote
+{
+    fn from(n: &CompactNoteCiphertextEx) -> Self
+    {
+        ReversibleNote{ id: n.id, block_number: n.block_number, leaf_index: n.leaf_index, epk: hex::encode(n.encrypted_note.epk_bytes) }
+    }
+}
+
+/// A note of this wallet which has been spent by a note from a reversible block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ReversibleSpend
+{
+    /// The global id of the (sent) note which spent 'note'
+    id: u64,
+    /// The EOS block number of the note which spent 'note'
+    block_number: u64,
+    /// The spent note
+    note: NoteEx,
 }
 
 fn default_proving_key() -> ProvingKey
@@ -84,11 +226,61 @@ fn default_proving_key() -> ProvingKey
     ProvingKey::build(Circuit::default(), K)
 }
 
-impl From<crate::zip32::Error> for JsError
+#[derive(thiserror::Error, Debug)]
+pub enum WalletError
+{
+    #[error("{0}")]
+    General(String),
+    #[error(transparent)]
+    KeyEncoding(#[from] KeyEncodingError),
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+    #[error(transparent)]
+    TransactionBuilder(#[from] TransactionBuilderError),
+    #[error(transparent)]
+    TreeDepth(#[from] TreeDepthError),
+    #[error(transparent)]
+    UnprovenTransaction(#[from] UnprovenTransactionError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Serialization(String),
+    #[error(transparent)]
+    Store(#[from] WalletStoreError),
+    #[error(transparent)]
+    PaymentRequest(#[from] PaymentRequestError)
+}
+
+impl From<crate::zip32::Error> for WalletError
 {
     fn from(err: crate::zip32::Error) -> Self
     {
-        JsError::new(&err.to_string())
+        Self::General(err.to_string())
+    }
+}
+
+impl From<hex::FromHexError> for WalletError
+{
+    fn from(err: hex::FromHexError) -> Self
+    {
+        Self::General(err.to_string())
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for WalletError
+{
+    fn from(err: serde_wasm_bindgen::Error) -> Self
+    {
+        Self::Serialization(err.to_string())
+    }
+}
+
+// errors are thrown as JS 'Error' objects (only converted in WASM builds, JS values must not be created natively)
+impl From<WalletError> for JsValue
+{
+    fn from(err: WalletError) -> Self
+    {
+        JsError::new(&err.to_string()).into()
     }
 }
 
@@ -99,174 +291,351 @@ where
     Ok(obj.serialize(&Serializer::json_compatible())?)
 }
 
-#[wasm_bindgen]
+/// Returns the memo of 'note' (up to the first zero byte)
+fn note_memo(note: &Note) -> String
+{
+    let memo = note.memo();
+    let len = memo.iter().position(|b| *b == 0).unwrap_or(memo.len());
+    String::from_utf8_lossy(&memo[0..len]).to_string()
+}
+
+/// Returns the note commitment of the auth token 'note' (hex) which identifies a private deposit
+fn auth_token_commitment(note: &Note) -> String
+{
+    hex::encode(ExtractedNoteCommitment::from(note.commitment()).to_bytes())
+}
+
+/// Uploads the proof (if any) and returns the JSON string of EOS actions ready to execute.
+async fn transaction_json(contract: &TokenContract, proof: Option<Proof>, actions: &Vec<EOSAction>) -> Result<String, WalletError>
+{
+    if proof.is_some()
+    {
+        let proof_str = hex::encode(proof.unwrap().as_ref());
+        contract.upload_proof_to_liquidstorage(&proof_str).await.map_err(WalletError::General)?;
+    }
+
+    Ok(actions_json(actions)?)
+}
+
 impl Wallet
 {
-    /// Creates a new wallet from seed phrase
-    /// TODO: add 'wallet birthday' (i.e. allow for initialization of 'state' as well)
-    pub fn new(seed: String) -> Result<Wallet, JsError>
+    /// Creates an empty wallet which operates on 'network' from a seed phrase or an imported key
+    fn from_parts(seed: String, imported_key: Option<String>, network: Network) -> Self
     {
-        SpendingKey::from_zip32_seed(seed.as_bytes(), 0, 0)?;
-        Ok(Wallet {
-            seed: seed.clone(),
+        Wallet {
+            seed,
+            imported_key,
             state: Global{ note_count: 0, leaf_count: 0, tree_depth: MERKLE_DEPTH_ORCHARD as u64 },
-            settings: Settings::default(),
+            settings: Settings::for_network(network),
             diversifier_index: 0,
             pk: default_proving_key(),
-            spendable_notes: Vec::new(),
-            sent_notes: Vec::new(),
-        })
+            private_deposits: Vec::new(),
+            last_irreversible_block: 0,
+            reversible_notes: Vec::new(),
+            reversible_spends: Vec::new(),
+            store: MemoryStore::default(),
+        }
+    }
+
+    /// Creates a new wallet from seed phrase
+    /// TODO: add 'wallet birthday' (i.e. allow for initialization of 'state' as well)
+    pub fn new(seed: String) -> Result<Wallet, WalletError>
+    {
+        Self::new_for_network(seed, Network::default().to_string())
     }
 
-    /// Restores a wallet from JSON string
-    pub fn from_string(json: String) -> Result<Wallet, JsError>
+    /// Creates a new wallet from seed phrase which operates on 'network' ("mainnet", "kylin", "jungle" or "local")
+    pub fn new_for_network(seed: String, network: String) -> Result<Wallet, WalletError>
     {
-        let res: Self = serde_json::from_str(&json)?;
-        Ok(res)
+        let network: Network = network.parse()?;
+        SpendingKey::from_zip32_seed(seed.as_bytes(), 0, 0)?;
+        Ok(Self::from_parts(seed, None, network))
+    }
+
+    /// Creates a new wallet from a Bech32m encoded spending key, full viewing key or incoming viewing key of 'network'.
+    /// A wallet imported from a full viewing key is watch-only: it can sync, show balances and create unproven
+    /// transactions but cannot authorize spends. A wallet imported from an incoming viewing key is receive-only: it
+    /// detects received notes (e.g. of a payment processor) but neither sent notes nor spends.
+    pub fn from_key(key: String, network: String) -> Result<Wallet, WalletError>
+    {
+        let network: Network = network.parse()?;
+        match SpendingKey::from_bech32m(&key, network) {
+            Ok(_) => (),
+            Err(KeyEncodingError::WrongKeyType{ found: KeyType::FullViewingKey, .. }) => { FullViewingKey::from_bech32m(&key, network)?; }
+            Err(KeyEncodingError::WrongKeyType{ found: KeyType::IncomingViewingKey, .. }) => { IncomingViewingKey::from_bech32m(&key, network)?; }
+            Err(e) => return Err(e.into())
+        }
+        Ok(Self::from_parts(String::new(), Some(key), network))
     }
 
-    /// Converts a wallet to JSON formatted string to be restored later using
-    /// the 'from_string' function above.
-    pub fn to_string(&self) -> Result<String, JsError>
+    /// Restores a wallet from JSON string. Wallets of older format versions are migrated to the current version (see:
+    /// wallet_format).
+    pub fn from_string(json: String) -> Result<Wallet, WalletError>
     {
-        let res = serde_json::to_string(self)?;
+        let (data, notes) = split_wallet_json(&json)?;
+        let mut res: Self = serde_json::from_str(&data)?;
+        res.store.apply(StoreBatch{ wallet: Some(data), insert: notes, remove: Vec::new() })?;
         Ok(res)
     }
+}
 
-    /// Synchronize wallet state with contract state
-    pub async fn sync(&mut self) -> Result<(), JsError>
+impl<S: WalletStore> Wallet<S>
+{
+    /// Opens the wallet kept in 'store' (e.g. a 'SqliteStore' a wallet has been moved into by 'with_store')
+    pub fn open(store: S) -> Result<Self, WalletError>
     {
-        let contract = TokenContract::new(ENDPOINTS.map(String::from));
-        let global = contract.get_global_state().await;
-        if global.note_count == self.state.note_count
+        let data = store.load()?.ok_or(WalletError::General(String::from("the store does not contain a wallet")))?;
+        let wallet: Wallet = serde_json::from_str(&data)?;
+        Ok(wallet.into_store(store))
+    }
+
+    /// Moves this wallet including its notes into the empty 'store' (e.g. a wallet created by 'new_for_network' into
+    /// a 'SqliteStore')
+    pub fn with_store<T: WalletStore>(self, mut store: T) -> Result<Wallet<T>, WalletError>
+    {
+        let mut insert: Vec<(NoteStatus, NoteEx)> = self.spendable_notes()?.into_iter().map(|n| (NoteStatus::Spendable, n)).collect();
+        insert.extend(self.sent_notes()?.into_iter().map(|n| (NoteStatus::Sent, n)));
+        store.apply(StoreBatch{ wallet: Some(serde_json::to_string(&self)?), insert, remove: Vec::new() })?;
+        Ok(self.into_store(store))
+    }
+
+    /// Replaces the store of this wallet by 'store' (the notes are not copied)
+    fn into_store<T: WalletStore>(self, store: T) -> Wallet<T>
+    {
+        Wallet {
+            seed: self.seed,
+            imported_key: self.imported_key,
+            state: self.state,
+            settings: self.settings,
+            diversifier_index: self.diversifier_index,
+            pk: self.pk,
+            private_deposits: self.private_deposits,
+            last_irreversible_block: self.last_irreversible_block,
+            reversible_notes: self.reversible_notes,
+            reversible_spends: self.reversible_spends,
+            store,
+        }
+    }
+
+    /// Writes the wallet data (keys, settings, addresses, private deposits and synchronization state) to the store.
+    /// Notes are written by 'sync'.
+    pub fn save(&mut self) -> Result<(), WalletError>
+    {
+        let wallet = serde_json::to_string(self)?;
+        Ok(self.store.apply(StoreBatch{ wallet: Some(wallet), insert: Vec::new(), remove: Vec::new() })?)
+    }
+
+    /// Returns the notes of this wallet which match 'query' ordered by note id
+    pub fn notes(&self, query: &NoteQuery) -> Result<Vec<NoteEx>, WalletError>
+    {
+        Ok(self.store.notes(query)?)
+    }
+
+    /// Returns the received (and still spendable) notes of this wallet ordered by note id
+    pub fn spendable_notes(&self) -> Result<Vec<NoteEx>, WalletError>
+    {
+        self.notes(&NoteQuery::default().with_status(NoteStatus::Spendable))
+    }
+
+    /// Returns the notes that have been sent from this wallet ordered by note id
+    pub fn sent_notes(&self) -> Result<Vec<NoteEx>, WalletError>
+    {
+        self.notes(&NoteQuery::default().with_status(NoteStatus::Sent))
+    }
+
+    /// Returns the spending key of this wallet which is derived from the seed phrase or imported. Fails if the wallet
+    /// is watch-only.
+    fn spending_key(&self) -> Result<SpendingKey, WalletError>
+    {
+        if !self.seed.is_empty()
         {
-            return Ok(());
+            return Ok(SpendingKey::from_zip32_seed(self.seed.as_bytes(), 0, 0)?);
+        }
+        match &self.imported_key {
+            Some(key) => SpendingKey::from_bech32m(key, self.settings.network).map_err(|_| WalletError::General(String::from("watch-only wallet: spending key not available"))),
+            None => Err(WalletError::General(String::from("wallet has neither a seed phrase nor a key")))
         }
+    }
 
-        // derive keys required to decrypt notes
-        let fvk = FullViewingKey::from(&SpendingKey::from_zip32_seed(self.seed.as_bytes(), 0, 0)?);
+    /// Returns the full viewing key of this wallet. Fails if the wallet is receive-only.
+    fn full_viewing_key(&self) -> Result<FullViewingKey, WalletError>
+    {
+        if let Ok(sk) = self.spending_key()
+        {
+            return Ok(FullViewingKey::from(&sk));
+        }
+        match &self.imported_key {
+            Some(key) => FullViewingKey::from_bech32m(key, self.settings.network).map_err(|e| match e {
+                KeyEncodingError::WrongKeyType{ found: KeyType::IncomingViewingKey, .. } => WalletError::General(String::from("receive-only wallet: full viewing key not available")),
+                e => e.into()
+            }),
+            None => Err(WalletError::General(String::from("wallet has neither a seed phrase nor a key")))
+        }
+    }
 
-        let encrypted_notes = contract.get_encrypted_notes(self.state.note_count, global.note_count).await;
-        let mut new_notes = Vec::new();
-        for en in encrypted_notes
+    /// Returns the (external) incoming viewing key of this wallet which is derived from the full viewing key or imported
+    fn incoming_viewing_key(&self) -> Result<IncomingViewingKey, WalletError>
+    {
+        if let Ok(fvk) = self.full_viewing_key()
         {
-            let o = en.try_decrypt_as_receiver(&PreparedIncomingViewingKey::new(&fvk.to_ivk(External)));
-            if o.is_some()
-            {
-                new_notes.push(o.unwrap());
-            }
-            let o = en.try_decrypt_as_sender(&fvk.to_ovk(External));
-            if o.is_some()
-            {
-                let sn = o.unwrap();
-                self.spendable_notes.retain(|n| n.note.nullifier(&fvk) != sn.note.rho());
-                self.sent_notes.push(sn);
-            }
+            return Ok(fvk.to_ivk(External));
+        }
+        match &self.imported_key {
+            Some(key) => Ok(IncomingViewingKey::from_bech32m(key, self.settings.network)?),
+            None => Err(WalletError::General(String::from("wallet has neither a seed phrase nor a key")))
         }
+    }
 
-        // move new notes into 'notes' and update wallet state
-        self.spendable_notes.append(&mut new_notes);
-        self.state = global;
+    /// Returns the token contract of this wallet's network which is queried using the configured endpoints: the DSP
+    /// endpoints first (only they serve the vRAM tables) followed by the EOS endpoints. Falls back to the default
+    /// endpoints of the network if none are configured.
+    fn token_contract(&self) -> TokenContract
+    {
+        let mut endpoints = self.settings.dsp_endpoints.clone();
+        endpoints.extend(self.settings.eos_endpoints.iter().cloned());
+        match NonEmpty::from_vec(endpoints) {
+            Some(endpoints) => TokenContract::new(self.settings.network, endpoints),
+            None => TokenContract::from_network(self.settings.network)
+        }
+    }
 
-        Ok(())
+    /// Returns a transaction builder for the current state which uses the remote prover if configured
+    fn transaction_builder(&self) -> TransactionBuilder
+    {
+        let builder = TransactionBuilder::new(self.settings.network, self.state.leaf_count);
+        match &self.settings.prover_url {
+            Some(url) => builder.with_remote_prover(RemoteProver::new(url)),
+            None => builder
+        }
     }
 
-    pub async fn create_transaction(
+    /// Builds the transaction described by 'action_descs' and returns the JSON string of EOS actions ready to execute
+    async fn build_transaction_json(
         &self,
-        js_action_descs: JsValue,   // Vec<EOSActionDesc>
-        js_eos_auth: JsValue        // Vec<EOSAuth>
-    ) -> Result<String, JsError>
+        action_descs: &Vec<EOSActionDesc>,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Result<String, WalletError>
     {
-        let action_descs = serde_wasm_bindgen::from_value(js_action_descs)?;
-        let eos_auth = serde_wasm_bindgen::from_value(js_eos_auth)?;
-        let mut contract = TokenContract::new(ENDPOINTS.map(String::from));
-        let builder = TransactionBuilder::new(self.state.leaf_count);
-        let sk = SpendingKey::from_zip32_seed(self.seed.as_bytes(), 0, 0)?;
-        //log(&format!("{:?}", action_descs));
+        self.build_transaction_json_from(&mut self.spendable_notes()?, action_descs, eos_auth).await
+    }
+
+    /// Like 'build_transaction_json' but spends only 'notes' (a subset of the spendable notes). The notes spent by the
+    /// transaction are removed from 'notes'.
+    async fn build_transaction_json_from(
+        &self,
+        notes: &mut Vec<NoteEx>,
+        action_descs: &Vec<EOSActionDesc>,
+        eos_auth: &Vec<EOSAuthorization>
+    ) -> Result<String, WalletError>
+    {
+        let mut contract = self.token_contract();
+        let builder = self.transaction_builder();
+        let sk = self.spending_key()?;
 
         let (proof, actions) = builder.build_transaction(
             &self.pk,
             &sk,
-            &mut self.spendable_notes.clone(),
-            &action_descs,
+            notes,
+            action_descs,
             &mut contract,
-            &eos_auth
+            eos_auth
         ).await?;
 
-        assert!(proof.is_some());
-        let proof_str = hex::encode(proof.unwrap().as_ref());
-        contract.upload_proof_to_liquidstorage(&proof_str).await;
+        transaction_json(&contract, proof, &actions).await
+    }
 
-        // Returns JSON string of EOS actions ready to execute.
-        // All non-serialized 'data' strings should be valid JSON
-        // => remove quotation marks and backslashes
-        Ok(serde_json::to_string(&actions)?
-            .replace(r#""data":"{"#, r#""data":{"#)
-            .replace(r#"}"}"#, r#"}}"#)
-            .replace("\\", "")
-        )
+    /// Returns the settings of this wallet
+    pub fn settings(&self) -> &Settings
+    {
+        &self.settings
     }
 
-    /// Returns the address of a certain diversifier as hex string
-    pub fn address(
-        &self,
-        diversifier_index: u32
-    ) -> String
+    /// Sets the API endpoints this wallet queries (e.g. of a simulated chain)
+    pub fn set_endpoints(&mut self, eos_endpoints: Vec<String>, dsp_endpoints: Vec<String>)
     {
-        let sk = SpendingKey::from_zip32_seed(self.seed.as_bytes(), 0, 0).unwrap();
-        let fvk = FullViewingKey::from(&sk);
-        let addr = fvk.address_at(diversifier_index, External);
-        addr.to_bech32m()
+        self.settings.eos_endpoints = eos_endpoints;
+        self.settings.dsp_endpoints = dsp_endpoints;
     }
 
-    /// Increments the internal diversifier index by one and returns a newly derived wallet address from that index
-    pub fn derive_new_wallet_address(&mut self) -> String
+    /// Sets the ZEOS indexer endpoints this wallet synchronizes with (see: indexer). The contract tables are queried
+    /// if there are none.
+    pub fn set_zeos_endpoints(&mut self, zeos_endpoints: Vec<String>)
     {
-        self.diversifier_index += 1;
-        let sk = SpendingKey::from_zip32_seed(self.seed.as_bytes(), 0, 0).unwrap();
-        let fvk = FullViewingKey::from(&sk);
-        let addr = fvk.address_at(self.diversifier_index, External);
-        addr.to_bech32m()
+        self.settings.zeos_endpoints = zeos_endpoints;
     }
 
-    /// Returns a key/value map of all ever generated wallet addresses (diversifier_index => address)
-    pub fn get_addresses(&self) -> JsValue
+    /// Returns the contract state this wallet is synchronized with
+    pub fn state(&self) -> &Global
     {
-        let mut map = HashMap::new();
-        let sk = SpendingKey::from_zip32_seed(self.seed.as_bytes(), 0, 0).unwrap();
-        let fvk = FullViewingKey::from(&sk);
-        for i in 0..self.diversifier_index
+        &self.state
+    }
+
+    /// Returns the last irreversible block of the chain as of the latest synchronization
+    pub fn last_irreversible_block(&self) -> u64
+    {
+        self.last_irreversible_block
+    }
+
+    /// Returns true if 'note' has been added in an irreversible block, i.e. it can not be rolled back by a fork
+    pub fn is_confirmed(&self, note: &NoteEx) -> bool
+    {
+        note.block_number <= self.last_irreversible_block
+    }
+
+    /// Returns the diversifier index of the current wallet address
+    pub fn diversifier_index(&self) -> u32
+    {
+        self.diversifier_index
+    }
+
+    /// Parses 'str' as asset: a fungible asset as quantity of a known token (e.g. "1.0000 EOS") or a non-fungible
+    /// asset as "<id>@<contract>" (e.g. "1099511627776@atomicassets").
+    pub fn parse_asset(&self, str: &str) -> Result<Asset, WalletError>
+    {
+        if let Some((id, contract)) = str.split_once('@')
         {
-            map.insert(i, fvk.address_at(i, External).to_bech32m());
+            let id = id.trim().parse::<u64>().map_err(|_| WalletError::General(format!("invalid NFT id: {}", id)))?;
+            return Ok(Asset::nft(id, &contract.trim().to_string()));
+        }
+        let symbol = str.trim().split_once(' ').map(|(_, s)| s.trim()).unwrap_or("");
+        let (contract, sym) = match self.settings.ft_contract(symbol) {
+            Some(c) => c,
+            None => return Err(WalletError::General(format!("unknown token: '{}'", symbol)))
+        };
+        match Asset::from_quantity_string(str, contract) {
+            Some(asset) if asset.d2() == *sym => Ok(asset),
+            Some(_) => Err(WalletError::General(format!("{} has precision {}", symbol, symbol_to_string_precision(*sym).1))),
+            None => Err(WalletError::General(format!("invalid quantity: '{}'", str)))
         }
-        serialize_json_compatible(&map).unwrap()
     }
 
-    /// Returns a key/value map of all fungible token balances of this wallet (symbol => balance)
-    pub fn get_balances(&self) -> JsValue
+    /// Returns all fungible token balances of this wallet (symbol => balance)
+    pub fn balances(&self) -> Result<HashMap<String, u64>, WalletError>
     {
         let mut map = HashMap::new();
-        for n in self.spendable_notes.iter()
+        for n in self.spendable_notes()?.iter()
         {
             if n.note.nft().inner() == 0
             {
                 let symbol = symbol_to_string_precision(n.note.d2().inner()).0;
                 let value = n.note.d1().inner();
-                // check if token contract is correct
-                if n.note.sc().inner() == self.settings.ft_contracts[&symbol].1
-                {
-                    map.entry(symbol).and_modify(|v| *v += value).or_insert(value);
+                // skip unknown tokens and notes of other token contracts with the same symbol
+                match self.settings.ft_contracts.get(&symbol) {
+                    Some((contract, _)) if n.note.sc().inner() == name_to_value(contract) => {
+                        map.entry(symbol).and_modify(|v| *v += value).or_insert(value);
+                    }
+                    _ => {}
                 }
             }
         }
-        serialize_json_compatible(&map).unwrap()
+        Ok(map)
     }
 
-    /// Returns a key/value map of all non-fungible token ids of this wallet (contract => array of id)
-    pub fn get_nfts(&self) -> JsValue
+    /// Returns all non-fungible token ids of this wallet (contract => list of ids)
+    pub fn nfts(&self) -> Result<HashMap<String, Vec<u64>>, WalletError>
     {
         let mut map = HashMap::new();
-        for n in self.spendable_notes.iter()
+        for n in self.spendable_notes()?.iter()
         {
             if n.note.nft().inner() != 0
             {
@@ -275,43 +644,1315 @@ impl Wallet
                 map.entry(contract).and_modify(|v: &mut Vec<u64>| (*v).append(&mut id)).or_insert(id);
             }
         }
-        serialize_json_compatible(&map).unwrap()
+        Ok(map)
     }
 
-    /// ...
-    pub async fn get_account_balances(&self, account: String) -> JsValue
+    /// Returns all ever generated wallet addresses (diversifier_index => address)
+    pub fn addresses(&self) -> Result<HashMap<u32, String>, WalletError>
     {
-        let contract = TokenContract::new(ENDPOINTS.map(String::from));
         let mut map = HashMap::new();
-        for (sym, (code, _)) in self.settings.ft_contracts.iter()
+        let ivk = self.incoming_viewing_key()?;
+        for i in 0..self.diversifier_index
         {
-            let (amount, _) = contract.get_currency_balance(code, &account, sym).await;
-            map.insert(sym.clone(), amount.to_string());
+            map.insert(i, ivk.address_at(i).to_bech32m(self.settings.network));
         }
-        serialize_json_compatible(&map).unwrap()
+        Ok(map)
     }
 
-    /// ...
-    pub async fn get_account_nfts(&self, account: String) -> JsValue
+    /// Returns the received (and still spendable) and the sent notes of this wallet ordered by note id
+    pub fn history(&self) -> Result<Vec<HistoryEntry>, WalletError>
     {
-        let contract = TokenContract::new(ENDPOINTS.map(String::from));
-        let mut map = HashMap::new();
-        for nftc in self.settings.nft_contracts.iter()
+        let entry = |n: &NoteEx, direction| {
+            HistoryEntry {
+                id: n.id,
+                block_number: n.block_number,
+                direction,
+                asset: Asset::from_note(&n.note),
+                address: n.note.recipient().to_bech32m(self.settings.network),
+                memo: note_memo(&n.note),
+                confirmed: self.is_confirmed(n)
+            }
+        };
+        let mut res: Vec<HistoryEntry> = self.spendable_notes()?.iter().map(|n| entry(n, "received"))
+            .chain(self.sent_notes()?.iter().map(|n| entry(n, "sent")))
+            .collect();
+        res.sort_by_key(|e| e.id);
+        Ok(res)
+    }
+
+    /// Shields 'asset' from EOS account 'account' into the wallet address 'to'. Returns the JSON string of the
+    /// transfer to the ZEOS token contract followed by the corresponding MINTFT (MINTNFT) zaction.
+    pub async fn shield_asset(&self, account: &String, asset: &Asset, to: &String) -> Result<String, WalletError>
+    {
+        let eos_auth = vec![EOSAuthorization{ actor: account.clone(), permission: String::from("active") }];
+        self.build_transaction_json(&shield_action_descs(self.settings.network, account, asset, to), &eos_auth).await
+    }
+
+    /// Transfers 'asset' from this wallet to the shielded address 'to' using 'memo'. The EOS actions of the
+    /// transaction are authorized by 'eos_auth'. Returns the JSON string of the TRANSFERFT (TRANSFERNFT) transaction.
+    pub async fn transfer_asset(&self, to: &String, asset: &Asset, memo: &String, eos_auth: &Vec<EOSAuthorization>) -> Result<String, WalletError>
+    {
+        if memo.len() >= 512
         {
-            let assets = contract.get_nfts(nftc, &account).await;
-            map.insert(nftc.clone(), assets.iter().map(|(id, col)| (id.to_string(), col.clone())).collect::<Vec<(String, String)>>());
+            return Err(WalletError::General(format!("memo too long: {} bytes", memo.len())));
         }
-        serialize_json_compatible(&map).unwrap()
+        self.build_transaction_json(&transfer_action_descs(self.settings.network, asset, to, memo), eos_auth).await
     }
-}
 
-#[cfg(test)]
-mod tests
-{
+    /// Transfers 'asset' to the shielded address 'to' using 'memo' via 'relayer' (for users without EOS account): the
+    /// transaction is authorized by the relayer and additionally pays the relayer's fee to its shielded address.
+    /// Returns the request to be sent to the relayer (see: 'relayer::relay').
+    pub async fn relay_transfer(&self, relayer: &RelayerInfo, to: &String, asset: &Asset, memo: &String) -> Result<RelayRequest, WalletError>
+    {
+        if relayer.network != self.settings.network
+        {
+            return Err(WalletError::General(format!("the relayer operates on {} but this wallet on {}", relayer.network, self.settings.network)));
+        }
+        if memo.len() >= 512
+        {
+            return Err(WalletError::General(format!("memo too long: {} bytes", memo.len())));
+        }
+        let mut action_descs = transfer_action_descs(self.settings.network, asset, to, memo);
+        action_descs.extend(transfer_action_descs(self.settings.network, &relayer.fee, &relayer.address, &String::from("relayer fee")));
 
-    #[test]
-    fn test_regex()
+        let mut contract = self.token_contract();
+        let builder = self.transaction_builder();
+        let sk = self.spending_key()?;
+        let (proof, actions) = builder.build_transaction(
+            &self.pk,
+            &sk,
+            &mut self.spendable_notes()?,
+            &action_descs,
+            &mut contract,
+            &vec![relayer.auth.clone()]
+        ).await?;
+        let proof = proof.ok_or(WalletError::General(String::from("no proof")))?;
+        Ok(RelayRequest{ actions, proof: hex::encode(proof.as_ref()) })
+    }
+
+    /// Pays many shielded recipients at once within the 'limits' of a single transaction. Returns the JSON strings of
+    /// the transactions (each a list of EOS actions) which can be executed independently of each other.
+    pub async fn send_payments(&self, payments: &Vec<Payment>, limits: &BatchLimits, eos_auth: &Vec<EOSAuthorization>) -> Result<Vec<String>, WalletError>
+    {
+        self.send_payments_from(&mut self.spendable_notes()?, payments, limits, eos_auth).await
+    }
+
+    /// Like 'send_payments' but spends only 'notes' (a subset of the spendable notes, e.g. without the notes spent by
+    /// pending transactions). The notes spent by the transactions are removed from 'notes'.
+    pub(crate) async fn send_payments_from(&self, notes: &mut Vec<NoteEx>, payments: &Vec<Payment>, limits: &BatchLimits, eos_auth: &Vec<EOSAuthorization>) -> Result<Vec<String>, WalletError>
+    {
+        let mut contract = self.token_contract();
+        let builder = self.transaction_builder();
+        let sk = self.spending_key()?;
+
+        let txs = builder.build_batch_payment(
+            &self.pk,
+            &sk,
+            notes,
+            payments,
+            limits,
+            &mut contract,
+            eos_auth
+        ).await?;
+
+        let mut res = Vec::new();
+        for (proof, actions) in txs
+        {
+            res.push(transaction_json(&contract, proof, &actions).await?);
+        }
+        Ok(res)
+    }
+
+    /// Parses the payment request URI 'uri' (see: payment_request) of the network of this wallet. Fungible tokens must
+    /// be known to this wallet and requested with the precision of their symbol. The payments of the request are
+    /// executed by 'send_payments' (see: 'PaymentRequest::to_payments').
+    pub fn parse_payment_request(&self, uri: &str) -> Result<PaymentRequest, WalletError>
+    {
+        Ok(PaymentRequest::parse_known_tokens(uri, self.settings.network, &self.settings.ft_contracts)?)
+    }
+
+    /// Creates a payment request for 'asset' to the current address of this wallet using 'memo' and 'label' (e.g. the
+    /// name of the shop)
+    pub fn payment_request(&self, asset: &Asset, memo: &str, label: Option<&str>) -> Result<PaymentRequest, WalletError>
+    {
+        let to = self.incoming_viewing_key()?.address_at(self.diversifier_index).to_bech32m(self.settings.network);
+        let payment = RequestedPayment::new(&to, asset, memo);
+        let payment = match label {
+            Some(label) => payment.with_label(label),
+            None => payment
+        };
+        Ok(PaymentRequest::new(self.settings.network, vec![payment])?)
+    }
+
+    /// Unshields 'asset' from this wallet into EOS account 'eos_account' using 'memo'. The EOS actions of the transaction
+    /// are authorized by 'eos_auth' (the payer which is not necessarily the recipient). Returns the JSON string of the
+    /// corresponding BURNFT (BURNNFT) transaction.
+    pub async fn unshield_asset(&self, asset: &Asset, eos_account: &String, memo: &String, eos_auth: &Vec<EOSAuthorization>) -> Result<String, WalletError>
+    {
+        self.unshield_asset_from(&mut self.spendable_notes()?, asset, eos_account, memo, eos_auth).await
+    }
+
+    /// Like 'unshield_asset' but spends only 'notes' (see: 'send_payments_from'). The notes spent by the transaction
+    /// are removed from 'notes'.
+    pub(crate) async fn unshield_asset_from(&self, notes: &mut Vec<NoteEx>, asset: &Asset, eos_account: &String, memo: &String, eos_auth: &Vec<EOSAuthorization>) -> Result<String, WalletError>
+    {
+        self.build_transaction_json_from(notes, &unshield_action_descs(self.settings.network, asset, eos_account, memo), eos_auth).await
+    }
+
+    /// Creates a private deposit of 'asset' into the third party smart contract 'contract' using 'memo'. The auth token
+    /// which is minted as part of the deposit is tracked by this wallet in order to withdraw the deposit later on: the
+    /// deposit is recorded as unconfirmed until sync receives the auth token (see: 'private_deposits') and written to
+    /// the store right away. Returns the JSON string of the transaction.
+    pub async fn private_deposit(&mut self, asset: &Asset, contract: &String, memo: &String, eos_auth: &Vec<EOSAuthorization>) -> Result<String, WalletError>
+    {
+        let mut token_contract = self.token_contract();
+        let builder = self.transaction_builder();
+        let sk = self.spending_key()?;
+
+        let (proof, actions, auth_note) = builder.build_private_deposit(
+            &self.pk,
+            &sk,
+            &mut self.spendable_notes()?,
+            asset,
+            contract,
+            memo,
+            &mut token_contract,
+            eos_auth
+        ).await?;
+        let json = transaction_json(&token_contract, proof, &actions).await?;
+
+        self.private_deposits.push(PrivateDeposit {
+            contract: contract.clone(),
+            asset: asset.clone(),
+            cm_auth: auth_token_commitment(&auth_note),
+            auth_note: Some(auth_note),
+            confirmed: false,
+            memo: memo.clone(),
+            withdrawn_note_id: None
+        });
+        // the record holds the only copy of the auth token: the transaction is not returned unless it is stored
+        if let Err(e) = self.save()
+        {
+            self.private_deposits.pop();
+            return Err(e);
+        }
+
+        Ok(json)
+    }
+
+    /// Creates a private withdrawal of a deposit made by 'private_deposit' from the third party smart contract. The
+    /// deposit is identified by the commitment of its auth token (hex) and must have been confirmed by sync. 'action' is
+    /// the private withdrawal action of the third party contract to which the BURNAUTH and MINTFT/MINTNFT zactions are
+    /// attached. Returns the JSON string of the transaction.
+    pub async fn private_withdrawal(&mut self, cm_auth: &str, action: &EOSAction, eos_auth: &Vec<EOSAuthorization>) -> Result<String, WalletError>
+    {
+        let deposit = match self.private_deposits.iter().position(|d| d.cm_auth == cm_auth && d.withdrawn_note_id.is_none())
+        {
+            Some(i) => i,
+            None => return Err(WalletError::General(format!("no open private deposit for auth token {}", cm_auth)))
+        };
+        if !self.private_deposits[deposit].confirmed
+        {
+            return Err(WalletError::General(format!("the private deposit of auth token {} has not been executed yet (see: sync)", cm_auth)));
+        }
+        let mut arr = [0; 32];
+        hex::decode_to_slice(cm_auth, &mut arr)?;
+        let nc = ExtractedNoteCommitment::from_bytes(&arr);
+        if nc.is_none().into()
+        {
+            return Err(WalletError::General(String::from("invalid auth token commitment")));
+        }
+
+        let mut contract = self.token_contract();
+        let builder = self.transaction_builder();
+        let sk = self.spending_key()?;
+        let fvk = FullViewingKey::from(&sk);
+
+        let (proof, actions) = builder.build_private_withdrawal(
+            &self.pk,
+            &sk,
+            &mut self.spendable_notes()?,
+            &nc.unwrap(),
+            &self.private_deposits[deposit].asset,
+            &self.private_deposits[deposit].contract,
+            &fvk.address_at(self.diversifier_index, External),
+            action,
+            &mut contract,
+            eos_auth
+        ).await?;
+
+        transaction_json(&contract, proof, &actions).await
+    }
+
+    /// Returns the private deposits into third party smart contracts made from this wallet
+    pub fn private_deposits(&self) -> &Vec<PrivateDeposit>
+    {
+        &self.private_deposits
+    }
+
+    /// Returns true if this wallet has been imported from a full or incoming viewing key
+    pub fn is_watch_only(&self) -> bool
+    {
+        self.spending_key().is_err()
+    }
+
+    /// Returns the Bech32m encoded spending key of this wallet
+    pub fn export_spending_key(&self) -> Result<String, WalletError>
+    {
+        Ok(self.spending_key()?.to_bech32m(self.settings.network))
+    }
+
+    /// Returns the Bech32m encoded full viewing key of this wallet (e.g. for an auditor)
+    pub fn export_full_viewing_key(&self) -> Result<String, WalletError>
+    {
+        Ok(self.full_viewing_key()?.to_bech32m(self.settings.network))
+    }
+
+    /// Returns the Bech32m encoded (external) incoming viewing key of this wallet (e.g. for a payment processor)
+    pub fn export_incoming_viewing_key(&self) -> Result<String, WalletError>
+    {
+        Ok(self.incoming_viewing_key()?.to_bech32m(self.settings.network))
+    }
+
+    /// Returns the Bech32m encoded (external) outgoing viewing key of this wallet
+    pub fn export_outgoing_viewing_key(&self) -> Result<String, WalletError>
+    {
+        Ok(self.full_viewing_key()?.to_ovk(External).to_bech32m(self.settings.network))
+    }
+
+    /// Converts a wallet to JSON formatted string (of the current format version, see: wallet_format) to be restored
+    /// later using the 'from_string' function above.
+    pub fn to_string(&self) -> Result<String, WalletError>
+    {
+        let res = join_wallet_json(&serde_json::to_string(self)?, &self.spendable_notes()?, &self.sent_notes()?)?;
+        Ok(res)
+    }
+
+    /// Returns the last consistent checkpoint: the contract state before the first note from a reversible block (the
+    /// current state if all synchronized notes are irreversible)
+    fn checkpoint(&self) -> Global
+    {
+        match self.reversible_notes.first() {
+            Some(n) => Global{ note_count: n.id, leaf_count: n.leaf_index, tree_depth: self.state.tree_depth },
+            None => self.state.clone()
+        }
+    }
+
+    /// Compares the notes synchronized from reversible blocks with the notes of the chain. Returns true if the chain
+    /// has forked, i.e. if any of these notes has been removed or replaced.
+    async fn has_forked(&self, contract: &TokenContract, indexer: Option<&IndexerClient>, global: &Global) -> Result<bool, WalletError>
+    {
+        let (first, last) = match (self.reversible_notes.first(), self.reversible_notes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Ok(false)
+        };
+        if global.note_count <= last.id || global.leaf_count < last.leaf_index
+        {
+            return Ok(true);
+        }
+        let notes: Vec<ReversibleNote> = match indexer {
+            Some(indexer) => indexer.get_compact_notes(first.id, last.id + 1).await.map_err(WalletError::General)?.iter().map(ReversibleNote::from).collect(),
+            None => contract.get_encrypted_notes(first.id, last.id).await.map_err(WalletError::General)?.iter().map(ReversibleNote::from).collect()
+        };
+        Ok(notes != self.reversible_notes)
+    }
+
+    /// Rolls this wallet and its 'spendable_notes' and 'sent_notes' back to the last consistent checkpoint: reverts
+    /// the effects of all notes synchronized from reversible blocks
+    fn rollback(&mut self, spendable_notes: &mut Vec<NoteEx>, sent_notes: &mut Vec<NoteEx>)
+    {
+        let checkpoint = self.checkpoint();
+        let id = checkpoint.note_count;
+        // private deposits whose auth tokens are rolled back are unconfirmed until they are received again
+        let rolled_back: Vec<String> = spendable_notes.iter().filter(|n| n.id >= id && n.note.header() == NT_AT).map(|n| auth_token_commitment(&n.note)).collect();
+        for deposit in self.private_deposits.iter_mut().filter(|d| rolled_back.contains(&d.cm_auth))
+        {
+            deposit.confirmed = false;
+        }
+        spendable_notes.retain(|n| n.id < id);
+        sent_notes.retain(|n| n.id < id);
+        // restore the notes spent by rolled back notes unless they have been rolled back themselves
+        let restored: Vec<NoteEx> = self.reversible_spends.drain(..).filter(|s| s.id >= id).map(|s| s.note).filter(|n| n.id < id).collect();
+        spendable_notes.extend(restored);
+        spendable_notes.sort_by_key(|n| n.id);
+        for deposit in self.private_deposits.iter_mut().filter(|d| d.withdrawn_note_id.map_or(false, |n| n >= id))
+        {
+            deposit.withdrawn_note_id = None;
+        }
+        self.reversible_notes.clear();
+        self.state = checkpoint;
+    }
+
+    /// Marks all notes up to block 'last_irreversible_block' as confirmed: they can not be rolled back anymore
+    fn confirm(&mut self, last_irreversible_block: u64)
+    {
+        self.last_irreversible_block = self.last_irreversible_block.max(last_irreversible_block);
+        let lib = self.last_irreversible_block;
+        self.reversible_notes.retain(|n| n.block_number > lib);
+        self.reversible_spends.retain(|s| s.block_number > lib);
+    }
+
+    /// Synchronize wallet state with contract state. Notes from reversible blocks are marked as unconfirmed and the
+    /// wallet is rolled back to the last consistent checkpoint if the chain has forked since the last synchronization.
+    /// All changes are written to the store at once, the wallet remains unchanged if the synchronization fails.
+    pub async fn sync(&mut self) -> Result<(), WalletError>
+    {
+        // sent notes older than the last consistent checkpoint are neither rolled back nor changed otherwise
+        let mut spendable_notes = self.spendable_notes()?;
+        let mut sent_notes = self.notes(&NoteQuery::default().with_status(NoteStatus::Sent).with_min_id(self.checkpoint().note_count))?;
+        let mut stored = HashMap::new();
+        for (status, notes) in [(NoteStatus::Spendable, &spendable_notes), (NoteStatus::Sent, &sent_notes)]
+        {
+            for n in notes.iter()
+            {
+                stored.insert((status, n.id), serde_json::to_string(n)?);
+            }
+        }
+        let wallet = serde_json::to_string(self)?;
+        let backup = (self.state.clone(), self.last_irreversible_block, self.reversible_notes.clone(), self.reversible_spends.clone(), self.private_deposits.clone());
+
+        let res = self.sync_notes(&mut spendable_notes, &mut sent_notes).await
+            .and_then(|()| self.write_batch(stored, &wallet, &spendable_notes, &sent_notes));
+        if res.is_err()
+        {
+            (self.state, self.last_irreversible_block, self.reversible_notes, self.reversible_spends, self.private_deposits) = backup;
+        }
+        res
+    }
+
+    /// Writes the changes of a synchronization to the store: the notes which differ from the 'stored' ones (JSON by
+    /// status and id), the removed notes and the wallet data if it differs from 'wallet'
+    fn write_batch(
+        &mut self,
+        mut stored: HashMap<(NoteStatus, u64), String>,
+        wallet: &str,
+        spendable_notes: &[NoteEx],
+        sent_notes: &[NoteEx]
+    ) -> Result<(), WalletError>
+    {
+        let mut batch = StoreBatch::default();
+        for (status, notes) in [(NoteStatus::Spendable, spendable_notes), (NoteStatus::Sent, sent_notes)]
+        {
+            for n in notes.iter()
+            {
+                if stored.remove(&(status, n.id)) != Some(serde_json::to_string(n)?)
+                {
+                    batch.insert.push((status, n.clone()));
+                }
+            }
+        }
+        // the remaining notes have been rolled back or spent
+        batch.remove = stored.into_keys().collect();
+        let data = serde_json::to_string(self)?;
+        if data != wallet
+        {
+            batch.wallet = Some(data);
+        }
+        if !batch.is_empty()
+        {
+            self.store.apply(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Synchronizes 'spendable_notes' and 'sent_notes' (which contains at least the sent notes since the last
+    /// consistent checkpoint) and the state of this wallet with the contract
+    async fn sync_notes(&mut self, spendable_notes: &mut Vec<NoteEx>, sent_notes: &mut Vec<NoteEx>) -> Result<(), WalletError>
+    {
+        let contract = self.token_contract();
+        let indexer = self.settings.zeos_endpoints.first().map(|url| IndexerClient::new(url));
+        let last_irreversible_block = contract.get_info().await.map_err(WalletError::General)?.last_irreversible_block_num;
+        let (global, last_irreversible_block) = match &indexer {
+            // the indexer discards forked notes as long as they are reversible as of its latest update only
+            Some(indexer) => {
+                let info = indexer.get_info().await.map_err(WalletError::General)?;
+                if info.network != self.settings.network
+                {
+                    return Err(WalletError::General(format!("the indexer serves network {}", info.network)));
+                }
+                (info.state, last_irreversible_block.min(info.last_irreversible_block))
+            }
+            None => (contract.get_global_state().await.map_err(WalletError::General)?, last_irreversible_block)
+        };
+        // the circuit (and thus the proving key) must match the merkle tree of the contract
+        global.check_tree_depth()?;
+        // forks are detected before confirming: notes of discarded blocks must be rolled back even if the chain has
+        // become irreversible past their block numbers in the meantime
+        if self.has_forked(&contract, indexer.as_ref(), &global).await?
+        {
+            self.rollback(spendable_notes, sent_notes);
+        }
+        self.confirm(last_irreversible_block);
+        if global.note_count < self.state.note_count
+        {
+            return Err(WalletError::General(format!("the contract has {} notes but the wallet has been synchronized up to {} notes", global.note_count, self.state.note_count)));
+        }
+        if global.note_count == self.state.note_count
+        {
+            return Ok(());
+        }
+
+        // derive keys required to decrypt notes: receive-only wallets have no full viewing key and thus detect neither
+        // sent notes nor spends
+        let fvk = self.full_viewing_key().ok();
+        let ovk = fvk.as_ref().map(|fvk| fvk.to_ovk(External));
+        let ivk = PreparedIncomingViewingKey::new(&self.incoming_viewing_key()?);
+        let lib = self.last_irreversible_block;
+        let (encrypted_notes, mut reversible_notes) = match &indexer {
+            // trial-decrypt the compact notes and fetch the full notes (including memos) of those which decrypt only
+            Some(indexer) => {
+                let compact_notes = indexer.get_compact_notes(self.state.note_count, global.note_count).await.map_err(WalletError::General)?;
+                let ids: Vec<u64> = compact_notes.iter()
+                    .filter(|n| n.try_decrypt_as_receiver(&ivk).is_some() || ovk.as_ref().map_or(false, |ovk| n.try_decrypt_as_sender(ovk).is_some()))
+                    .map(|n| n.id())
+                    .collect();
+                let reversible_notes: Vec<ReversibleNote> = compact_notes.iter().filter(|n| n.block_number > lib).map(ReversibleNote::from).collect();
+                (indexer.get_encrypted_notes_by_id(&ids).await.map_err(WalletError::General)?, reversible_notes)
+            }
+            // the upper bound is inclusive
+            None => {
+                let encrypted_notes = contract.get_encrypted_notes(self.state.note_count, global.note_count - 1).await.map_err(WalletError::General)?;
+                let reversible_notes: Vec<ReversibleNote> = encrypted_notes.iter().filter(|n| n.block_number > lib).map(ReversibleNote::from).collect();
+                (encrypted_notes, reversible_notes)
+            }
+        };
+        let mut new_notes = Vec::new();
+        for en in encrypted_notes
+        {
+            let o = en.try_decrypt_as_receiver(&ivk);
+            if o.is_some()
+            {
+                let rn = o.unwrap();
+                // burned auth tokens are transmitted to the owner's address as well
+                if rn.note.header() & NH_BURN_FLAG == 0
+                {
+                    new_notes.push(rn);
+                }
+            }
+            let o = match (&fvk, &ovk) {
+                (Some(fvk), Some(ovk)) => en.try_decrypt_as_sender(ovk).map(|sn| (fvk, sn)),
+                _ => None
+            };
+            if o.is_some()
+            {
+                let (fvk, sn) = o.unwrap();
+                // a burned auth token has the same commitment as the original auth token
+                let burned_cm: Option<ExtractedNoteCommitment> = if sn.note.header() == NT_AT | NH_BURN_FLAG { Some(sn.note.commitment().into()) } else { None };
+                let (spent, unspent): (Vec<NoteEx>, Vec<NoteEx>) = std::mem::take(spendable_notes).into_iter().partition(|n| {
+                    n.note.nullifier(fvk) == sn.note.rho() || burned_cm.as_ref().map_or(false, |cm| ExtractedNoteCommitment::from(n.note.commitment()) == *cm)
+                });
+                *spendable_notes = unspent;
+                // notes spent in reversible blocks are restored if the chain forks
+                if en.block_number > lib
+                {
+                    self.reversible_spends.extend(spent.into_iter().map(|note| ReversibleSpend{ id: en.id, block_number: en.block_number, note }));
+                }
+                sent_notes.push(sn);
+            }
+        }
+
+        // confirm the private deposits whose auth tokens have been received
+        for n in new_notes.iter().filter(|n| n.note.header() == NT_AT)
+        {
+            let cm = auth_token_commitment(&n.note);
+            for deposit in self.private_deposits.iter_mut().filter(|d| d.cm_auth == cm)
+            {
+                deposit.confirmed = true;
+            }
+        }
+
+        // reconcile assets returned from third party contracts by private withdrawals: the minted note carries the
+        // commitment of the burned auth token as memo (see: 'TransactionBuilder::build_private_withdrawal')
+        for n in new_notes.iter()
+        {
+            let memo = note_memo(&n.note);
+            let asset = Asset::from_note(&n.note);
+            if let Some(deposit) = self.private_deposits.iter_mut().find(|d| d.withdrawn_note_id.is_none() && d.cm_auth == memo && d.asset == asset)
+            {
+                deposit.withdrawn_note_id = Some(n.id);
+            }
+        }
+
+        // move new notes into 'notes' and update wallet state
+        spendable_notes.append(&mut new_notes);
+        self.reversible_notes.append(&mut reversible_notes);
+        self.state = global;
+
+        Ok(())
+    }
+
+    pub async fn create_transaction(
+        &self,
+        js_action_descs: JsValue,   // Vec<EOSActionDesc>
+        js_eos_auth: JsValue        // Vec<EOSAuth>
+    ) -> Result<String, WalletError>
+    {
+        let action_descs = serde_wasm_bindgen::from_value(js_action_descs)?;
+        let eos_auth = serde_wasm_bindgen::from_value(js_eos_auth)?;
+        //log(&format!("{:?}", action_descs));
+        self.build_transaction_json(&action_descs, &eos_auth).await
+    }
+
+    /// Plans the transaction described by 'js_action_descs' without proving it. Returns the plan (chosen notes, outputs,
+    /// zactions, number of circuits, estimated proof size, action sizes and proving time) or an error if the
+    /// transaction is not executable, e.g. due to insufficient funds.
+    pub fn plan_transaction(
+        &self,
+        js_action_descs: JsValue    // Vec<EOSActionDesc>
+    ) -> Result<JsValue, WalletError>
+    {
+        let action_descs = serde_wasm_bindgen::from_value(js_action_descs)?;
+        let fvk = self.full_viewing_key()?;
+        let plan = plan_transaction(self.settings.network, &fvk, &mut self.spendable_notes()?, &action_descs)?;
+        Ok(serialize_json_compatible(&plan).unwrap())
+    }
+
+    /// Creates the unproven transaction (JSON) described by 'js_action_descs'. It can be authorized and proved on
+    /// other devices (see: UnprovenTransaction).
+    pub async fn create_unproven_transaction(
+        &self,
+        js_action_descs: JsValue,   // Vec<EOSActionDesc>
+        js_eos_auth: JsValue        // Vec<EOSAuth>
+    ) -> Result<String, WalletError>
+    {
+        let action_descs = serde_wasm_bindgen::from_value(js_action_descs)?;
+        let eos_auth = serde_wasm_bindgen::from_value(js_eos_auth)?;
+        let mut contract = self.token_contract();
+        let builder = self.transaction_builder();
+        let fvk = self.full_viewing_key()?;
+
+        let utx = builder.build_unproven_transaction(
+            &fvk,
+            &mut self.spendable_notes()?,
+            &action_descs,
+            &mut contract,
+            &eos_auth
+        ).await?;
+        Ok(utx.to_json())
+    }
+
+    /// Authorizes an unproven transaction (JSON) with the spending key of this wallet (Signer role)
+    pub fn authorize_unproven_transaction(&self, json: String) -> Result<String, WalletError>
+    {
+        let mut utx = UnprovenTransaction::from_json(&json)?;
+        if utx.network() != self.settings.network
+        {
+            return Err(WalletError::General(format!("transaction of network {} cannot be authorized by a {} wallet", utx.network(), self.settings.network)));
+        }
+        utx.authorize(&self.spending_key()?)?;
+        Ok(utx.to_json())
+    }
+
+    /// Proves and finalizes an authorized unproven transaction (JSON) (Prover and Finalizer roles). Returns the JSON
+    /// string of EOS actions ready to execute.
+    pub async fn prove_unproven_transaction(&self, json: String) -> Result<String, WalletError>
+    {
+        let mut utx = UnprovenTransaction::from_json(&json)?;
+        utx.prove(&self.pk)?;
+        let (proof, actions) = utx.finalize()?;
+        let contract = self.token_contract();
+        transaction_json(&contract, proof, &actions).await
+    }
+
+    /// Shields an asset (Asset) from EOS account 'account' into the wallet address 'to'. Returns the JSON string of
+    /// the transfer to the ZEOS token contract followed by the corresponding MINTFT (MINTNFT) zaction.
+    pub async fn shield(
+        &self,
+        account: String,
+        js_asset: JsValue,  // Asset
+        to: String
+    ) -> Result<String, WalletError>
+    {
+        let asset: Asset = serde_wasm_bindgen::from_value(js_asset)?;
+        self.shield_asset(&account, &asset, &to).await
+    }
+
+    /// Transfers an asset (Asset) from this wallet to the shielded address 'to' using 'memo'. Returns the JSON string
+    /// of the corresponding TRANSFERFT (TRANSFERNFT) transaction.
+    pub async fn transfer(
+        &self,
+        to: String,
+        js_asset: JsValue,  // Asset
+        memo: String,
+        js_eos_auth: JsValue    // Vec<EOSAuth>
+    ) -> Result<String, WalletError>
+    {
+        let asset: Asset = serde_wasm_bindgen::from_value(js_asset)?;
+        let eos_auth = serde_wasm_bindgen::from_value(js_eos_auth)?;
+        self.transfer_asset(&to, &asset, &memo, &eos_auth).await
+    }
+
+    /// Transfers an asset (Asset) from this wallet to the shielded address 'to' using 'memo' via the relayer at
+    /// 'relayer_url' which authorizes and pushes the transaction in exchange for its fee. Returns the JSON string of the
+    /// relayer's response.
+    pub async fn relay(
+        &self,
+        relayer_url: String,
+        to: String,
+        js_asset: JsValue,  // Asset
+        memo: String
+    ) -> Result<String, WalletError>
+    {
+        let asset: Asset = serde_wasm_bindgen::from_value(js_asset)?;
+        let info = relayer::get_relayer_info(&relayer_url).await.map_err(WalletError::General)?;
+        let req = self.relay_transfer(&info, &to, &asset, &memo).await?;
+        let res = relayer::relay(&relayer_url, &req).await.map_err(WalletError::General)?;
+        Ok(serde_json::to_string(&res)?)
+    }
+
+    /// Unshields an asset (Asset) from this wallet into EOS account 'eos_account' using 'memo'. The EOS actions of the
+    /// transaction are authorized by 'js_eos_auth'. Returns the JSON string of the corresponding BURNFT (BURNNFT)
+    /// transaction.
+    pub async fn unshield(
+        &self,
+        js_asset: JsValue,      // Asset
+        eos_account: String,
+        memo: String,
+        js_eos_auth: JsValue    // Vec<EOSAuth>
+    ) -> Result<String, WalletError>
+    {
+        let asset: Asset = serde_wasm_bindgen::from_value(js_asset)?;
+        let eos_auth = serde_wasm_bindgen::from_value(js_eos_auth)?;
+        self.unshield_asset(&asset, &eos_account, &memo, &eos_auth).await
+    }
+
+    /// Pays many shielded recipients at once. 'js_payments' is a list of payments (address, asset, memo) and
+    /// 'js_limits' (optional) the limits of a single transaction (BatchLimits). Returns the JSON string of a list of
+    /// transactions (each a list of EOS actions) which can be executed independently of each other.
+    pub async fn send_many(
+        &self,
+        js_payments: JsValue,   // Vec<Payment>
+        js_limits: JsValue,     // Option<BatchLimits>
+        js_eos_auth: JsValue    // Vec<EOSAuth>
+    ) -> Result<String, WalletError>
+    {
+        let payments = serde_wasm_bindgen::from_value(js_payments)?;
+        let limits: Option<BatchLimits> = serde_wasm_bindgen::from_value(js_limits)?;
+        let eos_auth = serde_wasm_bindgen::from_value(js_eos_auth)?;
+        let res = self.send_payments(&payments, &limits.unwrap_or_default(), &eos_auth).await?;
+        Ok(format!("[{}]", res.join(",")))
+    }
+
+    /// Parses the payment request URI 'uri' (e.g. of a scanned QR code). Returns the request (network and payments)
+    /// whose payments can be passed to 'send_many'.
+    pub fn get_payment_request(&self, uri: String) -> Result<JsValue, WalletError>
+    {
+        Ok(serialize_json_compatible(&self.parse_payment_request(&uri)?)?)
+    }
+
+    /// Creates the URI of a payment request for an asset (Asset) to the current address of this wallet using 'memo' and
+    /// 'label' (optional)
+    pub fn create_payment_request(
+        &self,
+        js_asset: JsValue,  // Asset
+        memo: String,
+        label: Option<String>
+    ) -> Result<String, WalletError>
+    {
+        let asset: Asset = serde_wasm_bindgen::from_value(js_asset)?;
+        Ok(self.payment_request(&asset, &memo, label.as_deref())?.to_uri())
+    }
+
+    /// Creates a private deposit of an asset (Asset) into the third party smart contract 'contract' (see:
+    /// 'private_deposit'). Returns the JSON string of the transaction.
+    pub async fn create_private_deposit(
+        &mut self,
+        js_asset: JsValue,      // Asset
+        contract: String,
+        memo: String,
+        js_eos_auth: JsValue    // Vec<EOSAuth>
+    ) -> Result<String, WalletError>
+    {
+        let asset: Asset = serde_wasm_bindgen::from_value(js_asset)?;
+        let eos_auth = serde_wasm_bindgen::from_value(js_eos_auth)?;
+        self.private_deposit(&asset, &contract, &memo, &eos_auth).await
+    }
+
+    /// Creates a private withdrawal of the deposit with auth token commitment 'cm_auth' (hex) using the private
+    /// withdrawal action (EOSAction) of the third party contract (see: 'private_withdrawal'). Returns the JSON string
+    /// of the transaction.
+    pub async fn create_private_withdrawal(
+        &mut self,
+        cm_auth: String,
+        js_action: JsValue,     // EOSAction
+        js_eos_auth: JsValue    // Vec<EOSAuth>
+    ) -> Result<String, WalletError>
+    {
+        let action: EOSAction = serde_wasm_bindgen::from_value(js_action)?;
+        let eos_auth = serde_wasm_bindgen::from_value(js_eos_auth)?;
+        self.private_withdrawal(&cm_auth, &action, &eos_auth).await
+    }
+
+    /// Returns a key/value map of all auth tokens held by this wallet (contract => array of auth token commitments (hex))
+    pub fn get_auth_tokens(&self) -> Result<JsValue, WalletError>
+    {
+        let mut map = HashMap::new();
+        for n in self.spendable_notes()?.iter()
+        {
+            if n.note.header() == NT_AT
+            {
+                let contract = value_to_name(n.note.sc().inner());
+                let cm: ExtractedNoteCommitment = n.note.commitment().into();
+                let mut cm = vec![hex::encode(cm.to_bytes())];
+                map.entry(contract).and_modify(|v: &mut Vec<String>| (*v).append(&mut cm)).or_insert(cm);
+            }
+        }
+        Ok(serialize_json_compatible(&map)?)
+    }
+
+    /// Returns the list of private deposits into third party smart contracts made from this wallet
+    pub fn get_private_deposits(&self) -> JsValue
+    {
+        serialize_json_compatible(&self.private_deposits).unwrap()
+    }
+
+    /// Returns the network this wallet operates on
+    pub fn network(&self) -> String
+    {
+        self.settings.network.to_string()
+    }
+
+    /// Sets the URL of a remote prover service (e.g. "http://127.0.0.1:8787"). Proofs are calculated locally if 'None'.
+    pub fn set_prover_url(&mut self, url: Option<String>)
+    {
+        self.settings.prover_url = url;
+    }
+
+    /// Returns the address of a certain diversifier as hex string
+    pub fn address(
+        &self,
+        diversifier_index: u32
+    ) -> String
+    {
+        let ivk = self.incoming_viewing_key().unwrap();
+        let addr = ivk.address_at(diversifier_index);
+        addr.to_bech32m(self.settings.network)
+    }
+
+    /// Increments the internal diversifier index by one and returns a newly derived wallet address from that index
+    pub fn derive_new_wallet_address(&mut self) -> String
+    {
+        self.diversifier_index += 1;
+        let ivk = self.incoming_viewing_key().unwrap();
+        let addr = ivk.address_at(self.diversifier_index);
+        addr.to_bech32m(self.settings.network)
+    }
+
+    /// Returns a key/value map of all ever generated wallet addresses (diversifier_index => address)
+    pub fn get_addresses(&self) -> JsValue
+    {
+        serialize_json_compatible(&self.addresses().unwrap()).unwrap()
+    }
+
+    /// Returns a key/value map of all fungible token balances of this wallet (symbol => balance)
+    pub fn get_balances(&self) -> Result<JsValue, WalletError>
+    {
+        Ok(serialize_json_compatible(&self.balances()?)?)
+    }
+
+    /// Returns a key/value map of all non-fungible token ids of this wallet (contract => array of id)
+    pub fn get_nfts(&self) -> Result<JsValue, WalletError>
+    {
+        Ok(serialize_json_compatible(&self.nfts()?)?)
+    }
+
+    /// Returns the list of received (and still spendable) and sent notes of this wallet (HistoryEntry)
+    pub fn get_history(&self) -> Result<JsValue, WalletError>
+    {
+        Ok(serialize_json_compatible(&self.history()?)?)
+    }
+
+    /// ...
+    pub async fn get_account_balances(&self, account: String) -> Result<JsValue, WalletError>
+    {
+        let contract = self.token_contract();
+        let mut map = HashMap::new();
+        for (sym, (code, _)) in self.settings.ft_contracts.iter()
+        {
+            let (amount, _) = contract.get_currency_balance(code, &account, sym).await.map_err(WalletError::General)?;
+            map.insert(sym.clone(), amount.to_string());
+        }
+        Ok(serialize_json_compatible(&map)?)
+    }
+
+    /// ...
+    pub async fn get_account_nfts(&self, account: String) -> Result<JsValue, WalletError>
+    {
+        let contract = self.token_contract();
+        let mut map = HashMap::new();
+        for nftc in self.settings.nft_contracts.iter()
+        {
+            let assets = contract.get_nfts(nftc, &account).await.map_err(WalletError::General)?;
+            map.insert(nftc.clone(), assets.iter().map(|(id, col)| (id.to_string(), col.clone())).collect::<Vec<(String, String)>>());
+        }
+        Ok(serialize_json_compatible(&map)?)
+    }
+}
+
+/// The wallet exported to JavaScript as 'Wallet' (wasm-bindgen does not support generic types) which keeps its notes
+/// in memory
+#[wasm_bindgen(js_name = Wallet)]
+pub struct JsWallet(Wallet);
+
+#[wasm_bindgen(js_class = Wallet)]
+impl JsWallet
+{
+    /// Creates a new wallet from seed phrase
+    pub fn new(seed: String) -> Result<JsWallet, WalletError>
+    {
+        Ok(JsWallet(Wallet::new(seed)?))
+    }
+
+    /// Creates a new wallet from seed phrase which operates on 'network' ("mainnet", "kylin", "jungle" or "local")
+    pub fn new_for_network(seed: String, network: String) -> Result<JsWallet, WalletError>
+    {
+        Ok(JsWallet(Wallet::new_for_network(seed, network)?))
+    }
+
+    /// Creates a new wallet from a Bech32m encoded spending key, full viewing key (watch-only) or incoming viewing key
+    /// (receive-only) of 'network'
+    pub fn from_key(key: String, network: String) -> Result<JsWallet, WalletError>
+    {
+        Ok(JsWallet(Wallet::from_key(key, network)?))
+    }
+
+    /// Restores a wallet from JSON string (of any format version)
+    pub fn from_string(json: String) -> Result<JsWallet, WalletError>
+    {
+        Ok(JsWallet(Wallet::from_string(json)?))
+    }
+
+    /// Converts a wallet to JSON formatted string to be restored later using 'from_string'
+    pub fn to_string(&self) -> Result<String, WalletError>
+    {
+        self.0.to_string()
+    }
+
+    /// Returns true if this wallet has been imported from a full or incoming viewing key
+    pub fn is_watch_only(&self) -> bool
+    {
+        self.0.is_watch_only()
+    }
+
+    /// Returns the Bech32m encoded spending key of this wallet
+    pub fn export_spending_key(&self) -> Result<String, WalletError>
+    {
+        self.0.export_spending_key()
+    }
+
+    /// Returns the Bech32m encoded full viewing key of this wallet
+    pub fn export_full_viewing_key(&self) -> Result<String, WalletError>
+    {
+        self.0.export_full_viewing_key()
+    }
+
+    /// Returns the Bech32m encoded (external) incoming viewing key of this wallet
+    pub fn export_incoming_viewing_key(&self) -> Result<String, WalletError>
+    {
+        self.0.export_incoming_viewing_key()
+    }
+
+    /// Returns the Bech32m encoded (external) outgoing viewing key of this wallet
+    pub fn export_outgoing_viewing_key(&self) -> Result<String, WalletError>
+    {
+        self.0.export_outgoing_viewing_key()
+    }
+
+    /// Synchronize wallet state with contract state
+    pub async fn sync(&mut self) -> Result<(), WalletError>
+    {
+        self.0.sync().await
+    }
+
+    /// Creates the transaction described by 'js_action_descs' (Vec<EOSActionDesc>) authorized by 'js_eos_auth'
+    /// (Vec<EOSAuth>)
+    pub async fn create_transaction(&self, js_action_descs: JsValue, js_eos_auth: JsValue) -> Result<String, WalletError>
+    {
+        self.0.create_transaction(js_action_descs, js_eos_auth).await
+    }
+
+    /// Plans the transaction described by 'js_action_descs' (Vec<EOSActionDesc>) without proving it
+    pub fn plan_transaction(&self, js_action_descs: JsValue) -> Result<JsValue, WalletError>
+    {
+        self.0.plan_transaction(js_action_descs)
+    }
+
+    /// Creates the unproven transaction (JSON) described by 'js_action_descs' (Vec<EOSActionDesc>)
+    pub async fn create_unproven_transaction(&self, js_action_descs: JsValue, js_eos_auth: JsValue) -> Result<String, WalletError>
+    {
+        self.0.create_unproven_transaction(js_action_descs, js_eos_auth).await
+    }
+
+    /// Authorizes an unproven transaction (JSON) with the spending key of this wallet
+    pub fn authorize_unproven_transaction(&self, json: String) -> Result<String, WalletError>
+    {
+        self.0.authorize_unproven_transaction(json)
+    }
+
+    /// Proves and finalizes an authorized unproven transaction (JSON)
+    pub async fn prove_unproven_transaction(&self, json: String) -> Result<String, WalletError>
+    {
+        self.0.prove_unproven_transaction(json).await
+    }
+
+    /// Shields an asset (Asset) from EOS account 'account' into the wallet address 'to'
+    pub async fn shield(&self, account: String, js_asset: JsValue, to: String) -> Result<String, WalletError>
+    {
+        self.0.shield(account, js_asset, to).await
+    }
+
+    /// Transfers an asset (Asset) from this wallet to the shielded address 'to' using 'memo'
+    pub async fn transfer(&self, to: String, js_asset: JsValue, memo: String, js_eos_auth: JsValue) -> Result<String, WalletError>
+    {
+        self.0.transfer(to, js_asset, memo, js_eos_auth).await
+    }
+
+    /// Transfers an asset (Asset) from this wallet to the shielded address 'to' via the relayer at 'relayer_url'
+    pub async fn relay(&self, relayer_url: String, to: String, js_asset: JsValue, memo: String) -> Result<String, WalletError>
+    {
+        self.0.relay(relayer_url, to, js_asset, memo).await
+    }
+
+    /// Unshields an asset (Asset) from this wallet into EOS account 'eos_account' using 'memo'
+    pub async fn unshield(&self, js_asset: JsValue, eos_account: String, memo: String, js_eos_auth: JsValue) -> Result<String, WalletError>
+    {
+        self.0.unshield(js_asset, eos_account, memo, js_eos_auth).await
+    }
+
+    /// Pays many shielded recipients (Vec<Payment>) at once within the limits (Option<BatchLimits>) of a transaction
+    pub async fn send_many(&self, js_payments: JsValue, js_limits: JsValue, js_eos_auth: JsValue) -> Result<String, WalletError>
+    {
+        self.0.send_many(js_payments, js_limits, js_eos_auth).await
+    }
+
+    /// Parses the payment request URI 'uri'. Returns the request whose payments can be passed to 'send_many'.
+    pub fn get_payment_request(&self, uri: String) -> Result<JsValue, WalletError>
+    {
+        self.0.get_payment_request(uri)
+    }
+
+    /// Creates the URI of a payment request for an asset (Asset) to the current address of this wallet
+    pub fn create_payment_request(&self, js_asset: JsValue, memo: String, label: Option<String>) -> Result<String, WalletError>
+    {
+        self.0.create_payment_request(js_asset, memo, label)
+    }
+
+    /// Creates a private deposit of an asset (Asset) into the third party smart contract 'contract'
+    pub async fn create_private_deposit(&mut self, js_asset: JsValue, contract: String, memo: String, js_eos_auth: JsValue) -> Result<String, WalletError>
+    {
+        self.0.create_private_deposit(js_asset, contract, memo, js_eos_auth).await
+    }
+
+    /// Creates a private withdrawal of the deposit with auth token commitment 'cm_auth' (hex)
+    pub async fn create_private_withdrawal(&mut self, cm_auth: String, js_action: JsValue, js_eos_auth: JsValue) -> Result<String, WalletError>
+    {
+        self.0.create_private_withdrawal(cm_auth, js_action, js_eos_auth).await
+    }
+
+    /// Returns a key/value map of all auth tokens held by this wallet (contract => array of auth token commitments (hex))
+    pub fn get_auth_tokens(&self) -> Result<JsValue, WalletError>
+    {
+        self.0.get_auth_tokens()
+    }
+
+    /// Returns the list of private deposits into third party smart contracts made from this wallet
+    pub fn get_private_deposits(&self) -> JsValue
+    {
+        self.0.get_private_deposits()
+    }
+
+    /// Returns the network this wallet operates on
+    pub fn network(&self) -> String
+    {
+        self.0.network()
+    }
+
+    /// Sets the URL of a remote prover service. Proofs are calculated locally if 'None'.
+    pub fn set_prover_url(&mut self, url: Option<String>)
+    {
+        self.0.set_prover_url(url)
+    }
+
+    /// Returns the address of a certain diversifier
+    pub fn address(&self, diversifier_index: u32) -> String
+    {
+        self.0.address(diversifier_index)
+    }
+
+    /// Increments the internal diversifier index by one and returns a newly derived wallet address from that index
+    pub fn derive_new_wallet_address(&mut self) -> String
+    {
+        self.0.derive_new_wallet_address()
+    }
+
+    /// Returns a key/value map of all ever generated wallet addresses (diversifier_index => address)
+    pub fn get_addresses(&self) -> JsValue
+    {
+        self.0.get_addresses()
+    }
+
+    /// Returns a key/value map of all fungible token balances of this wallet (symbol => balance)
+    pub fn get_balances(&self) -> Result<JsValue, WalletError>
+    {
+        self.0.get_balances()
+    }
+
+    /// Returns a key/value map of all non-fungible token ids of this wallet (contract => array of id)
+    pub fn get_nfts(&self) -> Result<JsValue, WalletError>
+    {
+        self.0.get_nfts()
+    }
+
+    /// Returns the list of received (and still spendable) and sent notes of this wallet (HistoryEntry)
+    pub fn get_history(&self) -> Result<JsValue, WalletError>
+    {
+        self.0.get_history()
+    }
+
+    /// Returns the token balances of EOS account 'account' (symbol => balance)
+    pub async fn get_account_balances(&self, account: String) -> Result<JsValue, WalletError>
+    {
+        self.0.get_account_balances(account).await
+    }
+
+    /// Returns the NFTs of EOS account 'account' (contract => array of (id, collection))
+    pub async fn get_account_nfts(&self, account: String) -> Result<JsValue, WalletError>
+    {
+        self.0.get_account_nfts(account).await
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{Wallet, WalletError};
+    use crate::payment_request::PaymentRequestError;
+    use crate::builder::{EOSAction, EOSAuthorization};
+    use crate::wallet_store::{NoteQuery, NoteStatus, SqliteStore, WalletStore};
+    use crate::address::Address;
+    use crate::eosio::{name_to_value, string_to_symbol, Asset};
+    use crate::keys::Scope;
+    use crate::network::Network;
+    use crate::note::{Note, NT_FT};
+    use crate::simulator::SimulatedChain;
+    use crate::value::NoteValue;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_regex()
+    {
+
+    }
+
+    #[test]
+    fn asset_parsing()
+    {
+        let wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "jungle".to_string()).unwrap();
+        assert_eq!(wallet.settings().network(), Network::Jungle);
+        let (_, eos) = wallet.settings().ft_contract("EOS").unwrap();
+        assert_eq!(wallet.parse_asset("1.0000 EOS").unwrap(), Asset::ft(10000, *eos, &"eosio.token".to_string()));
+        assert_eq!(wallet.parse_asset("12@atomicassets").unwrap(), Asset::nft(12, &"atomicassets".to_string()));
+        assert!(matches!(wallet.parse_asset("1.00 EOS"), Err(WalletError::General(_))));
+        assert!(matches!(wallet.parse_asset("1.0000 FOO"), Err(WalletError::General(_))));
+        assert!(matches!(wallet.parse_asset("x@atomicassets"), Err(WalletError::General(_))));
+        let uri = format!("zeos:{}?amount=12.5%20EOS&contract=eosio.token", wallet.address(0));
+        assert!(matches!(wallet.parse_payment_request(&uri), Err(WalletError::PaymentRequest(PaymentRequestError::InvalidAmount{ index: 0, .. }))));
+        assert_eq!(wallet.parse_payment_request(&uri.replace("12.5%20", "12.5000%20")).unwrap().payments()[0].asset(), &wallet.parse_asset("12.5000 EOS").unwrap());
+        assert!(wallet.balances().unwrap().is_empty());
+        assert!(wallet.history().unwrap().is_empty());
+        assert!(matches!(Wallet::new_for_network("seed".to_string(), "testnet".to_string()), Err(WalletError::Network(_))));
+    }
+
+    #[tokio::test]
+    async fn balances()
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        let mut wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        wallet.set_endpoints(vec![url], vec![]);
+        let address = Address::from_bech32m(&wallet.address(0), Network::Local).unwrap();
+        let ft = |amount: u64, symbol: &str, contract: &str| Asset::ft(amount, string_to_symbol(&symbol.to_string(), 4), &contract.to_string());
+
+        // unknown tokens and tokens of other contracts with a known symbol (sent by third parties) are not counted
+        chain.mint(address, &ft(100000, "EOS", "eosio.token"), "");
+        chain.mint(address, &ft(25000, "EOS", "eosio.token"), "");
+        chain.mint(address, &ft(50000, "FOO", "foo.token"), "");
+        chain.mint(address, &ft(70000, "EOS", "fake.token"), "");
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.spendable_notes().unwrap().len(), 4);
+        assert_eq!(wallet.balances().unwrap(), std::collections::HashMap::from([("EOS".to_string(), 125000)]));
+    }
+
+    #[tokio::test]
+    async fn import_keys()
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        let mut wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        wallet.set_endpoints(vec![url.clone()], vec![]);
+        let address = Address::from_bech32m(&wallet.address(0), Network::Local).unwrap();
+        let eos = |amount: u64| Asset::ft(amount, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        let amounts = |wallet: &Wallet| wallet.spendable_notes().unwrap().iter().map(|n| n.note.d1().inner()).collect::<Vec<u64>>();
+
+        // note 0 is spent by a transfer of 3 to the wallet's own address
+        chain.mint(address, &eos(1), "");
+        chain.mint(address, &eos(2), "");
+        wallet.sync().await.unwrap();
+        let fvk = wallet.full_viewing_key().unwrap();
+        let nf = wallet.spendable_notes().unwrap()[0].note.nullifier(&fvk);
+        let note = Note::new(NT_FT, address, NoteValue::from_raw(3), NoteValue::from_raw(eos(3).d2()), NoteValue::from_raw(name_to_value(&"eosio.token".to_string())), NoteValue::from_raw(0), nf, OsRng, [0; 512]);
+        chain.add_notes(&[note], Some(fvk.to_ovk(Scope::External)));
+
+        // spending and full viewing keys detect the spend and the sent note, incoming viewing keys only the received notes
+        let sk = wallet.export_spending_key().unwrap();
+        let fvk = wallet.export_full_viewing_key().unwrap();
+        let ivk = wallet.export_incoming_viewing_key().unwrap();
+        for (key, watch_only, spendable, sent) in [(&sk, false, vec![2, 3], 1), (&fvk, true, vec![2, 3], 1), (&ivk, true, vec![1, 2, 3], 0)]
+        {
+            let mut imported = Wallet::from_key(key.clone(), "local".to_string()).unwrap();
+            imported.set_endpoints(vec![url.clone()], vec![]);
+            imported.sync().await.unwrap();
+            assert_eq!(imported.is_watch_only(), watch_only);
+            assert_eq!(imported.address(0), wallet.address(0));
+            assert_eq!(imported.export_incoming_viewing_key().unwrap(), ivk);
+            assert_eq!(amounts(&imported), spendable);
+            assert_eq!(imported.sent_notes().unwrap().len(), sent);
+        }
+        let imported = Wallet::from_key(ivk.clone(), "local".to_string()).unwrap();
+        assert!(matches!(imported.export_full_viewing_key(), Err(WalletError::General(_))));
+        assert!(matches!(imported.export_outgoing_viewing_key(), Err(WalletError::General(_))));
+        assert!(Wallet::from_key(wallet.export_outgoing_viewing_key().unwrap(), "local".to_string()).is_err());
+        assert!(Wallet::from_key(ivk, "kylin".to_string()).is_err());
+    }
+
+    #[tokio::test]
+    async fn private_deposit_withdrawal()
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        let path = std::env::temp_dir().join(format!("zeos-wallet-deposit-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        let mut wallet = wallet.with_store(SqliteStore::open(&path, "secret").unwrap()).unwrap();
+        wallet.set_endpoints(vec![url], vec![]);
+        let address = Address::from_bech32m(&wallet.address(0), Network::Local).unwrap();
+        let eos = |amount: u64| Asset::ft(amount, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        let auth = vec![EOSAuthorization{ actor: "alice".to_string(), permission: "active".to_string() }];
+        let withdraw = EOSAction{ account: "zeosexchange".to_string(), name: "withdraw".to_string(), authorization: auth.clone(), data: String::from("{}") };
+        chain.mint(address, &eos(10000), "");
+        wallet.sync().await.unwrap();
+
+        // the deposit is unconfirmed and cannot be withdrawn as long as its transaction has not been executed
+        wallet.private_deposit(&eos(5000), &"zeosexchange".to_string(), &"deposit".to_string(), &auth).await.unwrap();
+        let deposit = wallet.private_deposits()[0].clone();
+        assert!(!deposit.confirmed);
+        assert_eq!(deposit.asset, eos(5000));
+        // the deposit and its auth token are stored at once
+        let reopened = Wallet::open(SqliteStore::open(&path, "secret").unwrap()).unwrap();
+        assert_eq!(reopened.private_deposits()[0].cm_auth, deposit.cm_auth);
+        assert!(reopened.private_deposits()[0].auth_note.is_some());
+        assert!(wallet.private_withdrawal(&deposit.cm_auth, &withdraw, &auth).await.unwrap_err().to_string().contains("not been executed"));
+        wallet.sync().await.unwrap();
+        assert!(!wallet.private_deposits()[0].confirmed);
+
+        // the auth token is received once the deposit has been executed
+        chain.add_notes(&[deposit.auth_note.unwrap()], None);
+        wallet.sync().await.unwrap();
+        assert!(wallet.private_deposits()[0].confirmed);
+
+        // a withdrawal which has not been executed changes nothing
+        wallet.private_withdrawal(&deposit.cm_auth, &withdraw, &auth).await.unwrap();
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.private_deposits()[0].withdrawn_note_id, None);
+
+        // an unrelated payment of the same asset is not attributed to the deposit
+        chain.mint(address, &eos(5000), "payment");
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.private_deposits()[0].withdrawn_note_id, None);
+
+        // the note minted by the withdrawal carries the auth token commitment as memo
+        let id = chain.mint(address, &eos(5000), &deposit.cm_auth);
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.private_deposits()[0].withdrawn_note_id, Some(id));
+        assert!(wallet.private_withdrawal(&deposit.cm_auth, &withdraw, &auth).await.unwrap_err().to_string().contains("no open private deposit"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Synchronizes 'wallet' with a simulated chain which forks
+    async fn reorg<S: WalletStore>(mut wallet: Wallet<S>) -> Wallet<S>
+    {
+        let (chain, url) = SimulatedChain::spawn(Network::Local);
+        wallet.set_endpoints(vec![url], vec![]);
+        let address = Address::from_bech32m(&wallet.address(0), Network::Local).unwrap();
+        let eos = |amount: u64| Asset::ft(amount, string_to_symbol(&"EOS".to_string(), 4), &"eosio.token".to_string());
+        let amounts = |wallet: &Wallet<S>| wallet.spendable_notes().unwrap().iter().map(|n| n.note.d1().inner()).collect::<Vec<u64>>();
+        let confirmed = |wallet: &Wallet<S>| wallet.history().unwrap().iter().map(|e| e.confirmed).collect::<Vec<bool>>();
+
+        // the two most recent blocks are reversible: notes 1 and 2 are unconfirmed
+        chain.set_reversible_blocks(2);
+        for amount in [1, 2, 3]
+        {
+            chain.mint(address, &eos(amount), "");
+        }
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.last_irreversible_block(), 1);
+        assert_eq!(confirmed(&wallet), vec![true, false, false]);
+
+        // block 3 is replaced by a fork: note 2 changes
+        chain.fork(2).unwrap();
+        chain.mint(address, &eos(4), "");
+        chain.mint(address, &eos(5), "");
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.state().note_count, 4);
+        assert_eq!(amounts(&wallet), vec![1, 2, 4, 5]);
+        assert_eq!(confirmed(&wallet), vec![true, true, false, false]);
+        assert!(chain.fork(1).is_err());
+
+        // note 0 is spent in block 5 which is discarded by a fork: the spent note is restored
+        let fvk = wallet.full_viewing_key().unwrap();
+        let nf = wallet.spendable_notes().unwrap()[0].note.nullifier(&fvk);
+        let note = Note::new(NT_FT, address, NoteValue::from_raw(1), NoteValue::from_raw(eos(1).d2()), NoteValue::from_raw(name_to_value(&"eosio.token".to_string())), NoteValue::from_raw(0), nf, OsRng, [0; 512]);
+        chain.add_notes(&[note], Some(fvk.to_ovk(Scope::External)));
+        wallet.sync().await.unwrap();
+        assert_eq!(amounts(&wallet), vec![2, 4, 5, 1]);
+        assert_eq!(wallet.sent_notes().unwrap().len(), 1);
+        chain.fork(4).unwrap();
+        wallet.sync().await.unwrap();
+        assert_eq!(wallet.state().note_count, 4);
+        assert_eq!(amounts(&wallet), vec![1, 2, 4, 5]);
+        assert!(wallet.sent_notes().unwrap().is_empty());
+
+        // notes are not rolled back once they are irreversible
+        chain.set_reversible_blocks(0);
+        wallet.sync().await.unwrap();
+        assert!(wallet.reversible_notes.is_empty());
+        assert_eq!(confirmed(&wallet), vec![true; 4]);
+        wallet
+    }
+
+    #[tokio::test]
+    async fn reorg_in_memory()
+    {
+        let wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        let wallet = reorg(wallet).await;
+        // the serialization contains the notes
+        let restored = Wallet::from_string(wallet.to_string().unwrap()).unwrap();
+        assert_eq!(restored.balances().unwrap(), wallet.balances().unwrap());
+        assert_eq!(restored.to_string().unwrap(), wallet.to_string().unwrap());
+    }
+
+    #[tokio::test]
+    async fn reorg_in_sqlite()
     {
+        let path = std::env::temp_dir().join(format!("zeos-wallet-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = Wallet::new_for_network("miau seed miau 123 Der seed muss lang genug sein...".to_string(), "local".to_string()).unwrap();
+        let wallet = reorg(wallet.with_store(SqliteStore::open(&path, "secret").unwrap()).unwrap()).await;
 
+        // the synchronized wallet is restored from the database
+        let reopened = Wallet::open(SqliteStore::open(&path, "secret").unwrap()).unwrap();
+        assert_eq!(reopened.to_string().unwrap(), wallet.to_string().unwrap());
+        assert_eq!(reopened.state().note_count, 4);
+        let query = NoteQuery::default().with_status(NoteStatus::Spendable).with_token("eosio.token", string_to_symbol(&"EOS".to_string(), 4));
+        assert_eq!(reopened.notes(&query).unwrap().len(), 4);
+        assert!(reopened.notes(&query.with_nft_contract("atomicassets")).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
     }
 }
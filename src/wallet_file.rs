@@ -0,0 +1,195 @@
+//! Password encrypted wallet files for native applications (e.g. the 'zeos' command line wallet).
+//!
+//! The JSON serialization of a wallet (see: 'Wallet::to_string') contains the seed phrase and must never be written to
+//! disk in plain text. The file key is derived from the password and a random salt using Argon2id, the wallet is
+//! encrypted with ChaCha20Poly1305. A new salt and nonce are generated each time the wallet is saved.
+
+use std::fs;
+use std::path::Path;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use rand::rngs::OsRng;
+
+use crate::wallet::{Wallet, WalletError};
+
+/// The current version of the wallet file format
+pub const WALLET_FILE_VERSION: u32 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub enum WalletFileError
+{
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid wallet file: {0}")]
+    InvalidFormat(String),
+    #[error("unsupported wallet file version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("wrong password or corrupted wallet file")]
+    Decryption,
+    #[error(transparent)]
+    Wallet(#[from] WalletError)
+}
+
+/// The content of a wallet file (JSON)
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedWallet
+{
+    version: u32,
+    /// salt of the key derivation (hex)
+    salt: String,
+    /// nonce of the encryption (hex)
+    nonce: String,
+    /// the encrypted JSON serialization of the wallet (hex)
+    ciphertext: String
+}
+
+/// Derives the 256 bit key of 'password' and 'salt' using Argon2id
+pub(crate) fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32], WalletFileError>
+{
+    let mut key = [0; 32];
+    Argon2::default().hash_password_into(password.as_bytes(), salt, &mut key).map_err(|e| WalletFileError::InvalidFormat(e.to_string()))?;
+    Ok(key)
+}
+
+/// Encrypts 'plaintext' with 'password' and returns the content of the wallet file
+pub fn encrypt(plaintext: &[u8], password: &str) -> Result<String, WalletFileError>
+{
+    let mut salt = [0; 16];
+    let mut nonce = [0; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let key = derive_key(password, &salt)?;
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| WalletFileError::InvalidFormat(String::from("encryption failed")))?;
+    Ok(serde_json::to_string(&EncryptedWallet{
+        version: WALLET_FILE_VERSION,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext)
+    }).unwrap())
+}
+
+/// Encrypts 'plaintext' with 'key' (see: 'derive_key') and returns the random nonce followed by the ciphertext. Unlike
+/// 'encrypt' no key is derived which makes it suitable for many small records (e.g. the notes of 'SqliteStore').
+pub(crate) fn encrypt_with_key(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, WalletFileError>
+{
+    let mut nonce = [0; 12];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| WalletFileError::InvalidFormat(String::from("encryption failed")))?;
+    Ok([&nonce[..], &ciphertext].concat())
+}
+
+/// Decrypts 'data' (a nonce followed by the ciphertext, see: 'encrypt_with_key') with 'key'
+pub(crate) fn decrypt_with_key(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, WalletFileError>
+{
+    if data.len() < 12
+    {
+        return Err(WalletFileError::InvalidFormat(format!("invalid ciphertext length: {}", data.len())));
+    }
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(&data[..12]), &data[12..])
+        .map_err(|_| WalletFileError::Decryption)
+}
+
+/// Decrypts the content of a wallet file with 'password'
+pub fn decrypt(content: &str, password: &str) -> Result<Vec<u8>, WalletFileError>
+{
+    let file: EncryptedWallet = serde_json::from_str(content).map_err(|e| WalletFileError::InvalidFormat(e.to_string()))?;
+    if file.version != WALLET_FILE_VERSION
+    {
+        return Err(WalletFileError::UnsupportedVersion(file.version));
+    }
+    let decode = |str: &String| hex::decode(str).map_err(|e| WalletFileError::InvalidFormat(e.to_string()));
+    let salt = decode(&file.salt)?;
+    let nonce = decode(&file.nonce)?;
+    if nonce.len() != 12
+    {
+        return Err(WalletFileError::InvalidFormat(format!("invalid nonce length: {}", nonce.len())));
+    }
+    let key = derive_key(password, &salt)?;
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(&nonce), decode(&file.ciphertext)?.as_ref())
+        .map_err(|_| WalletFileError::Decryption)
+}
+
+/// Encrypts 'plaintext' with 'password' and writes it to 'path'. The file is replaced atomically and (on unix) only
+/// readable by its owner.
+pub fn write(path: &Path, plaintext: &str, password: &str) -> Result<(), WalletFileError>
+{
+    let content = encrypt(plaintext.as_bytes(), password)?;
+    if let Some(dir) = path.parent()
+    {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, content)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Reads the encrypted file at 'path' and decrypts it with 'password'
+pub fn read(path: &Path, password: &str) -> Result<String, WalletFileError>
+{
+    let plaintext = decrypt(&fs::read_to_string(path)?, password)?;
+    String::from_utf8(plaintext).map_err(|e| WalletFileError::InvalidFormat(e.to_string()))
+}
+
+/// Encrypts 'wallet' with 'password' and writes it to 'path' (see: 'write')
+pub fn save(path: &Path, wallet: &Wallet, password: &str) -> Result<(), WalletFileError>
+{
+    write(path, &wallet.to_string()?, password)
+}
+
+/// Reads the wallet file at 'path' and decrypts it with 'password'
+pub fn load(path: &Path, password: &str) -> Result<Wallet, WalletFileError>
+{
+    Ok(Wallet::from_string(read(path, password)?)?)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{encrypt, decrypt, derive_key, encrypt_with_key, decrypt_with_key, WalletFileError};
+
+    #[test]
+    fn encryption()
+    {
+        let plaintext = br#"{"seed":"miau seed miau 123 Der seed muss lang genug sein..."}"#;
+        let content = encrypt(plaintext, "password123").unwrap();
+        assert!(!content.contains("miau"));
+        assert_eq!(decrypt(&content, "password123").unwrap(), plaintext.to_vec());
+        assert!(matches!(decrypt(&content, "password124"), Err(WalletFileError::Decryption)));
+
+        // salt and nonce are random
+        assert_ne!(encrypt(plaintext, "password123").unwrap(), content);
+
+        let mut file: serde_json::Value = serde_json::from_str(&content).unwrap();
+        file["version"] = serde_json::json!(2);
+        assert!(matches!(decrypt(&file.to_string(), "password123"), Err(WalletFileError::UnsupportedVersion(2))));
+        assert!(matches!(decrypt("{}", "password123"), Err(WalletFileError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn encryption_with_key()
+    {
+        let key = derive_key("password123", &[7; 16]).unwrap();
+        let data = encrypt_with_key(&key, b"miau").unwrap();
+        assert_eq!(data.len(), 12 + 4 + 16);
+        assert_eq!(decrypt_with_key(&key, &data).unwrap(), b"miau".to_vec());
+        assert_ne!(encrypt_with_key(&key, b"miau").unwrap(), data);
+        let other = derive_key("password124", &[7; 16]).unwrap();
+        assert!(matches!(decrypt_with_key(&other, &data), Err(WalletFileError::Decryption)));
+        assert!(matches!(decrypt_with_key(&key, &data[..11]), Err(WalletFileError::InvalidFormat(_))));
+    }
+}
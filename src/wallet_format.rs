@@ -0,0 +1,206 @@
+//! Versioned serialization of wallets.
+//!
+//! The JSON serialization of a wallet (see: 'Wallet::to_string') is wrapped into an envelope which carries the version
+//! of its format:
+//!
+//!     {"version":1,"wallet":{"seed":"...","state":{...},"settings":{...},...}}
+//!
+//! Wallets of older formats (e.g. kept in the local storage of browsers) are upgraded by a chain of migrations when
+//! they are restored: 'MIGRATIONS[i]' migrates a wallet of version 'i' to version 'i + 1'. A change of the wallet
+//! format (e.g. a new field of 'Wallet', 'Settings', 'Global' or 'NoteEx') requires a new version and a migration
+//! from the previous one. The versions are:
+//!
+//! - 0: the unversioned JSON of 'Wallet::to_string' (without envelope). Fields were added over time: wallets without
+//!   'network' operate on Kylin, all other missing fields are empty.
+//! - 1: the first versioned format. All fields are present.
+//!
+//! A frozen fixture of each version is kept in 'src/test_vectors/wallet_format'.
+
+use serde_json::{json, Map, Value};
+
+/// The current version of the wallet format
+pub const WALLET_FORMAT_VERSION: u32 = 1;
+
+/// Migrates a wallet (JSON object) from one version to the next
+type Migration = fn(Map<String, Value>) -> Result<Map<String, Value>, WalletFormatError>;
+
+/// The migrations of all previous versions: 'MIGRATIONS[i]' migrates version 'i' to version 'i + 1'
+const MIGRATIONS: &[Migration] = &[
+    migrate_v0,
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum WalletFormatError
+{
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("not a wallet: {0}")]
+    InvalidFormat(String),
+    #[error("unsupported wallet format version: {0}")]
+    UnsupportedVersion(u64),
+    #[error("migration of wallet format version {version} failed: {reason}")]
+    Migration{ version: u32, reason: String },
+}
+
+/// Returns the version of the format of the serialized wallet 'json'
+pub fn format_version(json: &str) -> Result<u32, WalletFormatError>
+{
+    Ok(unwrap_envelope(serde_json::from_str(json)?)?.0)
+}
+
+/// Wraps the wallet (JSON object) of the current version into an envelope
+pub fn encode(wallet: Value) -> String
+{
+    json!({ "version": WALLET_FORMAT_VERSION, "wallet": wallet }).to_string()
+}
+
+/// Unwraps the serialized wallet 'json' of any supported version and migrates it to the current version. Returns the
+/// wallet (JSON object).
+pub fn decode(json: &str) -> Result<Value, WalletFormatError>
+{
+    let (version, mut wallet) = unwrap_envelope(serde_json::from_str(json)?)?;
+    for (v, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize)
+    {
+        wallet = migrate(wallet).map_err(|e| WalletFormatError::Migration{ version: v as u32, reason: e.to_string() })?;
+    }
+    Ok(Value::Object(wallet))
+}
+
+/// Returns the version and the wallet (JSON object) of the envelope 'value' or version 0 if 'value' is an unversioned
+/// wallet
+fn unwrap_envelope(value: Value) -> Result<(u32, Map<String, Value>), WalletFormatError>
+{
+    let mut obj = match value {
+        Value::Object(obj) => obj,
+        _ => return Err(WalletFormatError::InvalidFormat(String::from("not a JSON object")))
+    };
+    let version = match obj.get("version") {
+        None => return Ok((0, obj)),
+        Some(v) => v.as_u64().ok_or_else(|| WalletFormatError::InvalidFormat(format!("invalid version: {}", v)))?
+    };
+    if version == 0 || version > WALLET_FORMAT_VERSION as u64
+    {
+        return Err(WalletFormatError::UnsupportedVersion(version));
+    }
+    match obj.remove("wallet") {
+        Some(Value::Object(wallet)) => Ok((version as u32, wallet)),
+        _ => Err(WalletFormatError::InvalidFormat(String::from("missing wallet")))
+    }
+}
+
+/// Inserts 'value' as 'key' into 'obj' unless it is present
+fn insert_default(obj: &mut Map<String, Value>, key: &str, value: Value)
+{
+    if !obj.contains_key(key)
+    {
+        obj.insert(key.to_string(), value);
+    }
+}
+
+/// Version 0 => 1: adds all fields which have been added to the unversioned format over time
+fn migrate_v0(mut wallet: Map<String, Value>) -> Result<Map<String, Value>, WalletFormatError>
+{
+    if !wallet.contains_key("seed")
+    {
+        return Err(WalletFormatError::InvalidFormat(String::from("missing seed")));
+    }
+    let settings = match wallet.get_mut("settings") {
+        Some(Value::Object(settings)) => settings,
+        _ => return Err(WalletFormatError::InvalidFormat(String::from("missing settings")))
+    };
+    // wallets created before network profiles existed operate on Kylin
+    insert_default(settings, "network", json!("kylin"));
+    insert_default(settings, "zeos_endpoints", json!([]));
+    insert_default(settings, "prover_url", Value::Null);
+    insert_default(&mut wallet, "imported_key", Value::Null);
+    insert_default(&mut wallet, "private_deposits", json!([]));
+    if let Some(Value::Array(deposits)) = wallet.get_mut("private_deposits")
+    {
+        // the deposits of unversioned wallets have been recorded when their transactions were created and are assumed
+        // to be confirmed
+        for deposit in deposits.iter_mut().filter_map(Value::as_object_mut)
+        {
+            insert_default(deposit, "auth_note", Value::Null);
+            insert_default(deposit, "confirmed", json!(true));
+            insert_default(deposit, "withdrawn_note_id", Value::Null);
+        }
+    }
+    insert_default(&mut wallet, "last_irreversible_block", json!(0));
+    insert_default(&mut wallet, "reversible_notes", json!([]));
+    insert_default(&mut wallet, "reversible_spends", json!([]));
+    Ok(wallet)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{decode, encode, format_version, WalletFormatError, WALLET_FORMAT_VERSION, MIGRATIONS};
+    use crate::network::Network;
+    use crate::wallet::Wallet;
+    use serde_json::Value;
+
+    /// The frozen fixtures of all versions: (version, JSON)
+    const FIXTURES: &[(u32, &str)] = &[
+        (0, include_str!("test_vectors/wallet_format/v0_initial.json")),
+        (0, include_str!("test_vectors/wallet_format/v0.json")),
+        (1, include_str!("test_vectors/wallet_format/v1.json")),
+    ];
+
+    #[test]
+    fn migrations()
+    {
+        assert_eq!(MIGRATIONS.len(), WALLET_FORMAT_VERSION as usize);
+        for (version, json) in FIXTURES
+        {
+            assert_eq!(format_version(json).unwrap(), *version);
+
+            // the wallet is restored and serialized in the current format which is restored again
+            let wallet = Wallet::from_string(json.to_string()).unwrap();
+            let upgraded = wallet.to_string().unwrap();
+            assert_eq!(format_version(&upgraded).unwrap(), WALLET_FORMAT_VERSION);
+            let restored = Wallet::from_string(upgraded.clone()).unwrap();
+            assert_eq!(restored.to_string().unwrap(), upgraded);
+            assert_eq!(restored.balances().unwrap(), wallet.balances().unwrap());
+            assert_eq!(restored.history().unwrap().len(), wallet.history().unwrap().len());
+        }
+
+        // the first wallets operate on Kylin
+        let wallet = Wallet::from_string(FIXTURES[0].1.to_string()).unwrap();
+        assert_eq!(wallet.settings().network(), Network::Kylin);
+        assert_eq!(wallet.balances().unwrap()["EOS"], 15000);
+        assert_eq!(wallet.history().unwrap().len(), 2);
+        assert_eq!(wallet.last_irreversible_block(), 0);
+
+        // the last unversioned format and each versioned format migrate to the frozen current format
+        let current = |json: &str| serde_json::from_str::<Value>(&Wallet::from_string(json.to_string()).unwrap().to_string().unwrap()).unwrap();
+        let frozen: Value = serde_json::from_str(FIXTURES.last().unwrap().1).unwrap();
+        for (_, json) in FIXTURES[1..].iter()
+        {
+            assert_eq!(current(json), frozen);
+        }
+        let wallet = Wallet::from_string(FIXTURES[2].1.to_string()).unwrap();
+        assert_eq!(wallet.settings().network(), Network::Jungle);
+        assert_eq!(wallet.last_irreversible_block(), 1200);
+        assert_eq!(wallet.history().unwrap().len(), 3);
+        assert_eq!(serde_json::to_value(wallet.history().unwrap()).unwrap()[2]["confirmed"], false);
+        assert!(wallet.private_deposits()[0].confirmed);
+        assert!(wallet.private_deposits()[0].auth_note.is_none());
+    }
+
+    #[test]
+    fn envelope()
+    {
+        let wallet = serde_json::json!({ "seed": "", "settings": {} });
+        let json = encode(wallet.clone());
+        assert_eq!(format_version(&json).unwrap(), WALLET_FORMAT_VERSION);
+        assert_eq!(decode(&json).unwrap(), wallet);
+
+        // wallets of newer versions are rejected instead of losing their new fields
+        let newer = json.replacen(&format!("\"version\":{}", WALLET_FORMAT_VERSION), "\"version\":999", 1);
+        assert!(matches!(decode(&newer), Err(WalletFormatError::UnsupportedVersion(999))));
+        assert!(matches!(decode("[]"), Err(WalletFormatError::InvalidFormat(_))));
+        assert!(matches!(decode("{\"version\":1}"), Err(WalletFormatError::InvalidFormat(_))));
+        assert!(matches!(decode("{\"state\":{}}"), Err(WalletFormatError::Migration{ version: 0, .. })));
+        assert!(matches!(decode("{"), Err(WalletFormatError::Json(_))));
+    }
+}
@@ -0,0 +1,627 @@
+//! Storage backends of wallets.
+//!
+//! A wallet keeps its keys, settings and synchronization state in memory while its notes are kept by a 'WalletStore'
+//! which is queried on demand (see: 'NoteQuery'). All changes of a synchronization are written to the store at once
+//! (see: 'StoreBatch'). There are three backends:
+//!
+//! - 'MemoryStore' keeps the wallet in memory (the default, e.g. for wallets in the browser which are serialized by
+//!   'Wallet::to_string').
+//! - 'JsonFileStore' keeps the wallet in memory as well and rewrites a password encrypted wallet file (see:
+//!   wallet_file) on each change.
+//! - 'SqliteStore' keeps the notes in a SQLite database. The wallet data (including the seed phrase) and each note are
+//!   encrypted with a password. Only the id, status and block number of the notes are stored in plain text since
+//!   anything else (amounts, memos, recipients, tokens) would reveal the shielded history of the wallet.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use crate::address::Address;
+use crate::contract::NoteEx;
+use crate::eosio::name_to_value;
+use crate::wallet_format::{self, WalletFormatError};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+#[cfg(not(target_arch = "wasm32"))]
+use rand::RngCore;
+#[cfg(not(target_arch = "wasm32"))]
+use rand::rngs::OsRng;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::wallet_file::{self, WalletFileError};
+
+/// The keys of the lists of notes in the JSON serialization of a wallet (see: 'Wallet::to_string')
+const SPENDABLE_NOTES: &str = "spendable_notes";
+const SENT_NOTES: &str = "sent_notes";
+
+/// The migrations of the database schema of 'SqliteStore': the n-th migration upgrades a database from schema version
+/// n to n + 1 (the schema version is stored as 'user_version' of the database)
+#[cfg(not(target_arch = "wasm32"))]
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE wallet (id INTEGER PRIMARY KEY CHECK (id = 0), data TEXT NOT NULL);
+    CREATE TABLE notes_key (id INTEGER PRIMARY KEY CHECK (id = 0), salt BLOB NOT NULL);
+    CREATE TABLE notes (
+        id INTEGER NOT NULL,
+        status INTEGER NOT NULL,
+        block_number INTEGER NOT NULL,
+        note BLOB NOT NULL,
+        PRIMARY KEY (id, status)
+    );
+    CREATE INDEX notes_status ON notes (status, id);
+"];
+
+#[derive(thiserror::Error, Debug)]
+pub enum WalletStoreError
+{
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("invalid wallet data: {0}")]
+    InvalidFormat(String),
+    #[error(transparent)]
+    Format(#[from] WalletFormatError),
+    #[error("unsupported database schema version: {0}")]
+    SchemaVersion(u32),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error(transparent)]
+    Database(#[from] rusqlite::Error),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error(transparent)]
+    File(Box<WalletFileError>)
+}
+
+// boxed: a wallet file error may contain a wallet error which in turn may contain a wallet store error
+#[cfg(not(target_arch = "wasm32"))]
+impl From<WalletFileError> for WalletStoreError
+{
+    fn from(err: WalletFileError) -> Self
+    {
+        Self::File(Box::new(err))
+    }
+}
+
+/// The status of a note of a wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NoteStatus
+{
+    /// A received note which has not been spent yet
+    Spendable = 0,
+    /// A note sent from the wallet
+    Sent = 1
+}
+
+/// A query of the notes of a wallet. Criteria which are not set match all notes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NoteQuery
+{
+    status: Option<NoteStatus>,
+    nft: Option<bool>,
+    contract: Option<u64>,
+    symbol: Option<u64>,
+    address: Option<[u8; 43]>,
+    min_id: u64
+}
+
+impl NoteQuery
+{
+    /// Matches the notes with status 'status'
+    pub fn with_status(mut self, status: NoteStatus) -> Self
+    {
+        self.status = Some(status);
+        self
+    }
+
+    /// Matches the notes of the fungible token 'symbol' (see: string_to_symbol) of the token contract 'contract'
+    pub fn with_token(mut self, contract: &str, symbol: u64) -> Self
+    {
+        self.nft = Some(false);
+        self.contract = Some(name_to_value(&contract.to_string()));
+        self.symbol = Some(symbol);
+        self
+    }
+
+    /// Matches the NFTs of the NFT contract 'contract'
+    pub fn with_nft_contract(mut self, contract: &str) -> Self
+    {
+        self.nft = Some(true);
+        self.contract = Some(name_to_value(&contract.to_string()));
+        self
+    }
+
+    /// Matches the notes whose recipient is 'address'
+    pub fn with_address(mut self, address: &Address) -> Self
+    {
+        self.address = Some(address.to_raw_address_bytes());
+        self
+    }
+
+    /// Matches the notes with id 'id' or higher
+    pub fn with_min_id(mut self, id: u64) -> Self
+    {
+        self.min_id = id;
+        self
+    }
+
+    /// Returns true if note 'n' with status 'status' matches this query
+    pub fn matches(&self, status: NoteStatus, n: &NoteEx) -> bool
+    {
+        n.id >= self.min_id &&
+        self.status.map_or(true, |s| s == status) &&
+        self.nft.map_or(true, |nft| nft == (n.note.nft().inner() != 0)) &&
+        self.contract.map_or(true, |c| c == n.note.sc().inner()) &&
+        self.symbol.map_or(true, |s| s == n.note.d2().inner()) &&
+        self.address.map_or(true, |a| a == n.note.recipient().to_raw_address_bytes())
+    }
+}
+
+/// A batch of changes to a wallet store which is applied at once (e.g. all changes of a synchronization)
+#[derive(Debug, Default)]
+pub struct StoreBatch
+{
+    /// The new wallet data (the JSON serialization of the wallet without notes)
+    pub wallet: Option<String>,
+    /// The notes to be added (or replaced)
+    pub insert: Vec<(NoteStatus, NoteEx)>,
+    /// The notes to be removed (status and id)
+    pub remove: Vec<(NoteStatus, u64)>
+}
+
+impl StoreBatch
+{
+    /// Returns true if this batch contains no changes
+    pub fn is_empty(&self) -> bool
+    {
+        self.wallet.is_none() && self.insert.is_empty() && self.remove.is_empty()
+    }
+}
+
+/// A storage backend of wallets
+pub trait WalletStore: Debug
+{
+    /// Returns the wallet data written last (the JSON serialization of the wallet without notes) or 'None' if the
+    /// store is empty
+    fn load(&self) -> Result<Option<String>, WalletStoreError>;
+
+    /// Returns the notes which match 'query' ordered by id
+    fn notes(&self, query: &NoteQuery) -> Result<Vec<NoteEx>, WalletStoreError>;
+
+    /// Applies all changes of 'batch': either all or none of them are written
+    fn apply(&mut self, batch: StoreBatch) -> Result<(), WalletStoreError>;
+}
+
+/// Splits the JSON serialization of a wallet of any format version (see: 'Wallet::to_string') into the wallet data
+/// (migrated to the current version, see: wallet_format) and its notes
+pub(crate) fn split_wallet_json(json: &str) -> Result<(String, Vec<(NoteStatus, NoteEx)>), WalletStoreError>
+{
+    let mut value = wallet_format::decode(json)?;
+    let obj = value.as_object_mut().ok_or_else(|| WalletStoreError::InvalidFormat(String::from("not a JSON object")))?;
+    let mut notes = Vec::new();
+    for (key, status) in [(SPENDABLE_NOTES, NoteStatus::Spendable), (SENT_NOTES, NoteStatus::Sent)]
+    {
+        let list: Vec<NoteEx> = match obj.remove(key) {
+            Some(v) => serde_json::from_value(v)?,
+            None => Vec::new()
+        };
+        notes.extend(list.into_iter().map(|n| (status, n)));
+    }
+    Ok((value.to_string(), notes))
+}
+
+/// Joins the wallet data and its notes to the versioned JSON serialization of a wallet (see: 'Wallet::to_string')
+pub(crate) fn join_wallet_json(wallet: &str, spendable_notes: &[NoteEx], sent_notes: &[NoteEx]) -> Result<String, WalletStoreError>
+{
+    let mut value: serde_json::Value = serde_json::from_str(wallet)?;
+    let obj = value.as_object_mut().ok_or_else(|| WalletStoreError::InvalidFormat(String::from("not a JSON object")))?;
+    obj.insert(SPENDABLE_NOTES.to_string(), serde_json::to_value(spendable_notes)?);
+    obj.insert(SENT_NOTES.to_string(), serde_json::to_value(sent_notes)?);
+    Ok(wallet_format::encode(value))
+}
+
+/// Keeps a wallet in memory
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore
+{
+    wallet: Option<String>,
+    notes: BTreeMap<(u64, NoteStatus), NoteEx>
+}
+
+impl WalletStore for MemoryStore
+{
+    fn load(&self) -> Result<Option<String>, WalletStoreError>
+    {
+        Ok(self.wallet.clone())
+    }
+
+    fn notes(&self, query: &NoteQuery) -> Result<Vec<NoteEx>, WalletStoreError>
+    {
+        Ok(self.notes.range((query.min_id, NoteStatus::Spendable)..)
+            .filter(|((_, status), n)| query.matches(*status, n))
+            .map(|(_, n)| n.clone())
+            .collect())
+    }
+
+    fn apply(&mut self, batch: StoreBatch) -> Result<(), WalletStoreError>
+    {
+        if batch.wallet.is_some()
+        {
+            self.wallet = batch.wallet;
+        }
+        for (status, id) in batch.remove
+        {
+            self.notes.remove(&(id, status));
+        }
+        for (status, n) in batch.insert
+        {
+            self.notes.insert((n.id, status), n);
+        }
+        Ok(())
+    }
+}
+
+/// Keeps a wallet in memory and rewrites the wallet file at 'path' encrypted with 'password' (see: wallet_file) on
+/// each change. The files are compatible with the wallet files of the command line wallet.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct JsonFileStore
+{
+    path: PathBuf,
+    password: String,
+    memory: MemoryStore
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Debug for JsonFileStore
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        f.debug_struct("JsonFileStore").field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl JsonFileStore
+{
+    /// Opens the wallet file at 'path' which is encrypted with 'password'. The store is empty if the file does not
+    /// exist yet.
+    pub fn open(path: impl AsRef<Path>, password: &str) -> Result<Self, WalletStoreError>
+    {
+        let path = path.as_ref().to_path_buf();
+        let mut memory = MemoryStore::default();
+        if path.exists()
+        {
+            let (wallet, notes) = split_wallet_json(&wallet_file::read(&path, password)?)?;
+            memory.apply(StoreBatch{ wallet: Some(wallet), insert: notes, remove: Vec::new() })?;
+        }
+        Ok(JsonFileStore{ path, password: password.to_string(), memory })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl WalletStore for JsonFileStore
+{
+    fn load(&self) -> Result<Option<String>, WalletStoreError>
+    {
+        self.memory.load()
+    }
+
+    fn notes(&self, query: &NoteQuery) -> Result<Vec<NoteEx>, WalletStoreError>
+    {
+        self.memory.notes(query)
+    }
+
+    fn apply(&mut self, batch: StoreBatch) -> Result<(), WalletStoreError>
+    {
+        // the file is replaced atomically: the changes are kept only if it has been written
+        let mut memory = self.memory.clone();
+        memory.apply(batch)?;
+        let wallet = memory.load()?.ok_or_else(|| WalletStoreError::InvalidFormat(String::from("no wallet data")))?;
+        let spendable_notes = memory.notes(&NoteQuery::default().with_status(NoteStatus::Spendable))?;
+        let sent_notes = memory.notes(&NoteQuery::default().with_status(NoteStatus::Sent))?;
+        wallet_file::write(&self.path, &join_wallet_json(&wallet, &spendable_notes, &sent_notes)?, &self.password)?;
+        self.memory = memory;
+        Ok(())
+    }
+}
+
+/// Keeps a wallet in a SQLite database. The wallet data is encrypted with 'password' (see: wallet_file). Each note is
+/// encrypted with a key derived from 'password' and a random salt which is stored in the database. The notes are
+/// indexed by status and id only: all other criteria of a 'NoteQuery' are matched after decryption.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SqliteStore
+{
+    db: Connection,
+    password: String,
+    notes_key: [u8; 32]
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Debug for SqliteStore
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        f.debug_struct("SqliteStore").field("db", &self.db).finish_non_exhaustive()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SqliteStore
+{
+    /// Opens (or creates) the wallet database at 'path' whose wallet data is encrypted with 'password'. The schema of
+    /// an existing database is upgraded to the current version.
+    pub fn open(path: impl AsRef<Path>, password: &str) -> Result<Self, WalletStoreError>
+    {
+        Self::init(Connection::open(path)?, password)
+    }
+
+    /// Creates a wallet database which is kept in memory only
+    pub fn open_in_memory(password: &str) -> Result<Self, WalletStoreError>
+    {
+        Self::init(Connection::open_in_memory()?, password)
+    }
+
+    fn init(db: Connection, password: &str) -> Result<Self, WalletStoreError>
+    {
+        let version: u32 = db.pragma_query_value(None, "user_version", |r| r.get(0))?;
+        if version as usize > MIGRATIONS.len()
+        {
+            return Err(WalletStoreError::SchemaVersion(version));
+        }
+        for (v, migration) in MIGRATIONS.iter().enumerate().skip(version as usize)
+        {
+            let tx = db.unchecked_transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", v as u32 + 1)?;
+            tx.commit()?;
+        }
+        // removed notes are overwritten instead of being left in free pages of the database file
+        db.pragma_update(None, "secure_delete", true)?;
+        let salt = match db.query_row("SELECT salt FROM notes_key WHERE id = 0", [], |r| r.get::<_, Vec<u8>>(0)).optional()? {
+            Some(salt) => salt,
+            None => {
+                let mut salt = vec![0; 16];
+                OsRng.fill_bytes(&mut salt);
+                db.execute("INSERT INTO notes_key (id, salt) VALUES (0, ?1)", [&salt])?;
+                salt
+            }
+        };
+        let notes_key = wallet_file::derive_key(password, &salt)?;
+        Ok(SqliteStore{ db, password: password.to_string(), notes_key })
+    }
+
+    /// Returns the schema version of the database
+    pub fn schema_version(&self) -> Result<u32, WalletStoreError>
+    {
+        Ok(self.db.pragma_query_value(None, "user_version", |r| r.get(0))?)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl WalletStore for SqliteStore
+{
+    fn load(&self) -> Result<Option<String>, WalletStoreError>
+    {
+        let data: Option<String> = self.db.query_row("SELECT data FROM wallet WHERE id = 0", [], |r| r.get(0)).optional()?;
+        match data {
+            Some(data) => {
+                let plaintext = wallet_file::decrypt(&data, &self.password)?;
+                let json = String::from_utf8(plaintext).map_err(|e| WalletStoreError::InvalidFormat(e.to_string()))?;
+                Ok(Some(wallet_format::decode(&json)?.to_string()))
+            }
+            None => Ok(None)
+        }
+    }
+
+    fn notes(&self, query: &NoteQuery) -> Result<Vec<NoteEx>, WalletStoreError>
+    {
+        // only status and id are stored in plain text: the remaining criteria are matched after decryption
+        let mut sql = String::from("SELECT status, note FROM notes WHERE id >= ?1");
+        let mut args = vec![query.min_id as i64];
+        if let Some(status) = query.status
+        {
+            args.push(status as i64);
+            sql.push_str(" AND status = ?2");
+        }
+        sql.push_str(" ORDER BY id, status");
+
+        let mut stmt = self.db.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(args), |r| Ok((r.get::<_, i64>(0)?, r.get::<_, Vec<u8>>(1)?)))?.collect::<Result<Vec<_>, _>>()?;
+        let mut notes = Vec::new();
+        for (status, data) in rows
+        {
+            let status = if status == NoteStatus::Sent as i64 { NoteStatus::Sent } else { NoteStatus::Spendable };
+            let n: NoteEx = serde_json::from_slice(&wallet_file::decrypt_with_key(&self.notes_key, &data)?)?;
+            if query.matches(status, &n)
+            {
+                notes.push(n);
+            }
+        }
+        Ok(notes)
+    }
+
+    fn apply(&mut self, batch: StoreBatch) -> Result<(), WalletStoreError>
+    {
+        let wallet = match &batch.wallet {
+            Some(wallet) => Some(wallet_file::encrypt(wallet_format::encode(serde_json::from_str(wallet)?).as_bytes(), &self.password)?),
+            None => None
+        };
+        let tx = self.db.transaction()?;
+        if let Some(wallet) = wallet
+        {
+            tx.execute("INSERT OR REPLACE INTO wallet (id, data) VALUES (0, ?1)", [wallet])?;
+        }
+        for (status, id) in batch.remove.iter()
+        {
+            tx.execute("DELETE FROM notes WHERE id = ?1 AND status = ?2", params![*id as i64, *status as i64])?;
+        }
+        for (status, n) in batch.insert.iter()
+        {
+            tx.execute("INSERT OR REPLACE INTO notes (id, status, block_number, note) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    n.id as i64,
+                    *status as i64,
+                    n.block_number as i64,
+                    wallet_file::encrypt_with_key(&self.notes_key, serde_json::to_string(n)?.as_bytes())?
+                ])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{JsonFileStore, MemoryStore, NoteQuery, NoteStatus, SqliteStore, StoreBatch, WalletStore, WalletStoreError};
+    use crate::contract::NoteEx;
+    use crate::eosio::{name_to_value, string_to_symbol};
+    use crate::keys::{FullViewingKey, Scope, SpendingKey};
+    use crate::note::{Note, Nullifier, NT_FT, NT_NFT};
+    use crate::value::NoteValue;
+    use rand::rngs::OsRng;
+
+    fn notes() -> Vec<NoteEx>
+    {
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let fvk = FullViewingKey::from(&sk);
+        let eos = string_to_symbol(&"EOS".to_string(), 4);
+        let note = |id: u64, header: u64, d1: u64, d2: u64, contract: &str, i: u32| NoteEx {
+            id,
+            block_number: id + 1,
+            note: Note::new(
+                header,
+                fvk.address_at(i, Scope::External),
+                NoteValue::from_raw(d1),
+                NoteValue::from_raw(d2),
+                NoteValue::from_raw(name_to_value(&contract.to_string())),
+                NoteValue::from_raw(if header == NT_NFT { 1 } else { 0 }),
+                Nullifier::dummy(&mut OsRng),
+                OsRng,
+                [0; 512]
+            )
+        };
+        vec![
+            note(0, NT_FT, 10000, eos, "eosio.token", 0),
+            note(1, NT_FT, 20000, eos, "eosio.token", 1),
+            note(2, NT_NFT, 12345, 0, "atomicassets", 0),
+            // a contract name whose value exceeds i64::MAX
+            note(3, NT_FT, 30000, eos, "thezeostoken", 0),
+        ]
+    }
+
+    /// Applies batches to 'store' and checks the results of queries
+    fn check_store(store: &mut impl WalletStore, notes: &[NoteEx])
+    {
+        let ids = |query: NoteQuery| store.notes(&query).unwrap().iter().map(|n| n.id).collect::<Vec<u64>>();
+        assert!(store.load().unwrap().is_none());
+        assert!(ids(NoteQuery::default()).is_empty());
+
+        store.apply(StoreBatch {
+            wallet: Some(String::from("{\"seed\":\"\"}")),
+            insert: notes.iter().map(|n| (NoteStatus::Spendable, n.clone())).chain([(NoteStatus::Sent, notes[1].clone())]).collect(),
+            remove: Vec::new()
+        }).unwrap();
+        let ids = |query: NoteQuery| store.notes(&query).unwrap().iter().map(|n| n.id).collect::<Vec<u64>>();
+        assert_eq!(store.load().unwrap().unwrap(), "{\"seed\":\"\"}");
+        assert_eq!(ids(NoteQuery::default()), vec![0, 1, 1, 2, 3]);
+        assert_eq!(ids(NoteQuery::default().with_status(NoteStatus::Sent)), vec![1]);
+        assert_eq!(ids(NoteQuery::default().with_token("eosio.token", string_to_symbol(&"EOS".to_string(), 4))), vec![0, 1, 1]);
+        assert_eq!(ids(NoteQuery::default().with_token("thezeostoken", string_to_symbol(&"EOS".to_string(), 4))), vec![3]);
+        assert_eq!(ids(NoteQuery::default().with_nft_contract("atomicassets")), vec![2]);
+        assert_eq!(ids(NoteQuery::default().with_status(NoteStatus::Spendable).with_address(&notes[0].note.recipient())), vec![0, 2, 3]);
+        assert_eq!(ids(NoteQuery::default().with_min_id(2)), vec![2, 3]);
+        assert_eq!(store.notes(&NoteQuery::default().with_min_id(2)).unwrap()[0].note.d1().inner(), 12345);
+    }
+
+    /// Removes the spendable note 1 from 'store'
+    fn spend(store: &mut impl WalletStore)
+    {
+        store.apply(StoreBatch{ wallet: None, insert: Vec::new(), remove: vec![(NoteStatus::Spendable, 1)] }).unwrap();
+        let ids = store.notes(&NoteQuery::default()).unwrap().iter().map(|n| n.id).collect::<Vec<u64>>();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+        assert!(store.notes(&NoteQuery::default().with_status(NoteStatus::Spendable).with_token("eosio.token", string_to_symbol(&"EOS".to_string(), 4)).with_min_id(1)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn stores()
+    {
+        let notes = notes();
+        let mut store = MemoryStore::default();
+        check_store(&mut store, &notes);
+        spend(&mut store);
+
+        // file backends persist all batches
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("zeos-wallet-store-test-{}.wallet", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut store = JsonFileStore::open(&path, "secret").unwrap();
+        check_store(&mut store, &notes);
+        spend(&mut store);
+        let store = JsonFileStore::open(&path, "secret").unwrap();
+        assert_eq!(store.notes(&NoteQuery::default()).unwrap().len(), 4);
+        assert!(matches!(JsonFileStore::open(&path, "wrong"), Err(WalletStoreError::File(_))));
+        std::fs::remove_file(&path).unwrap();
+
+        let path = dir.join(format!("zeos-wallet-store-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut store = SqliteStore::open(&path, "secret").unwrap();
+        assert_eq!(store.schema_version().unwrap(), 1);
+        check_store(&mut store, &notes);
+        spend(&mut store);
+        drop(store);
+        let store = SqliteStore::open(&path, "secret").unwrap();
+        assert_eq!(store.notes(&NoteQuery::default()).unwrap().len(), 4);
+        assert_eq!(store.load().unwrap().unwrap(), "{\"seed\":\"\"}");
+        assert!(matches!(SqliteStore::open(&path, "wrong").unwrap().load(), Err(WalletStoreError::File(_))));
+        // a database of a newer version is rejected
+        store.db.pragma_update(None, "user_version", 99).unwrap();
+        drop(store);
+        assert!(matches!(SqliteStore::open(&path, "secret"), Err(WalletStoreError::SchemaVersion(99))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sqlite_notes_encrypted()
+    {
+        let sk = SpendingKey::from_zip32_seed(b"miau seed miau 123 Der seed muss lang genug sein...", 0, 0).unwrap();
+        let recipient = FullViewingKey::from(&sk).address_at(0u32, Scope::External);
+        let mut memo = [0; 512];
+        memo[..21].copy_from_slice(b"invoice 42: bike shop");
+        let n = NoteEx {
+            id: 0,
+            block_number: 1,
+            note: Note::new(
+                NT_FT,
+                recipient,
+                NoteValue::from_raw(123456789),
+                NoteValue::from_raw(string_to_symbol(&"EOS".to_string(), 4)),
+                NoteValue::from_raw(name_to_value(&"eosio.token".to_string())),
+                NoteValue::from_raw(0),
+                Nullifier::dummy(&mut OsRng),
+                OsRng,
+                memo
+            )
+        };
+
+        let path = std::env::temp_dir().join(format!("zeos-wallet-store-test-encrypted-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut store = SqliteStore::open(&path, "secret").unwrap();
+        store.apply(StoreBatch{ wallet: Some(String::from("{\"seed\":\"\"}")), insert: vec![(NoteStatus::Spendable, n.clone())], remove: Vec::new() }).unwrap();
+        drop(store);
+
+        // neither the memo nor the recipient can be found in the database file
+        let file = std::fs::read(&path).unwrap();
+        let contains = |needle: &[u8]| file.windows(needle.len()).any(|w| w == needle);
+        assert!(!contains(b"bike shop"));
+        assert!(!contains(hex::encode(b"bike shop").as_bytes()));
+        assert!(!contains(&recipient.to_raw_address_bytes()));
+        assert!(!contains(hex::encode(recipient.to_raw_address_bytes()).as_bytes()));
+
+        let store = SqliteStore::open(&path, "secret").unwrap();
+        let notes = store.notes(&NoteQuery::default().with_address(&recipient)).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].note.memo(), memo);
+        // the notes cannot be decrypted without the password
+        assert!(matches!(SqliteStore::open(&path, "wrong").unwrap().notes(&NoteQuery::default()), Err(WalletStoreError::File(_))));
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+}